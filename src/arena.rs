@@ -0,0 +1,525 @@
+use crate::grad_fn;
+use crate::{Float, GradFn, Operations, Val};
+
+
+// A node of a Graph, an index into its arrays. Only meaningful for the graph
+// that handed it out, and only until that graph is cleared past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValId(u32);
+
+
+impl ValId {
+    pub fn index(self) -> usize {
+        return self.0 as usize;
+    }
+}
+
+
+enum Op<T: Float> {
+    Leaf,
+    Add,
+    Sub,
+    Mul,
+    Neg,
+    Tanh,
+    Relu,
+    Sigmoid,
+    Exp,
+    Log,
+    Pow(T),
+    Scale(T),
+    Shift(T),
+    Sum,
+    Custom(Box<dyn GradFn<T> + Send + Sync>)
+}
+
+
+struct Node<T: Float> {
+    op:    Op<T>,
+    start: u32,
+    len:   u32
+}
+
+
+// An arena-allocated graph. Values, gradients and nodes live in flat arrays,
+// and children are ValId ranges into one shared list, so building a node is a
+// few pushes rather than an Rc allocation, and clear() frees a whole step's
+// graph at once while keeping the capacity for the next.
+//
+// Nodes can only refer to earlier ones, so creation order is already a
+// topological order and backward is a single reverse sweep.
+//
+// To move a training loop over from Val, keep the model as it is and bring
+// its parameters in with param() at the start of each step. After backward,
+// sync_grads() adds their gradients to the Vals, so the existing optimizers
+// step as before:
+//
+//     let mut g: Graph = Graph::new();
+//     for _ in 0..steps {
+//         g.clear();
+//         let params: Vec<ValId> = g.params(&model.parameters());
+//         let loss: ValId = ...;          // built with g.mul, g.add, g.tanh, ..
+//         optimizer.zero_grad();
+//         g.backward(loss);
+//         g.sync_grads(&model.parameters());
+//         optimizer.step();
+//     }
+//
+// A Graph holds no Rc and no Vals, only numbers and ids, so it is Send and
+// Sync: threads can each build and differentiate their own over shared plain
+// weights with leaves() and hand the results back with grads().
+pub struct Graph<T: Float = f64> {
+    data:     Vec<T>,
+    grad:     Vec<T>,
+    nodes:    Vec<Node<T>>,
+    children: Vec<ValId>,
+    params:   Vec<ValId>
+}
+
+
+impl<T: Float> Default for Graph<T> {
+    fn default() -> Graph<T> {
+        return Graph::new();
+    }
+}
+
+
+impl<T: Float> Graph<T> {
+    pub fn new() -> Graph<T> {
+        return Graph::with_capacity(0);
+    }
+
+    // Room for `nodes` nodes before any reallocation
+    pub fn with_capacity(nodes: usize) -> Graph<T> {
+        return Graph {
+            data:     Vec::with_capacity(nodes),
+            grad:     Vec::with_capacity(nodes),
+            nodes:    Vec::with_capacity(nodes),
+            children: Vec::with_capacity(2 * nodes),
+            params:   Vec::new()
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.nodes.is_empty();
+    }
+
+    // Drops every node, and the links made by param(). Handed-out ids become invalid.
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.grad.clear();
+        self.nodes.clear();
+        self.children.clear();
+        self.params.clear();
+    }
+
+    fn push(&mut self, op: Op<T>, value: T, inputs: &[ValId]) -> ValId {
+        for x in inputs {
+            assert!(x.index() < self.nodes.len(), "ValId {} is not in this graph", x.0);
+        }
+
+        let id: ValId = ValId(u32::try_from(self.nodes.len()).expect("graph is full"));
+        self.nodes.push(Node { op, start: self.children.len() as u32, len: inputs.len() as u32 });
+        self.children.extend_from_slice(inputs);
+        self.data.push(value);
+        self.grad.push(T::zero());
+
+        return id;
+    }
+
+    pub fn leaf(&mut self, x: T) -> ValId {
+        return self.push(Op::Leaf, x, &[]);
+    }
+
+    pub fn leaves(&mut self, xs: &[T]) -> Vec<ValId> {
+        return xs.iter().map(|&x| self.leaf(x)).collect();
+    }
+
+    // A leaf holding a Val's value. The graph only keeps its id, and sync_grads
+    // pairs the ids with Vals again in the order param() was called.
+    pub fn param(&mut self, v: &Val<T>) -> ValId {
+        let id: ValId = self.leaf(v.data());
+        self.params.push(id);
+        return id;
+    }
+
+    pub fn params(&mut self, vs: &[Val<T>]) -> Vec<ValId> {
+        return vs.iter().map(|v| self.param(v)).collect();
+    }
+
+    pub fn data(&self, id: ValId) -> T {
+        return self.data[id.index()];
+    }
+
+    pub fn grad(&self, id: ValId) -> T {
+        return self.grad[id.index()];
+    }
+
+    pub fn grads(&self, ids: &[ValId]) -> Vec<T> {
+        return ids.iter().map(|&id| self.grad(id)).collect();
+    }
+
+    pub fn inputs(&self, id: ValId) -> &[ValId] {
+        let n: &Node<T> = &self.nodes[id.index()];
+        return &self.children[n.start as usize..(n.start + n.len) as usize];
+    }
+
+    pub fn op(&self, id: ValId) -> Operations {
+        return match &self.nodes[id.index()].op {
+            Op::Leaf                         => Operations::Non,
+            Op::Add | Op::Shift(_)           => Operations::Add,
+            Op::Sub                          => Operations::Sub,
+            Op::Mul | Op::Neg | Op::Scale(_) => Operations::Mul,
+            Op::Tanh                         => Operations::Tanh,
+            Op::Relu                         => Operations::Relu,
+            Op::Sigmoid                      => Operations::Sigmoid,
+            Op::Exp                          => Operations::Exp,
+            Op::Log                          => Operations::Log,
+            Op::Pow(_)                       => Operations::Pow,
+            Op::Sum                          => Operations::Sum,
+            Op::Custom(f)                    => f.op()
+        };
+    }
+
+    pub fn add(&mut self, a: ValId, b: ValId) -> ValId {
+        let y: T = self.data(a) + self.data(b);
+        return self.push(Op::Add, y, &[a, b]);
+    }
+
+    pub fn sub(&mut self, a: ValId, b: ValId) -> ValId {
+        let y: T = self.data(a) - self.data(b);
+        return self.push(Op::Sub, y, &[a, b]);
+    }
+
+    pub fn mul(&mut self, a: ValId, b: ValId) -> ValId {
+        let y: T = self.data(a) * self.data(b);
+        return self.push(Op::Mul, y, &[a, b]);
+    }
+
+    pub fn neg(&mut self, a: ValId) -> ValId {
+        let y: T = -self.data(a);
+        return self.push(Op::Neg, y, &[a]);
+    }
+
+    // a * c and a + c for a constant c, without a leaf for it
+    pub fn scale(&mut self, a: ValId, c: T) -> ValId {
+        let y: T = self.data(a) * c;
+        return self.push(Op::Scale(c), y, &[a]);
+    }
+
+    pub fn shift(&mut self, a: ValId, c: T) -> ValId {
+        let y: T = self.data(a) + c;
+        return self.push(Op::Shift(c), y, &[a]);
+    }
+
+    pub fn tanh(&mut self, a: ValId) -> ValId {
+        let y: T = grad_fn::tanh_stable(self.data(a));
+        return self.push(Op::Tanh, y, &[a]);
+    }
+
+    pub fn relu(&mut self, a: ValId) -> ValId {
+        let x: T = self.data(a);
+        let y: T = if x > T::zero() { x } else { T::zero() };
+        return self.push(Op::Relu, y, &[a]);
+    }
+
+    pub fn sigmoid(&mut self, a: ValId) -> ValId {
+        let y: T = T::one() / (T::one() + (-self.data(a)).exp());
+        return self.push(Op::Sigmoid, y, &[a]);
+    }
+
+    pub fn exp(&mut self, a: ValId) -> ValId {
+        let y: T = self.data(a).exp();
+        return self.push(Op::Exp, y, &[a]);
+    }
+
+    pub fn log(&mut self, a: ValId) -> ValId {
+        let y: T = self.data(a).ln();
+        return self.push(Op::Log, y, &[a]);
+    }
+
+    pub fn pow(&mut self, a: ValId, n: T) -> ValId {
+        let y: T = self.data(a).powf(n);
+        return self.push(Op::Pow(n), y, &[a]);
+    }
+
+    // One node over all of xs, however many there are
+    pub fn sum(&mut self, xs: &[ValId]) -> ValId {
+        let y: T = xs.iter().fold(T::zero(), |acc, &x| acc + self.data(x));
+        return self.push(Op::Sum, y, xs);
+    }
+
+    // w·x + b, the body of a neuron
+    pub fn dot(&mut self, w: &[ValId], x: &[ValId], b: ValId) -> ValId {
+        assert_eq!(w.len(), x.len(), "expected as many weights as inputs");
+        let mut terms: Vec<ValId> = Vec::with_capacity(w.len() + 1);
+        for (&wi, &xi) in w.iter().zip(x.iter()) {
+            terms.push(self.mul(wi, xi));
+        }
+        terms.push(b);
+        return self.sum(&terms);
+    }
+
+    // Any GradFn, boxed; the built-in ops above avoid the allocation
+    pub fn apply<G>(&mut self, f: G, inputs: &[ValId]) -> ValId
+    where G: GradFn<T> + Send + Sync + 'static,
+    {
+        let xs: Vec<T> = inputs.iter().map(|&x| self.data(x)).collect();
+        let y: T = f.forward(&xs);
+        return self.push(Op::Custom(Box::new(f)), y, inputs);
+    }
+
+    // Gradients of `root` with respect to every node before it. Clears the
+    // gradients first, so nothing carries over from an earlier backward.
+    pub fn backward(&mut self, root: ValId) {
+        let r: usize = root.index();
+        self.grad[..=r].fill(T::zero());
+        self.grad[r] = T::one();
+
+        for i in (0..=r).rev() {
+            let g: T = self.grad[i];
+            let node: &Node<T> = &self.nodes[i];
+            let kids: &[ValId] = &self.children[node.start as usize..(node.start + node.len) as usize];
+            let (data, grad) = (&self.data, &mut self.grad);
+            let y: T = data[i];
+
+            match &node.op {
+                Op::Leaf     => {},
+                Op::Add      => {
+                    grad[kids[0].index()] += g;
+                    grad[kids[1].index()] += g;
+                },
+                Op::Sub      => {
+                    grad[kids[0].index()] += g;
+                    grad[kids[1].index()] += -g;
+                },
+                Op::Mul      => {
+                    let (a, b): (usize, usize) = (kids[0].index(), kids[1].index());
+                    let (da, db): (T, T) = (data[b] * g, data[a] * g);
+                    grad[a] += da;
+                    grad[b] += db;
+                },
+                Op::Neg      => grad[kids[0].index()] += -g,
+                Op::Scale(c) => grad[kids[0].index()] += *c * g,
+                Op::Shift(_) => grad[kids[0].index()] += g,
+                Op::Tanh     => grad[kids[0].index()] += (T::one() - y * y) * g,
+                Op::Relu     => if y > T::zero() { grad[kids[0].index()] += g },
+                Op::Sigmoid  => grad[kids[0].index()] += y * (T::one() - y) * g,
+                Op::Exp      => grad[kids[0].index()] += y * g,
+                Op::Log      => {
+                    let a: usize = kids[0].index();
+                    grad[a] += g / data[a];
+                },
+                Op::Pow(n)   => {
+                    let a: usize = kids[0].index();
+                    grad[a] += *n * data[a].powf(*n - T::one()) * g;
+                },
+                Op::Sum      => {
+                    for k in kids {
+                        grad[k.index()] += g;
+                    }
+                },
+                Op::Custom(f) => {
+                    let xs: Vec<T> = kids.iter().map(|k| data[k.index()]).collect();
+                    for (k, gk) in kids.iter().zip(f.backward(&xs, y, g)) {
+                        grad[k.index()] += gk;
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds each param() leaf's gradient into its Val, frozen Vals excepted.
+    // `vals` are the Vals passed to param() since the last clear, in order.
+    pub fn sync_grads(&self, vals: &[Val<T>]) {
+        assert_eq!(vals.len(), self.params.len(), "expected one Val per param() leaf");
+        for (id, v) in self.params.iter().zip(vals.iter()).filter(|(_, v)| v.requires_grad()) {
+            v.add_grad(self.grad(*id));
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod arena_ops {
+    use super::*;
+    use crate::loss::mse;
+    use crate::nn::{Module, Neuron, MLP};
+    use crate::optim::{Optimizer, SGD};
+
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn arena() {
+        {
+            // The same expression in both engines: tanh(a b + a^2) - exp(b) / 2 + log(a) sigmoid(b)
+            let (va, vb): (Val, Val) = (Val::new(0.7), Val::new(-0.4));
+            let left: Val = (&(&va * &vb) + &va.clone().pow(2.0)).tanh();
+            let right: Val = &(vb.clone().exp() * 0.5) - &(&va.clone().log() * &vb.clone().sigmoid());
+            let out: Val = &left - &right;
+            out.backward();
+
+            let mut g: Graph = Graph::new();
+            let (a, b): (ValId, ValId) = (g.leaf(0.7), g.leaf(-0.4));
+            let ab: ValId = g.mul(a, b);
+            let a2: ValId = g.pow(a, 2.0);
+            let s: ValId = g.add(ab, a2);
+            let left: ValId = g.tanh(s);
+            let eb: ValId = g.exp(b);
+            let half: ValId = g.scale(eb, 0.5);
+            let la: ValId = g.log(a);
+            let sb: ValId = g.sigmoid(b);
+            let prod: ValId = g.mul(la, sb);
+            let right: ValId = g.sub(half, prod);
+            let root: ValId = g.sub(left, right);
+            g.backward(root);
+
+            assert!(approx_eq(g.data(root), out.data()));
+            assert!(approx_eq(g.grad(a), va.grad()));
+            assert!(approx_eq(g.grad(b), vb.grad()));
+            assert_eq!(g.op(left), Operations::Tanh);
+            assert_eq!(g.inputs(prod), &[la, sb]);
+        }
+
+        {
+            // tanh saturates instead of overflowing to NaN
+            let mut g: Graph = Graph::new();
+            let x: ValId = g.leaf(400.0);
+            let y: ValId = g.tanh(x);
+            g.backward(y);
+            assert_eq!(g.data(y), 1.0);
+            assert_eq!(g.grad(x), 0.0);
+        }
+
+        {
+            // Backward starts afresh every time, and custom GradFns work too
+            let mut g: Graph = Graph::new();
+            let x: ValId = g.leaf(-1.5);
+            let r: ValId = g.apply(crate::grad_fn::Abs, &[x]);
+            let y: ValId = g.relu(x);
+            let n: ValId = g.neg(y);
+            let s: ValId = g.sum(&[r, n, x, x]);
+
+            g.backward(s);
+            g.backward(s);
+            assert_eq!(g.data(s), 1.5 - 0.0 - 3.0);
+            assert_eq!(g.grad(x), -1.0 + 0.0 + 2.0);
+            assert_eq!(g.op(r), Operations::Abs);
+
+            g.clear();
+            assert!(g.is_empty());
+        }
+    }
+
+    #[test]
+    fn migration() {
+        {
+            // A neuron's step in the arena matches the same step on Vals
+            let make = || Neuron::from_weights(&[0.3, -0.2], 0.1, true);
+            let (on_vals, in_arena): (Neuron, Neuron) = (make(), make());
+            let x: [f64; 2] = [1.0, 2.0];
+
+            let mut opt: SGD = SGD::new(on_vals.parameters(), 0.1);
+            let inputs: Vec<Val> = x.iter().map(|&xi| Val::new(xi)).collect();
+            mse(&on_vals.forward(&inputs), &[0.5]).backward();
+            opt.step();
+
+            let mut opt: SGD = SGD::new(in_arena.parameters(), 0.1);
+            let mut g: Graph = Graph::new();
+            let p: Vec<ValId> = g.params(&in_arena.parameters());
+            let xs: Vec<ValId> = x.iter().map(|&xi| g.leaf(xi)).collect();
+            let act: ValId = g.dot(&p[..2], &xs, p[2]);
+            let out: ValId = g.tanh(act);
+            let err: ValId = g.shift(out, -0.5);
+            let loss: ValId = g.pow(err, 2.0);
+            opt.zero_grad();
+            g.backward(loss);
+            g.sync_grads(&in_arena.parameters());
+            opt.step();
+
+            for (a, b) in on_vals.parameters().iter().zip(in_arena.parameters().iter()) {
+                assert!(approx_eq(a.data(), b.data()));
+            }
+        }
+
+        {
+            // One arena reused across steps keeps its capacity
+            let m: MLP = MLP::with_init(2, &[4, 1], crate::init::Init::Xavier, &mut crate::rand::Rng::new(3));
+            let mut opt: SGD = SGD::new(m.parameters(), 0.1);
+            let mut g: Graph = Graph::with_capacity(64);
+            let mut losses: Vec<f64> = Vec::new();
+
+            for _ in 0..50 {
+                g.clear();
+                let p: Vec<ValId> = g.params(&m.parameters());
+                let xs: Vec<ValId> = vec![g.leaf(0.5), g.leaf(-1.0)];
+
+                // Layer 0 is 4 neurons of 3 parameters, layer 1 one of 5
+                let hidden: Vec<ValId> = (0..4).map(|j| {
+                    let act: ValId = g.dot(&p[3 * j..3 * j + 2], &xs, p[3 * j + 2]);
+                    g.tanh(act)
+                }).collect();
+                let out: ValId = g.dot(&p[12..16], &hidden, p[16]);
+                let err: ValId = g.shift(out, -0.8);
+                let loss: ValId = g.pow(err, 2.0);
+                losses.push(g.data(loss));
+
+                opt.zero_grad();
+                g.backward(loss);
+                g.sync_grads(&m.parameters());
+                opt.step();
+            }
+
+            assert!(losses[49] < 1e-3 * losses[0]);
+            assert!(g.len() <= 64);
+        }
+    }
+
+    #[test]
+    fn threads() {
+        fn send_sync<S: Send + Sync>() {}
+        send_sync::<Graph>();
+        send_sync::<Graph<f32>>();
+        send_sync::<ValId>();
+
+        {
+            // Weights shared across threads; each builds its own graph for one
+            // example and its gradients are summed, as one graph over all of them would give
+            let w: std::sync::Arc<Vec<f64>> = std::sync::Arc::new(vec![0.4, -0.7, 0.1]);
+            let xs: Vec<[f64; 2]> = vec![[1.0, 0.5], [-0.3, 2.0], [0.8, -1.1], [0.0, 0.25]];
+
+            let grads: Vec<Vec<f64>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = xs.iter().map(|x| {
+                    let w = std::sync::Arc::clone(&w);
+                    scope.spawn(move || -> Vec<f64> {
+                        let mut g: Graph = Graph::new();
+                        let p: Vec<ValId> = g.leaves(&w);
+                        let inputs: Vec<ValId> = g.leaves(x);
+                        let act: ValId = g.dot(&p[..2], &inputs, p[2]);
+                        let out: ValId = g.tanh(act);
+                        g.backward(out);
+                        return g.grads(&p);
+                    })
+                }).collect();
+                return handles.into_iter().map(|h| h.join().unwrap()).collect();
+            });
+
+            let n: Neuron = Neuron::from_weights(&w[..2], w[2], true);
+            for x in xs.iter() {
+                let inputs: Vec<Val> = x.iter().map(|&xi| Val::new(xi)).collect();
+                n.forward(&inputs)[0].backward();
+            }
+            for (i, p) in n.parameters().iter().enumerate() {
+                assert!(approx_eq(grads.iter().map(|g| g[i]).sum(), p.grad()));
+            }
+        }
+    }
+}
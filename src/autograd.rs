@@ -0,0 +1,467 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::{ops, Float, GradFn, Operations, Val, ValData};
+
+
+// d output / d x for each of `inputs`, as nodes of a new graph rather than
+// numbers, so they can be used in a loss and backpropagated through again:
+// gradient penalties, Hessian-vector products. Every op on the way back needs
+// GradFn::backward_graph. Inputs the output doesn't depend on get a constant zero.
+pub fn grad_graph<T: Float>(output: &Val<T>, inputs: &[Val<T>]) -> Vec<Val<T>> {
+    let mut adjoints: HashMap<*const RefCell<ValData<T>>, Val<T>> = HashMap::new();
+    adjoints.insert(Rc::as_ptr(&output.0), Val::constant(T::one()));
+
+    for node in output.topo().iter().rev() {
+        let inner = node.0.borrow();
+        if inner.grad.is_none() || inner.prev.is_empty() {
+            continue;
+        }
+        let grad: Val<T> = match adjoints.get(&Rc::as_ptr(&node.0)) {
+            Some(g) => g.clone(),
+            None    => continue
+        };
+
+        let f = inner.grad_fn.as_ref().expect("a node with children has a grad_fn");
+        let grads: Vec<Val<T>> = f.backward_graph(&inner.prev, node, &grad)
+            .unwrap_or_else(|| panic!("{} has no backward_graph, so it can't be differentiated twice", f.op()));
+
+        for (x, g) in inner.prev.iter().zip(grads) {
+            if !x.requires_grad() {
+                continue;
+            }
+            let key: *const RefCell<ValData<T>> = Rc::as_ptr(&x.0);
+            let total: Val<T> = match adjoints.remove(&key) {
+                Some(acc) => acc + g,
+                None      => g
+            };
+            adjoints.insert(key, total);
+        }
+    }
+
+    return inputs.iter()
+        .map(|x| adjoints.get(&Rc::as_ptr(&x.0)).cloned().unwrap_or_else(|| Val::constant(T::zero())))
+        .collect();
+}
+
+
+// The Jacobian of outputs with respect to inputs, one row per output: row i
+// holds d outputs[i] / d inputs[j]. One backward pass per output; every
+// gradient in the graph is put back as it was afterwards, so this can sit
+// beside training without disturbing it.
+pub fn grad<T: Float>(outputs: &[Val<T>], inputs: &[Val<T>]) -> Vec<Vec<T>> {
+    let mut seen: HashSet<*const RefCell<ValData<T>>> = HashSet::new();
+    let nodes: Vec<Val<T>> = outputs.iter()
+        .flat_map(|y| y.topo())
+        .chain(inputs.iter().cloned())
+        .filter(|v| seen.insert(Rc::as_ptr(&v.0)))
+        .collect();
+    let saved: Vec<T> = nodes.iter().map(|v| v.grad()).collect();
+
+    let rows: Vec<Vec<T>> = outputs.iter()
+        .map(|y| {
+            for x in inputs.iter() {
+                x.set_grad(T::zero());
+            }
+            y.propagate(T::one());
+            inputs.iter().map(|x| x.grad()).collect()
+        })
+        .collect();
+
+    for (v, g) in nodes.iter().zip(saved) {
+        v.set_grad(g);
+    }
+
+    return rows;
+}
+
+
+// The Hessian of loss with respect to params times vector, H v, without forming
+// H: differentiates ∇loss · v a second time. Costs about two backward passes,
+// and leaves the params' grads untouched.
+pub fn hvp<T: Float>(loss: &Val<T>, params: &[Val<T>], vector: &[T]) -> Vec<T> {
+    assert_eq!(params.len(), vector.len(), "expected one vector entry per parameter");
+    let grads: Vec<Val<T>> = grad_graph(loss, params);
+    let dot: Vec<Val<T>> = grads.iter().zip(vector.iter()).map(|(g, &v)| g * v).collect();
+
+    return grad_graph(&ops::sum(&dot), params).iter().map(|h| h.data()).collect();
+}
+
+
+// f(inputs), keeping none of f's intermediate nodes: the outputs are single
+// nodes over the inputs, and backward through them rebuilds f's graph from
+// the input values, runs it, and drops it again. Trades a second forward for
+// the memory of every node inside, e.g. per chunk of steps of an unrolled RNN.
+// Parameters f closes over get their gradients as usual.
+//
+// f runs again on backward, so it must give the same result for the same
+// inputs: no dropout or other draws from a generator.
+pub fn checkpoint<T, F>(f: F, inputs: &[Val<T>]) -> Vec<Val<T>>
+where T: Float,
+      F: Fn(&[Val<T>]) -> Vec<Val<T>> + 'static,
+{
+    if !crate::is_grad_enabled() {
+        return f(inputs);
+    }
+
+    let segment: Rc<Segment<T>> = Rc::new(Segment { f: Box::new(f), values: RefCell::new(None), graph: RefCell::new(None) });
+    let xs: Vec<T> = inputs.iter().map(|x| x.data()).collect();
+    let n: usize = segment.values(&xs).len();
+
+    return (0..n)
+        .map(|index| {
+            let out: Val<T> = Val::apply(Checkpoint { segment: Rc::clone(&segment), index }, inputs);
+            // Parameters inside f may need gradients even when no input does
+            out.set_requires_grad(true);
+            out
+        })
+        .collect();
+}
+
+
+type SegmentFn<T> = Box<dyn Fn(&[Val<T>]) -> Vec<Val<T>>>;
+
+
+// One checkpointed call of f. Its output values are kept for the inputs last
+// seen, and its rebuilt graph only until every output has been through backward.
+struct Segment<T: Float> {
+    f:      SegmentFn<T>,
+    values: RefCell<Option<(Vec<T>, Vec<T>)>>,
+    graph:  RefCell<Option<Rebuilt<T>>>
+}
+
+
+struct Rebuilt<T: Float> {
+    inputs:  Vec<T>,
+    leaves:  Vec<Val<T>>,
+    outputs: Vec<Val<T>>,
+    pending: usize
+}
+
+
+impl<T: Float> Segment<T> {
+    fn values(&self, xs: &[T]) -> Vec<T> {
+        let mut cached = self.values.borrow_mut();
+        if let Some((at, values)) = cached.as_ref() {
+            if at.as_slice() == xs {
+                return values.clone();
+            }
+        }
+
+        let leaves: Vec<Val<T>> = xs.iter().map(|&x| Val::new(x)).collect();
+        let values: Vec<T> = crate::no_grad(|| (self.f)(&leaves)).iter().map(|v| v.data()).collect();
+        *cached = Some((xs.to_vec(), values.clone()));
+
+        return values;
+    }
+
+    // d output[index] / d inputs, times grad, also adding into any parameters f uses
+    fn backward(&self, xs: &[T], index: usize, grad: T) -> Vec<T> {
+        let mut graph = self.graph.borrow_mut();
+        if graph.as_ref().is_none_or(|g| g.inputs.as_slice() != xs) {
+            let leaves: Vec<Val<T>> = xs.iter().map(|&x| Val::new(x)).collect();
+            let outputs: Vec<Val<T>> = (self.f)(&leaves);
+            let pending: usize = outputs.len();
+            *graph = Some(Rebuilt { inputs: xs.to_vec(), leaves, outputs, pending });
+        }
+
+        let g: &mut Rebuilt<T> = graph.as_mut().expect("just rebuilt");
+        // Left from another output's pass; propagate clears the rest, and
+        // the parameters' gradients are meant to add up
+        for leaf in g.leaves.iter() {
+            leaf.set_grad(T::zero());
+        }
+
+        g.outputs[index].propagate(grad);
+        let grads: Vec<T> = g.leaves.iter().map(|l| l.grad()).collect();
+
+        g.pending -= 1;
+        if g.pending == 0 {
+            *graph = None;
+        }
+
+        return grads;
+    }
+}
+
+
+struct Checkpoint<T: Float> {
+    segment: Rc<Segment<T>>,
+    index:   usize
+}
+
+
+impl<T: Float> GradFn<T> for Checkpoint<T> {
+    fn op(&self) -> Operations {
+        return Operations::Custom("Checkpoint");
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return self.segment.values(inputs)[self.index];
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return self.segment.backward(inputs, self.index, grad);
+    }
+}
+
+
+
+#[cfg(test)]
+mod autograd_ops {
+    use super::*;
+    use crate::grad_check;
+    use crate::ops::{log_softmax, softmax};
+
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    // d/dx of f'(x), by central differences of the ordinary backward pass
+    fn numeric_second(f: fn(Val) -> Val, x: f64) -> f64 {
+        let first = |x: f64| -> f64 {
+            let v: Val = Val::new(x);
+            f(v.clone()).backward();
+            return v.grad();
+        };
+        let eps: f64 = 1e-5;
+        return (first(x + eps) - first(x - eps)) / (2.0 * eps);
+    }
+
+    #[test]
+    fn double_backward() {
+        {
+            // d/dx x³ = 3x², d²/dx² x³ = 6x
+            let x: Val = Val::new(2.0);
+            let y: Val = &(&x * &x) * &x;
+            let dx: Vec<Val> = grad_graph(&y, std::slice::from_ref(&x));
+            assert_eq!(dx[0].data(), 12.0);
+            assert_eq!(x.grad(), 0.0);
+
+            dx[0].backward();
+            assert_eq!(x.grad(), 12.0);
+
+            // And a third time
+            let x: Val = Val::new(1.5);
+            let y: Val = x.clone().pow(4.0);
+            let d1: Val = grad_graph(&y, std::slice::from_ref(&x)).remove(0);
+            let d2: Val = grad_graph(&d1, std::slice::from_ref(&x)).remove(0);
+            let d3: Val = grad_graph(&d2, std::slice::from_ref(&x)).remove(0);
+            assert!(approx_eq(d3.data(), 24.0 * 1.5));
+        }
+
+        {
+            let fs: Vec<fn(Val) -> Val> = vec![
+                |x| x.tanh(),
+                |x| x.sigmoid(),
+                |x| x.exp(),
+                |x| (&x * &x + 1.0).log(),
+                |x| (&x * &x + 1.0).pow(-1.5),
+                |x| (&x * &x).leaky_relu(0.1) - x,
+                |x| x.elu(1.5) * 3.0,
+                |x| x.gelu(),
+                |x| x.softplus(),
+                |x| x.silu(),
+                |x| (&x * &x).abs() + x.clone().relu() * 2.0 + x.sign(),
+                |x| 1.0 - x * 2.0
+            ];
+            for f in fs.iter() {
+                for x in [-1.7, -0.2, 0.4, 2.5] {
+                    let v: Val = Val::new(x);
+                    let d: Val = grad_graph(&f(v.clone()), std::slice::from_ref(&v)).remove(0);
+                    d.backward();
+                    assert!((v.grad() - numeric_second(*f, x)).abs() < 1e-6, "{} at {}", d, x);
+                }
+            }
+        }
+
+        {
+            // A gradient penalty, ‖∂y/∂x‖², differentiated with respect to the weights
+            let f = |p: &[Val]| -> Val {
+                let (w, x): (&[Val], &[Val]) = (&p[..3], &p[3..]);
+                let h: Val = (&(&w[0] * &x[0]) + &(&w[1] * &x[1])).tanh();
+                let logits: Vec<Val> = vec![h.clone(), &h * &w[2], &x[0] * &w[2]];
+                let y: Val = &softmax(&logits)[0] + &log_softmax(&logits)[2];
+
+                let dx: Vec<Val> = grad_graph(&y, x);
+                return &(&dx[0] * &dx[0]) + &(&dx[1] * &dx[1]);
+            };
+            for c in grad_check(f, &[0.4, -0.9, 1.3, 0.7, -0.2], 1e-6) {
+                assert!(c.rel_error < 1e-6, "{:?}", c);
+            }
+        }
+
+        {
+            // Unconnected and frozen inputs get a zero that doesn't require grad
+            let (a, b, c): (Val, Val, Val) = (Val::new(1.0), Val::new(2.0), Val::new(3.0));
+            c.set_requires_grad(false);
+            let y: Val = &a * &c;
+            let d: Vec<Val> = grad_graph(&y, &[a, b, c]);
+            assert_eq!(d[0].data(), 3.0);
+            assert_eq!((d[1].data(), d[1].requires_grad()), (0.0, false));
+            assert_eq!((d[2].data(), d[2].requires_grad()), (0.0, false));
+
+            // Nothing the gradient depends on requires grad, so backward leaves it alone
+            assert!(!d[0].requires_grad());
+        }
+    }
+
+    #[test]
+    fn hessian_vector() {
+        {
+            // ½ xᵀ A x has Hessian A, whatever x is
+            let a: [[f64; 2]; 2] = [[2.0, -1.0], [-1.0, 3.0]];
+            let x: Vec<Val> = vec![Val::new(0.3), Val::new(-1.2)];
+            let mut terms: Vec<Val> = Vec::new();
+            for i in 0..2 {
+                for j in 0..2 {
+                    terms.push(&(&x[i] * &x[j]) * (0.5 * a[i][j]));
+                }
+            }
+            let loss: Val = ops::sum(&terms);
+
+            assert_eq!(hvp(&loss, &x, &[1.0, 0.0]), vec![2.0, -1.0]);
+            assert_eq!(hvp(&loss, &x, &[1.0, 2.0]), vec![0.0, 5.0]);
+            assert!(x.iter().all(|xi| xi.grad() == 0.0));
+        }
+
+        {
+            // Against central differences of the gradient along v
+            let f = |p: &[Val]| -> Val {
+                let h: Val = (&(&p[0] * &p[1]) + &p[2]).tanh();
+                return &(&h * &h) + &(&p[0] * &p[2].clone().exp());
+            };
+            let grad_at = |p: &[f64]| -> Vec<f64> {
+                let vs: Vec<Val> = p.iter().map(|&x| Val::new(x)).collect();
+                f(&vs).backward();
+                return vs.iter().map(|v| v.grad()).collect();
+            };
+
+            let p: [f64; 3] = [0.5, -0.8, 0.3];
+            let v: [f64; 3] = [1.0, 0.5, -2.0];
+            let vals: Vec<Val> = p.iter().map(|&x| Val::new(x)).collect();
+            let hv: Vec<f64> = hvp(&f(&vals), &vals, &v);
+
+            let eps: f64 = 1e-5;
+            let plus: Vec<f64> = p.iter().zip(v.iter()).map(|(x, d)| x + eps * d).collect();
+            let minus: Vec<f64> = p.iter().zip(v.iter()).map(|(x, d)| x - eps * d).collect();
+            let (gp, gm): (Vec<f64>, Vec<f64>) = (grad_at(&plus), grad_at(&minus));
+            for i in 0..3 {
+                assert!((hv[i] - (gp[i] - gm[i]) / (2.0 * eps)).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn jacobian() {
+        {
+            // A linear map's Jacobian is its matrix
+            let x: Vec<Val> = vec![Val::new(0.3), Val::new(-1.2)];
+            let y: Vec<Val> = vec![&(&x[0] * 2.0) - &x[1], &x[1] * 3.0 + 1.0, &x[0] * -0.5];
+            assert_eq!(grad(&y, &x), vec![vec![2.0, -1.0], vec![0.0, 3.0], vec![-0.5, 0.0]]);
+        }
+
+        {
+            let p: Vec<Val> = vec![Val::new(0.5), Val::new(-1.5), Val::new(2.0)];
+            let shared: Val = (&p[0] * &p[1]).tanh();
+            let outputs: Vec<Val> = vec![&shared + &p[2], &shared * &shared, p[1].clone()];
+            p[0].set_grad(7.0);
+
+            let rows: Vec<Vec<f64>> = grad(&outputs, &p);
+            let ds: f64 = 1.0 - shared.data().powi(2);
+            assert_eq!(rows.len(), 3);
+            assert!(approx_eq(rows[0][0], ds * p[1].data()));
+            assert!(approx_eq(rows[0][1], ds * p[0].data()));
+            assert_eq!(rows[0][2], 1.0);
+            assert!(approx_eq(rows[1][1], 2.0 * shared.data() * ds * p[0].data()));
+            assert_eq!(rows[1][2], 0.0);
+            assert_eq!(rows[2], vec![0.0, 1.0, 0.0]);
+
+            // Each row matches its own backward, and the grads are left alone
+            assert_eq!(p[0].grad(), 7.0);
+            assert_eq!(p[1].grad(), 0.0);
+            for (y, row) in outputs.iter().zip(rows.iter()) {
+                for x in p.iter() {
+                    x.set_grad(0.0);
+                }
+                y.backward();
+                for (x, g) in p.iter().zip(row.iter()) {
+                    assert!(approx_eq(x.grad(), *g));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checkpointed() {
+        {
+            // 40 steps of h = tanh(w h + x_t), in chunks of 8, matches the plain unrolled graph
+            let xs: Vec<f64> = (0..40).map(|t| (t as f64 * 0.37).sin()).collect();
+            let run = |chunked: bool| -> (f64, f64, f64, usize) {
+                let (w, h0): (Val, Val) = (Val::new(0.8), Val::new(0.1));
+                let mut h: Val = h0.clone();
+                for chunk in xs.chunks(8) {
+                    let (w, chunk): (Val, Vec<f64>) = (w.clone(), chunk.to_vec());
+                    let step = move |hs: &[Val]| -> Vec<Val> {
+                        let mut h: Val = hs[0].clone();
+                        for &x in chunk.iter() {
+                            h = (&(&w * &h) + x).tanh();
+                        }
+                        return vec![h];
+                    };
+                    h = if chunked { checkpoint(step, &[h]).remove(0) } else { step(&[h]).remove(0) };
+                }
+                let loss: Val = &h * &h;
+                let nodes: usize = loss.graph_stats().nodes;
+                loss.backward();
+                return (loss.data(), w.grad(), h0.grad(), nodes);
+            };
+
+            let (plain, chunked) = (run(false), run(true));
+            assert!(approx_eq(plain.0, chunked.0));
+            assert!(approx_eq(plain.1, chunked.1));
+            assert!(approx_eq(plain.2, chunked.2));
+            // Per chunk one node instead of 8 steps of three
+            assert!(chunked.3 * 10 < plain.3);
+        }
+
+        {
+            // Several outputs, each used, with the segment rebuilt once per backward
+            let (a, b): (Val, Val) = (Val::new(0.5), Val::new(-1.5));
+            let outs: Vec<Val> = checkpoint(|x: &[Val]| vec![&x[0] * &x[1], x[0].clone().exp() + 1.0], &[a.clone(), b.clone()]);
+            assert_eq!(outs.len(), 2);
+            assert_eq!(outs[0].op(), Operations::Custom("Checkpoint"));
+            let loss: Val = &(&outs[0] * 3.0) + &outs[1];
+            loss.backward();
+
+            assert!(approx_eq(a.grad(), 3.0 * -1.5 + 0.5_f64.exp()));
+            assert!(approx_eq(b.grad(), 3.0 * 0.5));
+        }
+    }
+
+    struct Twice;
+
+    impl GradFn for Twice {
+        fn op(&self) -> Operations {
+            return Operations::Custom("Twice");
+        }
+
+        fn forward(&self, inputs: &[f64]) -> f64 {
+            return 2.0 * inputs[0];
+        }
+
+        fn backward(&self, _inputs: &[f64], _output: f64, grad: f64) -> Vec<f64> {
+            return vec![2.0 * grad];
+        }
+    }
+
+    #[test]
+    #[should_panic(expected="Twice has no backward_graph")]
+    fn first_order_only() {
+        let x: Val = Val::new(1.0);
+        let y: Val = Val::apply(Twice, std::slice::from_ref(&x));
+        grad_graph(&y, &[x]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod simd;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unary {
+    Tanh,
+    Relu,
+    // 1 where x > 0, else 0; the mask relu's backward multiplies by
+    Step,
+    Scale(f64),
+    Shift(f64)
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+    Add,
+    Sub,
+    Mul
+}
+
+
+// Where Tensor's arithmetic runs. Buffers are plain host slices in and out,
+// so a device backend copies across per call; row-major throughout. Tensor
+// only ever goes through these methods, so a new backend needs nothing else
+// changed.
+pub trait Backend {
+    fn name(&self) -> &'static str;
+
+    fn alloc(&self, len: usize) -> Vec<f64> {
+        return vec![0.0; len];
+    }
+
+    fn unary(&self, op: Unary, x: &[f64]) -> Vec<f64>;
+
+    // a and b have the same length
+    fn binary(&self, op: Binary, a: &[f64], b: &[f64]) -> Vec<f64>;
+
+    // [m, k] @ [k, n]
+    fn matmul(&self, a: &[f64], b: &[f64], m: usize, k: usize, n: usize) -> Vec<f64>;
+
+    // [r, c] -> [c, r]
+    fn transpose(&self, a: &[f64], r: usize, c: usize) -> Vec<f64>;
+
+    fn sum(&self, x: &[f64]) -> f64;
+
+    // Each of the rows of `cols` values on its own
+    fn softmax(&self, x: &[f64], rows: usize, cols: usize) -> Vec<f64>;
+}
+
+
+// The default: plain loops, with AVX in matmul under the `simd` feature
+pub struct Cpu;
+
+
+impl Backend for Cpu {
+    fn name(&self) -> &'static str {
+        return "cpu";
+    }
+
+    fn unary(&self, op: Unary, x: &[f64]) -> Vec<f64> {
+        return match op {
+            Unary::Tanh     => x.iter().map(|v| v.tanh()).collect(),
+            Unary::Relu     => x.iter().map(|&v| if v > 0.0 { v } else { 0.0 }).collect(),
+            Unary::Step     => x.iter().map(|&v| if v > 0.0 { 1.0 } else { 0.0 }).collect(),
+            Unary::Scale(c) => x.iter().map(|v| v * c).collect(),
+            Unary::Shift(c) => x.iter().map(|v| v + c).collect()
+        };
+    }
+
+    fn binary(&self, op: Binary, a: &[f64], b: &[f64]) -> Vec<f64> {
+        return match op {
+            Binary::Add => a.iter().zip(b.iter()).map(|(x, y)| x + y).collect(),
+            Binary::Sub => a.iter().zip(b.iter()).map(|(x, y)| x - y).collect(),
+            Binary::Mul => a.iter().zip(b.iter()).map(|(x, y)| x * y).collect()
+        };
+    }
+
+    fn matmul(&self, a: &[f64], b: &[f64], m: usize, k: usize, n: usize) -> Vec<f64> {
+        let mut out: Vec<f64> = self.alloc(m * n);
+        for i in 0..m {
+            for p in 0..k {
+                simd::axpy(&mut out[i * n..(i + 1) * n], a[i * k + p], &b[p * n..(p + 1) * n]);
+            }
+        }
+
+        return out;
+    }
+
+    fn transpose(&self, a: &[f64], r: usize, c: usize) -> Vec<f64> {
+        let mut out: Vec<f64> = self.alloc(r * c);
+        for i in 0..r {
+            for j in 0..c {
+                out[j * r + i] = a[i * c + j];
+            }
+        }
+
+        return out;
+    }
+
+    fn sum(&self, x: &[f64]) -> f64 {
+        return x.iter().sum();
+    }
+
+    // Shifted by the row's max, so large inputs can't overflow
+    fn softmax(&self, x: &[f64], rows: usize, cols: usize) -> Vec<f64> {
+        let mut out: Vec<f64> = self.alloc(rows * cols);
+        for (row, o) in x.chunks(cols.max(1)).zip(out.chunks_mut(cols.max(1))).take(rows) {
+            let max: f64 = row.iter().fold(f64::NEG_INFINITY, |m, &v| m.max(v));
+            for (oi, &v) in o.iter_mut().zip(row.iter()) {
+                *oi = (v - max).exp();
+            }
+            let total: f64 = o.iter().sum();
+            for oi in o.iter_mut() {
+                *oi /= total;
+            }
+        }
+
+        return out;
+    }
+}
+
+
+thread_local! {
+    static BACKEND: RefCell<Rc<dyn Backend>> = RefCell::new(Rc::new(Cpu));
+}
+
+
+// The backend new tensor ops run on. Each op keeps the one it was built with
+// for its backward.
+pub fn current() -> Rc<dyn Backend> {
+    return BACKEND.with(|b| b.borrow().clone());
+}
+
+
+// Runs `f` with tensor ops on `backend`, putting the previous one back
+// afterwards, even on panic. Scopes nest, as with no_grad.
+pub fn with_backend<B, R, F>(backend: B, f: F) -> R
+where B: Backend + 'static,
+      F: FnOnce() -> R,
+{
+    struct Restore(Option<Rc<dyn Backend>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(b) = self.0.take() {
+                BACKEND.with(|cell| *cell.borrow_mut() = b);
+            }
+        }
+    }
+
+    let previous: Rc<dyn Backend> = BACKEND.with(|cell| cell.replace(Rc::new(backend)));
+    let _restore: Restore = Restore(Some(previous));
+    return f();
+}
+
+
+
+#[cfg(test)]
+mod backend_ops {
+    use super::*;
+    use crate::tensor::Tensor;
+    use std::cell::Cell;
+
+    // Cpu underneath, counting the calls that reach it
+    struct Counting(Rc<Cell<usize>>);
+
+    impl Backend for Counting {
+        fn name(&self) -> &'static str {
+            return "counting";
+        }
+
+        fn unary(&self, op: Unary, x: &[f64]) -> Vec<f64> {
+            self.0.set(self.0.get() + 1);
+            return Cpu.unary(op, x);
+        }
+
+        fn binary(&self, op: Binary, a: &[f64], b: &[f64]) -> Vec<f64> {
+            self.0.set(self.0.get() + 1);
+            return Cpu.binary(op, a, b);
+        }
+
+        fn matmul(&self, a: &[f64], b: &[f64], m: usize, k: usize, n: usize) -> Vec<f64> {
+            self.0.set(self.0.get() + 1);
+            return Cpu.matmul(a, b, m, k, n);
+        }
+
+        fn transpose(&self, a: &[f64], r: usize, c: usize) -> Vec<f64> {
+            self.0.set(self.0.get() + 1);
+            return Cpu.transpose(a, r, c);
+        }
+
+        fn sum(&self, x: &[f64]) -> f64 {
+            self.0.set(self.0.get() + 1);
+            return Cpu.sum(x);
+        }
+
+        fn softmax(&self, x: &[f64], rows: usize, cols: usize) -> Vec<f64> {
+            self.0.set(self.0.get() + 1);
+            return Cpu.softmax(x, rows, cols);
+        }
+    }
+
+    #[test]
+    fn cpu() {
+        {
+            assert_eq!(Cpu.unary(Unary::Relu, &[-1.0, 2.0]), vec![0.0, 2.0]);
+            assert_eq!(Cpu.unary(Unary::Step, &[-1.0, 0.0, 2.0]), vec![0.0, 0.0, 1.0]);
+            assert_eq!(Cpu.unary(Unary::Shift(1.5), &[1.0]), vec![2.5]);
+            assert_eq!(Cpu.binary(Binary::Sub, &[3.0, 1.0], &[1.0, 1.0]), vec![2.0, 0.0]);
+            assert_eq!(Cpu.matmul(&[1.0, 2.0, 3.0, 4.0], &[5.0, 6.0, 7.0, 8.0], 2, 2, 2), vec![19.0, 22.0, 43.0, 50.0]);
+            assert_eq!(Cpu.transpose(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+            assert_eq!(Cpu.sum(&[1.0, 2.0, 3.5]), 6.5);
+            assert_eq!(Cpu.softmax(&[0.0, 0.0, 1000.0, 1000.0], 2, 2), vec![0.5, 0.5, 0.5, 0.5]);
+            assert_eq!(current().name(), "cpu");
+        }
+    }
+
+    #[test]
+    fn swapped() {
+        {
+            // Forward and backward both go through the backend in scope when the op was built
+            let calls: Rc<Cell<usize>> = Rc::new(Cell::new(0));
+            let x: Tensor = Tensor::new(vec![1.0, -2.0], &[1, 2]);
+            let w: Tensor = Tensor::new(vec![0.5, 1.0, -0.25, -1.0], &[2, 2]);
+
+            let y: Tensor = with_backend(Counting(calls.clone()), || {
+                assert_eq!(current().name(), "counting");
+                return (&x.matmul(&w) * 2.0).relu();
+            });
+            assert_eq!(calls.get(), 3);
+            assert_eq!(current().name(), "cpu");
+
+            y.backward();
+            assert!(calls.get() > 3);
+            assert_eq!(w.grad(), vec![2.0, 2.0, -4.0, -4.0]);
+        }
+
+        {
+            // Restored after a panic too
+            let result = std::panic::catch_unwind(|| with_backend(Counting(Rc::new(Cell::new(0))), || panic!("inside")));
+            assert!(result.is_err());
+            assert_eq!(current().name(), "cpu");
+        }
+    }
+}
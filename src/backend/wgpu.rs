@@ -0,0 +1,67 @@
+use super::{Backend, Binary, Unary};
+
+
+// No adapter can exist until the wgpu crate is a dependency, so there are
+// no values: a Wgpu can't be built, and none of its methods can be reached.
+enum Device {}
+
+
+// The GPU slot behind the `wgpu` feature. Use it as
+// with_backend(Wgpu::new()?, || ...); until the device code lands, new()
+// reports why it can't open one, and callers keep running on Cpu.
+pub struct Wgpu {
+    device: Device
+}
+
+
+impl Wgpu {
+    pub fn new() -> Result<Wgpu, String> {
+        return Err(String::from("rusty_nn was built without the wgpu crate, so no GPU adapter can be opened"));
+    }
+}
+
+
+impl Backend for Wgpu {
+    fn name(&self) -> &'static str {
+        return "wgpu";
+    }
+
+    fn unary(&self, _op: Unary, _x: &[f64]) -> Vec<f64> {
+        match self.device {}
+    }
+
+    fn binary(&self, _op: Binary, _a: &[f64], _b: &[f64]) -> Vec<f64> {
+        match self.device {}
+    }
+
+    fn matmul(&self, _a: &[f64], _b: &[f64], _m: usize, _k: usize, _n: usize) -> Vec<f64> {
+        match self.device {}
+    }
+
+    fn transpose(&self, _a: &[f64], _r: usize, _c: usize) -> Vec<f64> {
+        match self.device {}
+    }
+
+    fn sum(&self, _x: &[f64]) -> f64 {
+        match self.device {}
+    }
+
+    fn softmax(&self, _x: &[f64], _rows: usize, _cols: usize) -> Vec<f64> {
+        match self.device {}
+    }
+}
+
+
+
+#[cfg(test)]
+mod wgpu_ops {
+    use super::*;
+
+    #[test]
+    fn unavailable() {
+        {
+            let e: String = Wgpu::new().err().unwrap();
+            assert!(e.contains("without the wgpu crate"));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{fs, io};
+
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::{rand, Float, Val};
+
+
+const MAGIC:   &[u8; 4] = b"RNNC";
+const VERSION: u32      = 1;
+
+
+// Everything needed to resume a run: parameter values, optimizer buffers,
+// the epoch counter and the global RNG.
+//
+// Layout, little endian: magic, version u32, epoch u64, rng u64,
+// then the parameters and the optimizer state, each as a u64 count of f64s.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Checkpoint {
+    pub epoch:     usize,
+    pub rng:       u64,
+    pub params:    Vec<f64>,
+    pub optimizer: Vec<f64>
+}
+
+
+impl Checkpoint {
+    pub fn capture<T, M, O>(model: &M, optimizer: &O, epoch: usize) -> Checkpoint
+    where T: Float,
+          M: Module<T> + ?Sized,
+          O: Optimizer + ?Sized,
+    {
+        return Checkpoint {
+            epoch,
+            rng: rand::global_state(),
+            params: model.parameters().iter().map(|p| p.data().to_f64()).collect(),
+            optimizer: optimizer.state()
+        };
+    }
+
+    // Writes the values back into model and optimizer and reseeds the global
+    // RNG. Returns the epoch to continue from.
+    pub fn restore<T, M, O>(&self, model: &M, optimizer: &mut O) -> Result<usize, String>
+    where T: Float,
+          M: Module<T> + ?Sized,
+          O: Optimizer + ?Sized,
+    {
+        let params: Vec<Val<T>> = model.parameters();
+        if params.len() != self.params.len() {
+            return Err(format!("checkpoint has {} parameters, model has {}", self.params.len(), params.len()));
+        }
+        optimizer.load_state(&self.optimizer)?;
+
+        for (p, &x) in params.iter().zip(self.params.iter()) {
+            p.set_data(T::from_f64(x));
+        }
+        rand::set_seed(self.rng);
+
+        return Ok(self.epoch);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::with_capacity(36 + 8 * (self.params.len() + self.optimizer.len()));
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.epoch as u64).to_le_bytes());
+        out.extend_from_slice(&self.rng.to_le_bytes());
+
+        for xs in [&self.params, &self.optimizer] {
+            out.extend_from_slice(&(xs.len() as u64).to_le_bytes());
+            for x in xs.iter() {
+                out.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Checkpoint, String> {
+        let mut r: Reader = Reader { bytes, pos: 0 };
+
+        if r.take(4)? != MAGIC {
+            return Err(String::from("not a checkpoint"));
+        }
+        let version: u32 = u32::from_le_bytes(r.take(4)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported checkpoint version {}", version));
+        }
+
+        let epoch: usize = r.u64()? as usize;
+        let rng: u64 = r.u64()?;
+        let params: Vec<f64> = r.f64s()?;
+        let optimizer: Vec<f64> = r.f64s()?;
+
+        if r.pos != bytes.len() {
+            return Err(String::from("trailing bytes after checkpoint"));
+        }
+
+        return Ok(Checkpoint { epoch, rng, params, optimizer });
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        return fs::write(path, self.to_bytes());
+    }
+
+    pub fn load(path: &str) -> io::Result<Checkpoint> {
+        let bytes: Vec<u8> = fs::read(path)?;
+        return Checkpoint::from_bytes(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+}
+
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos:   usize
+}
+
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end: usize = self.pos.checked_add(n).filter(|&e| e <= self.bytes.len()).ok_or("checkpoint is truncated")?;
+        let out: &[u8] = &self.bytes[self.pos..end];
+        self.pos = end;
+
+        return Ok(out);
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    fn f64s(&mut self) -> Result<Vec<f64>, String> {
+        let n: usize = self.u64()? as usize;
+        let raw: &[u8] = self.take(n.checked_mul(8).ok_or("checkpoint is truncated")?)?;
+
+        return Ok(raw.chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect());
+    }
+}
+
+
+
+#[cfg(test)]
+mod checkpoint_ops {
+    use super::*;
+    use crate::init::Init;
+    use crate::loss::mse;
+    use crate::nn::MLP;
+    use crate::optim::{Adam, SGD};
+    use crate::train::{fit_batched, BatchConfig};
+
+    fn data() -> Vec<(Vec<f64>, Vec<f64>)> {
+        return (0..8).map(|i| (vec![i as f64 * 0.1, 1.0 - i as f64 * 0.1], vec![(i % 3) as f64 - 1.0])).collect();
+    }
+
+    #[test]
+    fn bytes() {
+        {
+            let c: Checkpoint = Checkpoint { epoch: 12, rng: u64::MAX - 3, params: vec![0.1, -2.5, 1e-300], optimizer: vec![0.01, 3.0] };
+            let bytes: Vec<u8> = c.to_bytes();
+
+            assert_eq!(bytes.len(), 4 + 4 + 8 + 8 + 8 + 3 * 8 + 8 + 2 * 8);
+            assert_eq!(Checkpoint::from_bytes(&bytes).unwrap(), c);
+
+            assert!(Checkpoint::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            assert!(Checkpoint::from_bytes(b"JUNKJUNK").is_err());
+            assert!(Checkpoint::from_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn resume() {
+        {
+            // Five epochs at once or two, a save and reload, then three more
+            let config: BatchConfig = BatchConfig::new(3).seed(2);
+
+            crate::set_seed(21);
+            let full: MLP = MLP::random(2, &[3, 1], Init::Xavier);
+            let mut opt: Adam = Adam::new(full.parameters(), 0.05);
+            fit_batched(&full, &data(), &mut opt, mse, 5, &config);
+
+            crate::set_seed(21);
+            let first: MLP = MLP::random(2, &[3, 1], Init::Xavier);
+            let mut opt: Adam = Adam::new(first.parameters(), 0.05);
+            fit_batched(&first, &data(), &mut opt, mse, 2, &config);
+
+            let path: String = std::env::temp_dir().join("rusty_nn_resume.ckpt").to_string_lossy().into_owned();
+            Checkpoint::capture(&first, &opt, 2).save(&path).unwrap();
+            let loaded: Checkpoint = Checkpoint::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            crate::set_seed(99);
+            let second: MLP = MLP::new(2, &[3, 1]);
+            let mut opt: Adam = Adam::new(second.parameters(), 0.5);
+            assert_eq!(loaded.restore(&second, &mut opt), Ok(2));
+            fit_batched(&second, &data(), &mut opt, mse, 3, &config);
+
+            let a: Vec<f64> = full.parameters().iter().map(|p| p.data()).collect();
+            let b: Vec<f64> = second.parameters().iter().map(|p| p.data()).collect();
+            assert_eq!(a, b);
+        }
+
+        {
+            crate::set_seed(3);
+            let m: MLP = MLP::random(2, &[2, 1], Init::Uniform);
+            let mut opt: SGD = SGD::with_momentum(m.parameters(), 0.1, 0.9);
+            fit_batched(&m, &data(), &mut opt, mse, 1, &BatchConfig::new(4));
+            let c: Checkpoint = Checkpoint::capture(&m, &opt, 1);
+
+            let mut other: SGD = SGD::with_momentum(m.parameters(), 0.1, 0.9);
+            assert!(c.restore(&m, &mut other).is_ok());
+            assert_eq!(other.state(), opt.state());
+
+            // Shapes must line up
+            let small: MLP = MLP::new(2, &[1]);
+            let mut opt: SGD = SGD::new(small.parameters(), 0.1);
+            assert!(c.restore(&small, &mut opt).is_err());
+        }
+    }
+}
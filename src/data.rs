@@ -0,0 +1,124 @@
+use crate::Float;
+
+mod csv;
+mod idx;
+mod loader;
+mod scaler;
+mod split;
+pub mod synthetic;
+pub use csv::{from_csv, from_csv_with, parse_csv, CsvOptions};
+pub use idx::{load_mnist, mnist, IdxArray};
+pub use loader::{Batch, Batches, DataLoader};
+pub use scaler::{Scaler, Scaling};
+pub use split::{split, stratified_split};
+
+
+// Examples addressed by index, each an input vector and a target vector
+pub trait Dataset<T: Float = f64> {
+    fn len(&self) -> usize;
+
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>);
+
+    fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    // Everything at once, in the form train::fit takes
+    fn to_vec(&self) -> Vec<(Vec<T>, Vec<T>)> {
+        return (0..self.len()).map(|i| self.get(i)).collect();
+    }
+}
+
+
+// Slices of pairs are datasets already
+impl<T: Float> Dataset<T> for [(Vec<T>, Vec<T>)] {
+    fn len(&self) -> usize {
+        return <[(Vec<T>, Vec<T>)]>::len(self);
+    }
+
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>) {
+        return self[i].clone();
+    }
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct InMemoryDataset<T: Float = f64> {
+    examples: Vec<(Vec<T>, Vec<T>)>
+}
+
+
+impl<T: Float> InMemoryDataset<T> {
+    // One target row per input row
+    pub fn new(inputs: Vec<Vec<T>>, targets: Vec<Vec<T>>) -> InMemoryDataset<T> {
+        assert_eq!(inputs.len(), targets.len(), "expected one target per input");
+        return InMemoryDataset { examples: inputs.into_iter().zip(targets).collect() };
+    }
+
+    pub fn from_pairs(examples: Vec<(Vec<T>, Vec<T>)>) -> InMemoryDataset<T> {
+        return InMemoryDataset { examples };
+    }
+
+    // Single-valued targets, as for regression or class indices
+    pub fn from_slices(inputs: &[&[T]], targets: &[T]) -> InMemoryDataset<T> {
+        assert_eq!(inputs.len(), targets.len(), "expected one target per input");
+        return InMemoryDataset { examples: inputs.iter().zip(targets.iter()).map(|(x, &y)| (x.to_vec(), vec![y])).collect() };
+    }
+
+    pub fn examples(&self) -> &[(Vec<T>, Vec<T>)] {
+        return &self.examples;
+    }
+
+    pub fn push(&mut self, input: Vec<T>, target: Vec<T>) {
+        self.examples.push((input, target));
+    }
+}
+
+
+impl<T: Float> Dataset<T> for InMemoryDataset<T> {
+    fn len(&self) -> usize {
+        return self.examples.len();
+    }
+
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>) {
+        return self.examples[i].clone();
+    }
+}
+
+
+
+#[cfg(test)]
+mod data_ops {
+    use super::*;
+
+    #[test]
+    fn in_memory() {
+        {
+            let ds: InMemoryDataset = InMemoryDataset::new(vec![vec![1.0, 2.0], vec![3.0, 4.0]], vec![vec![0.0], vec![1.0]]);
+
+            assert_eq!(ds.len(), 2);
+            assert!(!ds.is_empty());
+            assert_eq!(ds.get(1), (vec![3.0, 4.0], vec![1.0]));
+            assert_eq!(ds.to_vec(), ds.examples().to_vec());
+
+            let same: InMemoryDataset = InMemoryDataset::from_slices(&[&[1.0, 2.0], &[3.0, 4.0]], &[0.0, 1.0]);
+            assert_eq!(same, ds);
+        }
+
+        {
+            // Generic code takes either form
+            fn total<D: Dataset + ?Sized>(ds: &D) -> f64 {
+                return (0..ds.len()).map(|i| ds.get(i).1[0]).sum();
+            }
+
+            let pairs: Vec<(Vec<f64>, Vec<f64>)> = vec![(vec![0.0], vec![2.0]), (vec![1.0], vec![3.0])];
+            let mut ds: InMemoryDataset = InMemoryDataset::from_pairs(pairs.clone());
+            assert_eq!(total(pairs.as_slice()), 5.0);
+            assert_eq!(total(&ds), 5.0);
+
+            ds.push(vec![2.0], vec![4.0]);
+            assert_eq!(total(&ds), 9.0);
+            assert!(InMemoryDataset::<f64>::from_pairs(Vec::new()).is_empty());
+        }
+    }
+}
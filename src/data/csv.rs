@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+
+use crate::Float;
+
+use super::{Dataset, InMemoryDataset, Scaler};
+
+
+// How from_csv_with reads a file. Defaults are comma separated, no header,
+// values as written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvOptions {
+    header:    bool,
+    delimiter: char,
+    normalize: bool
+}
+
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        return CsvOptions::new();
+    }
+}
+
+
+impl CsvOptions {
+    pub fn new() -> CsvOptions {
+        return CsvOptions { header: false, delimiter: ',', normalize: false };
+    }
+
+    // Skip the first line
+    pub fn header(mut self, header: bool) -> CsvOptions {
+        self.header = header;
+        return self;
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> CsvOptions {
+        self.delimiter = delimiter;
+        return self;
+    }
+
+    // Shift and scale every feature column to zero mean and unit variance,
+    // fitted on this file alone. Targets are left alone. To scale a test file
+    // with the training statistics, read both raw and use a Scaler.
+    pub fn normalize(mut self, normalize: bool) -> CsvOptions {
+        self.normalize = normalize;
+        return self;
+    }
+}
+
+
+// Numeric columns only; the target becomes a one-element vector
+pub fn from_csv<T: Float>(path: &str, feature_cols: &[usize], target_col: usize) -> io::Result<InMemoryDataset<T>> {
+    return from_csv_with(path, feature_cols, target_col, CsvOptions::new());
+}
+
+
+pub fn from_csv_with<T: Float>(path: &str, feature_cols: &[usize], target_col: usize, options: CsvOptions) -> io::Result<InMemoryDataset<T>> {
+    let text: String = fs::read_to_string(path)?;
+    return parse_csv(&text, feature_cols, target_col, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+}
+
+
+// Blank lines are skipped; errors name the 1-based line
+pub fn parse_csv<T: Float>(text: &str, feature_cols: &[usize], target_col: usize, options: CsvOptions) -> Result<InMemoryDataset<T>, String> {
+    let mut inputs: Vec<Vec<T>> = Vec::new();
+    let mut targets: Vec<Vec<T>> = Vec::new();
+
+    let skip: usize = if options.header { 1 } else { 0 };
+    for (n, line) in text.lines().enumerate().skip(skip) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(options.delimiter).map(|f| f.trim()).collect();
+        let field = |col: usize| -> Result<f64, String> {
+            let raw: &str = fields.get(col).ok_or(format!("line {}: no column {}", n + 1, col))?;
+            return raw.parse::<f64>().map_err(|_| format!("line {}: column {} is not a number: {:?}", n + 1, col, raw));
+        };
+
+        inputs.push(feature_cols.iter().map(|&c| field(c).map(T::from_f64)).collect::<Result<Vec<T>, String>>()?);
+        targets.push(vec![T::from_f64(field(target_col)?)]);
+    }
+
+    let dataset: InMemoryDataset<T> = InMemoryDataset::new(inputs, targets);
+    if options.normalize && !dataset.is_empty() {
+        return Ok(Scaler::standard(&dataset).transform_dataset(&dataset));
+    }
+
+    return Ok(dataset);
+}
+
+
+
+#[cfg(test)]
+mod csv_ops {
+    use super::*;
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn parse() {
+        {
+            let text: &str = "sepal,petal,class\n5.1, 1.4, 0\n\n6.3,4.9,1\n";
+            let ds: InMemoryDataset = parse_csv(text, &[0, 1], 2, CsvOptions::new().header(true)).unwrap();
+
+            assert_eq!(ds.len(), 2);
+            assert_eq!(ds.get(0), (vec![5.1, 1.4], vec![0.0]));
+            assert_eq!(ds.get(1), (vec![6.3, 4.9], vec![1.0]));
+
+            // Columns in any order, and another delimiter
+            let ds: InMemoryDataset<f32> = parse_csv("1;2;3\n4;5;6", &[2, 0], 1, CsvOptions::new().delimiter(';')).unwrap();
+            assert_eq!(ds.get(1), (vec![6.0, 4.0], vec![5.0]));
+        }
+
+        {
+            let ds: InMemoryDataset = parse_csv("1,10,0\n2,10,0\n3,10,1", &[0, 1], 2, CsvOptions::new().normalize(true)).unwrap();
+            let col = |c: usize| -> Vec<f64> { return ds.examples().iter().map(|(x, _)| x[c]).collect() };
+
+            assert!(approx_eq(col(0).iter().sum(), 0.0));
+            assert!(approx_eq(col(0).iter().map(|x| x * x).sum::<f64>() / 3.0, 1.0));
+            assert_eq!(col(1), vec![0.0, 0.0, 0.0]);
+            assert_eq!(ds.get(2).1, vec![1.0]);
+        }
+
+        {
+            let header: CsvOptions = CsvOptions::new().header(true);
+            assert_eq!(parse_csv::<f64>("a,b\n1,x", &[0], 1, header).unwrap_err(), "line 2: column 1 is not a number: \"x\"");
+            assert_eq!(parse_csv::<f64>("1,2\n3", &[0], 1, CsvOptions::new()).unwrap_err(), "line 2: no column 1");
+            // Without header the names are data
+            assert!(parse_csv::<f64>("a,b\n1,2", &[0], 1, CsvOptions::new()).is_err());
+        }
+
+        {
+            let path: String = std::env::temp_dir().join("rusty_nn_from_csv.csv").to_string_lossy().into_owned();
+            fs::write(&path, "0,0,0\n0,1,1\n1,0,1\n1,1,0\n").unwrap();
+            let ds: InMemoryDataset = from_csv(&path, &[0, 1], 2).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(ds.len(), 4);
+            assert_eq!(ds.get(3), (vec![1.0, 1.0], vec![0.0]));
+            assert_eq!(from_csv::<f64>(&path, &[0], 1).unwrap_err().kind(), io::ErrorKind::NotFound);
+        }
+    }
+}
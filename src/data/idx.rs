@@ -0,0 +1,172 @@
+use std::fs;
+use std::io;
+
+use crate::Float;
+
+use super::InMemoryDataset;
+
+
+// An IDX array, the format MNIST ships in: a magic number giving the element
+// type and rank, big-endian u32 dimensions, then the elements row-major.
+// Files must be decompressed first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxArray {
+    dims:  Vec<usize>,
+    bytes: bool,
+    data:  Vec<f64>
+}
+
+
+impl IdxArray {
+    pub fn from_bytes(raw: &[u8]) -> Result<IdxArray, String> {
+        if raw.len() < 4 || raw[0] != 0 || raw[1] != 0 {
+            return Err(String::from("not an IDX file"));
+        }
+
+        let (code, rank): (u8, usize) = (raw[2], raw[3] as usize);
+        let width: usize = match code {
+            0x08 | 0x09 => 1,
+            0x0B        => 2,
+            0x0C | 0x0D => 4,
+            0x0E        => 8,
+            _           => return Err(format!("unknown IDX element type 0x{:02x}", code))
+        };
+
+        let header: usize = 4 + 4 * rank;
+        if raw.len() < header {
+            return Err(String::from("IDX header is truncated"));
+        }
+        let dims: Vec<usize> = raw[4..header].chunks(4)
+            .map(|d| u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize)
+            .collect();
+
+        let count: usize = dims.iter().product();
+        let body: &[u8] = &raw[header..];
+        if body.len() != count * width {
+            return Err(format!("expected {} bytes of IDX data, found {}", count * width, body.len()));
+        }
+
+        let data: Vec<f64> = body.chunks(width).map(|b| match code {
+            0x08 => b[0] as f64,
+            0x09 => b[0] as i8 as f64,
+            0x0B => i16::from_be_bytes([b[0], b[1]]) as f64,
+            0x0C => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            0x0D => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            _    => f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+        }).collect();
+
+        return Ok(IdxArray { dims, bytes: code == 0x08, data });
+    }
+
+    pub fn load(path: &str) -> io::Result<IdxArray> {
+        let raw: Vec<u8> = fs::read(path)?;
+        return IdxArray::from_bytes(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+
+    pub fn dims(&self) -> &[usize] {
+        return &self.dims;
+    }
+
+    pub fn data(&self) -> &[f64] {
+        return &self.data;
+    }
+
+    // One flattened row per entry along the first dimension
+    pub fn rows(&self) -> Vec<Vec<f64>> {
+        let n: usize = self.dims.first().copied().unwrap_or(1);
+        let size: usize = self.data.len().checked_div(n).unwrap_or(0);
+        return (0..n).map(|i| self.data[i * size..(i + 1) * size].to_vec()).collect();
+    }
+}
+
+
+// Images scaled from 0..=255 to [0, 1] and flattened, each paired with its
+// class index as a one-element target
+pub fn mnist<T: Float>(images: &IdxArray, labels: &IdxArray) -> Result<InMemoryDataset<T>, String> {
+    if !images.bytes || !labels.bytes {
+        return Err(String::from("expected unsigned byte images and labels"));
+    }
+    if labels.dims.len() != 1 || images.dims.first() != labels.dims.first() {
+        return Err(format!("{:?} images but {:?} labels", images.dims, labels.dims));
+    }
+
+    let inputs: Vec<Vec<T>> = images.rows().into_iter()
+        .map(|r| r.into_iter().map(|p| T::from_f64(p / 255.0)).collect())
+        .collect();
+    let targets: Vec<Vec<T>> = labels.data.iter().map(|&y| vec![T::from_f64(y)]).collect();
+
+    return Ok(InMemoryDataset::new(inputs, targets));
+}
+
+
+// The pair of files, e.g. train-images-idx3-ubyte and train-labels-idx1-ubyte
+pub fn load_mnist<T: Float>(images_path: &str, labels_path: &str) -> io::Result<InMemoryDataset<T>> {
+    let images: IdxArray = IdxArray::load(images_path)?;
+    let labels: IdxArray = IdxArray::load(labels_path)?;
+    return mnist(&images, &labels).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+}
+
+
+
+#[cfg(test)]
+mod idx_ops {
+    use super::*;
+    use crate::data::Dataset;
+
+    fn idx(code: u8, dims: &[u32], body: &[u8]) -> Vec<u8> {
+        let mut raw: Vec<u8> = vec![0, 0, code, dims.len() as u8];
+        for d in dims {
+            raw.extend_from_slice(&d.to_be_bytes());
+        }
+        raw.extend_from_slice(body);
+        return raw;
+    }
+
+    #[test]
+    fn parse() {
+        {
+            // Two 2x3 images
+            let images: IdxArray = IdxArray::from_bytes(&idx(0x08, &[2, 2, 3], &[0, 51, 255, 0, 0, 0, 255, 255, 255, 102, 0, 0])).unwrap();
+            let labels: IdxArray = IdxArray::from_bytes(&idx(0x08, &[2], &[7, 3])).unwrap();
+
+            assert_eq!(images.dims(), &[2, 2, 3]);
+            assert_eq!(images.rows()[1], vec![255.0, 255.0, 255.0, 102.0, 0.0, 0.0]);
+
+            let ds: InMemoryDataset = mnist(&images, &labels).unwrap();
+            assert_eq!(ds.len(), 2);
+            assert_eq!(ds.get(0), (vec![0.0, 0.2, 1.0, 0.0, 0.0, 0.0], vec![7.0]));
+            assert_eq!(ds.get(1).1, vec![3.0]);
+            assert!(ds.examples().iter().all(|(x, _)| x.iter().all(|&p| (0.0..=1.0).contains(&p))));
+
+            let short: IdxArray = IdxArray::from_bytes(&idx(0x08, &[1], &[7])).unwrap();
+            assert!(mnist::<f64>(&images, &short).is_err());
+        }
+
+        {
+            // Wider element types are big-endian
+            let mut body: Vec<u8> = (-2_i16).to_be_bytes().to_vec();
+            body.extend_from_slice(&300_i16.to_be_bytes());
+            assert_eq!(IdxArray::from_bytes(&idx(0x0B, &[2], &body)).unwrap().data(), &[-2.0, 300.0]);
+            assert_eq!(IdxArray::from_bytes(&idx(0x0E, &[1], &1.5_f64.to_be_bytes())).unwrap().data(), &[1.5]);
+        }
+
+        {
+            assert_eq!(IdxArray::from_bytes(b"PK\x03\x04").unwrap_err(), "not an IDX file");
+            assert_eq!(IdxArray::from_bytes(&idx(0x07, &[1], &[0])).unwrap_err(), "unknown IDX element type 0x07");
+            assert_eq!(IdxArray::from_bytes(&idx(0x08, &[4], &[0, 1])).unwrap_err(), "expected 4 bytes of IDX data, found 2");
+        }
+
+        {
+            let dir = std::env::temp_dir();
+            let images: String = dir.join("rusty_nn_images.idx").to_string_lossy().into_owned();
+            let labels: String = dir.join("rusty_nn_labels.idx").to_string_lossy().into_owned();
+            fs::write(&images, idx(0x08, &[1, 2, 2], &[255, 0, 0, 255])).unwrap();
+            fs::write(&labels, idx(0x08, &[1], &[1])).unwrap();
+
+            let ds: InMemoryDataset<f32> = load_mnist(&images, &labels).unwrap();
+            fs::remove_file(&images).unwrap();
+            fs::remove_file(&labels).unwrap();
+            assert_eq!(ds.get(0), (vec![1.0, 0.0, 0.0, 1.0], vec![1.0]));
+        }
+    }
+}
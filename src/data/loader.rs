@@ -0,0 +1,176 @@
+use std::marker::PhantomData;
+
+use crate::rand::{self, Rng};
+use crate::{Float, Val};
+
+use super::Dataset;
+
+
+// A mini-batch, inputs and targets row by row
+#[derive(Debug, PartialEq, Clone)]
+pub struct Batch<T: Float = f64> {
+    pub inputs:  Vec<Vec<T>>,
+    pub targets: Vec<Vec<T>>
+}
+
+
+impl<T: Float> Batch<T> {
+    pub fn len(&self) -> usize {
+        return self.inputs.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.inputs.is_empty();
+    }
+
+    // Fresh constant leaves for every input, ready for Module::forward_examples
+    pub fn input_vals(&self) -> Vec<Vec<Val<T>>> {
+        return self.inputs.iter().map(|x| x.iter().map(|&xi| Val::constant(xi)).collect()).collect();
+    }
+}
+
+
+// Walks a dataset in mini-batches. Each call to iter() is one epoch, reshuffled
+// when shuffling is on. Without drop_last the final batch may be short.
+pub struct DataLoader<'a, T: Float, D: Dataset<T> + ?Sized> {
+    dataset:    &'a D,
+    batch_size: usize,
+    shuffle:    bool,
+    drop_last:  bool,
+    rng:        Rng,
+    _float:     PhantomData<T>
+}
+
+
+impl<'a, T: Float, D: Dataset<T> + ?Sized> DataLoader<'a, T, D> {
+    // Shuffles, if asked to, with a generator forked from the global one
+    pub fn new(dataset: &'a D, batch_size: usize) -> DataLoader<'a, T, D> {
+        assert!(batch_size > 0, "batch size must be positive");
+        return DataLoader { dataset, batch_size, shuffle: false, drop_last: false, rng: rand::fork(), _float: PhantomData };
+    }
+
+    pub fn shuffle(mut self) -> DataLoader<'a, T, D> {
+        self.shuffle = true;
+        return self;
+    }
+
+    // Shuffles with a private generator instead
+    pub fn seed(mut self, seed: u64) -> DataLoader<'a, T, D> {
+        self.rng = Rng::new(seed);
+        return self;
+    }
+
+    pub fn drop_last(mut self) -> DataLoader<'a, T, D> {
+        self.drop_last = true;
+        return self;
+    }
+
+    // Batches per epoch
+    pub fn len(&self) -> usize {
+        let n: usize = self.dataset.len();
+        return if self.drop_last { n / self.batch_size } else { n.div_ceil(self.batch_size) };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn iter(&mut self) -> Batches<'a, T, D> {
+        let mut order: Vec<usize> = (0..self.dataset.len()).collect();
+        if self.shuffle {
+            self.rng.shuffle(&mut order);
+        }
+        if self.drop_last {
+            order.truncate(self.len() * self.batch_size);
+        }
+
+        return Batches { dataset: self.dataset, order, batch_size: self.batch_size, next: 0, _float: PhantomData };
+    }
+}
+
+
+pub struct Batches<'a, T: Float, D: Dataset<T> + ?Sized> {
+    dataset:    &'a D,
+    order:      Vec<usize>,
+    batch_size: usize,
+    next:       usize,
+    _float:     PhantomData<T>
+}
+
+
+impl<T: Float, D: Dataset<T> + ?Sized> Iterator for Batches<'_, T, D> {
+    type Item = Batch<T>;
+
+    fn next(&mut self) -> Option<Batch<T>> {
+        if self.next >= self.order.len() {
+            return None;
+        }
+
+        let end: usize = (self.next + self.batch_size).min(self.order.len());
+        let (inputs, targets): (Vec<Vec<T>>, Vec<Vec<T>>) = self.order[self.next..end].iter().map(|&i| self.dataset.get(i)).unzip();
+        self.next = end;
+
+        return Some(Batch { inputs, targets });
+    }
+}
+
+
+
+#[cfg(test)]
+mod loader_ops {
+    use super::*;
+    use crate::data::InMemoryDataset;
+
+    fn numbered(n: usize) -> InMemoryDataset {
+        return InMemoryDataset::from_pairs((0..n).map(|i| (vec![i as f64], vec![2.0 * i as f64])).collect());
+    }
+
+    #[test]
+    fn batches() {
+        {
+            let ds: InMemoryDataset = numbered(10);
+            let mut loader = DataLoader::new(&ds, 4);
+            let batches: Vec<Batch> = loader.iter().collect();
+
+            assert_eq!(loader.len(), 3);
+            assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<usize>>(), vec![4, 4, 2]);
+            assert_eq!(batches[1].inputs, vec![vec![4.0], vec![5.0], vec![6.0], vec![7.0]]);
+            assert_eq!(batches[2].targets, vec![vec![16.0], vec![18.0]]);
+            assert_eq!(batches[0].input_vals()[3][0].data(), 3.0);
+            assert!(!batches[0].input_vals()[3][0].requires_grad());
+
+            let mut dropping = DataLoader::new(&ds, 4).drop_last();
+            assert_eq!(dropping.len(), 2);
+            assert_eq!(dropping.iter().count(), 2);
+        }
+
+        {
+            // Every example once per epoch, in a new order each time
+            let ds: InMemoryDataset = numbered(50);
+            let mut loader = DataLoader::new(&ds, 8).shuffle().seed(4);
+
+            let epoch = |loader: &mut DataLoader<f64, InMemoryDataset>| -> Vec<f64> {
+                return loader.iter().flat_map(|b| b.inputs.into_iter().map(|x| x[0])).collect();
+            };
+            let first: Vec<f64> = epoch(&mut loader);
+            let second: Vec<f64> = epoch(&mut loader);
+
+            let mut sorted: Vec<f64> = first.clone();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(sorted, (0..50).map(|i| i as f64).collect::<Vec<f64>>());
+            assert_ne!(first, second);
+
+            let mut again = DataLoader::new(&ds, 8).shuffle().seed(4);
+            assert_eq!(epoch(&mut again), first);
+        }
+
+        {
+            // Targets stay with their inputs, and plain slices work too
+            let pairs: Vec<(Vec<f64>, Vec<f64>)> = numbered(7).to_vec();
+            let mut loader = DataLoader::new(pairs.as_slice(), 3).shuffle().seed(1);
+            for b in loader.iter() {
+                assert!(b.inputs.iter().zip(b.targets.iter()).all(|(x, y)| y[0] == 2.0 * x[0]));
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::io;
+
+use crate::json::Json;
+use crate::Float;
+
+use super::{Dataset, InMemoryDataset};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    // Zero mean and unit variance
+    Standard,
+    // Onto [0, 1] over the fitted range
+    MinMax
+}
+
+
+// Per-feature statistics fitted on training inputs, so every split is scaled
+// the same way: x' = (x - offset) / scale. For Standard the offset and scale
+// are the mean and standard deviation, for MinMax the minimum and range.
+// Constant features get a scale of one and are only shifted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scaler {
+    scaling: Scaling,
+    offset:  Vec<f64>,
+    scale:   Vec<f64>
+}
+
+
+impl Scaler {
+    pub fn fit<T: Float, D: Dataset<T> + ?Sized>(scaling: Scaling, dataset: &D) -> Scaler {
+        let rows: Vec<Vec<f64>> = (0..dataset.len())
+            .map(|i| dataset.get(i).0.into_iter().map(T::to_f64).collect())
+            .collect();
+        return Scaler::fit_rows(scaling, &rows);
+    }
+
+    pub fn standard<T: Float, D: Dataset<T> + ?Sized>(dataset: &D) -> Scaler {
+        return Scaler::fit(Scaling::Standard, dataset);
+    }
+
+    pub fn min_max<T: Float, D: Dataset<T> + ?Sized>(dataset: &D) -> Scaler {
+        return Scaler::fit(Scaling::MinMax, dataset);
+    }
+
+    fn fit_rows(scaling: Scaling, rows: &[Vec<f64>]) -> Scaler {
+        assert!(!rows.is_empty(), "cannot fit a scaler to no examples");
+        let n: f64 = rows.len() as f64;
+        let width: usize = rows[0].len();
+
+        let mut offset: Vec<f64> = Vec::with_capacity(width);
+        let mut scale: Vec<f64> = Vec::with_capacity(width);
+        for c in 0..width {
+            let (o, s): (f64, f64) = match scaling {
+                Scaling::Standard => {
+                    let mean: f64 = rows.iter().map(|r| r[c]).sum::<f64>() / n;
+                    let var: f64 = rows.iter().map(|r| (r[c] - mean).powi(2)).sum::<f64>() / n;
+                    (mean, var.sqrt())
+                },
+                Scaling::MinMax => {
+                    let lo: f64 = rows.iter().map(|r| r[c]).fold(f64::INFINITY, f64::min);
+                    let hi: f64 = rows.iter().map(|r| r[c]).fold(f64::NEG_INFINITY, f64::max);
+                    (lo, hi - lo)
+                }
+            };
+            offset.push(o);
+            scale.push(if s > 0.0 { s } else { 1.0 });
+        }
+
+        return Scaler { scaling, offset, scale };
+    }
+
+    pub fn scaling(&self) -> Scaling {
+        return self.scaling;
+    }
+
+    pub fn offset(&self) -> &[f64] {
+        return &self.offset;
+    }
+
+    pub fn scale(&self) -> &[f64] {
+        return &self.scale;
+    }
+
+    pub fn transform<T: Float>(&self, x: &[T]) -> Vec<T> {
+        assert_eq!(x.len(), self.offset.len(), "scaler was fitted to {} features", self.offset.len());
+        return x.iter().enumerate()
+            .map(|(c, &xi)| T::from_f64((xi.to_f64() - self.offset[c]) / self.scale[c]))
+            .collect();
+    }
+
+    pub fn inverse_transform<T: Float>(&self, x: &[T]) -> Vec<T> {
+        assert_eq!(x.len(), self.offset.len(), "scaler was fitted to {} features", self.offset.len());
+        return x.iter().enumerate()
+            .map(|(c, &xi)| T::from_f64(xi.to_f64() * self.scale[c] + self.offset[c]))
+            .collect();
+    }
+
+    // Inputs are scaled, targets copied as they are
+    pub fn transform_dataset<T: Float, D: Dataset<T> + ?Sized>(&self, dataset: &D) -> InMemoryDataset<T> {
+        return InMemoryDataset::from_pairs((0..dataset.len())
+            .map(|i| {
+                let (x, y): (Vec<T>, Vec<T>) = dataset.get(i);
+                (self.transform(&x), y)
+            })
+            .collect());
+    }
+
+    // {"scaling": "standard" | "min_max", "offset": [..], "scale": [..]}
+    pub fn to_json(&self) -> Json {
+        let nums = |xs: &[f64]| -> Json { Json::Arr(xs.iter().map(|&x| Json::Num(x)).collect()) };
+        let name: &str = match self.scaling {
+            Scaling::Standard => "standard",
+            Scaling::MinMax   => "min_max"
+        };
+
+        return Json::Obj(vec![
+            (String::from("scaling"), Json::Str(String::from(name))),
+            (String::from("offset"),  nums(&self.offset)),
+            (String::from("scale"),   nums(&self.scale))
+        ]);
+    }
+
+    pub fn from_json(json: &Json) -> Result<Scaler, String> {
+        let nums = |key: &str| -> Result<Vec<f64>, String> {
+            return json.get(key)
+                .and_then(Json::as_array)
+                .ok_or(format!("missing \"{}\"", key))?
+                .iter()
+                .map(|x| x.as_f64().ok_or(format!("\"{}\": expected a number", key)))
+                .collect();
+        };
+
+        let scaling: Scaling = match json.get("scaling").and_then(Json::as_str) {
+            Some("standard") => Scaling::Standard,
+            Some("min_max")  => Scaling::MinMax,
+            _                => return Err(String::from("unknown \"scaling\""))
+        };
+        let (offset, scale): (Vec<f64>, Vec<f64>) = (nums("offset")?, nums("scale")?);
+        if offset.len() != scale.len() {
+            return Err(format!("{} offsets but {} scales", offset.len(), scale.len()));
+        }
+        if scale.iter().any(|&s| s <= 0.0) {
+            return Err(String::from("scales must be positive"));
+        }
+
+        return Ok(Scaler { scaling, offset, scale });
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        return fs::write(path, self.to_json().to_string());
+    }
+
+    pub fn load(path: &str) -> io::Result<Scaler> {
+        let text: String = fs::read_to_string(path)?;
+        return Json::parse(&text)
+            .and_then(|json| Scaler::from_json(&json))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+}
+
+
+
+#[cfg(test)]
+mod scaler_ops {
+    use super::*;
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn scaling() {
+        let train: InMemoryDataset = InMemoryDataset::new(
+            vec![vec![1.0, 5.0, 2.0], vec![2.0, 5.0, 4.0], vec![3.0, 5.0, 12.0]],
+            vec![vec![0.0], vec![1.0], vec![2.0]]
+        );
+
+        {
+            let s: Scaler = Scaler::standard(&train);
+            assert!(approx_eq(s.offset()[0], 2.0));
+            assert!(approx_eq(s.scale()[0], (2.0_f64 / 3.0).sqrt()));
+
+            let scaled: InMemoryDataset = s.transform_dataset(&train);
+            for c in 0..3 {
+                let col: Vec<f64> = scaled.examples().iter().map(|(x, _)| x[c]).collect();
+                assert!(approx_eq(col.iter().sum(), 0.0));
+            }
+            // The constant column is only shifted; targets are untouched
+            assert_eq!(scaled.get(1).0[1], 0.0);
+            assert_eq!(scaled.get(2).1, vec![2.0]);
+
+            let back: Vec<f64> = s.inverse_transform(&scaled.get(2).0);
+            assert!(back.iter().zip([3.0, 5.0, 12.0]).all(|(&a, b)| approx_eq(a, b)));
+        }
+
+        {
+            // Test data goes through the training statistics, so may leave [0, 1]
+            let s: Scaler = Scaler::min_max(&train);
+            assert_eq!(s.transform(&[2.0, 5.0, 7.0]), vec![0.5, 0.0, 0.5]);
+            assert_eq!(s.transform(&[5.0_f32, 6.0, 2.0]), vec![2.0, 1.0, 0.0]);
+        }
+
+        {
+            let s: Scaler = Scaler::standard(&train);
+            assert_eq!(Scaler::from_json(&s.to_json()).unwrap(), s);
+
+            let path: String = std::env::temp_dir().join("rusty_nn_scaler.json").to_string_lossy().into_owned();
+            let m: Scaler = Scaler::min_max(&train);
+            m.save(&path).unwrap();
+            let loaded: Scaler = Scaler::load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded, m);
+            assert_eq!(loaded.scaling(), Scaling::MinMax);
+
+            let bad: Json = Json::parse("{\"scaling\": \"log\", \"offset\": [], \"scale\": []}").unwrap();
+            assert_eq!(Scaler::from_json(&bad).unwrap_err(), "unknown \"scaling\"");
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::rand::Rng;
+use crate::Float;
+
+use super::{Dataset, InMemoryDataset};
+
+
+// Cut points for shuffled indices: rounding the running total, so the
+// pieces cover every index once
+fn bounds(n: usize, ratios: &[f64]) -> Vec<usize> {
+    assert!(!ratios.is_empty() && ratios.iter().all(|&r| r >= 0.0), "ratios must be non-negative");
+    let total: f64 = ratios.iter().sum();
+    assert!((total - 1.0).abs() < 1e-9, "ratios must sum to one");
+
+    let mut cum: f64 = 0.0;
+    let mut out: Vec<usize> = vec![0];
+    for &r in &ratios[..ratios.len() - 1] {
+        cum += r;
+        out.push(((n as f64 * cum).round() as usize).min(n));
+    }
+    out.push(n);
+
+    return out;
+}
+
+
+fn gather<T: Float, D: Dataset<T> + ?Sized>(dataset: &D, parts: Vec<Vec<usize>>) -> Vec<InMemoryDataset<T>> {
+    return parts.into_iter()
+        .map(|idx| InMemoryDataset::from_pairs(idx.into_iter().map(|i| dataset.get(i)).collect()))
+        .collect();
+}
+
+
+// Disjoint random splits with sizes in proportion to `ratios`, which sum to
+// one, e.g. &[0.8, 0.1, 0.1] for train, validation and test
+pub fn split<T: Float, D: Dataset<T> + ?Sized>(dataset: &D, ratios: &[f64], seed: u64) -> Vec<InMemoryDataset<T>> {
+    let mut rng: Rng = Rng::new(seed);
+    let mut order: Vec<usize> = (0..dataset.len()).collect();
+    rng.shuffle(&mut order);
+
+    let b: Vec<usize> = bounds(order.len(), ratios);
+    return gather(dataset, b.windows(2).map(|w| order[w[0]..w[1]].to_vec()).collect());
+}
+
+
+// As split, but each class, the first element of the target, is divided on
+// its own so every split keeps the class proportions
+pub fn stratified_split<T: Float, D: Dataset<T> + ?Sized>(dataset: &D, ratios: &[f64], seed: u64) -> Vec<InMemoryDataset<T>> {
+    let mut rng: Rng = Rng::new(seed);
+
+    // Classes in order of first appearance
+    let mut classes: Vec<(T, Vec<usize>)> = Vec::new();
+    for i in 0..dataset.len() {
+        let label: T = dataset.get(i).1[0];
+        match classes.iter_mut().find(|(c, _)| *c == label) {
+            Some((_, members)) => members.push(i),
+            None               => classes.push((label, vec![i]))
+        }
+    }
+
+    let mut parts: Vec<Vec<usize>> = vec![Vec::new(); ratios.len()];
+    for (_, mut members) in classes {
+        rng.shuffle(&mut members);
+        let b: Vec<usize> = bounds(members.len(), ratios);
+        for (k, w) in b.windows(2).enumerate() {
+            parts[k].extend_from_slice(&members[w[0]..w[1]]);
+        }
+    }
+    for p in parts.iter_mut() {
+        rng.shuffle(p);
+    }
+
+    return gather(dataset, parts);
+}
+
+
+
+#[cfg(test)]
+mod split_ops {
+    use super::*;
+
+    fn numbered(n: usize) -> InMemoryDataset {
+        return InMemoryDataset::from_pairs((0..n).map(|i| (vec![i as f64], vec![(i % 4 == 0) as u8 as f64])).collect());
+    }
+
+    fn ids(ds: &InMemoryDataset) -> Vec<usize> {
+        return ds.examples().iter().map(|(x, _)| x[0] as usize).collect();
+    }
+
+    #[test]
+    fn splits() {
+        {
+            let ds: InMemoryDataset = numbered(100);
+            let parts: Vec<InMemoryDataset> = split(&ds, &[0.7, 0.2, 0.1], 3);
+            assert_eq!(parts.iter().map(|p| p.len()).collect::<Vec<usize>>(), vec![70, 20, 10]);
+
+            // Disjoint and complete
+            let mut all: Vec<usize> = parts.iter().flat_map(ids).collect();
+            all.sort();
+            assert_eq!(all, (0..100).collect::<Vec<usize>>());
+
+            assert_eq!(split(&ds, &[0.7, 0.2, 0.1], 3), parts);
+            assert_ne!(split(&ds, &[0.7, 0.2, 0.1], 4), parts);
+            assert_ne!(ids(&parts[0]), (0..70).collect::<Vec<usize>>());
+        }
+
+        {
+            // A quarter are class 1, in every split
+            let ds: InMemoryDataset = numbered(200);
+            let parts: Vec<InMemoryDataset> = stratified_split(&ds, &[0.8, 0.2], 5);
+            assert_eq!((parts[0].len(), parts[1].len()), (160, 40));
+            for (p, n) in parts.iter().zip([40, 10]) {
+                assert_eq!(p.examples().iter().filter(|(_, y)| y[0] == 1.0).count(), n);
+            }
+
+            let mut all: Vec<usize> = parts.iter().flat_map(ids).collect();
+            all.sort();
+            assert_eq!(all, (0..200).collect::<Vec<usize>>());
+        }
+
+        {
+            let pairs: Vec<(Vec<f64>, Vec<f64>)> = numbered(3).to_vec();
+            let parts: Vec<InMemoryDataset> = split(pairs.as_slice(), &[0.5, 0.5], 0);
+            assert_eq!(parts[0].len() + parts[1].len(), 3);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected="ratios must sum to one")]
+    fn bad_ratios() {
+        split(&numbered(10), &[0.5, 0.4], 0);
+    }
+}
@@ -0,0 +1,164 @@
+use std::f64::consts::PI;
+
+use crate::rand::Rng;
+use crate::Float;
+
+use super::InMemoryDataset;
+
+
+// Points and [target] rows, shuffled, then converted
+fn finish<T: Float>(mut examples: Vec<(Vec<f64>, f64)>, rng: &mut Rng) -> InMemoryDataset<T> {
+    rng.shuffle(&mut examples);
+    return InMemoryDataset::from_pairs(examples.into_iter()
+        .map(|(x, y)| (x.into_iter().map(T::from_f64).collect(), vec![T::from_f64(y)]))
+        .collect());
+}
+
+
+// Evenly spaced over [0, 1], both ends included
+fn spaced(i: usize, n: usize) -> f64 {
+    return if n > 1 { i as f64 / (n - 1) as f64 } else { 0.0 };
+}
+
+
+// Two interleaving half circles, classes 0 (upper) and 1 (lower).
+// Gaussian noise of the given standard deviation on both coordinates.
+pub fn moons<T: Float>(n: usize, noise: f64, rng: &mut Rng) -> InMemoryDataset<T> {
+    let upper: usize = n.div_ceil(2);
+    let mut examples: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let (t, label): (f64, f64) = if i < upper { (PI * spaced(i, upper), 0.0) } else { (PI * spaced(i - upper, n - upper), 1.0) };
+        let (x, y): (f64, f64) = if label == 0.0 { (t.cos(), t.sin()) } else { (1.0 - t.cos(), 0.5 - t.sin()) };
+        examples.push((vec![x + noise * rng.normal(), y + noise * rng.normal()], label));
+    }
+
+    return finish(examples, rng);
+}
+
+
+// A large circle of class 0 around a smaller one, radius `factor`, of class 1
+pub fn circles<T: Float>(n: usize, noise: f64, factor: f64, rng: &mut Rng) -> InMemoryDataset<T> {
+    assert!(factor > 0.0 && factor < 1.0, "factor must be in (0, 1)");
+    let outer: usize = n.div_ceil(2);
+    let mut examples: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let (k, m, r, label): (usize, usize, f64, f64) = if i < outer { (i, outer, 1.0, 0.0) } else { (i - outer, n - outer, factor, 1.0) };
+        let t: f64 = 2.0 * PI * k as f64 / m as f64;
+        examples.push((vec![r * t.cos() + noise * rng.normal(), r * t.sin() + noise * rng.normal()], label));
+    }
+
+    return finish(examples, rng);
+}
+
+
+// `classes` arms winding out from the origin, `per_class` points each,
+// labelled 0..classes. Noise perturbs the angle.
+pub fn spirals<T: Float>(per_class: usize, classes: usize, noise: f64, rng: &mut Rng) -> InMemoryDataset<T> {
+    let mut examples: Vec<(Vec<f64>, f64)> = Vec::with_capacity(per_class * classes);
+
+    for c in 0..classes {
+        for i in 0..per_class {
+            let r: f64 = spaced(i, per_class);
+            let t: f64 = 4.0 * (c as f64 + r) + noise * rng.normal();
+            examples.push((vec![r * t.sin(), r * t.cos()], c as f64));
+        }
+    }
+
+    return finish(examples, rng);
+}
+
+
+// Uniform in [-1, 1)², class 1 where the coordinates have opposite signs
+pub fn xor<T: Float>(n: usize, noise: f64, rng: &mut Rng) -> InMemoryDataset<T> {
+    let mut examples: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n);
+
+    for _ in 0..n {
+        let (x, y): (f64, f64) = (rng.uniform(-1.0, 1.0), rng.uniform(-1.0, 1.0));
+        let label: f64 = if (x < 0.0) != (y < 0.0) { 1.0 } else { 0.0 };
+        examples.push((vec![x + noise * rng.normal(), y + noise * rng.normal()], label));
+    }
+
+    return finish(examples, rng);
+}
+
+
+// Inputs uniform in [-1, 1), one per weight, and targets w·x + bias plus noise
+pub fn linear<T: Float>(n: usize, weights: &[f64], bias: f64, noise: f64, rng: &mut Rng) -> InMemoryDataset<T> {
+    let mut examples: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n);
+
+    for _ in 0..n {
+        let x: Vec<f64> = weights.iter().map(|_| rng.uniform(-1.0, 1.0)).collect();
+        let y: f64 = x.iter().zip(weights.iter()).map(|(xi, wi)| xi * wi).sum::<f64>() + bias;
+        examples.push((x, y + noise * rng.normal()));
+    }
+
+    return finish(examples, rng);
+}
+
+
+
+#[cfg(test)]
+mod synthetic_ops {
+    use super::*;
+    use crate::data::Dataset;
+
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn count(ds: &InMemoryDataset, label: f64) -> usize {
+        return ds.examples().iter().filter(|(_, y)| y[0] == label).count();
+    }
+
+    #[test]
+    fn shapes() {
+        {
+            let ds: InMemoryDataset = moons(101, 0.0, &mut Rng::new(0));
+            assert_eq!(ds.len(), 101);
+            assert_eq!((count(&ds, 0.0), count(&ds, 1.0)), (51, 50));
+
+            // Noise-free points lie on their arcs
+            for (x, y) in ds.examples() {
+                let (cx, cy): (f64, f64) = if y[0] == 0.0 { (0.0, 0.0) } else { (1.0, 0.5) };
+                assert!(approx_eq((x[0] - cx).hypot(x[1] - cy), 1.0));
+                assert!(if y[0] == 0.0 { x[1] >= -1e-12 } else { x[1] <= 0.5 + 1e-12 });
+            }
+        }
+
+        {
+            let ds: InMemoryDataset = circles(40, 0.0, 0.3, &mut Rng::new(1));
+            for (x, y) in ds.examples() {
+                let r: f64 = if y[0] == 0.0 { 1.0 } else { 0.3 };
+                assert!(approx_eq(x[0].hypot(x[1]), r));
+            }
+
+            let ds: InMemoryDataset = spirals(30, 3, 0.1, &mut Rng::new(2));
+            assert_eq!(ds.len(), 90);
+            assert_eq!((0..3).map(|c| count(&ds, c as f64)).collect::<Vec<usize>>(), vec![30, 30, 30]);
+            assert!(ds.examples().iter().all(|(x, _)| x[0].hypot(x[1]) <= 1.0 + 1e-12));
+        }
+
+        {
+            let ds: InMemoryDataset = xor(200, 0.0, &mut Rng::new(3));
+            assert!(ds.examples().iter().all(|(x, y)| (x[0] * x[1] < 0.0) == (y[0] == 1.0)));
+
+            let ds: InMemoryDataset = linear(20, &[2.0, -1.0], 0.5, 0.0, &mut Rng::new(4));
+            assert_eq!(ds.get(0).0.len(), 2);
+            assert!(ds.examples().iter().all(|(x, y)| approx_eq(y[0], 2.0 * x[0] - x[1] + 0.5)));
+        }
+
+        {
+            // Seeded, so reproducible, and shuffled rather than grouped by class
+            let a: InMemoryDataset = moons(50, 0.1, &mut Rng::new(7));
+            let b: InMemoryDataset = moons(50, 0.1, &mut Rng::new(7));
+            assert_eq!(a, b);
+            assert_ne!(a, moons(50, 0.1, &mut Rng::new(8)));
+            assert!(a.examples()[..25].iter().any(|(_, y)| y[0] == 1.0));
+
+            let f: InMemoryDataset<f32> = moons(10, 0.1, &mut Rng::new(7));
+            assert_eq!(f.len(), 10);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::ops;
+
+use crate::grad_fn::GELU_C;
+use crate::Float;
+
+
+// A dual number re + eps ε with ε² = 0. Carrying eps = dx/dt through a
+// computation yields dy/dt alongside y, so one forward pass gives a
+// directional derivative with nothing recorded: forward-mode AD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual<T: Float = f64> {
+    pub re:  T,
+    pub eps: T
+}
+
+
+impl<T: Float> Dual<T> {
+    pub fn new(re: T, eps: T) -> Dual<T> {
+        return Dual { re, eps };
+    }
+
+    // A constant, whose derivative is zero
+    pub fn constant(re: T) -> Dual<T> {
+        return Dual { re, eps: T::zero() };
+    }
+
+    // The input being differentiated against, dx/dx = 1
+    pub fn variable(re: T) -> Dual<T> {
+        return Dual { re, eps: T::one() };
+    }
+
+    // f(re) with derivative f'(re) eps
+    fn chain(self, value: T, slope: T) -> Dual<T> {
+        return Dual { re: value, eps: slope * self.eps };
+    }
+
+    pub fn tanh(self) -> Dual<T> {
+        // Through e^(-2|x|), which can't overflow
+        let e: T = (T::from_f64(-2.0) * self.re.abs()).exp();
+        let t: T = (T::one() - e) / (T::one() + e);
+        let t: T = if self.re < T::zero() { -t } else { t };
+        return self.chain(t, T::one() - t * t);
+    }
+
+    pub fn relu(self) -> Dual<T> {
+        return if self.re > T::zero() { self } else { self.chain(T::zero(), T::zero()) };
+    }
+
+    pub fn sigmoid(self) -> Dual<T> {
+        let s: T = T::one() / (T::one() + (-self.re).exp());
+        return self.chain(s, s * (T::one() - s));
+    }
+
+    pub fn leaky_relu(self, alpha: T) -> Dual<T> {
+        return if self.re > T::zero() { self } else { self * alpha };
+    }
+
+    pub fn elu(self, alpha: T) -> Dual<T> {
+        return if self.re > T::zero() { self } else { (self.exp() - T::one()) * alpha };
+    }
+
+    pub fn gelu(self) -> Dual<T> {
+        let u: Dual<T> = (self + self * self * self * T::from_f64(0.044715)) * T::from_f64(GELU_C);
+        return self * (u.tanh() + T::one()) * T::from_f64(0.5);
+    }
+
+    pub fn softplus(self) -> Dual<T> {
+        return self.relu() + ((-self.abs()).exp() + T::one()).log();
+    }
+
+    pub fn silu(self) -> Dual<T> {
+        return self * self.sigmoid();
+    }
+
+    pub fn abs(self) -> Dual<T> {
+        return self * self.sign().re;
+    }
+
+    pub fn sign(self) -> Dual<T> {
+        let s: T = if self.re > T::zero() { T::one() } else if self.re < T::zero() { -T::one() } else { T::zero() };
+        return self.chain(s, T::zero());
+    }
+
+    pub fn exp(self) -> Dual<T> {
+        let e: T = self.re.exp();
+        return self.chain(e, e);
+    }
+
+    // Natural log
+    pub fn log(self) -> Dual<T> {
+        return self.chain(self.re.ln(), T::one() / self.re);
+    }
+
+    pub fn pow(self, n: T) -> Dual<T> {
+        return self.chain(self.re.powf(n), n * self.re.powf(n - T::one()));
+    }
+}
+
+
+// df/dx at x
+pub fn derivative<T, F>(f: F, x: T) -> T
+where T: Float,
+      F: Fn(Dual<T>) -> Dual<T>,
+{
+    return f(Dual::variable(x)).eps;
+}
+
+
+// The derivative of f at x along v, ∇f(x) · v, in a single pass
+pub fn directional<T, F>(f: F, x: &[T], v: &[T]) -> T
+where T: Float,
+      F: Fn(&[Dual<T>]) -> Dual<T>,
+{
+    assert_eq!(x.len(), v.len(), "expected a direction for every input");
+    let xs: Vec<Dual<T>> = x.iter().zip(v.iter()).map(|(&xi, &vi)| Dual::new(xi, vi)).collect();
+    return f(&xs).eps;
+}
+
+
+// One pass per input, so for checking rather than training
+pub fn gradient<T, F>(f: F, x: &[T]) -> Vec<T>
+where T: Float,
+      F: Fn(&[Dual<T>]) -> Dual<T>,
+{
+    return (0..x.len())
+        .map(|i| {
+            let v: Vec<T> = (0..x.len()).map(|j| if i == j { T::one() } else { T::zero() }).collect();
+            directional(&f, x, &v)
+        })
+        .collect();
+}
+
+
+
+/*** Overloads ***/
+
+impl<T: Float> ops::Add for Dual<T> {
+    type Output = Dual<T>;
+
+    fn add(self, other: Dual<T>) -> Dual<T> {
+        return Dual { re: self.re + other.re, eps: self.eps + other.eps };
+    }
+}
+
+
+impl<T: Float> ops::Sub for Dual<T> {
+    type Output = Dual<T>;
+
+    fn sub(self, other: Dual<T>) -> Dual<T> {
+        return Dual { re: self.re - other.re, eps: self.eps - other.eps };
+    }
+}
+
+
+impl<T: Float> ops::Mul for Dual<T> {
+    type Output = Dual<T>;
+
+    fn mul(self, other: Dual<T>) -> Dual<T> {
+        return Dual { re: self.re * other.re, eps: self.eps * other.re + self.re * other.eps };
+    }
+}
+
+
+impl<T: Float> ops::Div for Dual<T> {
+    type Output = Dual<T>;
+
+    fn div(self, other: Dual<T>) -> Dual<T> {
+        return Dual { re: self.re / other.re, eps: (self.eps * other.re - self.re * other.eps) / (other.re * other.re) };
+    }
+}
+
+
+impl<T: Float> ops::Neg for Dual<T> {
+    type Output = Dual<T>;
+
+    fn neg(self) -> Dual<T> {
+        return Dual { re: -self.re, eps: -self.eps };
+    }
+}
+
+
+macro_rules! impl_dual_scalar {
+    ($trait:ident, $method:ident) => {
+        impl<T: Float> ops::$trait<T> for Dual<T> {
+            type Output = Dual<T>;
+
+            fn $method(self, other: T) -> Dual<T> {
+                return ops::$trait::$method(self, Dual::constant(other));
+            }
+        }
+    };
+}
+
+impl_dual_scalar!(Add, add);
+impl_dual_scalar!(Sub, sub);
+impl_dual_scalar!(Mul, mul);
+impl_dual_scalar!(Div, div);
+
+/*** End Overloads ***/
+
+
+
+#[cfg(test)]
+mod dual_ops {
+    use super::*;
+    use crate::Val;
+
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    type Pair = (fn(Dual) -> Dual, fn(Val) -> Val);
+
+    #[test]
+    fn forward_mode() {
+        {
+            assert_eq!(derivative(|x| x * x * x, 2.0), 12.0);
+            assert!(approx_eq(derivative(|x| x.exp().log(), 0.3), 1.0));
+            assert!(approx_eq(derivative(|x| Dual::constant(1.0) / x, 4.0), -1.0 / 16.0));
+            assert!(approx_eq(derivative(|x| x.pow(0.5), 9.0), 1.0 / 6.0));
+
+            // Stable where the plain formulas overflow
+            assert!(approx_eq(derivative(|x| x.tanh(), 800.0), 0.0));
+            assert!(approx_eq(derivative(|x| x.softplus(), 800.0), 1.0));
+            assert!(approx_eq(derivative(|x| x.gelu(), 800.0), 1.0));
+        }
+
+        {
+            // Each activation against the reverse-mode engine, on both sides of zero
+            let pairs: Vec<Pair> = vec![
+                (|x| x.tanh(),            |x| x.tanh()),
+                (|x| x.relu(),            |x| x.relu()),
+                (|x| x.sigmoid(),         |x| x.sigmoid()),
+                (|x| x.leaky_relu(0.1),   |x| x.leaky_relu(0.1)),
+                (|x| x.elu(1.5),          |x| x.elu(1.5)),
+                (|x| x.gelu(),            |x| x.gelu()),
+                (|x| x.softplus(),        |x| x.softplus()),
+                (|x| x.silu(),            |x| x.silu()),
+                (|x| x.abs(),             |x| x.abs()),
+                (|x| x.sign(),            |x| x.sign()),
+                (|x| x.exp(),             |x| x.exp())
+            ];
+            for (fd, fv) in pairs.iter() {
+                for x in [-1.7, -0.2, 0.4, 2.5] {
+                    let v: Val = Val::new(x);
+                    let y: Val = fv(v.clone());
+                    y.backward();
+
+                    let d: Dual = fd(Dual::variable(x));
+                    assert!(approx_eq(d.re, y.data()));
+                    assert!(approx_eq(d.eps, v.grad()), "{} at {}", y.op(), x);
+                }
+            }
+        }
+
+        {
+            // A whole expression: the reverse-mode gradient, one forward pass per input,
+            // and a directional derivative all agree
+            let x: [f64; 3] = [0.7, -1.3, 0.4];
+            let vals: Vec<Val> = x.iter().map(|&xi| Val::new(xi)).collect();
+            let a: Val = (&vals[0] * &vals[1]).tanh();
+            let b: Val = (&vals[1] - 0.5).pow(3.0) + vals[2].clone().exp();
+            let c: Val = (&vals[2] * &vals[2] + 1.0).log() * vals[0].clone().sigmoid();
+            (&(&a + &b) * &c).backward();
+
+            let f = |x: &[Dual]| -> Dual {
+                let a: Dual = (x[0] * x[1]).tanh();
+                let b: Dual = (x[1] - 0.5).pow(3.0) + x[2].exp();
+                let c: Dual = (x[2] * x[2] + 1.0).log() * x[0].sigmoid();
+                return (a + b) * c;
+            };
+            let g: Vec<f64> = gradient(f, &x);
+            for (gi, v) in g.iter().zip(vals.iter()) {
+                assert!(approx_eq(*gi, v.grad()));
+            }
+
+            let dir: [f64; 3] = [1.0, -2.0, 0.5];
+            let expected: f64 = g.iter().zip(dir.iter()).map(|(gi, di)| gi * di).sum();
+            assert!(approx_eq(directional(f, &x, &dir), expected));
+        }
+    }
+}
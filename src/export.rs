@@ -0,0 +1,4 @@
+// Writers that take a trained model out of this crate
+pub mod onnx;
+mod rust;
+pub use rust::{to_rust_fn, to_rust_source};
@@ -0,0 +1,342 @@
+use std::{fs, io};
+
+use crate::nn::{Activation, Layer, MLP};
+use crate::Float;
+
+
+// Each dense layer becomes a Gemm (Y = X W^T + B), followed by its activation
+// (Tanh, Relu or Sigmoid) unless it is linear. The graph takes "input" of shape [N, nin] and gives
+// "output" of shape [N, nout], in float32.
+const IR_VERSION: i64 = 7;
+const OPSET:      i64 = 13;
+
+// TensorProto.DataType.FLOAT and AttributeProto.AttributeType.INT
+const FLOAT: i64 = 1;
+const INT:   i64 = 2;
+
+
+pub fn to_bytes<T: Float>(model: &MLP<T>) -> Result<Vec<u8>, String> {
+    let layers: &[Layer<T>] = model.layers();
+    if layers.is_empty() {
+        return Err(String::from("model has no layers"));
+    }
+
+    let mut graph: Vec<u8> = Vec::new();
+    let mut x: String = String::from("input");
+    for (i, layer) in layers.iter().enumerate() {
+        let neurons = layer.neurons();
+        if neurons.is_empty() {
+            return Err(format!("layer {} is empty", i));
+        }
+        let activation: Activation = neurons[0].activation();
+        if neurons.iter().any(|n| n.activation() != activation) {
+            return Err(format!("layer {} mixes activations", i));
+        }
+        let op: Option<&str> = match activation {
+            Activation::Identity => None,
+            Activation::Tanh     => Some("Tanh"),
+            Activation::Relu     => Some("Relu"),
+            Activation::Sigmoid  => Some("Sigmoid"),
+            _                    => return Err(format!("layer {}: {} has no ONNX export", i, activation.name()))
+        };
+        let nonlin: bool = op.is_some();
+
+        let (nin, nout): (usize, usize) = (neurons[0].nin(), neurons.len());
+        let w: Vec<f64> = neurons.iter().flat_map(|n| n.weights().iter().map(|v| v.data().to_f64())).collect();
+        let b: Vec<f64> = neurons.iter().map(|n| n.bias().data().to_f64()).collect();
+
+        let (wname, bname): (String, String) = (format!("layer{}.weight", i), format!("layer{}.bias", i));
+        message(&mut graph, 5, &tensor(&wname, &[nout, nin], &w));
+        message(&mut graph, 5, &tensor(&bname, &[nout], &b));
+
+        let last: bool = i + 1 == layers.len();
+        let gemm_out: String = if last && !nonlin { String::from("output") } else { format!("layer{}.gemm", i) };
+        message(&mut graph, 1, &node("Gemm", &format!("layer{}/Gemm", i), &[&x, &wname, &bname], &gemm_out, Some(("transB", 1))));
+        x = gemm_out;
+
+        if let Some(op) = op {
+            let act_out: String = if last { String::from("output") } else { format!("layer{}.{}", i, activation.name()) };
+            message(&mut graph, 1, &node(op, &format!("layer{}/{}", i, op), &[&x], &act_out, None));
+            x = act_out;
+        }
+    }
+    string(&mut graph, 2, "rusty_nn_mlp");
+
+    let nin: usize = layers[0].neurons()[0].nin();
+    let nout: usize = layers[layers.len() - 1].nout();
+    message(&mut graph, 11, &value_info("input", nin));
+    message(&mut graph, 12, &value_info("output", nout));
+
+    let mut opset: Vec<u8> = Vec::new();
+    string(&mut opset, 1, "");
+    int(&mut opset, 2, OPSET);
+
+    let mut model_proto: Vec<u8> = Vec::new();
+    int(&mut model_proto, 1, IR_VERSION);
+    string(&mut model_proto, 2, "rusty_nn");
+    message(&mut model_proto, 7, &graph);
+    message(&mut model_proto, 8, &opset);
+
+    return Ok(model_proto);
+}
+
+
+pub fn export<T: Float>(model: &MLP<T>, path: &str) -> io::Result<()> {
+    let bytes: Vec<u8> = to_bytes(model).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    return fs::write(path, bytes);
+}
+
+
+/*** Messages ***/
+fn tensor(name: &str, dims: &[usize], values: &[f64]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    for &d in dims.iter() {
+        int(&mut out, 1, d as i64);
+    }
+    int(&mut out, 2, FLOAT);
+    string(&mut out, 8, name);
+
+    let raw: Vec<u8> = values.iter().flat_map(|&x| (x as f32).to_le_bytes()).collect();
+    message(&mut out, 9, &raw);
+
+    return out;
+}
+
+
+fn node(op: &str, name: &str, inputs: &[&str], output: &str, attr: Option<(&str, i64)>) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    for input in inputs.iter() {
+        string(&mut out, 1, input);
+    }
+    string(&mut out, 2, output);
+    string(&mut out, 3, name);
+    string(&mut out, 4, op);
+
+    if let Some((key, value)) = attr {
+        let mut a: Vec<u8> = Vec::new();
+        string(&mut a, 1, key);
+        int(&mut a, 3, value);
+        int(&mut a, 20, INT);
+        message(&mut out, 5, &a);
+    }
+
+    return out;
+}
+
+
+// A float tensor of shape [N, width], with N left symbolic
+fn value_info(name: &str, width: usize) -> Vec<u8> {
+    let mut batch: Vec<u8> = Vec::new();
+    string(&mut batch, 2, "N");
+    let mut features: Vec<u8> = Vec::new();
+    int(&mut features, 1, width as i64);
+
+    let mut shape: Vec<u8> = Vec::new();
+    message(&mut shape, 1, &batch);
+    message(&mut shape, 1, &features);
+
+    let mut tensor_type: Vec<u8> = Vec::new();
+    int(&mut tensor_type, 1, FLOAT);
+    message(&mut tensor_type, 2, &shape);
+
+    let mut type_proto: Vec<u8> = Vec::new();
+    message(&mut type_proto, 1, &tensor_type);
+
+    let mut out: Vec<u8> = Vec::new();
+    string(&mut out, 1, name);
+    message(&mut out, 2, &type_proto);
+
+    return out;
+}
+/*** End Messages ***/
+
+
+/*** Wire Format ***/
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+
+fn int(out: &mut Vec<u8>, field: u64, v: i64) {
+    varint(out, field << 3);
+    varint(out, v as u64);
+}
+
+
+fn message(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(out, (field << 3) | 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+
+fn string(out: &mut Vec<u8>, field: u64, s: &str) {
+    message(out, field, s.as_bytes());
+}
+/*** End Wire Format ***/
+
+
+
+#[cfg(test)]
+mod onnx_ops {
+    use super::*;
+    use crate::nn::Neuron;
+
+    enum Field {
+        Int(u64),
+        Bytes(Vec<u8>)
+    }
+
+    // Enough of a protobuf reader to look inside what we wrote
+    fn decode(bytes: &[u8]) -> Vec<(u64, Field)> {
+        let mut pos: usize = 0;
+        let read_varint = |pos: &mut usize| -> u64 {
+            let mut v: u64 = 0;
+            let mut shift: u32 = 0;
+            loop {
+                let b: u8 = bytes[*pos];
+                *pos += 1;
+                v |= ((b & 0x7F) as u64) << shift;
+                if b < 0x80 {
+                    return v;
+                }
+                shift += 7;
+            }
+        };
+
+        let mut fields: Vec<(u64, Field)> = Vec::new();
+        while pos < bytes.len() {
+            let key: u64 = read_varint(&mut pos);
+            match key & 7 {
+                0 => fields.push((key >> 3, Field::Int(read_varint(&mut pos)))),
+                2 => {
+                    let n: usize = read_varint(&mut pos) as usize;
+                    fields.push((key >> 3, Field::Bytes(bytes[pos..pos + n].to_vec())));
+                    pos += n;
+                },
+                w => panic!("unexpected wire type {}", w)
+            }
+        }
+
+        return fields;
+    }
+
+    fn submessages(fields: &[(u64, Field)], field: u64) -> Vec<Vec<(u64, Field)>> {
+        return fields.iter()
+            .filter_map(|(k, f)| match f {
+                Field::Bytes(b) if *k == field => Some(decode(b)),
+                _                              => None
+            })
+            .collect();
+    }
+
+    fn strings(fields: &[(u64, Field)], field: u64) -> Vec<String> {
+        return fields.iter()
+            .filter_map(|(k, f)| match f {
+                Field::Bytes(b) if *k == field => Some(String::from_utf8(b.clone()).unwrap()),
+                _                              => None
+            })
+            .collect();
+    }
+
+    fn ints(fields: &[(u64, Field)], field: u64) -> Vec<u64> {
+        return fields.iter()
+            .filter_map(|(k, f)| match f {
+                Field::Int(v) if *k == field => Some(*v),
+                _                            => None
+            })
+            .collect();
+    }
+
+    #[test]
+    fn wire() {
+        {
+            let mut out: Vec<u8> = Vec::new();
+            varint(&mut out, 300);
+            assert_eq!(out, vec![0xAC, 0x02]);
+
+            let mut out: Vec<u8> = Vec::new();
+            int(&mut out, 1, 7);
+            string(&mut out, 2, "hi");
+            assert_eq!(out, vec![0x08, 0x07, 0x12, 0x02, b'h', b'i']);
+        }
+    }
+
+    #[test]
+    fn mlp() {
+        {
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[1.0, 2.0], 0.5, true),
+                    Neuron::from_weights(&[3.0, 4.0], -0.5, true),
+                    Neuron::from_weights(&[5.0, 6.0], 0.0, true)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[1.0, -1.0, 0.25], 2.0, false)])
+            ]);
+            let model: Vec<(u64, Field)> = decode(&to_bytes(&m).unwrap());
+
+            assert_eq!(ints(&model, 1), vec![IR_VERSION as u64]);
+            assert_eq!(strings(&model, 2), vec!["rusty_nn"]);
+            assert_eq!(ints(&submessages(&model, 8)[0], 2), vec![OPSET as u64]);
+
+            let graph: &Vec<(u64, Field)> = &submessages(&model, 7)[0];
+            let nodes: Vec<Vec<(u64, Field)>> = submessages(graph, 1);
+            let ops: Vec<String> = nodes.iter().flat_map(|n| strings(n, 4)).collect();
+            assert_eq!(ops, vec!["Gemm", "Tanh", "Gemm"]);
+
+            // Every node reads what the one before it wrote
+            assert_eq!(strings(&nodes[0], 1), vec!["input", "layer0.weight", "layer0.bias"]);
+            assert_eq!(strings(&nodes[1], 1), strings(&nodes[0], 2));
+            assert_eq!(strings(&nodes[2], 1)[0], strings(&nodes[1], 2)[0]);
+            assert_eq!(strings(&nodes[2], 2), vec!["output"]);
+
+            let attr: &Vec<(u64, Field)> = &submessages(&nodes[0], 5)[0];
+            assert_eq!(strings(attr, 1), vec!["transB"]);
+            assert_eq!(ints(attr, 3), vec![1]);
+
+            let inits: Vec<Vec<(u64, Field)>> = submessages(graph, 5);
+            assert_eq!(inits.len(), 4);
+            assert_eq!(strings(&inits[0], 8), vec!["layer0.weight"]);
+            assert_eq!(ints(&inits[0], 1), vec![3, 2]);
+            let raw: Vec<f32> = match &inits[0].iter().find(|(k, _)| *k == 9).unwrap().1 {
+                Field::Bytes(b) => b.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect(),
+                Field::Int(_)   => panic!("raw_data should be bytes")
+            };
+            assert_eq!(raw, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+            assert_eq!(ints(&inits[3], 1), vec![1]);
+
+            assert_eq!(strings(&submessages(graph, 11)[0], 1), vec!["input"]);
+            assert_eq!(strings(&submessages(graph, 12)[0], 1), vec!["output"]);
+        }
+
+        {
+            // A nonlinear last layer ends on its Tanh
+            let m: MLP = MLP::from_layers(vec![Layer::new(2, 2, true)]);
+            let model: Vec<(u64, Field)> = decode(&to_bytes(&m).unwrap());
+            let nodes: Vec<Vec<(u64, Field)>> = submessages(&submessages(&model, 7)[0], 1);
+
+            assert_eq!(nodes.len(), 2);
+            assert_eq!(strings(&nodes[1], 4), vec!["Tanh"]);
+            assert_eq!(strings(&nodes[1], 2), vec!["output"]);
+        }
+
+        {
+            let mixed: MLP = MLP::from_layers(vec![Layer::from_neurons(vec![
+                Neuron::from_weights(&[1.0], 0.0, true),
+                Neuron::from_weights(&[1.0], 0.0, false)
+            ])]);
+            assert!(to_bytes(&mixed).is_err());
+            assert!(to_bytes(&MLP::<f64>::from_layers(Vec::new())).is_err());
+
+            let relu: MLP = MLP::from_layers(vec![Layer::new(2, 2, Activation::Relu), Layer::new(2, 1, false)]);
+            let nodes: Vec<Vec<(u64, Field)>> = submessages(&submessages(&decode(&to_bytes(&relu).unwrap()), 7)[0], 1);
+            assert_eq!(nodes.iter().flat_map(|n| strings(n, 4)).collect::<Vec<String>>(), vec!["Gemm", "Relu", "Gemm"]);
+
+            assert!(to_bytes(&MLP::<f64>::from_layers(vec![Layer::new(2, 2, Activation::Gelu)])).is_err());
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fs, io};
+
+use crate::grad_fn::GELU_C;
+use crate::nn::{Activation, Layer, MLP};
+use crate::Float;
+
+
+// Helpers the generated code calls, emitted only when some layer uses them.
+// tanh goes through e^(-2|u|), like GELU's, so large inputs can't overflow.
+const TANH: &str = "fn tanh(u: f64) -> f64 {
+    let e: f64 = (-2.0 * u.abs()).exp();
+    let t: f64 = (1.0 - e) / (1.0 + e);
+    if u < 0.0 { -t } else { t }
+}
+";
+
+const SIGMOID: &str = "fn sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        return 1.0 / (1.0 + (-x).exp());
+    }
+    let e: f64 = x.exp();
+    e / (1.0 + e)
+}
+";
+
+const DENSE: &str = "fn dense<const I: usize, const O: usize>(w: &[[f64; I]; O], b: &[f64; O], x: &[f64; I]) -> [f64; O] {
+    let mut out: [f64; O] = *b;
+    for (o, row) in out.iter_mut().zip(w.iter()) {
+        for (wi, xi) in row.iter().zip(x.iter()) {
+            *o += wi * xi;
+        }
+    }
+    out
+}
+";
+
+
+// A literal that reads back as exactly this f64
+fn literal(x: f64) -> Result<String, String> {
+    if !x.is_finite() {
+        return Err(format!("{} has no Rust literal", x));
+    }
+    return Ok(format!("{:?}", x));
+}
+
+
+fn list(xs: &[f64]) -> Result<String, String> {
+    let items: Vec<String> = xs.iter().map(|&x| literal(x)).collect::<Result<Vec<String>, String>>()?;
+    return Ok(format!("[{}]", items.join(", ")));
+}
+
+
+// Rust source for `pub fn <name>(input: &[f64; nin]) -> [f64; nout]`
+// computing the model's predict, with the weights as consts. It uses nothing
+// outside std: the activations take f64::exp, so no_std targets need a libm.
+pub fn to_rust_source<T: Float>(model: &MLP<T>, name: &str) -> Result<String, String> {
+    let layers: &[Layer<T>] = model.layers();
+    if layers.is_empty() {
+        return Err(String::from("model has no layers"));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("{:?} isn't a Rust identifier", name));
+    }
+
+    let mut shape: Vec<String> = vec![layers[0].neurons().first().map_or(0, |n| n.nin()).to_string()];
+    let mut consts: String = String::new();
+    let mut body: String = String::new();
+    let (mut uses_tanh, mut uses_sigmoid): (bool, bool) = (false, false);
+
+    for (i, layer) in layers.iter().enumerate() {
+        let neurons = layer.neurons();
+        if neurons.is_empty() {
+            return Err(format!("layer {} is empty", i));
+        }
+        let activation: Activation = neurons[0].activation();
+        if neurons.iter().any(|n| n.activation() != activation) {
+            return Err(format!("layer {} mixes activations", i));
+        }
+        let (nin, nout): (usize, usize) = (neurons[0].nin(), neurons.len());
+        if i > 0 && nin != layers[i - 1].nout() {
+            return Err(format!("layer {} expects {} inputs but layer {} has {} outputs", i, nin, i - 1, layers[i - 1].nout()));
+        }
+        shape.push(nout.to_string());
+
+        consts += &format!("const LAYER{}_W: [[f64; {}]; {}] = [\n", i, nin, nout);
+        for n in neurons.iter() {
+            let w: Vec<f64> = n.weights().iter().map(|v| v.data().to_f64()).collect();
+            consts += &format!("    {},\n", list(&w)?);
+        }
+        consts += "];\n";
+        let b: Vec<f64> = neurons.iter().map(|n| n.bias().data().to_f64()).collect();
+        consts += &format!("const LAYER{}_B: [f64; {}] = {};\n\n", i, nout, list(&b)?);
+
+        let map: String = match activation {
+            Activation::Identity     => String::new(),
+            Activation::Tanh         => { uses_tanh = true; String::from(".map(tanh)") },
+            Activation::Relu         => String::from(".map(|v| if v > 0.0 { v } else { 0.0 })"),
+            Activation::Sigmoid      => { uses_sigmoid = true; String::from(".map(sigmoid)") },
+            Activation::LeakyRelu(a) => format!(".map(|v| if v > 0.0 {{ v }} else {{ {} * v }})", literal(a)?),
+            Activation::Gelu         => {
+                uses_tanh = true;
+                format!(".map(|v| 0.5 * v * (1.0 + tanh({} * (v + 0.044715 * v * v * v))))", literal(GELU_C)?)
+            }
+        };
+        let input: &str = if i == 0 { "input" } else { "&x" };
+        body += &format!("    let x: [f64; {}] = dense(&LAYER{}_W, &LAYER{}_B, {}){};\n", nout, i, i, input, map);
+    }
+
+    let nin: &str = &shape[0];
+    let nout: &str = &shape[shape.len() - 1];
+    let mut out: String = format!("// Generated by rusty_nn from a {} MLP\n\n", shape.join(" -> "));
+    out += &consts;
+    out += &format!("pub fn {}(input: &[f64; {}]) -> [f64; {}] {{\n{}    x\n}}\n\n", name, nin, nout, body);
+    out += DENSE;
+    if uses_tanh {
+        out += "\n";
+        out += TANH;
+    }
+    if uses_sigmoid {
+        out += "\n";
+        out += SIGMOID;
+    }
+
+    return Ok(out);
+}
+
+
+// Writes to_rust_source's `predict` to path, ready to include!() or copy into another crate
+pub fn to_rust_fn<T: Float>(model: &MLP<T>, path: &str) -> io::Result<()> {
+    let source: String = to_rust_source(model, "predict").map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    return fs::write(path, source);
+}
+
+
+
+#[cfg(test)]
+mod rust_ops {
+    use super::*;
+    use crate::init::Init;
+    use crate::nn::{Module, Neuron};
+    use crate::rand::Rng;
+
+    #[test]
+    fn source() {
+        {
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[0.5, -1.0], 0.1, Activation::Relu),
+                    Neuron::from_weights(&[1e-7, 2.0], 0.0, Activation::Relu)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[3.0, -0.25], -1.5, false)])
+            ]);
+            let src: String = to_rust_source(&m, "score").unwrap();
+
+            assert!(src.starts_with("// Generated by rusty_nn from a 2 -> 2 -> 1 MLP\n"));
+            assert!(src.contains("const LAYER0_W: [[f64; 2]; 2] = [\n    [0.5, -1.0],\n    [1e-7, 2.0],\n];\n"));
+            assert!(src.contains("const LAYER1_B: [f64; 1] = [-1.5];\n"));
+            assert!(src.contains("pub fn score(input: &[f64; 2]) -> [f64; 1] {\n"));
+            assert!(src.contains("    let x: [f64; 2] = dense(&LAYER0_W, &LAYER0_B, input).map(|v| if v > 0.0 { v } else { 0.0 });\n"));
+            assert!(src.contains("    let x: [f64; 1] = dense(&LAYER1_W, &LAYER1_B, &x);\n    x\n}\n"));
+            // Helpers only when they're called
+            assert!(src.contains("fn dense<"));
+            assert!(!src.contains("fn tanh(") && !src.contains("fn sigmoid("));
+        }
+
+        {
+            let mut rng: Rng = Rng::new(1);
+            let m: MLP = MLP::from_layers(vec![
+                Layer::with_init(3, 4, Activation::Gelu, Init::He, &mut rng),
+                Layer::with_init(4, 2, Activation::Sigmoid, Init::Xavier, &mut rng)
+            ]);
+            let src: String = to_rust_source(&m, "predict").unwrap();
+            assert!(src.contains("fn tanh(") && src.contains("fn sigmoid("));
+            assert!(src.contains(&format!("tanh({:?} * (v + 0.044715 * v * v * v))", GELU_C)));
+
+            // Every weight survives the trip through decimal
+            let w: f64 = m.layers()[1].neurons()[1].weights()[2].data();
+            assert!(src.contains(&format!("{:?}", w)));
+            assert_eq!(format!("{:?}", w).parse::<f64>().unwrap(), w);
+
+            let path: String = std::env::temp_dir().join("rusty_nn_model.rs").to_string_lossy().into_owned();
+            to_rust_fn(&m, &path).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), src);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        {
+            let empty: MLP = MLP::from_layers(Vec::new());
+            assert_eq!(to_rust_source(&empty, "f"), Err(String::from("model has no layers")));
+
+            let m: MLP = MLP::new(2, &[1]);
+            assert!(to_rust_source(&m, "2fast").is_err());
+            assert!(to_rust_source(&m, "has space").is_err());
+
+            let bad: MLP = MLP::from_layers(vec![Layer::new(2, 3, true), Layer::new(2, 1, false)]);
+            assert!(to_rust_source(&bad, "f").unwrap_err().contains("expects 2 inputs"));
+
+            m.parameters()[0].set_data(f64::NAN);
+            assert!(to_rust_source(&m, "f").is_err());
+        }
+    }
+}
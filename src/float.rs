@@ -0,0 +1,48 @@
+use std::fmt;
+use std::ops;
+
+
+// The numeric operations the engine relies on, so Val can hold either f32 or f64
+pub trait Float:
+    Copy + PartialOrd + fmt::Debug + fmt::Display + 'static
+    + ops::Add<Output = Self> + ops::Sub<Output = Self> + ops::Mul<Output = Self>
+    + ops::Div<Output = Self> + ops::Neg<Output = Self> + ops::AddAssign
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn neg_infinity() -> Self;
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+}
+
+
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Float for $t {
+            fn zero() -> $t { return 0.0; }
+            fn one() -> $t { return 1.0; }
+            fn neg_infinity() -> $t { return <$t>::NEG_INFINITY; }
+            fn from_f64(x: f64) -> $t { return x as $t; }
+            fn to_f64(self) -> f64 { return self as f64; }
+
+            fn exp(self) -> $t { return <$t>::exp(self); }
+            fn ln(self) -> $t { return <$t>::ln(self); }
+            fn powf(self, n: $t) -> $t { return <$t>::powf(self, n); }
+            fn sqrt(self) -> $t { return <$t>::sqrt(self); }
+            fn abs(self) -> $t { return <$t>::abs(self); }
+            fn max(self, other: $t) -> $t { return <$t>::max(self, other); }
+            fn min(self, other: $t) -> $t { return <$t>::min(self, other); }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
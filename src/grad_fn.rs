@@ -0,0 +1,590 @@
+use crate::{Float, Operations, Val};
+
+
+// A differentiable operation. `forward` maps the input values to the output,
+// and `backward` returns one gradient per input given the gradient of the
+// output. Implement it to add operations from outside the crate, then build
+// nodes with Val::apply.
+pub trait GradFn<T: Float = f64> {
+    // Label used by Display and to_dot; new operations use Operations::Custom
+    fn op(&self) -> Operations;
+
+    fn forward(&self, inputs: &[T]) -> T;
+
+    fn backward(&self, inputs: &[T], output: T, grad: T) -> Vec<T>;
+
+    // backward built out of Val operations, so the gradients are graph nodes
+    // that autograd::grad_graph can differentiate again. None, the default,
+    // leaves the operation differentiable only once.
+    fn backward_graph(&self, _inputs: &[Val<T>], _output: &Val<T>, _grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return None;
+    }
+
+    // Identifies the operation and its parameters for GraphBuilder::dedup.
+    // Two nodes with equal keys and the same children are merged; None opts out.
+    fn dedup_key(&self) -> Option<String> {
+        return None;
+    }
+}
+
+
+pub struct Add;
+pub struct Sub;
+pub struct Mul;
+pub struct Tanh;
+pub struct Relu;
+pub struct Sigmoid;
+pub struct Exp;
+// Natural log
+pub struct Log;
+pub struct Pow<T: Float = f64>(pub T);
+// The slope alpha below zero
+pub struct LeakyRelu<T: Float = f64>(pub T);
+// alpha (e^x - 1) below zero
+pub struct Elu<T: Float = f64>(pub T);
+pub struct Gelu;
+pub struct Softplus;
+pub struct Silu;
+pub struct Abs;
+pub struct Sign;
+
+
+// sqrt(2 / π), for the tanh approximation of GELU
+pub(crate) const GELU_C: f64 = 0.7978845608028654;
+
+
+// tanh through e^(-2|u|), which can't overflow; e^(2x) is inf past |x| ≈ 355,
+// and the cubic in GELU gets there fast
+pub(crate) fn tanh_stable<T: Float>(u: T) -> T {
+    let e: T = (T::from_f64(-2.0) * u.abs()).exp();
+    let t: T = (T::one() - e) / (T::one() + e);
+    return if u < T::zero() { -t } else { t };
+}
+
+
+// Wraps a binary op whose input at `index` is a constant, which never receives a gradient
+pub struct WithConstant<G> {
+    pub op:    G,
+    pub index: usize
+}
+
+
+impl<T: Float> GradFn<T> for Add {
+    fn op(&self) -> Operations {
+        return Operations::Add;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0] + inputs[1];
+    }
+
+    fn backward(&self, _inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![grad, grad];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad.clone(), grad.clone()]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("+"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Sub {
+    fn op(&self) -> Operations {
+        return Operations::Sub;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0] - inputs[1];
+    }
+
+    fn backward(&self, _inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![grad, -grad];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad.clone(), -grad]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("-"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Mul {
+    fn op(&self) -> Operations {
+        return Operations::Mul;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0] * inputs[1];
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![inputs[1] * grad, inputs[0] * grad];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * &inputs[1], grad * &inputs[0]]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("*"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Tanh {
+    fn op(&self) -> Operations {
+        return Operations::Tanh;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return tanh_stable(inputs[0]);
+    }
+
+    fn backward(&self, _inputs: &[T], t: T, grad: T) -> Vec<T> {
+        return vec![(T::one() - t * t) * grad];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], t: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * &(-(t * t) + T::one())]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Tanh"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Relu {
+    fn op(&self) -> Operations {
+        return Operations::Relu;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return if inputs[0] > T::zero() { inputs[0] } else { T::zero() };
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![if inputs[0] > T::zero() { grad } else { T::zero() }];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * if inputs[0].data() > T::zero() { T::one() } else { T::zero() }]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("ReLU"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Sigmoid {
+    fn op(&self) -> Operations {
+        return Operations::Sigmoid;
+    }
+
+    // Branch on the sign so exp() is only ever taken of a non-positive number
+    fn forward(&self, inputs: &[T]) -> T {
+        let x: T = inputs[0];
+        if x >= T::zero() {
+            return T::one() / (T::one() + (-x).exp());
+        }
+
+        let e: T = x.exp();
+        return e / (T::one() + e);
+    }
+
+    fn backward(&self, _inputs: &[T], s: T, grad: T) -> Vec<T> {
+        return vec![s * (T::one() - s) * grad];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], s: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![&(grad * s) * &(-s + T::one())]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Sigmoid"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Exp {
+    fn op(&self) -> Operations {
+        return Operations::Exp;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0].exp();
+    }
+
+    fn backward(&self, _inputs: &[T], e: T, grad: T) -> Vec<T> {
+        return vec![e * grad];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], e: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * e]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Exp"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Log {
+    fn op(&self) -> Operations {
+        return Operations::Log;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0].ln();
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![grad / inputs[0]];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * &inputs[0].clone().pow(-T::one())]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Log"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Pow<T> {
+    fn op(&self) -> Operations {
+        return Operations::Pow;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0].powf(self.0);
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        let n: T = self.0;
+        return vec![n * inputs[0].powf(n - T::one()) * grad];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let n: T = self.0;
+        return Some(vec![grad * &(inputs[0].clone().pow(n - T::one()) * n)]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(format!("Pow {}", self.0));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for LeakyRelu<T> {
+    fn op(&self) -> Operations {
+        return Operations::LeakyRelu;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return if inputs[0] > T::zero() { inputs[0] } else { self.0 * inputs[0] };
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![if inputs[0] > T::zero() { grad } else { self.0 * grad }];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * if inputs[0].data() > T::zero() { T::one() } else { self.0 }]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(format!("LeakyReLU {}", self.0));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Elu<T> {
+    fn op(&self) -> Operations {
+        return Operations::Elu;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return if inputs[0] > T::zero() { inputs[0] } else { self.0 * (inputs[0].exp() - T::one()) };
+    }
+
+    // Below zero the derivative alpha e^x is the output plus alpha
+    fn backward(&self, inputs: &[T], output: T, grad: T) -> Vec<T> {
+        return vec![if inputs[0] > T::zero() { grad } else { (output + self.0) * grad }];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![if inputs[0].data() > T::zero() { grad.clone() } else { grad * &(output + self.0) }]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(format!("ELU {}", self.0));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Gelu {
+    fn op(&self) -> Operations {
+        return Operations::Gelu;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        let x: T = inputs[0];
+        let u: T = T::from_f64(GELU_C) * (x + T::from_f64(0.044715) * x * x * x);
+        return T::from_f64(0.5) * x * (T::one() + tanh_stable(u));
+    }
+
+    // 0.5 (1 + t) + 0.5 x (1 - t²) du/dx, with t = tanh(u)
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        let x: T = inputs[0];
+        let u: T = T::from_f64(GELU_C) * (x + T::from_f64(0.044715) * x * x * x);
+        let t: T = tanh_stable(u);
+        let du: T = T::from_f64(GELU_C) * (T::one() + T::from_f64(3.0 * 0.044715) * x * x);
+        let half: T = T::from_f64(0.5);
+
+        return vec![(half * (T::one() + t) + half * x * (T::one() - t * t) * du) * grad];
+    }
+
+    // tanh(u) as 2σ(2u) - 1, since the sigmoid node is the one that can't overflow
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let x: &Val<T> = &inputs[0];
+        let u: Val<T> = (x + &(&(x * x) * x * T::from_f64(0.044715))) * T::from_f64(GELU_C);
+        let t: Val<T> = (u * T::from_f64(2.0)).sigmoid() * T::from_f64(2.0) - T::one();
+        let du: Val<T> = (x * x * T::from_f64(3.0 * 0.044715) + T::one()) * T::from_f64(GELU_C);
+        let half: T = T::from_f64(0.5);
+
+        let d: Val<T> = (&t + T::one()) * half + &(x * &(-(&t * &t) + T::one())) * &du * half;
+        return Some(vec![grad * &d]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("GELU"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Softplus {
+    fn op(&self) -> Operations {
+        return Operations::Softplus;
+    }
+
+    // max(x, 0) + ln(1 + e^-|x|), so exp() never sees a large positive number
+    fn forward(&self, inputs: &[T]) -> T {
+        let x: T = inputs[0];
+        return x.max(T::zero()) + (T::one() + (-x.abs()).exp()).ln();
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![Sigmoid.forward(inputs) * grad];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * &inputs[0].clone().sigmoid()]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Softplus"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Silu {
+    fn op(&self) -> Operations {
+        return Operations::Silu;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0] * Sigmoid.forward(inputs);
+    }
+
+    // σ + x σ (1 - σ)
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        let s: T = Sigmoid.forward(inputs);
+        return vec![s * (T::one() + inputs[0] * (T::one() - s)) * grad];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let s: Val<T> = inputs[0].clone().sigmoid();
+        let d: Val<T> = &s * &(&inputs[0] * &(-&s + T::one()) + T::one());
+        return Some(vec![grad * &d]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("SiLU"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Abs {
+    fn op(&self) -> Operations {
+        return Operations::Abs;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[0].abs();
+    }
+
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        return vec![Sign.forward(inputs) * grad];
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * Sign.forward(&[inputs[0].data()])]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Abs"));
+    }
+}
+
+
+impl<T: Float> GradFn<T> for Sign {
+    fn op(&self) -> Operations {
+        return Operations::Sign;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        let x: T = inputs[0];
+        if x > T::zero() {
+            return T::one();
+        }
+
+        return if x < T::zero() { -T::one() } else { T::zero() };
+    }
+
+    fn backward(&self, _inputs: &[T], _output: T, _grad: T) -> Vec<T> {
+        return vec![T::zero()];
+    }
+
+    fn backward_graph(&self, _inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(vec![grad * T::zero()]);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Sign"));
+    }
+}
+
+
+impl<T: Float, G: GradFn<T>> GradFn<T> for WithConstant<G> {
+    fn op(&self) -> Operations {
+        return self.op.op();
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return self.op.forward(inputs);
+    }
+
+    fn backward(&self, inputs: &[T], output: T, grad: T) -> Vec<T> {
+        let mut grads: Vec<T> = self.op.backward(inputs, output, grad);
+        grads[self.index] = T::zero();
+
+        return grads;
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let mut grads: Vec<Val<T>> = self.op.backward_graph(inputs, output, grad)?;
+        grads[self.index] = grad * T::zero();
+
+        return Some(grads);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return self.op.dedup_key().map(|k| format!("{} const {}", k, self.index));
+    }
+}
+
+
+
+#[cfg(test)]
+mod grad_fn_ops {
+    use super::*;
+    use crate::{grad_check, Val};
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    // What a downstream crate would write
+    struct Hypot;
+
+    impl GradFn for Hypot {
+        fn op(&self) -> Operations {
+            return Operations::Custom("Hypot");
+        }
+
+        fn forward(&self, inputs: &[f64]) -> f64 {
+            return inputs[0].hypot(inputs[1]);
+        }
+
+        fn backward(&self, inputs: &[f64], h: f64, grad: f64) -> Vec<f64> {
+            return vec![inputs[0] / h * grad, inputs[1] / h * grad];
+        }
+    }
+
+    #[test]
+    fn custom() {
+        {
+            let a: Val = Val::new(3.0);
+            let b: Val = Val::new(4.0);
+            let h: Val = Val::apply(Hypot, &[a.clone(), b.clone()]);
+
+            assert_eq!(h.data(), 5.0);
+            assert_eq!(h.op(), Operations::Custom("Hypot"));
+            assert_eq!(h.op().to_string(), "Hypot");
+            assert_eq!(h.prev().len(), 2);
+
+            let out: Val = &h * &h;
+            out.backward();
+            assert!(approx_eq(a.grad(), 6.0));
+            assert!(approx_eq(b.grad(), 8.0));
+        }
+
+        {
+            let f = |x: &[Val]| -> Val { Val::apply(Hypot, &[x[0].clone(), x[1].clone().tanh()]).log() };
+            assert!(grad_check(f, &[0.7, -1.2], 1e-6).iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        {
+            // Built-ins go through the same entry point as the methods
+            let x: Val = Val::new(0.5);
+            let y: Val = Val::apply(Mul, &[x.clone(), x.clone().exp()]);
+
+            assert!(approx_eq(y.data(), 0.5 * 0.5_f64.exp()));
+            assert_eq!(y.op(), Operations::Mul);
+
+            y.backward();
+            assert!(approx_eq(x.grad(), 1.5 * 0.5_f64.exp()));
+        }
+
+        {
+            let c: WithConstant<Mul> = WithConstant { op: Mul, index: 1 };
+            assert_eq!(GradFn::<f64>::backward(&c, &[2.0, 3.0], 6.0, 1.0), vec![3.0, 0.0]);
+        }
+
+        {
+            // e^(2x) overflows to inf / inf here
+            for &(x, t) in [(400.0, 1.0), (-800.0, -1.0), (0.3, 0.3_f64.tanh()), (-2.0, (-2.0_f64).tanh())].iter() {
+                let y: Val = Val::new(x).tanh();
+                assert!(approx_eq(y.data(), t), "tanh({}) = {}", x, y.data());
+                y.backward();
+            }
+            let x: Val = Val::new(-400.0);
+            let y: Val = x.clone().tanh();
+            y.backward();
+            assert_eq!(x.grad(), 0.0);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{Float, Val};
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GradCheck {
+    pub analytic:  f64,
+    pub numeric:   f64,
+    pub rel_error: f64
+}
+
+
+// Compares backward() against central differences, (f(x + eps) - f(x - eps)) / 2 eps,
+// for every input in turn. f is rebuilt from fresh leaves on each evaluation.
+pub fn grad_check<T, F>(f: F, inputs: &[T], eps: T) -> Vec<GradCheck>
+where T: Float,
+      F: Fn(&[Val<T>]) -> Val<T>,
+{
+    let leaves: Vec<Val<T>> = inputs.iter().map(|&x| Val::new(x)).collect();
+    f(&leaves).backward();
+
+    let eval = |i: usize, dx: T| -> f64 {
+        let xs: Vec<Val<T>> = inputs.iter()
+            .enumerate()
+            .map(|(j, &x)| Val::new(if i == j { x + dx } else { x }))
+            .collect();
+        return f(&xs).data().to_f64();
+    };
+
+    let mut report: Vec<GradCheck> = Vec::with_capacity(inputs.len());
+    for (i, leaf) in leaves.iter().enumerate() {
+        let analytic: f64 = leaf.grad().to_f64();
+        let numeric: f64 = (eval(i, eps) - eval(i, -eps)) / (2.0 * eps.to_f64());
+
+        let scale: f64 = analytic.abs().max(numeric.abs());
+        let rel_error: f64 = if scale == 0.0 { 0.0 } else { (analytic - numeric).abs() / scale };
+
+        report.push(GradCheck { analytic, numeric, rel_error });
+    }
+
+    return report;
+}
+
+
+
+#[cfg(test)]
+mod gradcheck_ops {
+    use super::*;
+    use crate::loss::{cross_entropy, mse};
+    use crate::nn::{Layer, Module, Neuron, MLP};
+    use crate::ops::softmax;
+
+    fn worst(report: &[GradCheck]) -> f64 {
+        return report.iter().map(|c| c.rel_error).fold(0.0, f64::max);
+    }
+
+    #[test]
+    fn scalar_ops() {
+        {
+            let f = |x: &[Val]| -> Val {
+                let a: Val = (&x[0] * &x[1]).tanh();
+                let b: Val = (&x[1] - 0.5).pow(3.0) + x[2].clone().exp();
+                let c: Val = (&x[2] * &x[2] + 1.0).log() * x[0].clone().sigmoid();
+                return &(&a + &b) * &c;
+            };
+            let report: Vec<GradCheck> = grad_check(f, &[0.7, -1.3, 0.4], 1e-6);
+
+            assert_eq!(report.len(), 3);
+            assert!(worst(&report) < 1e-6, "{:?}", report);
+        }
+
+        {
+            // Inputs the output ignores check out as zero
+            let report: Vec<GradCheck> = grad_check(|x: &[Val]| &x[0] * 2.0, &[1.0, 5.0], 1e-6);
+
+            assert!((report[0].analytic - 2.0).abs() < 1e-12);
+            assert_eq!(report[1], GradCheck { analytic: 0.0, numeric: 0.0, rel_error: 0.0 });
+        }
+    }
+
+    #[test]
+    fn composites() {
+        {
+            let f = |x: &[Val]| -> Val {
+                let s: Vec<Val> = softmax(x);
+                return &(&s[0] * 3.0) - &(&s[2] * &s[1]);
+            };
+            assert!(worst(&grad_check(f, &[0.2, -0.5, 1.1], 1e-6)) < 1e-6);
+
+            let f = |x: &[Val]| -> Val { cross_entropy(x, 1) };
+            assert!(worst(&grad_check(f, &[0.2, -0.5, 1.1, 2.0], 1e-6)) < 1e-6);
+        }
+
+        {
+            // Gradients with respect to the inputs of a small network
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[0.5, -0.3], 0.1, true),
+                    Neuron::from_weights(&[-0.8, 0.2], 0.0, true)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[1.5, -0.7], 0.3, false)])
+            ]);
+            let f = |x: &[Val]| -> Val { mse(&m.forward(x), &[0.25]) };
+
+            assert!(worst(&grad_check(f, &[0.9, -0.4], 1e-6)) < 1e-6);
+        }
+
+        {
+            let f = |x: &[Val<f32>]| -> Val<f32> { (&x[0] * &x[1]).tanh() };
+            assert!(worst(&grad_check(f, &[0.5_f32, 0.8], 1e-2)) < 1e-2);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use crate::{Float, Operations, Val, ValData};
+
+mod compile;
+pub use compile::{compile, Compiled};
+
+
+// Element type, operation key, and the addresses of the children
+type Key = (TypeId, String, Vec<usize>);
+
+thread_local! {
+    static CACHE: RefCell<Option<HashMap<Key, Box<dyn Any>>>> = const { RefCell::new(None) };
+}
+
+
+// Options for graphs built inside `build`. With dedup on, an operation applied
+// again to the same children (and constants of the same value) returns the
+// node built the first time instead of a new one, so loops that recompute a
+// subexpression share it. Ops whose GradFn has no dedup_key are never merged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GraphBuilder {
+    dedup: bool
+}
+
+
+impl GraphBuilder {
+    pub fn new() -> GraphBuilder {
+        return GraphBuilder { dedup: false };
+    }
+
+    pub fn dedup(mut self, dedup: bool) -> GraphBuilder {
+        self.dedup = dedup;
+        return self;
+    }
+
+    // Nodes are only shared within one call; the previous mode comes back afterwards
+    pub fn build<R, F>(&self, f: F) -> R
+    where F: FnOnce() -> R,
+    {
+        struct Restore(Option<HashMap<Key, Box<dyn Any>>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer: Option<HashMap<Key, Box<dyn Any>>> = self.0.take();
+                CACHE.with(|c| *c.borrow_mut() = outer);
+            }
+        }
+
+        let fresh: Option<HashMap<Key, Box<dyn Any>>> = if self.dedup { Some(HashMap::new()) } else { None };
+        let _restore: Restore = Restore(CACHE.with(|c| c.replace(fresh)));
+        return f();
+    }
+}
+
+
+// Callers check this first, so building keys costs nothing outside a dedup scope
+pub(crate) fn deduplicating() -> bool {
+    return CACHE.with(|c| c.borrow().is_some());
+}
+
+
+// Returns the node cached under (key, inputs), or makes and caches one.
+// Without a key this is just make().
+pub(crate) fn intern<T, F>(key: Option<String>, inputs: &[Val<T>], make: F) -> Val<T>
+where T: Float,
+      F: FnOnce() -> Val<T>,
+{
+    let key: Key = match key {
+        Some(k) => (TypeId::of::<T>(), k, inputs.iter().map(|v| Rc::as_ptr(&v.0) as usize).collect()),
+        None    => return make()
+    };
+
+    let hit: Option<Val<T>> = CACHE.with(|c| {
+        return c.borrow().as_ref()
+            .and_then(|m| m.get(&key))
+            .and_then(|v| v.downcast_ref::<Val<T>>())
+            .cloned();
+    });
+    if let Some(v) = hit {
+        return v;
+    }
+
+    let result: Val<T> = make();
+    CACHE.with(|c| {
+        if let Some(m) = c.borrow_mut().as_mut() {
+            m.insert(key, Box::new(result.clone()));
+        }
+    });
+
+    return result;
+}
+
+
+// The size and shape of the graph behind a node, from Val::graph_stats.
+// Depth counts nodes on the longest path from a leaf, so a lone leaf has
+// depth 1. Bytes is an estimate of what the nodes hold on the heap: the
+// node itself, its list of children, and its GradFn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphStats {
+    pub nodes:  usize,
+    pub leaves: usize,
+    pub depth:  usize,
+    // Most common first; leaves are counted under Non
+    pub ops:    Vec<(Operations, usize)>,
+    pub bytes:  usize
+}
+
+
+impl GraphStats {
+    pub fn count(&self, op: Operations) -> usize {
+        return self.ops.iter().find(|(o, _)| *o == op).map_or(0, |(_, n)| *n);
+    }
+}
+
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} nodes ({} leaves), depth {}, about {} KiB", self.nodes, self.leaves, self.depth, self.bytes.div_ceil(1024))?;
+        for (op, n) in self.ops.iter() {
+            writeln!(f, "  {:<12} {}", op.to_string(), n)?;
+        }
+        return Ok(());
+    }
+}
+
+
+// Over a topological order, children first
+pub(crate) fn stats<T: Float>(order: &[Val<T>]) -> GraphStats {
+    // Two reference counts in front of the RefCell
+    let node_size: usize = 2 * mem::size_of::<usize>() + mem::size_of::<RefCell<ValData<T>>>();
+
+    let mut depths: HashMap<*const RefCell<ValData<T>>, usize> = HashMap::with_capacity(order.len());
+    let mut ops: Vec<(Operations, usize)> = Vec::new();
+    let (mut leaves, mut depth, mut bytes): (usize, usize, usize) = (0, 0, 0);
+
+    for v in order {
+        let inner = v.0.borrow();
+        let d: usize = 1 + inner.prev.iter().map(|c| depths[&Rc::as_ptr(&c.0)]).max().unwrap_or(0);
+        depths.insert(Rc::as_ptr(&v.0), d);
+        depth = depth.max(d);
+
+        if inner.prev.is_empty() {
+            leaves += 1;
+        }
+        bytes += node_size + inner.prev.capacity() * mem::size_of::<Val<T>>();
+        if let Some(f) = &inner.grad_fn {
+            bytes += mem::size_of_val(&**f);
+        }
+
+        let op: Operations = inner.grad_fn.as_ref().map_or(Operations::Non, |f| f.op());
+        match ops.iter_mut().find(|(o, _)| *o == op) {
+            Some((_, n)) => *n += 1,
+            None         => ops.push((op, 1))
+        }
+    }
+    ops.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+
+    return GraphStats { nodes: order.len(), leaves, depth, ops, bytes };
+}
+
+
+
+#[cfg(test)]
+mod graph_ops {
+    use super::*;
+    use crate::ops::softmax;
+
+    fn step(x: &Val) -> Val {
+        return (x * 2.0 + 1.0).tanh();
+    }
+
+    #[test]
+    fn dedup() {
+        {
+            let x: Val = Val::new(0.3);
+
+            let plain: Val = GraphBuilder::new().build(|| {
+                let mut total: Val = Val::new(0.0);
+                for _ in 0..10 {
+                    total += step(&x);
+                }
+                return total;
+            });
+            let shared: Val = GraphBuilder::new().dedup(true).build(|| {
+                let mut total: Val = Val::new(0.0);
+                for _ in 0..10 {
+                    total += step(&x);
+                }
+                return total;
+            });
+
+            assert_eq!(plain.data(), shared.data());
+            assert!(shared.topo_iter().count() < plain.topo_iter().count());
+            // x, the two constants, *, +, tanh, then the running total
+            assert_eq!(shared.topo_iter().count(), 6 + 1 + 10);
+
+            plain.backward();
+            let g: f64 = x.grad();
+            x.zero_grad();
+            shared.backward();
+            assert!((x.grad() - g).abs() < 1e-12);
+        }
+
+        {
+            // Only identical children and constants are merged
+            let (a, b): (Val, Val) = (Val::new(1.0), Val::new(1.0));
+            GraphBuilder::new().dedup(true).build(|| {
+                assert!(Rc::ptr_eq(&(&a * &b).0, &(&a * &b).0));
+                assert!(!Rc::ptr_eq(&(&a * &b).0, &(&b * &a).0));
+                assert!(!Rc::ptr_eq(&(&a * 2.0).0, &(&a * 3.0).0));
+                assert!(!Rc::ptr_eq(&(&a + 2.0).0, &(&a * 2.0).0));
+                assert!(!Rc::ptr_eq(&(2.0 - &a).0, &(&a - 2.0).0));
+                assert!(Rc::ptr_eq(&a.clone().pow(2.0).0, &a.clone().pow(2.0).0));
+                assert!(Rc::ptr_eq(&softmax(&[a.clone(), b.clone()])[1].0, &softmax(&[a.clone(), b.clone()])[1].0));
+            });
+
+            // The mode ends with the scope
+            assert!(!Rc::ptr_eq(&(&a * &b).0, &(&a * &b).0));
+        }
+
+        {
+            let a: Val = Val::new(1.0);
+            GraphBuilder::new().dedup(true).build(|| {
+                let outer: Val = &a * &a;
+                GraphBuilder::new().build(|| assert!(!Rc::ptr_eq(&(&a * &a).0, &outer.0)));
+                assert!(Rc::ptr_eq(&(&a * &a).0, &outer.0));
+            });
+        }
+    }
+
+    #[test]
+    fn graph_stats() {
+        {
+            let x: Val = Val::new(0.5);
+            let s: GraphStats = x.graph_stats();
+            assert_eq!((s.nodes, s.leaves, s.depth), (1, 1, 1));
+            assert_eq!(s.ops, vec![(Operations::Non, 1)]);
+        }
+
+        {
+            // tanh(2x + 1) + x: x, 2, 1 are leaves; * and + and tanh and + above them
+            let x: Val = Val::new(0.3);
+            let y: Val = step(&x) + x.clone();
+            let s: GraphStats = y.graph_stats();
+
+            assert_eq!((s.nodes, s.leaves, s.depth), (7, 3, 5));
+            assert_eq!(s.ops[0], (Operations::Non, 3));
+            assert_eq!(s.count(Operations::Add), 2);
+            assert_eq!(s.count(Operations::Tanh), 1);
+            assert_eq!(s.count(Operations::Relu), 0);
+            assert!(s.bytes >= 7 * mem::size_of::<RefCell<ValData<f64>>>());
+            assert!(s.to_string().starts_with("7 nodes (3 leaves), depth 5, about 1 KiB\n"));
+        }
+
+        {
+            // Shared nodes count once, and depth follows the longest path
+            let x: Val = Val::new(1.0);
+            let mut y: Val = x.clone();
+            for _ in 0..50 {
+                y = &y * &x;
+            }
+            let s: GraphStats = y.graph_stats();
+            assert_eq!((s.nodes, s.leaves, s.depth), (51, 1, 51));
+            assert!(y.graph_stats().bytes > x.graph_stats().bytes * 50);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::{Float, Operations, Val, ValData};
+
+
+// Where a step reads one of its operands from
+enum Arg<T: Float> {
+    // The previous step of the same kernel
+    Carry,
+    // An earlier kernel's result
+    Slot(usize),
+    // Entry i of the values passed to run
+    Input(usize),
+    // A parameter, read when run is called
+    Leaf(Val<T>),
+    // Folded at compile time
+    Const(T)
+}
+
+
+// One node's forward, taken from the node itself
+struct Step<T: Float> {
+    node: Val<T>,
+    args: Vec<Arg<T>>
+}
+
+
+// A chain of steps, each feeding only the next, run without storing the
+// values in between
+struct Kernel<T: Float> {
+    steps: Vec<Step<T>>
+}
+
+
+// A fixed graph flattened for repeated evaluation: only the nodes the outputs
+// depend on, in order, with chains of elementwise ops fused into single
+// kernels. Running it computes values only, with no graph built and nothing
+// allocated per node, so new inputs cost one pass over a flat list.
+//
+// Before that the graph is simplified. Leaves that don't require grad
+// (scalar constants, and frozen parameters) are baked in, anything computed
+// only from them is folded to its value, and x + 0, x - 0, x * 1 and x * 0
+// are rewritten away; x * 0 becomes 0 even where x would be NaN or infinite.
+// Leaves that do require grad are read live, so parameter updates show up on
+// the next run. It holds on to the original nodes for their operations.
+pub struct Compiled<T: Float = f64> {
+    inputs:  usize,
+    kernels: Vec<Kernel<T>>,
+    outputs: Vec<Arg<T>>,
+    nodes:   usize,
+    folded:  usize
+}
+
+
+type Key<T> = *const RefCell<ValData<T>>;
+
+
+// What a node comes down to after simplification
+#[derive(Clone)]
+enum Source<T: Float> {
+    Const(T),
+    Input(usize),
+    Leaf(Val<T>),
+    // Computed by this node, which is kept
+    Node(Key<T>)
+}
+
+
+// The Source a node reduces to when one operand is an identity for its op
+fn simplify<T: Float>(v: &Val<T>, args: &[Source<T>]) -> Option<Source<T>> {
+    let is = |s: &Source<T>, c: T| -> bool { matches!(s, Source::Const(x) if *x == c) };
+    if args.len() != 2 {
+        return None;
+    }
+
+    let (a, b): (&Source<T>, &Source<T>) = (&args[0], &args[1]);
+    return match v.op() {
+        Operations::Add if is(b, T::zero())                  => Some(a.clone()),
+        Operations::Add if is(a, T::zero())                  => Some(b.clone()),
+        Operations::Sub if is(b, T::zero())                  => Some(a.clone()),
+        Operations::Mul if is(a, T::zero()) || is(b, T::zero()) => Some(Source::Const(T::zero())),
+        Operations::Mul if is(b, T::one())                   => Some(a.clone()),
+        Operations::Mul if is(a, T::one())                   => Some(b.clone()),
+        _                                                    => None
+    };
+}
+
+
+// Compiles the graph from `inputs` to `outputs`. Each input must be a leaf;
+// run takes their values in the same order.
+pub fn compile<T: Float>(outputs: &[Val<T>], inputs: &[Val<T>]) -> Compiled<T> {
+    assert!(inputs.iter().all(|x| x.prev().is_empty()), "compile inputs must be leaves");
+    let input_index: HashMap<Key<T>, usize> = inputs.iter().enumerate().map(|(i, x)| (Rc::as_ptr(&x.0), i)).collect();
+
+    // Every node feeding an output, children first, each once. Unreachable nodes are dropped here.
+    let mut seen: HashSet<Key<T>> = HashSet::new();
+    let order: Vec<Val<T>> = outputs.iter()
+        .flat_map(|out| out.topo())
+        .filter(|v| seen.insert(Rc::as_ptr(&v.0)))
+        .collect();
+
+    // Fold and simplify
+    let mut source: HashMap<Key<T>, Source<T>> = HashMap::new();
+    for v in order.iter() {
+        let key: Key<T> = Rc::as_ptr(&v.0);
+        let inner = v.0.borrow();
+        let resolved: Source<T> = match &inner.grad_fn {
+            _ if input_index.contains_key(&key) => Source::Input(input_index[&key]),
+            None if inner.grad.is_none()        => Source::Const(inner.data),
+            None                                => Source::Leaf(v.clone()),
+            Some(f)                             => {
+                let args: Vec<Source<T>> = inner.prev.iter().map(|c| source[&Rc::as_ptr(&c.0)].clone()).collect();
+                let consts: Option<Vec<T>> = args.iter().map(|a| if let Source::Const(c) = a { Some(*c) } else { None }).collect();
+                match consts {
+                    // Bridges from Tensor and VecVal read live state, so they're never folded
+                    Some(xs) if !xs.is_empty() => Source::Const(f.forward(&xs)),
+                    _                          => simplify(v, &args).unwrap_or(Source::Node(key))
+                }
+            }
+        };
+        source.insert(key, resolved);
+    }
+
+    // Simplifying can leave nodes nothing reads any more, such as x in x * 0
+    let mut live: HashSet<Key<T>> = outputs.iter()
+        .filter_map(|v| if let Source::Node(k) = source[&Rc::as_ptr(&v.0)] { Some(k) } else { None })
+        .collect();
+    for v in order.iter().rev() {
+        if !live.contains(&Rc::as_ptr(&v.0)) {
+            continue;
+        }
+        for child in v.0.borrow().prev.iter() {
+            if let Source::Node(k) = source[&Rc::as_ptr(&child.0)] {
+                live.insert(k);
+            }
+        }
+    }
+    let kept: Vec<&Val<T>> = order.iter().filter(|v| live.contains(&Rc::as_ptr(&v.0))).collect();
+
+    let mut consumers: HashMap<Key<T>, usize> = HashMap::new();
+    for v in kept.iter() {
+        for child in v.0.borrow().prev.iter() {
+            if let Source::Node(k) = source[&Rc::as_ptr(&child.0)] {
+                *consumers.entry(k).or_insert(0) += 1;
+            }
+        }
+    }
+    let output_keys: HashSet<Key<T>> = outputs.iter()
+        .filter_map(|v| if let Source::Node(k) = source[&Rc::as_ptr(&v.0)] { Some(k) } else { None })
+        .collect();
+
+    let mut kernels: Vec<Kernel<T>> = Vec::new();
+    let mut kernel_of: HashMap<Key<T>, usize> = HashMap::new();
+    let to_arg = |s: &Source<T>, kernel_of: &HashMap<Key<T>, usize>| -> Arg<T> {
+        return match s {
+            Source::Const(c) => Arg::Const(*c),
+            Source::Input(i) => Arg::Input(*i),
+            Source::Leaf(v)  => Arg::Leaf(v.clone()),
+            Source::Node(k)  => Arg::Slot(kernel_of[k])
+        };
+    };
+
+    for v in kept.iter() {
+        let inner = v.0.borrow();
+        let args: Vec<Source<T>> = inner.prev.iter().map(|c| source[&Rc::as_ptr(&c.0)].clone()).collect();
+
+        // A node extends its operand's kernel when that operand is its only
+        // computed one and nothing else reads it
+        let computed: Vec<Key<T>> = args.iter().filter_map(|a| if let Source::Node(k) = a { Some(*k) } else { None }).collect();
+        let fused: Option<Key<T>> = match computed.as_slice() {
+            [k] if consumers[k] == 1 && !output_keys.contains(k) => Some(*k),
+            _                                                    => None
+        };
+
+        let args: Vec<Arg<T>> = args.iter()
+            .map(|a| match a {
+                Source::Node(k) if Some(*k) == fused => Arg::Carry,
+                _                                    => to_arg(a, &kernel_of)
+            })
+            .collect();
+        let step: Step<T> = Step { node: (*v).clone(), args };
+
+        let k: usize = match fused {
+            Some(key) => {
+                let k: usize = kernel_of[&key];
+                kernels[k].steps.push(step);
+                k
+            },
+            None      => {
+                kernels.push(Kernel { steps: vec![step] });
+                kernels.len() - 1
+            }
+        };
+        kernel_of.insert(Rc::as_ptr(&v.0), k);
+    }
+
+    let computed: usize = order.iter().filter(|v| v.0.borrow().grad_fn.is_some()).count();
+    let outputs: Vec<Arg<T>> = outputs.iter().map(|v| to_arg(&source[&Rc::as_ptr(&v.0)], &kernel_of)).collect();
+
+    return Compiled { inputs: inputs.len(), kernels, outputs, nodes: order.len(), folded: computed - kept.len() };
+}
+
+
+impl<T: Float> Compiled<T> {
+    // The outputs for these input values
+    pub fn run(&self, inputs: &[T]) -> Vec<T> {
+        assert_eq!(inputs.len(), self.inputs, "expected {} inputs", self.inputs);
+
+        let mut slots: Vec<T> = Vec::with_capacity(self.kernels.len());
+        let mut xs: Vec<T> = Vec::new();
+        for kernel in self.kernels.iter() {
+            let mut carry: T = T::zero();
+            for step in kernel.steps.iter() {
+                xs.clear();
+                xs.extend(step.args.iter().map(|a| read(a, carry, &slots, inputs)));
+                carry = step.node.0.borrow().grad_fn.as_ref().expect("compiled steps have a grad_fn").forward(&xs);
+            }
+            slots.push(carry);
+        }
+
+        return self.outputs.iter().map(|a| read(a, T::zero(), &slots, inputs)).collect();
+    }
+
+    // Kernels run per call, after fusion
+    pub fn len(&self) -> usize {
+        return self.kernels.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.kernels.is_empty();
+    }
+
+    // Operations across all kernels; the graph's non-leaf nodes the outputs depend on
+    pub fn ops(&self) -> usize {
+        return self.kernels.iter().map(|k| k.steps.len()).sum();
+    }
+
+    // Nodes, leaves included, that the outputs depend on
+    pub fn nodes(&self) -> usize {
+        return self.nodes;
+    }
+
+    // Operations folded to constants or simplified away
+    pub fn folded(&self) -> usize {
+        return self.folded;
+    }
+}
+
+
+fn read<T: Float>(arg: &Arg<T>, carry: T, slots: &[T], inputs: &[T]) -> T {
+    return match arg {
+        Arg::Carry    => carry,
+        Arg::Slot(k)  => slots[*k],
+        Arg::Input(i) => inputs[*i],
+        Arg::Leaf(v)  => v.data(),
+        Arg::Const(c) => *c
+    };
+}
+
+
+
+#[cfg(test)]
+mod compile_ops {
+    use super::*;
+    use crate::nn::{Module, MLP};
+    use crate::rand::Rng;
+
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn compiled() {
+        {
+            // tanh(x * 2 + 1).exp() is one kernel of four steps
+            let x: Val = Val::new(0.3);
+            let y: Val = (&x * 2.0 + 1.0).tanh().exp();
+            let c: Compiled = compile(std::slice::from_ref(&y), std::slice::from_ref(&x));
+
+            assert_eq!((c.len(), c.ops()), (1, 4));
+            assert!(approx_eq(c.run(&[0.3])[0], y.data()));
+            assert!(approx_eq(c.run(&[-1.2])[0], (-1.4_f64).tanh().exp()));
+        }
+
+        {
+            // A node used twice isn't fused into either consumer; a dead branch is dropped
+            let (a, b): (Val, Val) = (Val::new(1.0), Val::new(2.0));
+            let s: Val = (&a * &b).sigmoid();
+            let y: Val = &s.clone().relu() + &s.clone().pow(2.0);
+            let _dead: Val = s.clone().exp() * 3.0;
+            let c: Compiled = compile(std::slice::from_ref(&y), &[a, b]);
+
+            // a*b → sigmoid, then relu and pow, then +
+            assert_eq!((c.len(), c.ops()), (4, 5));
+            assert_eq!(c.nodes(), 7);
+
+            let s: f64 = 1.0 / (1.0 + (-(0.5 * -3.0_f64)).exp());
+            assert!(approx_eq(c.run(&[0.5, -3.0])[0], s + s * s));
+        }
+
+        {
+            // A model compiled once runs like forward, and sees parameter updates
+            let m: MLP = MLP::with_init(3, &[4, 4, 2], crate::init::Init::Xavier, &mut Rng::new(5));
+            let xs: Vec<Val> = vec![Val::new(0.0), Val::new(0.0), Val::new(0.0)];
+            let c: Compiled = compile(&m.forward(&xs), &xs);
+            assert!(c.len() < c.ops());
+
+            for x in [[0.1, -0.5, 2.0], [1.0, 1.0, -1.0]] {
+                let expected: Vec<f64> = m.forward(&x.iter().map(|&xi| Val::new(xi)).collect::<Vec<Val>>()).iter().map(|v| v.data()).collect();
+                let got: Vec<f64> = c.run(&x);
+                assert!(got.iter().zip(expected.iter()).all(|(a, b)| approx_eq(*a, *b)));
+            }
+
+            let p: Val = m.parameters()[0].clone();
+            let before: Vec<f64> = c.run(&[1.0, 0.0, 0.0]);
+            p.set_data(p.data() + 1.0);
+            assert_ne!(c.run(&[1.0, 0.0, 0.0]), before);
+        }
+
+        {
+            // Outputs that are leaves themselves
+            let (x, w): (Val, Val) = (Val::new(1.0), Val::new(4.0));
+            let c: Compiled = compile(&[x.clone(), w], std::slice::from_ref(&x));
+            assert!(c.is_empty());
+            assert_eq!(c.run(&[7.0]), vec![7.0, 4.0]);
+        }
+
+        {
+            // Generic code's identities and constant subexpressions disappear
+            let x: Val = Val::new(0.5);
+            let zero: Val = Val::new(0.0);
+            zero.set_requires_grad(false);
+            let two: Val = Val::new(2.0);
+            two.set_requires_grad(false);
+            let scale: Val = (two * 3.0).exp();
+            let lin: Val = &x * 1.0 + 0.0;
+            let h: Val = (&lin - &zero).tanh() * 1.0;
+            let dead: Val = &(&x * 0.0) * &x.clone().exp();
+            let y: Val = &h + &dead;
+            let z: Val = &y * &scale.clone().log();
+
+            // tanh and the final product are all that's left of 13 ops
+            let c: Compiled = compile(&[y.clone(), z.clone()], std::slice::from_ref(&x));
+            assert_eq!((c.ops(), c.folded()), (2, 11));
+            let out: Vec<f64> = c.run(&[-0.3]);
+            assert!(approx_eq(out[0], (-0.3_f64).tanh()));
+            assert!(approx_eq(out[1], 6.0 * (-0.3_f64).tanh()));
+            assert!(approx_eq(c.run(&[0.5])[1], z.data()));
+
+            // Folded entirely
+            let k: Compiled = compile(std::slice::from_ref(&scale), &[]);
+            assert!(k.is_empty());
+            assert!(approx_eq(k.run(&[])[0], 6.0_f64.exp()));
+        }
+    }
+}
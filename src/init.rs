@@ -0,0 +1,90 @@
+use crate::rand::Rng;
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Init {
+    // Weights and biases from U(-1, 1), as in micrograd
+    Uniform,
+    // Glorot uniform, U(-a, a) with a = sqrt(6 / (fan_in + fan_out)), zero bias
+    Xavier,
+    // Kaiming normal, N(0, 2 / fan_in), zero bias
+    He
+}
+
+
+impl Init {
+    pub fn weight(&self, fan_in: usize, fan_out: usize, rng: &mut Rng) -> f64 {
+        return match self {
+            Init::Uniform => rng.uniform(-1.0, 1.0),
+            Init::Xavier  => {
+                let a: f64 = (6.0 / (fan_in + fan_out) as f64).sqrt();
+                rng.uniform(-a, a)
+            },
+            Init::He      => rng.normal() * (2.0 / fan_in as f64).sqrt()
+        };
+    }
+
+    pub fn bias(&self, rng: &mut Rng) -> f64 {
+        return match self {
+            Init::Uniform => rng.uniform(-1.0, 1.0),
+            _             => 0.0
+        };
+    }
+
+    pub fn weights(&self, n: usize, fan_in: usize, fan_out: usize, rng: &mut Rng) -> Vec<f64> {
+        return (0..n).map(|_| self.weight(fan_in, fan_out, rng)).collect();
+    }
+}
+
+
+
+#[cfg(test)]
+mod init_ops {
+    use super::*;
+
+    fn stats(xs: &[f64]) -> (f64, f64) {
+        let mean: f64 = xs.iter().sum::<f64>() / xs.len() as f64;
+        let var: f64 = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+        return (mean, var);
+    }
+
+    #[test]
+    fn init() {
+        {
+            let mut rng: Rng = Rng::new(0);
+            let w: Vec<f64> = Init::Uniform.weights(5000, 10, 10, &mut rng);
+
+            assert!(w.iter().all(|&x| (-1.0..1.0).contains(&x)));
+            assert!(Init::Uniform.bias(&mut rng) != 0.0);
+        }
+
+        {
+            let mut rng: Rng = Rng::new(0);
+            let w: Vec<f64> = Init::Xavier.weights(20_000, 30, 20, &mut rng);
+            let a: f64 = (6.0_f64 / 50.0).sqrt();
+            let (mean, var) = stats(&w);
+
+            assert!(w.iter().all(|&x| x.abs() <= a));
+            assert!(mean.abs() < 0.01);
+            assert!((var - a * a / 3.0).abs() < 0.005);
+            assert_eq!(Init::Xavier.bias(&mut rng), 0.0);
+        }
+
+        {
+            let mut rng: Rng = Rng::new(0);
+            let w: Vec<f64> = Init::He.weights(20_000, 50, 10, &mut rng);
+            let (mean, var) = stats(&w);
+
+            assert!(mean.abs() < 0.01);
+            assert!((var - 2.0 / 50.0).abs() < 0.004);
+            assert_eq!(Init::He.bias(&mut rng), 0.0);
+        }
+
+        {
+            let mut a: Rng = Rng::new(9);
+            let mut b: Rng = Rng::new(9);
+
+            assert_eq!(Init::He.weights(16, 4, 4, &mut a), Init::He.weights(16, 4, 4, &mut b));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+
+
+// Just enough JSON for saving models and statistics; object keys keep their order
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>)
+}
+
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        return match self {
+            Json::Obj(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _                 => None
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            Json::Num(x) => Some(*x),
+            _            => None
+        };
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        return match self {
+            Json::Num(x) if *x >= 0.0 && x.fract() == 0.0 => Some(*x as usize),
+            _                                             => None
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Json::Str(s) => Some(s),
+            _            => None
+        };
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        return match self {
+            Json::Arr(xs) => Some(xs),
+            _             => None
+        };
+    }
+
+    pub fn parse(src: &str) -> Result<Json, String> {
+        let mut p: Parser = Parser { src: src.as_bytes(), pos: 0 };
+        let value: Json = p.value()?;
+        p.skip_ws();
+        if p.pos != p.src.len() {
+            return Err(format!("trailing characters at {}", p.pos));
+        }
+
+        return Ok(value);
+    }
+}
+
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize
+}
+
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        return self.src.get(self.pos).copied();
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}' at {}", c as char, self.pos));
+        }
+        self.pos += 1;
+
+        return Ok(());
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            return Ok(value);
+        }
+
+        return Err(format!("unexpected token at {}", self.pos));
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        return match self.peek() {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(_)    => self.number(),
+            None       => Err(String::from("unexpected end of input"))
+        };
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start: usize = self.pos;
+        while self.pos < self.src.len() && matches!(self.src[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+
+        let text: &str = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or("");
+        return text.parse::<f64>()
+            .map(Json::Num)
+            .map_err(|_| format!("bad number at {}", start));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+
+        let mut out: String = String::new();
+        loop {
+            let c: u8 = *self.src.get(self.pos).ok_or("unterminated string")?;
+            self.pos += 1;
+            match c {
+                b'"'  => return Ok(out),
+                b'\\' => {
+                    let e: u8 = *self.src.get(self.pos).ok_or("unterminated string")?;
+                    self.pos += 1;
+                    match e {
+                        b'n' => out.push('\n'),
+                        b't' => out.push('\t'),
+                        b'r' => out.push('\r'),
+                        b'u' => {
+                            let hex: &str = std::str::from_utf8(self.src.get(self.pos..self.pos + 4).ok_or("bad escape")?)
+                                .map_err(|_| "bad escape")?;
+                            let code: u32 = u32::from_str_radix(hex, 16).map_err(|_| "bad escape")?;
+                            out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                            self.pos += 4;
+                        },
+                        other => out.push(other as char)
+                    }
+                },
+                _     => {
+                    // Copy a whole UTF-8 sequence at once
+                    let start: usize = self.pos - 1;
+                    while self.pos < self.src.len() && (self.src[self.pos] & 0xC0) == 0x80 {
+                        self.pos += 1;
+                    }
+                    out.push_str(std::str::from_utf8(&self.src[start..self.pos]).map_err(|_| "bad utf-8")?);
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+
+        let mut items: Vec<Json> = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Arr(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => { self.pos += 1; return Ok(Json::Arr(items)); },
+                _          => return Err(format!("expected ',' or ']' at {}", self.pos))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+
+        let mut fields: Vec<(String, Json)> = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Obj(fields));
+        }
+        loop {
+            self.skip_ws();
+            let key: String = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => { self.pos += 1; return Ok(Json::Obj(fields)); },
+                _          => return Err(format!("expected ',' or '}}' at {}", self.pos))
+            }
+        }
+    }
+}
+
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null     => write!(f, "null"),
+            Json::Bool(b)  => write!(f, "{}", b),
+            // Non-finite numbers have no JSON form
+            Json::Num(x)   => if x.is_finite() { write!(f, "{}", x) } else { write!(f, "null") },
+            Json::Str(s)   => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"'  => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c    => write!(f, "{}", c)?
+                    }
+                }
+                write!(f, "\"")
+            },
+            Json::Arr(xs)  => {
+                write!(f, "[")?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                write!(f, "]")
+            },
+            Json::Obj(kvs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in kvs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::Str(k.clone()), v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod json_ops {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        {
+            let j: Json = Json::Obj(vec![
+                (String::from("a"), Json::Num(1.5)),
+                (String::from("b"), Json::Arr(vec![Json::Bool(true), Json::Null, Json::Num(-2e-9)])),
+                (String::from("c \"q\""), Json::Str(String::from("line\nnext é")))
+            ]);
+            let text: String = j.to_string();
+
+            assert_eq!(Json::parse(&text).unwrap(), j);
+        }
+
+        {
+            let x: f64 = 0.1 + 0.2;
+            let text: String = Json::Num(x).to_string();
+
+            assert_eq!(Json::parse(&text).unwrap().as_f64().unwrap(), x);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        {
+            let j: Json = Json::parse(" { \"n\" : [1, 2 ,3], \"s\": \"x\\u0041\", \"o\": {} } ").unwrap();
+
+            assert_eq!(j.get("n").unwrap().as_array().unwrap().len(), 3);
+            assert_eq!(j.get("n").unwrap().as_array().unwrap()[2].as_usize(), Some(3));
+            assert_eq!(j.get("s").unwrap().as_str(), Some("xA"));
+            assert_eq!(j.get("o"), Some(&Json::Obj(Vec::new())));
+            assert_eq!(j.get("missing"), None);
+        }
+
+        {
+            assert!(Json::parse("[1, 2").is_err());
+            assert!(Json::parse("{\"a\" 1}").is_err());
+            assert!(Json::parse("[1] x").is_err());
+            assert!(Json::parse("\"open").is_err());
+            assert!(Json::parse("").is_err());
+        }
+    }
+}
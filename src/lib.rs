@@ -1,109 +1,723 @@
+#![allow(clippy::needless_return)]
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::ops;
+use std::fs;
+use std::io;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::rc::Rc;
+
+pub mod arena;
+pub mod autograd;
+pub mod backend;
+pub mod checkpoint;
+pub mod data;
+pub mod dual;
+pub mod export;
+mod float;
+pub mod grad_fn;
+pub mod gradcheck;
+pub mod graph;
+pub mod init;
+pub mod json;
+pub mod loss;
+pub mod metrics;
+pub mod nn;
+pub mod ops;
+pub mod optim;
+pub mod prune;
+pub mod rand;
+pub mod rl;
+pub mod tape;
+pub mod tensor;
+pub mod train;
+pub mod tune;
+pub mod vecval;
+
+pub use float::Float;
+pub use grad_fn::GradFn;
+pub use gradcheck::grad_check;
+pub use graph::{GraphBuilder, GraphStats};
+pub use rand::set_seed;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum Operations {
+pub enum Operations {
     Add,
     Sub,
     Mul,
     Tanh,
+    Relu,
+    Sigmoid,
+    LeakyRelu,
+    Elu,
+    Gelu,
+    Softplus,
+    Silu,
+    Abs,
+    Sign,
+    Softmax,
+    LogSoftmax,
+    Pow,
+    Exp,
+    Log,
+    Sum,
+    Index,
+    MatMul,
+    Conv,
+    Max,
+    Custom(&'static str),
     Non
 }
 
 
 // #[derive(Debug, PartialEq)]
-struct Val {
-    data:     f64,
-    grad:     f64,
-    prev:     Vec<Val>,
-    op:       Operations,
-    backward: Option<Box<dyn Fn()>>
+// grad is None for nodes that don't require one: constants, frozen leaves and
+// whatever is built only from them
+struct ValData<T: Float> {
+    data:    T,
+    grad:    Option<T>,
+    prev:    Vec<Val<T>>,
+    grad_fn: Option<Rc<dyn GradFn<T>>>
+}
+
+
+// Dropping the last handle to a long chain would otherwise recurse once per
+// node. Children that this node owns outright are unlinked onto a work list.
+impl<T: Float> Drop for ValData<T> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Val<T>> = std::mem::take(&mut self.prev);
+        while let Some(v) = stack.pop() {
+            if let Ok(cell) = Rc::try_unwrap(v.0) {
+                stack.append(&mut cell.borrow_mut().prev);
+            }
+        }
+    }
+}
+
+
+// Nodes are shared so a backward closure can reach the operands it was built from
+#[derive(Clone)]
+pub struct Val<T: Float = f64>(Rc<RefCell<ValData<T>>>);
+
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+
+// Runs `f` without recording any graph: results are plain leaves, for Vals and
+// Tensors alike. Scopes nest, and the previous state comes back even on panic.
+pub fn no_grad<R, F>(f: F) -> R
+where F: FnOnce() -> R,
+{
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            GRAD_ENABLED.with(|g| g.set(self.0));
+        }
+    }
+
+    let _restore: Restore = Restore(GRAD_ENABLED.with(|g| g.replace(false)));
+    return f();
+}
+
+
+pub fn is_grad_enabled() -> bool {
+    return GRAD_ENABLED.with(|g| g.get());
+}
+
+
+// How Val::backward_with runs. The defaults are backward's: gradients add
+// onto what the leaves already hold, and the graph stays for another pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackwardOptions {
+    pub retain_graph: bool,
+    pub accumulate:   bool
+}
+
+
+impl BackwardOptions {
+    pub fn new() -> BackwardOptions {
+        return BackwardOptions { retain_graph: true, accumulate: true };
+    }
+
+    // With false, every node's children and backward are dropped after the pass
+    pub fn retain_graph(mut self, retain_graph: bool) -> BackwardOptions {
+        self.retain_graph = retain_graph;
+        return self;
+    }
+
+    // With false, the leaves' gradients are cleared before the pass
+    pub fn accumulate(mut self, accumulate: bool) -> BackwardOptions {
+        self.accumulate = accumulate;
+        return self;
+    }
+}
+
+
+impl Default for BackwardOptions {
+    fn default() -> BackwardOptions {
+        return BackwardOptions::new();
+    }
+}
+
+
+// What is left of a node's GradFn once backward_with has freed the graph
+struct Freed(Operations);
+
+
+impl<T: Float> GradFn<T> for Freed {
+    fn op(&self) -> Operations {
+        return self.0;
+    }
+
+    fn forward(&self, _inputs: &[T]) -> T {
+        panic!("a freed {} node can't be recomputed", self.0);
+    }
+
+    fn backward(&self, _inputs: &[T], _output: T, _grad: T) -> Vec<T> {
+        panic!("backward through a {} node whose graph was freed by backward_with(retain_graph: false)", self.0);
+    }
 }
 
 
-impl Val {
-    fn new(d: f64) -> Val {
-        return Val { data: d, grad: 0.0, prev: Vec::new(), op: Operations::Non, backward: None };
+impl<T: Float> Val<T> {
+    pub fn new(d: T) -> Val<T> {
+        let node: ValData<T> = ValData { data: d, grad: Some(T::zero()), prev: Vec::new(), grad_fn: None };
+        return Val(Rc::new(RefCell::new(node)));
+    }
+
+    // A leaf to be trained, the same as Val::new
+    pub fn param(d: T) -> Val<T> {
+        return Val::new(d);
+    }
+
+    // A leaf holding data, such as an input. It has no gradient: grad() reads
+    // zero and anything added to it is dropped. Nodes built only from
+    // constants are skipped by backward.
+    pub fn constant(d: T) -> Val<T> {
+        let node: ValData<T> = ValData { data: d, grad: None, prev: Vec::new(), grad_fn: None };
+        return Val(Rc::new(RefCell::new(node)));
+    }
+
+    pub fn data(&self) -> T {
+        return self.0.borrow().data;
+    }
+
+    pub fn grad(&self) -> T {
+        return self.0.borrow().grad.unwrap_or(T::zero());
+    }
+
+    pub fn prev(&self) -> Vec<Val<T>> {
+        return self.0.borrow().prev.clone();
+    }
+
+    pub fn op(&self) -> Operations {
+        return match &self.0.borrow().grad_fn {
+            Some(f) => f.op(),
+            None    => Operations::Non
+        };
+    }
+
+    // A node computed by `f` from `inputs`, which are recorded as its children
+    pub fn apply<G>(f: G, inputs: &[Val<T>]) -> Val<T>
+    where G: GradFn<T> + 'static,
+    {
+        let xs: Vec<T> = inputs.iter().map(|x| x.data()).collect();
+        if !is_grad_enabled() {
+            return Val::new(f.forward(&xs));
+        }
+
+        let key: Option<String> = if graph::deduplicating() { f.dedup_key() } else { None };
+        return graph::intern(key, inputs, move || {
+            let result: Val<T> = Val::new(f.forward(&xs));
+
+            let mut inner = result.0.borrow_mut();
+            inner.prev = inputs.to_vec();
+            inner.grad_fn = Some(Rc::new(f));
+            // Nodes without children (bridges from Tensor and VecVal) pass gradients on themselves
+            if !inputs.is_empty() && !inputs.iter().any(|x| x.requires_grad()) {
+                inner.grad = None;
+            }
+            drop(inner);
+
+            return result;
+        });
+    }
+
+    // A leaf with the same value, so no gradient flows back through it
+    pub fn detach(&self) -> Val<T> {
+        return Val::new(self.data());
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        return self.0.borrow().grad.is_some();
+    }
+
+    // Set on leaves. A leaf without it has no gradient, like a constant, and
+    // optimizers leave it alone; nodes built only from such leaves are skipped
+    // by backward. Nodes take the flag when they are made, so set it before
+    // building the graph. Turning it back on starts the gradient from zero.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        let mut inner = self.0.borrow_mut();
+        if requires_grad != inner.grad.is_some() {
+            inner.grad = if requires_grad { Some(T::zero()) } else { None };
+        }
+    }
+
+    fn set_data(&self, d: T) {
+        self.0.borrow_mut().data = d;
+    }
+
+    // Both do nothing on nodes without a gradient
+    fn set_grad(&self, g: T) {
+        if let Some(grad) = self.0.borrow_mut().grad.as_mut() {
+            *grad = g;
+        }
+    }
+
+    // Every consumer of a node contributes to its gradient, so closures add rather than assign
+    fn add_grad(&self, g: T) {
+        if let Some(grad) = self.0.borrow_mut().grad.as_mut() {
+            *grad += g;
+        }
+    }
+
+
+    pub fn backward(&self) {
+        self.backward_with(BackwardOptions::new());
+    }
+
+    // backward, optionally clearing the leaves' gradients first and freeing the
+    // graph afterwards. A freed node keeps its value and op but drops its
+    // children, so a later backward that reaches it panics.
+    pub fn backward_with(&self, opts: BackwardOptions) {
+        let order: Vec<Val<T>> = self.topo();
+        if !opts.accumulate {
+            for leaf in order.iter().filter(|v| v.0.borrow().grad_fn.is_none()) {
+                leaf.set_grad(T::zero());
+            }
+        }
+
+        self.propagate_over(&order, T::one());
+
+        if !opts.retain_graph {
+            for node in order.iter() {
+                let mut inner = node.0.borrow_mut();
+                if let Some(f) = inner.grad_fn.take() {
+                    inner.grad_fn = Some(Rc::new(Freed(f.op())));
+                    inner.prev.clear();
+                }
+            }
+        }
+    }
+
+    // backward with the output's gradient set to `seed` rather than one
+    fn propagate(&self, seed: T) {
+        self.propagate_over(&self.topo(), seed);
+    }
+
+    // propagate, given this node's topo order
+    fn propagate_over(&self, order: &[Val<T>], seed: T) {
+        // Computed nodes start from zero, so heads sharing part of a graph can
+        // each go through backward; leaves keep adding up
+        for node in order.iter().filter(|v| v.0.borrow().grad_fn.is_some()) {
+            node.set_grad(T::zero());
+        }
+        self.set_grad(seed);
+
+        // A node's gradient is only complete once all of its consumers have contributed
+        for node in order.iter().rev() {
+            let inner = node.0.borrow();
+            let grad: T = match inner.grad {
+                Some(g) => g,
+                None    => continue
+            };
+            if let Some(f) = &inner.grad_fn {
+                let xs: Vec<T> = inner.prev.iter().map(|x| x.data()).collect();
+                let grads: Vec<T> = f.backward(&xs, inner.data, grad);
+                for (x, g) in inner.prev.iter().zip(grads) {
+                    if x.requires_grad() {
+                        x.add_grad(g);
+                    }
+                }
+            }
+        }
+    }
+
+    // A copy of the graph feeding into this node, with nodes of its own: its
+    // gradients are separate from the original's. With share_leaves the copy
+    // computes from the same leaves, so both add into their gradients;
+    // otherwise the leaves are copied too, values and requires_grad included.
+    // Ops are shared rather than copied, so a bridge from Tensor or VecVal
+    // still passes gradients on to the same source.
+    pub fn deep_clone(&self, share_leaves: bool) -> Val<T> {
+        let mut copies: HashMap<*const RefCell<ValData<T>>, Val<T>> = HashMap::new();
+        for node in self.topo().iter() {
+            let inner = node.0.borrow();
+            let copy: Val<T> = match &inner.grad_fn {
+                None if share_leaves => node.clone(),
+                grad_fn              => {
+                    let prev: Vec<Val<T>> = inner.prev.iter().map(|c| copies[&Rc::as_ptr(&c.0)].clone()).collect();
+                    let data: ValData<T> = ValData { data: inner.data, grad: inner.grad.map(|_| T::zero()), prev, grad_fn: grad_fn.clone() };
+                    Val(Rc::new(RefCell::new(data)))
+                }
+            };
+            copies.insert(Rc::as_ptr(&node.0), copy);
+        }
+
+        return copies.remove(&Rc::as_ptr(&self.0)).expect("topo ends with this node");
+    }
+
+    // Clears every gradient in the graph feeding into this node, this node included
+    pub fn zero_grad(&self) {
+        for node in self.topo().iter() {
+            node.set_grad(T::zero());
+        }
+    }
+
+    // Graphviz source in the style of micrograd's draw_dot: a record per value,
+    // with a separate op node between it and its children
+    pub fn to_dot(&self) -> String {
+        let order: Vec<Val<T>> = self.topo();
+        let ids: HashMap<*const RefCell<ValData<T>>, usize> = order.iter()
+            .enumerate()
+            .map(|(i, v)| (Rc::as_ptr(&v.0), i))
+            .collect();
+
+        let mut out: String = String::from("digraph G {\n    rankdir=LR;\n");
+        for (i, v) in order.iter().enumerate() {
+            out += &format!("    n{} [shape=record, label=\"{{ data {:.4} | grad {:.4} }}\"];\n", i, v.data(), v.grad());
+
+            if v.op() != Operations::Non {
+                out += &format!("    n{}op [label=\"{}\"];\n", i, v.op());
+                out += &format!("    n{}op -> n{};\n", i, i);
+                for child in v.0.borrow().prev.iter() {
+                    out += &format!("    n{} -> n{}op;\n", ids[&Rc::as_ptr(&child.0)], i);
+                }
+            }
+        }
+        out += "}\n";
+
+        return out;
+    }
+
+    pub fn write_dot(&self, path: &str) -> io::Result<()> {
+        return fs::write(path, self.to_dot());
+    }
+
+    // Every node feeding into this one, children before parents and this node last
+    pub fn topo_iter(&self) -> impl Iterator<Item = Val<T>> {
+        return self.topo().into_iter();
+    }
+
+    // Node and leaf counts, depth, a histogram of ops and an estimate of the
+    // memory held, for the graph feeding into this node
+    pub fn graph_stats(&self) -> GraphStats {
+        return graph::stats(&self.topo());
+    }
+
+    // Children before parents, each shared node appearing once. Walks with an
+    // explicit stack, since graphs can be far deeper than the call stack.
+    // A node is pushed twice: once to expand it, then again to emit it after its children.
+    fn topo(&self) -> Vec<Val<T>> {
+        let mut visited: HashSet<*const RefCell<ValData<T>>> = HashSet::new();
+        let mut order: Vec<Val<T>> = Vec::new();
+
+        let mut stack: Vec<(Val<T>, bool)> = vec![(self.clone(), false)];
+        while let Some((v, expanded)) = stack.pop() {
+            if expanded {
+                order.push(v);
+                continue;
+            }
+            if !visited.insert(Rc::as_ptr(&v.0)) {
+                continue;
+            }
+
+            stack.push((v.clone(), true));
+            for child in v.0.borrow().prev.iter().rev() {
+                if !visited.contains(&Rc::as_ptr(&child.0)) {
+                    stack.push((child.clone(), false));
+                }
+            }
+        }
+
+        return order;
+    }
+
+    pub fn tanh(self) -> Val<T> {
+        return Val::apply(grad_fn::Tanh, &[self]);
     }
 
-    fn set_op(&mut self, op: Operations) {
-        self.op = op;
+    pub fn relu(self) -> Val<T> {
+        return Val::apply(grad_fn::Relu, &[self]);
     }
 
-    fn set_backward<F>(&mut self, func: F)
-    where F: Fn() + 'static,
+    // Constants are recorded as leaves, but only `self` ever receives a gradient
+    fn with_constant<G>(self, c: T, op: G, c_first: bool) -> Val<T>
+    where G: GradFn<T> + 'static,
     {
-        self.backward = Some(Box::new(func));
+        // Equal constants are one node when deduplicating
+        let key: Option<String> = if graph::deduplicating() { Some(format!("Const {}", c)) } else { None };
+        let c: Val<T> = graph::intern(key, &[], || Val::constant(c));
+        if c_first {
+            return Val::apply(grad_fn::WithConstant { op, index: 0 }, &[c, self]);
+        }
+
+        return Val::apply(grad_fn::WithConstant { op, index: 1 }, &[self, c]);
+    }
+
+    pub fn pow(self, n: T) -> Val<T> {
+        return Val::apply(grad_fn::Pow(n), &[self]);
+    }
+
+    pub fn exp(self) -> Val<T> {
+        return Val::apply(grad_fn::Exp, &[self]);
+    }
+
+    // Natural log
+    pub fn log(self) -> Val<T> {
+        return Val::apply(grad_fn::Log, &[self]);
+    }
+
+    pub fn sigmoid(self) -> Val<T> {
+        return Val::apply(grad_fn::Sigmoid, &[self]);
+    }
+
+    // x for x > 0, alpha x otherwise
+    pub fn leaky_relu(self, alpha: T) -> Val<T> {
+        return Val::apply(grad_fn::LeakyRelu(alpha), &[self]);
+    }
+
+    // x for x > 0, alpha (e^x - 1) otherwise
+    pub fn elu(self, alpha: T) -> Val<T> {
+        return Val::apply(grad_fn::Elu(alpha), &[self]);
+    }
+
+    // The tanh approximation, 0.5 x (1 + tanh(sqrt(2/π) (x + 0.044715 x³)))
+    pub fn gelu(self) -> Val<T> {
+        return Val::apply(grad_fn::Gelu, &[self]);
+    }
+
+    // ln(1 + e^x)
+    pub fn softplus(self) -> Val<T> {
+        return Val::apply(grad_fn::Softplus, &[self]);
     }
 
-    fn tanh(self) -> Val {
-        let x: f64 = self.data;
-        let t: f64 = ((2.0 * x).exp() - 1.0) / ((2.0 * x).exp() + 1.0);
-        let mut result: Val = Val::new(t);
+    // x σ(x), also known as swish
+    pub fn silu(self) -> Val<T> {
+        return Val::apply(grad_fn::Silu, &[self]);
+    }
 
-        result.prev.push(self);
-        result.grad = 1.0;
-        result.set_op(Operations::Tanh);
+    // The subgradient at 0 is taken as 0
+    pub fn abs(self) -> Val<T> {
+        return Val::apply(grad_fn::Abs, &[self]);
+    }
 
-        return result;
+    // -1, 0 or 1; piecewise constant, so it passes no gradient back
+    pub fn sign(self) -> Val<T> {
+        return Val::apply(grad_fn::Sign, &[self]);
     }
 }
 
 
 /*** Operator Overloads ***/
 
-impl ops::Neg for Val {
-    type Output = Val;
-    fn neg(mut self) -> Val {
-        self.data = -self.data;
+impl<T: Float> Neg for Val<T> {
+    type Output = Val<T>;
+    fn neg(self) -> Val<T> {
+        return self * -T::one();
+    }
+}
 
-        return self;
+
+impl<T: Float> Add for Val<T> {
+    type Output = Val<T>;
+    fn add(self, rhs: Self) -> Val<T> {
+        return Val::apply(grad_fn::Add, &[self, rhs]);
     }
 }
 
 
-impl ops::Add for Val {
-    type Output = Val;
-    fn add(mut self, mut rhs: Self) -> Val {
-        let mut result: Val = Val::new(self.data + rhs.data);
-        result.prev.push(self);
-        result.prev.push(rhs);
-        result.set_op(Operations::Add);
+impl<T: Float> Sub for Val<T> {
+    type Output = Val<T>;
+    fn sub(self, rhs: Self) -> Val<T> {
+        return Val::apply(grad_fn::Sub, &[self, rhs]);
+    }
+}
+
+
+impl<T: Float> Mul for Val<T> {
+    type Output = Val<T>;
+    fn mul(self, rhs: Self) -> Val<T> {
+        return Val::apply(grad_fn::Mul, &[self, rhs]);
+    }
+}
+
+
+impl<T: Float> Add<T> for Val<T> {
+    type Output = Val<T>;
+    fn add(self, rhs: T) -> Val<T> {
+        return self.with_constant(rhs, grad_fn::Add, false);
+    }
+}
+
+
+impl<T: Float> Sub<T> for Val<T> {
+    type Output = Val<T>;
+    fn sub(self, rhs: T) -> Val<T> {
+        return self.with_constant(rhs, grad_fn::Sub, false);
+    }
+}
 
-        self.set_backward::<Fn()>(|| {
-            self.grad = result.grad;
-            rhs.grad  = result.grad;
-        });
 
-        return result;
+impl<T: Float> Mul<T> for Val<T> {
+    type Output = Val<T>;
+    fn mul(self, rhs: T) -> Val<T> {
+        return self.with_constant(rhs, grad_fn::Mul, false);
     }
 }
 
 
-impl ops::Sub for Val {
-    type Output = Val;
-    fn sub(self, rhs: Self) -> Val {
-        let mut result: Val = Val::new(self.data - rhs.data);
-        result.prev.push(self);
-        result.prev.push(rhs);
-        result.set_op(Operations::Sub);
+// Borrowing forms share the operand nodes, so the operands stay usable afterwards
+impl<T: Float> Neg for &Val<T> {
+    type Output = Val<T>;
+    fn neg(self) -> Val<T> {
+        return -self.clone();
+    }
+}
+
+
+impl<T: Float> Add<&Val<T>> for &Val<T> {
+    type Output = Val<T>;
+    fn add(self, rhs: &Val<T>) -> Val<T> {
+        return self.clone() + rhs.clone();
+    }
+}
+
+
+impl<T: Float> Sub<&Val<T>> for &Val<T> {
+    type Output = Val<T>;
+    fn sub(self, rhs: &Val<T>) -> Val<T> {
+        return self.clone() - rhs.clone();
+    }
+}
+
+
+impl<T: Float> Mul<&Val<T>> for &Val<T> {
+    type Output = Val<T>;
+    fn mul(self, rhs: &Val<T>) -> Val<T> {
+        return self.clone() * rhs.clone();
+    }
+}
 
-        return result;
+
+impl<T: Float> Add<T> for &Val<T> {
+    type Output = Val<T>;
+    fn add(self, rhs: T) -> Val<T> {
+        return self.clone() + rhs;
     }
 }
 
 
-impl ops::Mul for Val {
-    type Output = Val;
-    fn mul(mut self, rhs: Self) -> Val {
-        let mut result: Val = Val::new(self.data * rhs.data);
-        result.prev.push(self);
-        result.prev.push(rhs);
-        result.set_op(Operations::Mul);
+impl<T: Float> Sub<T> for &Val<T> {
+    type Output = Val<T>;
+    fn sub(self, rhs: T) -> Val<T> {
+        return self.clone() - rhs;
+    }
+}
+
 
-        return result;
+impl<T: Float> Mul<T> for &Val<T> {
+    type Output = Val<T>;
+    fn mul(self, rhs: T) -> Val<T> {
+        return self.clone() * rhs;
     }
 }
+
+
+// A constant on the left can't be written generically, since the float
+// types are foreign, so these are stamped out for each one
+macro_rules! impl_scalar_lhs {
+    ($t:ty) => {
+        impl Add<Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn add(self, rhs: Val<$t>) -> Val<$t> {
+                return rhs.with_constant(self, grad_fn::Add, true);
+            }
+        }
+
+        impl Sub<Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn sub(self, rhs: Val<$t>) -> Val<$t> {
+                return rhs.with_constant(self, grad_fn::Sub, true);
+            }
+        }
+
+        impl Mul<Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn mul(self, rhs: Val<$t>) -> Val<$t> {
+                return rhs.with_constant(self, grad_fn::Mul, true);
+            }
+        }
+
+        impl Add<&Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn add(self, rhs: &Val<$t>) -> Val<$t> {
+                return self + rhs.clone();
+            }
+        }
+
+        impl Sub<&Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn sub(self, rhs: &Val<$t>) -> Val<$t> {
+                return self - rhs.clone();
+            }
+        }
+
+        impl Mul<&Val<$t>> for $t {
+            type Output = Val<$t>;
+            fn mul(self, rhs: &Val<$t>) -> Val<$t> {
+                return self * rhs.clone();
+            }
+        }
+    };
+}
+
+impl_scalar_lhs!(f32);
+impl_scalar_lhs!(f64);
+
+
+// `a += b` rebinds `a` to a new node built from the old one, so history is
+// kept and the old node stays in the graph. Other clones of `a` still point
+// at the old node and see the old value.
+macro_rules! impl_assign {
+    ($trait:ident, $method:ident, $op:tt) => {
+        impl<T: Float> $trait for Val<T> {
+            fn $method(&mut self, rhs: Val<T>) {
+                *self = self.clone() $op rhs;
+            }
+        }
+
+        impl<T: Float> $trait<&Val<T>> for Val<T> {
+            fn $method(&mut self, rhs: &Val<T>) {
+                *self = self.clone() $op rhs.clone();
+            }
+        }
+
+        impl<T: Float> $trait<T> for Val<T> {
+            fn $method(&mut self, rhs: T) {
+                *self = self.clone() $op rhs;
+            }
+        }
+    };
+}
+
+impl_assign!(AddAssign, add_assign, +);
+impl_assign!(SubAssign, sub_assign, -);
+impl_assign!(MulAssign, mul_assign, *);
 /*** End Overloads ***/
 
 
@@ -112,19 +726,69 @@ impl ops::Mul for Val {
 impl fmt::Display for Operations {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Operations::Add  => write!(f, "+"),
-            Operations::Sub  => write!(f, "-"),
-            Operations::Mul  => write!(f, "*"),
-            Operations::Tanh => write!(f, "Tanh"),
-            Operations::Non  => write!(f, "Non")
+            Operations::Add          => write!(f, "+"),
+            Operations::Sub          => write!(f, "-"),
+            Operations::Mul          => write!(f, "*"),
+            Operations::Tanh         => write!(f, "Tanh"),
+            Operations::Relu         => write!(f, "ReLU"),
+            Operations::Sigmoid      => write!(f, "Sigmoid"),
+            Operations::LeakyRelu    => write!(f, "LeakyReLU"),
+            Operations::Elu          => write!(f, "ELU"),
+            Operations::Gelu         => write!(f, "GELU"),
+            Operations::Softplus     => write!(f, "Softplus"),
+            Operations::Silu         => write!(f, "SiLU"),
+            Operations::Abs          => write!(f, "Abs"),
+            Operations::Sign         => write!(f, "Sign"),
+            Operations::Softmax      => write!(f, "Softmax"),
+            Operations::LogSoftmax   => write!(f, "LogSoftmax"),
+            Operations::Pow          => write!(f, "Pow"),
+            Operations::Exp          => write!(f, "Exp"),
+            Operations::Log          => write!(f, "Log"),
+            Operations::Sum          => write!(f, "Sum"),
+            Operations::Index        => write!(f, "Index"),
+            Operations::MatMul       => write!(f, "@"),
+            Operations::Conv         => write!(f, "Conv"),
+            Operations::Max          => write!(f, "Max"),
+            Operations::Custom(name) => write!(f, "{}", name),
+            Operations::Non          => write!(f, "Non")
+        }
+    }
+}
+
+
+// `{:#}` prints the whole graph below the node, one level of indentation per
+// step. A node reached a second time is marked rather than printed again.
+impl<T: Float> fmt::Display for Val<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "Data: {}, Grad: {}, Op: {}", self.data(), self.grad(), self.op());
         }
+
+        let mut seen: HashSet<*const RefCell<ValData<T>>> = HashSet::new();
+        let mut stack: Vec<(Val<T>, usize)> = vec![(self.clone(), 0)];
+        while let Some((v, depth)) = stack.pop() {
+            if depth > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}Data: {}, Grad: {}, Op: {}", "  ".repeat(depth), v.data(), v.grad(), v.op())?;
+            if !seen.insert(Rc::as_ptr(&v.0)) {
+                write!(f, " (shared)")?;
+                continue;
+            }
+
+            for child in v.0.borrow().prev.iter().rev() {
+                stack.push((child.clone(), depth + 1));
+            }
+        }
+
+        return Ok(());
     }
 }
 
 
-impl fmt::Display for Val {
+impl<T: Float> fmt::Debug for Val<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "Data: {}, Grad: {}, Op: {}", self.data, self.grad, self.op);
+        return fmt::Display::fmt(self, f);
     }
 }
 /*** End Displays ***/
@@ -132,6 +796,7 @@ impl fmt::Display for Val {
 
 
 #[cfg(test)]
+#[allow(clippy::excessive_precision)]
 mod val_ops {
     use super::*;
     // Helper function for floating point arithmetic
@@ -142,10 +807,10 @@ mod val_ops {
     #[test]
     fn val() {
         let v: Val = Val::new(3.9);
-        assert_eq!(v.data, 3.9);
-        assert_eq!(v.grad, 0.0);
-        assert_eq!(v.prev.len(), 0);
-        assert_eq!(v.op, Operations::Non);
+        assert_eq!(v.data(), 3.9);
+        assert_eq!(v.grad(), 0.0);
+        assert_eq!(v.prev().len(), 0);
+        assert_eq!(v.op(), Operations::Non);
     }
 
     #[test]
@@ -154,14 +819,14 @@ mod val_ops {
             let v1: Val = Val::new(10.0);
             let result: Val = -v1;
 
-            assert_eq!(result.data, -10.0);
+            assert_eq!(result.data(), -10.0);
         }
 
         {
             let v1: Val = Val::new(-30.3);
             let result: Val = -v1;
 
-            assert_eq!(result.data, 30.3);
+            assert_eq!(result.data(), 30.3);
         }
 
         {
@@ -169,7 +834,7 @@ mod val_ops {
             let v2: Val = Val::new(20.3);
             let result: Val = -v1 + v2;
 
-            assert_eq!(result.data, 50.6);
+            assert_eq!(result.data(), 50.6);
         }
 
         {
@@ -177,7 +842,7 @@ mod val_ops {
             let v2: Val = Val::new(-263.413276);
             let result: Val = v2 - -v1;
 
-            assert_eq!(result.data, -265.869976);
+            assert_eq!(result.data(), -265.869976);
         }
     }
 
@@ -188,10 +853,10 @@ mod val_ops {
             let v2: Val = Val::new(4.5);
             let result: Val = v1 + v2;
 
-            assert_eq!(result.data, 6.5);
-            assert_eq!(result.prev[0].data, 2.0);
-            assert_eq!(result.prev[1].data, 4.5);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), 6.5);
+            assert_eq!(result.prev()[0].data(), 2.0);
+            assert_eq!(result.prev()[1].data(), 4.5);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -199,10 +864,10 @@ mod val_ops {
             let v2: Val = Val::new(4.5);
             let result: Val = v2 + v1;
 
-            assert_eq!(result.data, 6.5);
-            assert_eq!(result.prev[0].data, 4.5);
-            assert_eq!(result.prev[1].data, 2.0);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), 6.5);
+            assert_eq!(result.prev()[0].data(), 4.5);
+            assert_eq!(result.prev()[1].data(), 2.0);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -210,10 +875,10 @@ mod val_ops {
             let v2: Val = Val::new(2.3);
             let result: Val = v1 + v2;
 
-            assert_eq!(result.data, -2.8);
-            assert_eq!(result.prev[0].data, -5.1);
-            assert_eq!(result.prev[1].data, 2.3);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), -2.8);
+            assert_eq!(result.prev()[0].data(), -5.1);
+            assert_eq!(result.prev()[1].data(), 2.3);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -221,10 +886,10 @@ mod val_ops {
             let v2: Val = Val::new(2.3);
             let result: Val = v2 + v1;
 
-            assert_eq!(result.data, -2.8);
-            assert_eq!(result.prev[0].data, 2.3);
-            assert_eq!(result.prev[1].data, -5.1);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), -2.8);
+            assert_eq!(result.prev()[0].data(), 2.3);
+            assert_eq!(result.prev()[1].data(), -5.1);
+            assert_eq!(result.op(), Operations::Add);
         }
         
         {
@@ -232,10 +897,10 @@ mod val_ops {
             let v2: Val = Val::new(2.3);
             let result: Val = v2 + v1;
 
-            assert_eq!(result.data, 2.3);
-            assert_eq!(result.prev[0].data, 2.3);
-            assert_eq!(result.prev[1].data, 0.0);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), 2.3);
+            assert_eq!(result.prev()[0].data(), 2.3);
+            assert_eq!(result.prev()[1].data(), 0.0);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -243,10 +908,10 @@ mod val_ops {
             let v2: Val = Val::new(0.0);
             let result: Val = v2 + v1;
 
-            assert_eq!(result.data, -5.1);
-            assert_eq!(result.prev[0].data, 0.0);
-            assert_eq!(result.prev[1].data, -5.1);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), -5.1);
+            assert_eq!(result.prev()[0].data(), 0.0);
+            assert_eq!(result.prev()[1].data(), -5.1);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -254,10 +919,10 @@ mod val_ops {
             let v2: Val = Val::new(82.999999993);
             let result: Val = v2 + v1;
 
-            assert_eq!(result.data, 77.8999999924);
-            assert_eq!(result.prev[0].data, 82.999999993);
-            assert_eq!(result.prev[1].data, -5.1000000006);
-            assert_eq!(result.op, Operations::Add);
+            assert_eq!(result.data(), 77.8999999924);
+            assert_eq!(result.prev()[0].data(), 82.999999993);
+            assert_eq!(result.prev()[1].data(), -5.1000000006);
+            assert_eq!(result.op(), Operations::Add);
         }
     }
 
@@ -268,10 +933,10 @@ mod val_ops {
             let v2: Val = Val::new(100.1);
             let result: Val = v1 - v2;
 
-            assert_eq!(result.data, 0.0);
-            assert_eq!(result.prev[0].data, 100.1);
-            assert_eq!(result.prev[1].data, 100.1);
-            assert_eq!(result.op, Operations::Sub);
+            assert_eq!(result.data(), 0.0);
+            assert_eq!(result.prev()[0].data(), 100.1);
+            assert_eq!(result.prev()[1].data(), 100.1);
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -279,8 +944,8 @@ mod val_ops {
             let v2: Val = Val::new(2.3);
             let result: Val = v1 - v2;
 
-            assert!(approx_eq(result.data, 6.6));
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), 6.6));
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -288,10 +953,10 @@ mod val_ops {
             let v2: Val = Val::new(2.3);
             let result: Val = v2 - v1;
 
-            assert!(approx_eq(result.data, -6.6));
-            assert_eq!(result.prev[0].data, 2.3);
-            assert_eq!(result.prev[1].data, 8.9);
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), -6.6));
+            assert_eq!(result.prev()[0].data(), 2.3);
+            assert_eq!(result.prev()[1].data(), 8.9);
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -299,10 +964,10 @@ mod val_ops {
             let v2: Val = Val::new(-367.11);
             let result: Val = v1 - v2;
 
-            assert!(approx_eq(result.data, 656.48));
-            assert_eq!(result.prev[0].data, 289.37);
-            assert_eq!(result.prev[1].data, -367.11);
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), 656.48));
+            assert_eq!(result.prev()[0].data(), 289.37);
+            assert_eq!(result.prev()[1].data(), -367.11);
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -310,10 +975,10 @@ mod val_ops {
             let v2: Val = Val::new(0.0);
             let result: Val = v1 - v2;
 
-            assert!(approx_eq(result.data, 289.37));
-            assert_eq!(result.prev[0].data, 289.37);
-            assert_eq!(result.prev[1].data, 0.0);
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), 289.37));
+            assert_eq!(result.prev()[0].data(), 289.37);
+            assert_eq!(result.prev()[1].data(), 0.0);
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -321,10 +986,10 @@ mod val_ops {
             let v2: Val = Val::new(-367.11);
             let result: Val = v1 - v2;
 
-            assert!(approx_eq(result.data, 367.11));
-            assert_eq!(result.prev[0].data, 0.0);
-            assert_eq!(result.prev[1].data, -367.11);
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), 367.11));
+            assert_eq!(result.prev()[0].data(), 0.0);
+            assert_eq!(result.prev()[1].data(), -367.11);
+            assert_eq!(result.op(), Operations::Sub);
         }
 
         {
@@ -332,10 +997,10 @@ mod val_ops {
             let v2: Val = Val::new(0.0987654321);
             let result: Val = v1 - v2;
 
-            assert!(approx_eq(result.data, 472.0246913569));
-            assert_eq!(result.prev[0].data, 472.123456789);
-            assert_eq!(result.prev[1].data, 0.0987654321);
-            assert_eq!(result.op, Operations::Sub);
+            assert!(approx_eq(result.data(), 472.0246913569));
+            assert_eq!(result.prev()[0].data(), 472.123456789);
+            assert_eq!(result.prev()[1].data(), 0.0987654321);
+            assert_eq!(result.op(), Operations::Sub);
         }
     }
 
@@ -346,10 +1011,10 @@ mod val_ops {
             let v2: Val = Val::new(2.0);
             let result: Val = v1 * v2;
 
-            assert_eq!(result.data, 32.4);
-            assert_eq!(result.prev[0].data, 16.2);
-            assert_eq!(result.prev[1].data, 2.0);
-            assert_eq!(result.op, Operations::Mul);
+            assert_eq!(result.data(), 32.4);
+            assert_eq!(result.prev()[0].data(), 16.2);
+            assert_eq!(result.prev()[1].data(), 2.0);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -357,10 +1022,10 @@ mod val_ops {
             let v2: Val = Val::new(2.0);
             let result: Val = v2 * v1;
 
-            assert_eq!(result.data, 32.4);
-            assert_eq!(result.prev[0].data, 2.0);
-            assert_eq!(result.prev[1].data, 16.2);
-            assert_eq!(result.op, Operations::Mul);
+            assert_eq!(result.data(), 32.4);
+            assert_eq!(result.prev()[0].data(), 2.0);
+            assert_eq!(result.prev()[1].data(), 16.2);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -368,10 +1033,10 @@ mod val_ops {
             let v2: Val = Val::new(0.0);
             let result: Val = v2 * v1;
 
-            assert_eq!(result.data, 0.0);
-            assert_eq!(result.prev[0].data, 0.0);
-            assert_eq!(result.prev[1].data, 16.2);
-            assert_eq!(result.op, Operations::Mul);
+            assert_eq!(result.data(), 0.0);
+            assert_eq!(result.prev()[0].data(), 0.0);
+            assert_eq!(result.prev()[1].data(), 16.2);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -379,10 +1044,10 @@ mod val_ops {
             let v2: Val = Val::new(0.0);
             let result: Val = v1 * v2;
 
-            assert_eq!(result.data, 0.0);
-            assert_eq!(result.prev[0].data, 16.2);
-            assert_eq!(result.prev[1].data, 0.0);
-            assert_eq!(result.op, Operations::Mul);
+            assert_eq!(result.data(), 0.0);
+            assert_eq!(result.prev()[0].data(), 16.2);
+            assert_eq!(result.prev()[1].data(), 0.0);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -390,10 +1055,10 @@ mod val_ops {
             let v2: Val = Val::new(99.0987654321);
             let result: Val = v1 * v2;
 
-            assert!(approx_eq(result.data, 73_246.222069696));
-            assert_eq!(result.prev[0].data, 739.123456789);
-            assert_eq!(result.prev[1].data, 99.0987654321);
-            assert_eq!(result.op, Operations::Mul);
+            assert!(approx_eq(result.data(), 73_246.222069696));
+            assert_eq!(result.prev()[0].data(), 739.123456789);
+            assert_eq!(result.prev()[1].data(), 99.0987654321);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -401,10 +1066,10 @@ mod val_ops {
             let v2: Val = Val::new(99.0987654321);
             let result: Val = v2 * v1;
 
-            assert!(approx_eq(result.data, 73_246.222069696));
-            assert_eq!(result.prev[0].data, 99.0987654321);
-            assert_eq!(result.prev[1].data, 739.123456789);
-            assert_eq!(result.op, Operations::Mul);
+            assert!(approx_eq(result.data(), 73_246.222069696));
+            assert_eq!(result.prev()[0].data(), 99.0987654321);
+            assert_eq!(result.prev()[1].data(), 739.123456789);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -412,10 +1077,10 @@ mod val_ops {
             let v2: Val = Val::new(99.0987654321);
             let result: Val = v1 * v2;
 
-            assert!(approx_eq(result.data, -73_246.222069696));
-            assert_eq!(result.prev[0].data, -739.123456789);
-            assert_eq!(result.prev[1].data, 99.0987654321);
-            assert_eq!(result.op, Operations::Mul);
+            assert!(approx_eq(result.data(), -73_246.222069696));
+            assert_eq!(result.prev()[0].data(), -739.123456789);
+            assert_eq!(result.prev()[1].data(), 99.0987654321);
+            assert_eq!(result.op(), Operations::Mul);
         }
 
         {
@@ -423,10 +1088,10 @@ mod val_ops {
             let v2: Val = Val::new(-99.0987654321);
             let result: Val = v1 * v2;
 
-            assert!(approx_eq(result.data, -73_246.222069696));
-            assert_eq!(result.prev[0].data, 739.123456789);
-            assert_eq!(result.prev[1].data, -99.0987654321);
-            assert_eq!(result.op, Operations::Mul);
+            assert!(approx_eq(result.data(), -73_246.222069696));
+            assert_eq!(result.prev()[0].data(), 739.123456789);
+            assert_eq!(result.prev()[1].data(), -99.0987654321);
+            assert_eq!(result.op(), Operations::Mul);
         }
     }
 
@@ -438,11 +1103,11 @@ mod val_ops {
             let v3: Val = Val::new(-526.9637);
             let result: Val = v1 * v2 + v3;
 
-            assert!(approx_eq(result.data, -49.91115398));
-            assert_eq!(result.prev[0].op, Operations::Mul);
-            assert_eq!(result.prev[0].data, 40.0034 * 11.9253);
-            assert_eq!(result.prev[1].data, -526.9637);
-            assert_eq!(result.op, Operations::Add);
+            assert!(approx_eq(result.data(), -49.91115398));
+            assert_eq!(result.prev()[0].op(), Operations::Mul);
+            assert_eq!(result.prev()[0].data(), 40.0034 * 11.9253);
+            assert_eq!(result.prev()[1].data(), -526.9637);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -451,11 +1116,11 @@ mod val_ops {
             let v3: Val = Val::new(-526.9637);
             let result: Val = v2 * v1 + v3;
 
-            assert!(approx_eq(result.data, -49.91115398));
-            assert_eq!(result.prev[0].op, Operations::Mul);
-            assert_eq!(result.prev[0].data, 40.0034 * 11.9253);
-            assert_eq!(result.prev[1].data, -526.9637);
-            assert_eq!(result.op, Operations::Add);
+            assert!(approx_eq(result.data(), -49.91115398));
+            assert_eq!(result.prev()[0].op(), Operations::Mul);
+            assert_eq!(result.prev()[0].data(), 40.0034 * 11.9253);
+            assert_eq!(result.prev()[1].data(), -526.9637);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -464,11 +1129,11 @@ mod val_ops {
             let v3: Val = Val::new(-526.9637);
             let result: Val = v3 * v2 + v1;
 
-            assert!(approx_eq(result.data, -6244.19681161));
-            assert_eq!(result.prev[0].op, Operations::Mul);
-            assert_eq!(result.prev[0].data, -526.9637 * 11.9253);
-            assert_eq!(result.prev[1].data, 40.0034);
-            assert_eq!(result.op, Operations::Add);
+            assert!(approx_eq(result.data(), -6244.19681161));
+            assert_eq!(result.prev()[0].op(), Operations::Mul);
+            assert_eq!(result.prev()[0].data(), -526.9637 * 11.9253);
+            assert_eq!(result.prev()[1].data(), 40.0034);
+            assert_eq!(result.op(), Operations::Add);
         }
 
         {
@@ -481,34 +1146,34 @@ mod val_ops {
             let b: Val  = Val::new(6.7);
 
             let x1w1: Val = x1 * w1;
-            assert_eq!(x1w1.data, -6.0);
-            assert_eq!(x1w1.prev[0].data, 2.0);
-            assert_eq!(x1w1.prev[1].data, -3.0);
-            assert_eq!(x1w1.op, Operations::Mul);
+            assert_eq!(x1w1.data(), -6.0);
+            assert_eq!(x1w1.prev()[0].data(), 2.0);
+            assert_eq!(x1w1.prev()[1].data(), -3.0);
+            assert_eq!(x1w1.op(), Operations::Mul);
 
             let x2w2: Val = x2 * w2;
-            assert_eq!(x2w2.data, 0.0);
-            assert_eq!(x2w2.prev[0].data, 0.0);
-            assert_eq!(x2w2.prev[1].data, 1.0);
-            assert_eq!(x2w2.op, Operations::Mul);
+            assert_eq!(x2w2.data(), 0.0);
+            assert_eq!(x2w2.prev()[0].data(), 0.0);
+            assert_eq!(x2w2.prev()[1].data(), 1.0);
+            assert_eq!(x2w2.op(), Operations::Mul);
 
             let x1w1x2w2: Val = x1w1 + x2w2;
-            assert_eq!(x1w1x2w2.data, -6.0);
-            assert_eq!(x1w1x2w2.prev[0].data, -6.0);
-            assert_eq!(x1w1x2w2.prev[1].data, 0.0);
-            assert_eq!(x1w1x2w2.op, Operations::Add);
+            assert_eq!(x1w1x2w2.data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[0].data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[1].data(), 0.0);
+            assert_eq!(x1w1x2w2.op(), Operations::Add);
 
             let n: Val = x1w1x2w2 + b;
-            assert!(approx_eq(n.data, 0.7));
-            assert_eq!(n.prev[0].data, -6.0);
-            assert_eq!(n.prev[1].data, 6.7);
-            assert_eq!(n.op, Operations::Add);
+            assert!(approx_eq(n.data(), 0.7));
+            assert_eq!(n.prev()[0].data(), -6.0);
+            assert_eq!(n.prev()[1].data(), 6.7);
+            assert_eq!(n.op(), Operations::Add);
 
             let o: Val = n.tanh();
-            assert!(approx_eq(o.data, 0.6043677771171636));
-            assert_eq!(o.prev.len(), 1);
-            assert!(approx_eq(o.prev[0].data, 0.7));
-            assert_eq!(o.op, Operations::Tanh);
+            assert!(approx_eq(o.data(), 0.6043677771171636));
+            assert_eq!(o.prev().len(), 1);
+            assert!(approx_eq(o.prev()[0].data(), 0.7));
+            assert_eq!(o.op(), Operations::Tanh);
         }
 
         {
@@ -521,90 +1186,1013 @@ mod val_ops {
             let b: Val  = Val::new(8.0);
 
             let x1w1: Val = x1 * w1;
-            assert_eq!(x1w1.data, -6.0);
-            assert_eq!(x1w1.prev[0].data, 2.0);
-            assert_eq!(x1w1.prev[1].data, -3.0);
-            assert_eq!(x1w1.op, Operations::Mul);
+            assert_eq!(x1w1.data(), -6.0);
+            assert_eq!(x1w1.prev()[0].data(), 2.0);
+            assert_eq!(x1w1.prev()[1].data(), -3.0);
+            assert_eq!(x1w1.op(), Operations::Mul);
 
             let x2w2: Val = x2 * w2;
-            assert_eq!(x2w2.data, 0.0);
-            assert_eq!(x2w2.prev[0].data, 0.0);
-            assert_eq!(x2w2.prev[1].data, 1.0);
-            assert_eq!(x2w2.op, Operations::Mul);
+            assert_eq!(x2w2.data(), 0.0);
+            assert_eq!(x2w2.prev()[0].data(), 0.0);
+            assert_eq!(x2w2.prev()[1].data(), 1.0);
+            assert_eq!(x2w2.op(), Operations::Mul);
 
             let x1w1x2w2: Val = x1w1 + x2w2;
-            assert_eq!(x1w1x2w2.data, -6.0);
-            assert_eq!(x1w1x2w2.prev[0].data, -6.0);
-            assert_eq!(x1w1x2w2.prev[1].data, 0.0);
-            assert_eq!(x1w1x2w2.op, Operations::Add);
+            assert_eq!(x1w1x2w2.data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[0].data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[1].data(), 0.0);
+            assert_eq!(x1w1x2w2.op(), Operations::Add);
 
             let n: Val = x1w1x2w2 + b;
-            assert!(approx_eq(n.data, 2.0));
-            assert_eq!(n.prev[0].data, -6.0);
-            assert_eq!(n.prev[1].data, 8.0);
-            assert_eq!(n.op, Operations::Add);
+            assert!(approx_eq(n.data(), 2.0));
+            assert_eq!(n.prev()[0].data(), -6.0);
+            assert_eq!(n.prev()[1].data(), 8.0);
+            assert_eq!(n.op(), Operations::Add);
 
             let o: Val = n.tanh();
-            assert!(approx_eq(o.data, 0.9640275800758169));
-            assert_eq!(o.prev.len(), 1);
-            assert!(approx_eq(o.prev[0].data, 2.0));
-            assert_eq!(o.op, Operations::Tanh);
+            assert!(approx_eq(o.data(), 0.9640275800758169));
+            assert_eq!(o.prev().len(), 1);
+            assert!(approx_eq(o.prev()[0].data(), 2.0));
+            assert_eq!(o.op(), Operations::Tanh);
         }
     }
 
     #[test]
-    fn prp() {
+    fn relu() {
         {
-            let x1: Val = Val::new(2.0);
-            let x2: Val = Val::new(0.0);
+            let v1: Val = Val::new(3.25);
+            let o: Val = v1.clone().relu();
+
+            assert_eq!(o.data(), 3.25);
+            assert_eq!(o.prev().len(), 1);
+            assert_eq!(o.op(), Operations::Relu);
+
+            o.backward();
+            assert_eq!(v1.grad(), 1.0);
+        }
+
+        {
+            let v1: Val = Val::new(-3.25);
+            let o: Val = v1.clone().relu();
+
+            assert_eq!(o.data(), 0.0);
+            assert_eq!(o.op(), Operations::Relu);
+
+            o.backward();
+            assert_eq!(v1.grad(), 0.0);
+        }
 
+        {
+            let x1: Val = Val::new(2.0);
             let w1: Val = Val::new(-3.0);
-            let w2: Val = Val::new(1.0);
+            let b: Val  = Val::new(7.5);
 
-            let b: Val  = Val::new(6.8813735870195432);
+            let n: Val = x1.clone() * w1.clone() + b.clone();
+            let o: Val = n.relu();
+            assert!(approx_eq(o.data(), 1.5));
 
-            let x1w1: Val = x1 * w1;
-            assert_eq!(x1w1.data, -6.0);
-            assert_eq!(x1w1.prev[0].data, 2.0);
-            assert_eq!(x1w1.prev[1].data, -3.0);
-            assert_eq!(x1w1.op, Operations::Mul);
+            o.backward();
+            assert!(approx_eq(b.grad(), 1.0));
+            assert!(approx_eq(x1.grad(), -3.0));
+            assert!(approx_eq(w1.grad(), 2.0));
+        }
+    }
 
-            let x2w2: Val = x2 * w2;
-            assert_eq!(x2w2.data, 0.0);
-            assert_eq!(x2w2.prev[0].data, 0.0);
-            assert_eq!(x2w2.prev[1].data, 1.0);
-            assert_eq!(x2w2.op, Operations::Mul);
+    #[test]
+    fn sigmoid() {
+        {
+            let v1: Val = Val::new(0.0);
+            let o: Val = v1.clone().sigmoid();
 
-            let x1w1x2w2: Val = x1w1 + x2w2;
-            assert_eq!(x1w1x2w2.data, -6.0);
-            assert_eq!(x1w1x2w2.prev[0].data, -6.0);
-            assert_eq!(x1w1x2w2.prev[1].data, 0.0);
-            assert_eq!(x1w1x2w2.op, Operations::Add);
+            assert_eq!(o.data(), 0.5);
+            assert_eq!(o.prev().len(), 1);
+            assert_eq!(o.op(), Operations::Sigmoid);
 
-            let n: Val = x1w1x2w2 + b;
-            assert!(approx_eq(n.data, 0.8813735870195432));
-            assert_eq!(n.prev[0].data, -6.0);
-            assert_eq!(n.prev[1].data, 6.8813735870195432);
-            assert_eq!(n.op, Operations::Add);
+            o.backward();
+            assert!(approx_eq(v1.grad(), 0.25));
+        }
 
-            let o: Val = n.tanh();
-            assert!(approx_eq(o.data, 0.7071067811865477));
-            assert_eq!(o.grad, 1.0);
-            assert_eq!(o.prev.len(), 1);
-            assert!(approx_eq(o.prev[0].data, 0.8813735870195432));
-            assert_eq!(o.op, Operations::Tanh);
+        {
+            let v1: Val = Val::new(2.0);
+            let o: Val = v1.clone().sigmoid();
+
+            assert!(approx_eq(o.data(), 0.8807970779778823));
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), 0.10499358540350662));
+        }
+
+        {
+            let v1: Val = Val::new(-2.0);
+            let o: Val = v1.clone().sigmoid();
+
+            assert!(approx_eq(o.data(), 0.11920292202211755));
 
             o.backward();
+            assert!(approx_eq(v1.grad(), 0.10499358540350662));
+        }
+
+        {
+            let v1: Val = Val::new(-1000.0);
+            let v2: Val = Val::new(1000.0);
+
+            assert_eq!(v1.sigmoid().data(), 0.0);
+            assert_eq!(v2.sigmoid().data(), 1.0);
+        }
+    }
+
+    #[test]
+    fn leaky_relu_elu() {
+        {
+            let v1: Val = Val::new(-2.0);
+            let v2: Val = Val::new(3.0);
+            let o1: Val = v1.clone().leaky_relu(0.1);
+            let o2: Val = v2.clone().leaky_relu(0.1);
+
+            assert!(approx_eq(o1.data(), -0.2));
+            assert_eq!(o2.data(), 3.0);
+            assert_eq!(o1.op(), Operations::LeakyRelu);
+
+            o1.backward();
+            o2.backward();
+            assert!(approx_eq(v1.grad(), 0.1));
+            assert_eq!(v2.grad(), 1.0);
+        }
+
+        {
+            let v1: Val = Val::new(-1.0);
+            let v2: Val = Val::new(0.5);
+            let o1: Val = v1.clone().elu(2.0);
+            let o2: Val = v2.clone().elu(2.0);
+
+            assert!(approx_eq(o1.data(), 2.0 * ((-1.0_f64).exp() - 1.0)));
+            assert_eq!(o2.data(), 0.5);
+            assert_eq!(o1.op(), Operations::Elu);
+
+            // alpha e^x below zero, which is also output + alpha
+            o1.backward();
+            o2.backward();
+            assert!(approx_eq(v1.grad(), 2.0 * (-1.0_f64).exp()));
+            assert_eq!(v2.grad(), 1.0);
+        }
+
+        {
+            let f = |x: &[Val]| -> Val { &x[0].clone().leaky_relu(0.3) * &x[1].clone().elu(1.5) };
+            assert!(grad_check(f, &[-0.7, -1.2], 1e-6).iter().all(|c| c.rel_error < 1e-6));
+            assert!(grad_check(f, &[0.4, 0.9], 1e-6).iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn gelu() {
+        {
+            let v1: Val = Val::new(1.0);
+            let o: Val = v1.clone().gelu();
+
+            assert!(approx_eq(o.data(), 0.8411919906082768));
+            assert_eq!(o.op(), Operations::Gelu);
+            assert_eq!(Val::new(0.0).gelu().data(), 0.0);
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), 1.0829640838457826));
+        }
+
+        {
+            // Close to relu far from the origin, without overflowing
+            assert_eq!(Val::new(50.0).gelu().data(), 50.0);
+            assert_eq!(Val::new(-50.0).gelu().data(), 0.0);
+
+            let f = |x: &[Val]| -> Val { &x[0].clone().gelu() * &x[1].clone().gelu() };
+            assert!(grad_check(f, &[-1.3, 0.6], 1e-6).iter().all(|c| c.rel_error < 1e-6));
+            assert!(grad_check(f, &[2.5, -0.05], 1e-6).iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn softplus_silu() {
+        {
+            let v1: Val = Val::new(0.0);
+            let o: Val = v1.clone().softplus();
+
+            assert!(approx_eq(o.data(), 2.0_f64.ln()));
+            assert_eq!(o.op(), Operations::Softplus);
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), 0.5));
+
+            // Stable at both ends: x above, e^x below
+            assert_eq!(Val::new(1000.0).softplus().data(), 1000.0);
+            assert_eq!(Val::new(-1000.0).softplus().data(), 0.0);
+            assert!(approx_eq(Val::new(-30.0).softplus().data(), (-30.0_f64).exp()));
+        }
+
+        {
+            let v1: Val = Val::new(2.0);
+            let o: Val = v1.clone().silu();
+            let s: f64 = 1.0 / (1.0 + (-2.0_f64).exp());
+
+            assert!(approx_eq(o.data(), 2.0 * s));
+            assert_eq!(o.op(), Operations::Silu);
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), s * (1.0 + 2.0 * (1.0 - s))));
+            assert_eq!(Val::new(-1000.0).silu().data(), 0.0);
+        }
+
+        {
+            let f = |x: &[Val]| -> Val { &x[0].clone().softplus() * &x[1].clone().silu() };
+            for point in [[-2.0, 0.7], [3.0, -1.5], [0.1, 10.0]].iter() {
+                assert!(grad_check(f, point, 1e-6).iter().all(|c| c.rel_error < 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn abs_sign() {
+        {
+            let v1: Val = Val::new(-3.0);
+            let v2: Val = Val::new(2.0);
+            let v3: Val = Val::new(0.0);
+            let o: Val = v1.clone().abs() + v2.clone().abs() + v3.clone().abs();
+
+            assert_eq!(o.data(), 5.0);
+
+            o.backward();
+            assert_eq!(v1.grad(), -1.0);
+            assert_eq!(v2.grad(), 1.0);
+            assert_eq!(v3.grad(), 0.0);
+        }
+
+        {
+            let v1: Val = Val::new(-0.5);
+            let s: Val = v1.clone().sign();
+
+            assert_eq!(s.data(), -1.0);
+            assert_eq!(s.op(), Operations::Sign);
+            assert_eq!(Val::new(4.0).sign().data(), 1.0);
+            assert_eq!(Val::new(0.0).sign().data(), 0.0);
+
+            s.backward();
+            assert_eq!(v1.grad(), 0.0);
+        }
+
+        {
+            // An L1 penalty written directly
+            let w: Vec<Val> = vec![Val::new(0.5), Val::new(-2.0)];
+            let l1: Val = w[0].clone().abs() + w[1].clone().abs();
+            l1.backward();
+            assert_eq!((w[0].grad(), w[1].grad()), (1.0, -1.0));
+            assert_eq!(Val::new(-1.5).abs().op(), Operations::Abs);
+        }
+    }
+
+    #[test]
+    fn scl() {
+        {
+            let v1: Val = Val::new(3.0);
+            let result: Val = v1.clone() * 2.0 + 1.0;
+
+            assert_eq!(result.data(), 7.0);
+            assert_eq!(result.op(), Operations::Add);
+            assert_eq!(result.prev()[0].op(), Operations::Mul);
+            assert_eq!(result.prev()[1].data(), 1.0);
+
+            result.backward();
+            assert_eq!(v1.grad(), 2.0);
+        }
+
+        {
+            let v1: Val = Val::new(3.0);
+            let result: Val = 1.0 + 2.0 * v1.clone();
+
+            assert_eq!(result.data(), 7.0);
+            assert_eq!(result.prev()[0].data(), 1.0);
+            assert_eq!(result.prev()[1].prev()[0].data(), 2.0);
+
+            result.backward();
+            assert_eq!(v1.grad(), 2.0);
+            assert_eq!(result.prev()[0].grad(), 0.0);
+        }
+
+        {
+            let v1: Val = Val::new(-4.5);
+            let result: Val = v1.clone() - 0.5;
+
+            assert_eq!(result.data(), -5.0);
+            assert_eq!(result.op(), Operations::Sub);
+
+            result.backward();
+            assert_eq!(v1.grad(), 1.0);
+        }
+
+        {
+            let v1: Val = Val::new(-4.5);
+            let result: Val = 0.5 - v1.clone();
+
+            assert_eq!(result.data(), 5.0);
+            assert_eq!(result.prev()[0].data(), 0.5);
+            assert_eq!(result.prev()[1].data(), -4.5);
+
+            result.backward();
+            assert_eq!(v1.grad(), -1.0);
+        }
+
+        {
+            let v1: Val = Val::new(10.0);
+            let result: Val = -v1.clone();
+
+            assert_eq!(result.data(), -10.0);
+
+            result.backward();
+            assert_eq!(v1.grad(), -1.0);
+        }
+    }
+
+    #[test]
+    fn refs() {
+        {
+            let x: Val = Val::new(3.0);
+            let y: Val = &x * &x;
+
+            assert_eq!(y.data(), 9.0);
+            assert_eq!(x.data(), 3.0);
+            assert_eq!(y.op(), Operations::Mul);
+        }
+
+        {
+            let a: Val = Val::new(2.5);
+            let b: Val = Val::new(-1.5);
+            let sum: Val = &a + &b;
+            let dif: Val = &a - &b;
+            let neg: Val = -&a;
+
+            assert_eq!(sum.data(), 1.0);
+            assert_eq!(dif.data(), 4.0);
+            assert_eq!(neg.data(), -2.5);
+            assert_eq!(a.data(), 2.5);
+            assert_eq!(b.data(), -1.5);
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = Val::new(-3.0);
+            let c: Val = Val::new(1.0);
+            let result: Val = &(&a * &b) + &c;
+
+            result.backward();
+            assert_eq!(a.grad(), -3.0);
+            assert_eq!(b.grad(), 2.0);
+            assert_eq!(c.grad(), 1.0);
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let result: Val = 1.0 - &a * 3.0 + 0.5;
+
+            assert_eq!(result.data(), -4.5);
+            assert_eq!(a.data(), 2.0);
+        }
+    }
+
+    #[test]
+    fn pow() {
+        {
+            let v1: Val = Val::new(3.0);
+            let o: Val = v1.clone().pow(2.0);
+
+            assert_eq!(o.data(), 9.0);
+            assert_eq!(o.prev().len(), 1);
+            assert_eq!(o.op(), Operations::Pow);
+
+            o.backward();
+            assert_eq!(v1.grad(), 6.0);
+        }
+
+        {
+            let v1: Val = Val::new(4.0);
+            let o: Val = v1.clone().pow(-0.5);
+
+            assert!(approx_eq(o.data(), 0.5));
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), -0.0625));
+        }
+    }
+
+    #[test]
+    fn exp_log() {
+        {
+            let v1: Val = Val::new(2.0);
+            let o: Val = v1.clone().exp();
+
+            assert!(approx_eq(o.data(), 2.0_f64.exp()));
+            assert_eq!(o.op(), Operations::Exp);
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), 2.0_f64.exp()));
+        }
+
+        {
+            let v1: Val = Val::new(4.0);
+            let o: Val = v1.clone().log();
+
+            assert!(approx_eq(o.data(), 4.0_f64.ln()));
+            assert_eq!(o.op(), Operations::Log);
+
+            o.backward();
+            assert!(approx_eq(v1.grad(), 0.25));
+        }
+    }
+
+    #[test]
+    fn acc() {
+        {
+            // b = a + a
+            let a: Val = Val::new(3.0);
+            let b: Val = &a + &a;
+
+            b.backward();
+            assert_eq!(b.data(), 6.0);
+            assert_eq!(a.grad(), 2.0);
+        }
+
+        {
+            // b = a * a
+            let a: Val = Val::new(3.0);
+            let b: Val = &a * &a;
+
+            b.backward();
+            assert_eq!(a.grad(), 6.0);
+        }
+
+        {
+            // Shared intermediate: d = c + c with c = a * a, so dd/da = 4a
+            let a: Val = Val::new(-2.0);
+            let c: Val = &a * &a;
+            let d: Val = &c + &c;
+
+            d.backward();
+            assert_eq!(c.grad(), 2.0);
+            assert_eq!(a.grad(), -8.0);
+        }
+
+        {
+            // Diamond: e = (a * b) + (a + b), de/da = b + 1, de/db = a + 1
+            let a: Val = Val::new(-2.0);
+            let b: Val = Val::new(3.0);
+            let d: Val = &a * &b;
+            let c: Val = &a + &b;
+            let e: Val = &d + &c;
+
+            e.backward();
+            assert_eq!(e.data(), -5.0);
+            assert_eq!(a.grad(), 4.0);
+            assert_eq!(b.grad(), -1.0);
+        }
+
+        {
+            // f = (a + b) * (a - b) = a^2 - b^2
+            let a: Val = Val::new(1.5);
+            let b: Val = Val::new(-0.5);
+            let f: Val = (&a + &b) * (&a - &b);
+
+            f.backward();
+            assert!(approx_eq(a.grad(), 3.0));
+            assert!(approx_eq(b.grad(), 1.0));
+        }
+
+        {
+            // Activations feeding several consumers
+            let a: Val = Val::new(0.3);
+            let t: Val = a.clone().tanh();
+            let o: Val = &(&t * &t) + &t;
+
+            o.backward();
+            let th: f64 = 0.3_f64.tanh();
+            assert!(approx_eq(t.grad(), 2.0 * th + 1.0));
+            assert!(approx_eq(a.grad(), (2.0 * th + 1.0) * (1.0 - th * th)));
+        }
+    }
+
+    #[test]
+    fn zero() {
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = Val::new(-0.5);
+            let c: Val = &a * &b;
+            let o: Val = (&c + &a).tanh();
+
+            o.backward();
+            assert!(a.grad() != 0.0);
+            assert!(b.grad() != 0.0);
+
+            o.zero_grad();
+            assert_eq!(o.grad(), 0.0);
+            assert_eq!(c.grad(), 0.0);
+            assert_eq!(a.grad(), 0.0);
+            assert_eq!(b.grad(), 0.0);
+        }
+
+        {
+            // Without a reset, a second pass accumulates on top of the first
+            let a: Val = Val::new(2.0);
+            let o: Val = &a * 3.0;
+
+            o.backward();
+            o.backward();
+            assert_eq!(a.grad(), 6.0);
+
+            o.zero_grad();
+            o.backward();
+            assert_eq!(a.grad(), 3.0);
+        }
+    }
+
+    #[test]
+    fn dot() {
+        {
+            let v1: Val = Val::new(2.0);
+            let dot: String = v1.to_dot();
+
+            assert!(dot.starts_with("digraph G {"));
+            assert!(dot.contains("n0 [shape=record, label=\"{ data 2.0000 | grad 0.0000 }\"];"));
+            assert!(!dot.contains("->"));
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = Val::new(-3.0);
+            let o: Val = (&a * &b).tanh();
+            o.backward();
+            let dot: String = o.to_dot();
+
+            // a, b, a*b, tanh
+            assert_eq!(dot.matches("shape=record").count(), 4);
+            assert!(dot.contains("n2op [label=\"*\"];"));
+            assert!(dot.contains("n3op [label=\"Tanh\"];"));
+            assert!(dot.contains("n0 -> n2op;"));
+            assert!(dot.contains("n1 -> n2op;"));
+            assert!(dot.contains("n2 -> n3op;"));
+            assert!(dot.contains("n3op -> n3;"));
+            assert!(dot.contains("grad 1.0000"));
+        }
+
+        {
+            // A shared node is drawn once, with one edge per use
+            let a: Val = Val::new(1.0);
+            let o: Val = &a + &a;
+            let dot: String = o.to_dot();
+
+            assert_eq!(dot.matches("shape=record").count(), 2);
+            assert_eq!(dot.matches("n0 -> n1op;").count(), 2);
+        }
+
+        {
+            let path = std::env::temp_dir().join("rusty_nn_dot_test.dot");
+            let path: &str = path.to_str().unwrap();
+            let o: Val = Val::new(1.0) + Val::new(2.0);
+
+            o.write_dot(path).unwrap();
+            assert_eq!(std::fs::read_to_string(path).unwrap(), o.to_dot());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn topo() {
+        {
+            let v1: Val = Val::new(1.5);
+            let nodes: Vec<Val> = v1.topo_iter().collect();
+
+            assert_eq!(nodes.len(), 1);
+            assert_eq!(nodes[0].data(), 1.5);
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = Val::new(-3.0);
+            let c: Val = &a * &b;
+            let d: Val = &c + &a;
+            let o: Val = d.clone().tanh();
+            let nodes: Vec<Val> = o.topo_iter().collect();
+
+            // Shared `a` is only visited once
+            assert_eq!(nodes.len(), 5);
+            assert_eq!(nodes.last().unwrap().op(), Operations::Tanh);
+
+            // Every node comes after all of its children
+            for (i, n) in nodes.iter().enumerate() {
+                for child in n.prev().iter() {
+                    let j: usize = nodes.iter().position(|m| Rc::ptr_eq(&m.0, &child.0)).unwrap();
+                    assert!(j < i);
+                }
+            }
+        }
+
+        {
+            let a: Val = Val::new(1.0);
+            let o: Val = (&a + 1.0) * (&a + 2.0) * 3.0;
+            let adds: usize = o.topo_iter().filter(|n| n.op() == Operations::Add).count();
+
+            assert_eq!(adds, 2);
+        }
+    }
+
+    #[test]
+    fn f32s() {
+        {
+            let a: Val<f32> = Val::new(2.0);
+            let b: Val<f32> = Val::new(-3.0);
+            let o: Val<f32> = (&a * &b + 1.0).tanh();
+
+            assert!((o.data() - (-5.0_f32).tanh()).abs() < 1e-6);
+
+            o.backward();
+            let d: f32 = 1.0 - o.data() * o.data();
+            assert!((a.grad() - d * -3.0).abs() < 1e-6);
+            assert!((b.grad() - d * 2.0).abs() < 1e-6);
+        }
+
+        {
+            let a: Val<f32> = Val::new(0.5);
+            let o: Val<f32> = 2.0 * a.clone().sigmoid() - 1.0_f32 * a.clone().exp().log();
+
+            o.backward();
+            let s: f32 = 1.0 / (1.0 + (-0.5_f32).exp());
+            assert!((a.grad() - (2.0 * s * (1.0 - s) - 1.0)).abs() < 1e-6);
+        }
+
+        {
+            assert_eq!(format!("{}", Val::new(1.5_f32)), "Data: 1.5, Grad: 0, Op: Non");
+        }
+    }
+
+    #[test]
+    fn prp() {
+        {
+            let x1: Val = Val::new(2.0);
+            let x2: Val = Val::new(0.0);
+
+            let w1: Val = Val::new(-3.0);
+            let w2: Val = Val::new(1.0);
+
+            let b: Val  = Val::new(6.8813735870195432);
+
+            let x1w1: Val = x1.clone() * w1.clone();
+            assert_eq!(x1w1.data(), -6.0);
+            assert_eq!(x1w1.prev()[0].data(), 2.0);
+            assert_eq!(x1w1.prev()[1].data(), -3.0);
+            assert_eq!(x1w1.op(), Operations::Mul);
+
+            let x2w2: Val = x2.clone() * w2.clone();
+            assert_eq!(x2w2.data(), 0.0);
+            assert_eq!(x2w2.prev()[0].data(), 0.0);
+            assert_eq!(x2w2.prev()[1].data(), 1.0);
+            assert_eq!(x2w2.op(), Operations::Mul);
+
+            let x1w1x2w2: Val = x1w1.clone() + x2w2.clone();
+            assert_eq!(x1w1x2w2.data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[0].data(), -6.0);
+            assert_eq!(x1w1x2w2.prev()[1].data(), 0.0);
+            assert_eq!(x1w1x2w2.op(), Operations::Add);
+
+            let n: Val = x1w1x2w2.clone() + b.clone();
+            assert!(approx_eq(n.data(), 0.8813735870195432));
+            assert_eq!(n.prev()[0].data(), -6.0);
+            assert_eq!(n.prev()[1].data(), 6.8813735870195432);
+            assert_eq!(n.op(), Operations::Add);
+
+            let o: Val = n.clone().tanh();
+            assert!(approx_eq(o.data(), 0.7071067811865477));
+            assert_eq!(o.grad(), 0.0);
+            assert_eq!(o.prev().len(), 1);
+            assert!(approx_eq(o.prev()[0].data(), 0.8813735870195432));
+            assert_eq!(o.op(), Operations::Tanh);
+
+            o.backward();
+
+            assert_eq!(o.grad(), 1.0);
+
+            assert!(approx_eq(n.grad(), 0.5));
+            assert!(approx_eq(x1w1x2w2.grad(), 0.5));
+            assert!(approx_eq(b.grad(), 0.5));
+            assert!(approx_eq(x1w1.grad(), 0.5));
+            assert!(approx_eq(x2w2.grad(), 0.5));
+            assert!(approx_eq(x1.grad(), -1.5));
+            assert!(approx_eq(w1.grad(), 1.0));
+            assert!(approx_eq(x2.grad(), 0.5));
+            assert!(approx_eq(w2.grad(), 0.0));
+        }
+    }
+
+    #[test]
+    fn tree() {
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = Val::new(-3.0);
+            let c: Val = &a * &b;
+            let d: Val = &c + 10.0;
+            d.backward();
+
+            assert_eq!(format!("{}", d), "Data: 4, Grad: 1, Op: +");
+            assert_eq!(format!("{:#}", d), [
+                "Data: 4, Grad: 1, Op: +",
+                "  Data: -6, Grad: 1, Op: *",
+                "    Data: 2, Grad: -3, Op: Non",
+                "    Data: -3, Grad: 2, Op: Non",
+                "  Data: 10, Grad: 0, Op: Non"
+            ].join("\n"));
+            assert_eq!(format!("{:?}", d), format!("{}", d));
+            assert_eq!(format!("{:#?}", d), format!("{:#}", d));
+        }
+
+        {
+            // A shared node is expanded once
+            let a: Val = Val::new(1.0);
+            let b: Val = a.clone().exp();
+            let c: Val = &b * &b;
+
+            assert_eq!(format!("{:#}", c).lines().count(), 4);
+            assert!(format!("{:#}", c).lines().last().unwrap().ends_with("Op: Exp (shared)"));
+        }
+
+        {
+            assert_eq!(format!("{:#}", Val::new(0.5)), "Data: 0.5, Grad: 0, Op: Non");
+        }
+
+        {
+            // Setting the root's grad by hand leaves the rest of the tree at zero
+            let result: Val = Val::new(8.0) * Val::new(2.0) + Val::new(-2.5);
+            result.set_grad(1.0);
+            assert_eq!(format!("{:#}", result), [
+                "Data: 13.5, Grad: 1, Op: +",
+                "  Data: 16, Grad: 0, Op: *",
+                "    Data: 8, Grad: 0, Op: Non",
+                "    Data: 2, Grad: 0, Op: Non",
+                "  Data: -2.5, Grad: 0, Op: Non"
+            ].join("\n"));
+        }
+    }
+
+    #[test]
+    fn detach() {
+        {
+            let a: Val = Val::new(3.0);
+            let b: Val = &a * &a;
+            let c: Val = b.detach();
+
+            assert_eq!(c.data(), 9.0);
+            assert_eq!(c.op(), Operations::Non);
+            assert!(c.prev().is_empty());
+
+            // Gradient only reaches `a` through the attached factor
+            let out: Val = &b * &c;
+            out.backward();
+            assert_eq!(a.grad(), 2.0 * 3.0 * 9.0);
+            assert_eq!(c.grad(), 9.0);
+        }
+    }
+
+    #[test]
+    fn requires_grad() {
+        {
+            let (a, b): (Val, Val) = (Val::new(2.0), Val::new(3.0));
+            a.set_requires_grad(false);
+            assert!(!a.requires_grad() && b.requires_grad());
+
+            let c: Val = &a * &b;
+            let d: Val = a.clone().exp();
+            assert!(c.requires_grad());
+            assert!(!d.requires_grad());
+
+            let out: Val = &c + &d;
+            out.backward();
+            assert_eq!(a.grad(), 0.0);
+            assert_eq!(b.grad(), 2.0);
+            // Built only from frozen leaves, so skipped
+            assert_eq!(d.grad(), 0.0);
+        }
+
+        {
+            // The flag is read when nodes are built, not at backward
+            let a: Val = Val::new(1.0);
+            let b: Val = a.clone().tanh();
+            a.set_requires_grad(false);
+            b.backward();
+            assert_eq!(a.grad(), 0.0);
+
+            a.set_requires_grad(true);
+            b.backward();
+            assert!(approx_eq(a.grad(), 1.0 - 1.0_f64.tanh().powi(2)));
+        }
+    }
+
+    #[test]
+    fn backward_options() {
+        {
+            // Two heads on a shared intermediate: the leaf gets the sum
+            let x: Val = Val::new(0.5);
+            let h: Val = (&x * 3.0).tanh();
+            let head1: Val = &h * &h;
+            let head2: Val = h.clone().exp();
+
+            head1.backward();
+            head2.backward();
+            let dh: f64 = 2.0 * h.data() + h.data().exp();
+            assert!(approx_eq(x.grad(), dh * 3.0 * (1.0 - h.data().powi(2))));
+            assert!(approx_eq(h.grad(), h.data().exp()));
+
+            // Without accumulating, only the last pass counts
+            head1.backward_with(BackwardOptions::new().accumulate(false));
+            assert!(approx_eq(x.grad(), 2.0 * h.data() * 3.0 * (1.0 - h.data().powi(2))));
+            assert_eq!(BackwardOptions::default(), BackwardOptions::new());
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = &(&a * &a) + 1.0;
+            b.backward_with(BackwardOptions::new().retain_graph(false));
+            assert_eq!(a.grad(), 4.0);
+
+            // The values and ops stay, the children go
+            assert_eq!(b.data(), 5.0);
+            assert_eq!(b.op(), Operations::Add);
+            assert!(b.prev().is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "freed")]
+    fn backward_freed() {
+        let a: Val = Val::new(2.0);
+        let b: Val = a.clone().tanh();
+        b.backward_with(BackwardOptions::new().retain_graph(false));
+        b.backward();
+    }
+
+    #[test]
+    fn constants() {
+        {
+            let (w, x): (Val, Val) = (Val::param(0.5), Val::constant(2.0));
+            assert!(w.requires_grad() && !x.requires_grad());
+
+            // (x² + 1) only involves the constant, so it's skipped
+            let sq: Val = &(&x * &x) + 1.0;
+            let y: Val = (&w * &sq).tanh();
+            y.backward();
+
+            assert!(approx_eq(w.grad(), 5.0 * (1.0 - 2.5_f64.tanh().powi(2))));
+            assert_eq!(x.grad(), 0.0);
+            assert_eq!(sq.grad(), 0.0);
+            assert!(!sq.requires_grad());
+        }
+
+        {
+            // A constant has no gradient to set or add to
+            let c: Val = Val::constant(3.0);
+            c.set_grad(5.0);
+            c.add_grad(2.0);
+            assert_eq!(c.grad(), 0.0);
+            c.backward();
+            assert_eq!(c.grad(), 0.0);
+
+            let w: Val = Val::param(2.0);
+            let y: Val = &w * &c;
+            y.backward();
+            assert_eq!(w.grad(), 3.0);
+            assert!(c.0.borrow().grad.is_none());
+
+            // Freezing drops a leaf's gradient, and unfreezing starts it afresh
+            w.set_requires_grad(false);
+            w.add_grad(1.0);
+            assert_eq!(w.grad(), 0.0);
+            w.set_requires_grad(true);
+            w.add_grad(1.0);
+            assert_eq!(w.grad(), 1.0);
+        }
+    }
+
+    #[test]
+    fn deep_clone() {
+        {
+            let (w, x): (Val, Val) = (Val::new(0.5), Val::constant(2.0));
+            let h: Val = (&w * &x).tanh();
+            let y: Val = &(&h * &h) + &w;
+
+            let shared: Val = y.deep_clone(true);
+            assert_eq!(shared.data(), y.data());
+            assert_eq!(shared.op(), Operations::Add);
+            assert_eq!(shared.topo_iter().count(), y.topo_iter().count());
+            assert!(!Rc::ptr_eq(&shared.0, &y.0));
+
+            // Both graphs add into the one w
+            y.backward();
+            let g: f64 = w.grad();
+            shared.backward();
+            assert!(approx_eq(w.grad(), 2.0 * g));
+            assert_eq!(h.grad(), 2.0 * h.data());
+
+            let copied: Val = y.deep_clone(false);
+            copied.backward();
+            assert!(approx_eq(w.grad(), 2.0 * g));
+            let leaves: Vec<Val> = copied.topo_iter().filter(|v| v.op() == Operations::Non).collect();
+            assert_eq!(leaves.len(), 2);
+            assert!(leaves.iter().all(|l| !Rc::ptr_eq(&l.0, &w.0) && !Rc::ptr_eq(&l.0, &x.0)));
+            assert!(leaves.iter().any(|l| l.data() == 0.5 && approx_eq(l.grad(), g)));
+            assert!(leaves.iter().any(|l| l.data() == 2.0 && !l.requires_grad() && l.grad() == 0.0));
+        }
+    }
+
+    #[test]
+    fn no_grad_scope() {
+        {
+            let a: Val = Val::new(2.0);
+            let b: Val = no_grad(|| (&a * 3.0).tanh() + a.clone());
+
+            assert_eq!(b.data(), 6.0_f64.tanh() + 2.0);
+            assert_eq!(b.op(), Operations::Non);
+            assert!(b.prev().is_empty());
+
+            b.backward();
+            assert_eq!(a.grad(), 0.0);
+        }
+
+        {
+            assert!(is_grad_enabled());
+            no_grad(|| {
+                assert!(!is_grad_enabled());
+                no_grad(|| assert!(!is_grad_enabled()));
+                assert!(!is_grad_enabled());
+            });
+            assert!(is_grad_enabled());
+
+            let caught = std::panic::catch_unwind(|| no_grad(|| panic!("inside")));
+            assert!(caught.is_err());
+            assert!(is_grad_enabled());
+
+            let c: Val = &Val::new(1.0) + 1.0;
+            assert_eq!(c.prev().len(), 2);
+        }
+    }
+
+    #[test]
+    fn assign() {
+        {
+            // Accumulating in a loop keeps every step in the graph
+            let xs: Vec<Val> = vec![Val::new(1.0), Val::new(2.0), Val::new(3.0)];
+            let w: Val = Val::new(0.5);
+            let mut total: Val = Val::new(0.0);
+            for x in xs.iter() {
+                total += x * &w;
+            }
+            total -= 1.0;
+            total *= &w;
+
+            assert_eq!(total.data(), (3.0 - 1.0) * 0.5);
+            assert_eq!(total.op(), Operations::Mul);
+
+            total.backward();
+            // d/dw of (6w - 1) w = 12w - 1
+            assert_eq!(w.grad(), 5.0);
+            assert_eq!(xs[0].grad(), 0.25);
+        }
+
+        {
+            let a: Val = Val::new(2.0);
+            let mut b: Val = a.clone();
+            b *= 3.0;
+            b += Val::new(1.0);
+            b -= &a;
+
+            assert_eq!(b.data(), 5.0);
+            assert_eq!(a.data(), 2.0);
+
+            b.backward();
+            assert_eq!(a.grad(), 2.0);
+        }
+    }
+
+    #[test]
+    fn deep() {
+        {
+            // Recursion at this depth would overflow the test thread's stack
+            let x: Val = Val::new(1.0);
+            let mut y: Val = x.clone();
+            for _ in 0..1_000_000 {
+                y = &y + &x;
+            }
+
+            assert_eq!(y.data(), 1_000_001.0);
+            assert_eq!(y.topo_iter().count(), 1_000_001);
+
+            y.backward();
+            assert_eq!(x.grad(), 1_000_001.0);
+
+            y.zero_grad();
+            assert_eq!(x.grad(), 0.0);
 
-            assert!(approx_eq(n.grad, 0.5));
-            assert!(approx_eq(x1w1x2w2.grad, 0.5));
-            assert!(approx_eq(b.grad, 0.5));
-            assert!(approx_eq(x1w1.grad, 0.5));
-            assert!(approx_eq(x2w2.grad, 0.5));
-            assert!(approx_eq(x1.grad, -1.5));
-            assert!(approx_eq(w1.grad, 1.0));
-            assert!(approx_eq(x2.grad, 0.5));
-            assert!(approx_eq(w2.grad, 0.0));
+            drop(y);
+            assert!(x.prev().is_empty());
         }
     }
 }
@@ -619,8 +2207,8 @@ mod prt {
         println!("{}", v1);
         let v2: Val = Val::new(2.2123);
         let v3: Val = Val::new(-2.2);
-        let mut result: Val = v1 * v2 + v3;
-        result.grad = 1.0;
+        let result: Val = v1 * v2 + v3;
+        result.set_grad(1.0);
 
         println!("Result: {}", result);
     }
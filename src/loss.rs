@@ -0,0 +1,356 @@
+use crate::{ops, Float, Val};
+
+
+pub fn mse<T: Float>(predictions: &[Val<T>], targets: &[T]) -> Val<T> {
+    assert_eq!(predictions.len(), targets.len(), "mse expects one target per prediction");
+    assert!(!predictions.is_empty(), "mse of an empty batch");
+
+    let errors: Vec<Val<T>> = predictions.iter()
+        .zip(targets.iter())
+        .map(|(p, &t)| (p - t).pow(T::from_f64(2.0)))
+        .collect();
+
+    return ops::mean(&errors);
+}
+
+
+pub fn mae<T: Float>(predictions: &[Val<T>], targets: &[T]) -> Val<T> {
+    assert_eq!(predictions.len(), targets.len(), "mae expects one target per prediction");
+    assert!(!predictions.is_empty(), "mae of an empty batch");
+
+    let errors: Vec<Val<T>> = predictions.iter()
+        .zip(targets.iter())
+        .map(|(p, &t)| (p - t).abs())
+        .collect();
+
+    return ops::mean(&errors);
+}
+
+
+// Squared within delta of the target and linear beyond it, 0.5 e² or
+// delta (|e| - 0.5 delta), so outliers pull with at most delta. With delta = 1
+// this is SmoothL1. To pass to fit, wrap it: |p, t| huber(p, t, 1.0).
+pub fn huber<T: Float>(predictions: &[Val<T>], targets: &[T], delta: f64) -> Val<T> {
+    assert_eq!(predictions.len(), targets.len(), "huber expects one target per prediction");
+    assert!(!predictions.is_empty(), "huber of an empty batch");
+    assert!(delta > 0.0, "huber delta must be positive");
+
+    let d: T = T::from_f64(delta);
+    let errors: Vec<Val<T>> = predictions.iter()
+        .zip(targets.iter())
+        .map(|(p, &t)| {
+            let e: Val<T> = p - t;
+            if e.data().abs() <= d {
+                return (&e * &e) * T::from_f64(0.5);
+            }
+            return (e.abs() - T::from_f64(0.5 * delta)) * d;
+        })
+        .collect();
+
+    return ops::mean(&errors);
+}
+
+
+// Softmax is folded in through log-sum-exp, shifted by the largest logit so exp() cannot overflow
+pub fn cross_entropy<T: Float>(logits: &[Val<T>], target_class: usize) -> Val<T> {
+    assert!(target_class < logits.len(), "target class {} out of range for {} logits", target_class, logits.len());
+
+    let m: T = logits.iter().map(|l| l.data()).fold(T::neg_infinity(), T::max);
+
+    let exps: Vec<Val<T>> = logits.iter().map(|l| (l - m).exp()).collect();
+
+    return ops::sum(&exps).log() + m - logits[target_class].clone();
+}
+
+
+// The max-margin loss from micrograd's moons demo: mean of max(0, 1 - y s)
+// with labels y of ±1, zero once every score is on the right side by 1
+pub fn hinge<T: Float>(scores: &[Val<T>], labels: &[T]) -> Val<T> {
+    assert_eq!(scores.len(), labels.len(), "hinge expects one label per score");
+    assert!(!scores.is_empty(), "hinge of an empty batch");
+
+    let margins: Vec<Val<T>> = scores.iter()
+        .zip(labels.iter())
+        .map(|(s, &y)| (-(s * y) + T::one()).relu())
+        .collect();
+
+    return ops::mean(&margins);
+}
+
+
+// Mean of max(0, margin - y (a - b)): y = 1 asks for a to rank above b by the
+// margin, y = -1 for b above a
+pub fn margin_ranking<T: Float>(a: &[Val<T>], b: &[Val<T>], labels: &[T], margin: f64) -> Val<T> {
+    assert!(a.len() == b.len() && a.len() == labels.len(), "margin_ranking expects matching lengths");
+    assert!(!a.is_empty(), "margin_ranking of an empty batch");
+
+    let losses: Vec<Val<T>> = a.iter()
+        .zip(b.iter().zip(labels.iter()))
+        .map(|(ai, (bi, &y))| (-((ai - bi) * y) + T::from_f64(margin)).relu())
+        .collect();
+
+    return ops::mean(&losses);
+}
+
+
+// -(t ln σ(x) + (1 - t) ln(1 - σ(x))), written as softplus(x) - t x so that
+// neither log ever sees an exact 0. The gradient is σ(x) - t.
+pub fn bce_with_logits<T: Float>(logit: &Val<T>, target: T) -> Val<T> {
+    return logit.clone().softplus() - logit * target;
+}
+
+
+// Negative log-likelihood of the target class, given log-probabilities
+pub fn nll<T: Float>(log_probs: &[Val<T>], target: usize) -> Val<T> {
+    assert!(target < log_probs.len(), "target class {} out of range for {} log-probabilities", target, log_probs.len());
+    return -&log_probs[target];
+}
+
+
+// lambda * Σ p², for adding to a loss; its gradient pulls each parameter by 2 lambda p
+pub fn l2_penalty<T: Float>(params: &[Val<T>], lambda: f64) -> Val<T> {
+    let squares: Vec<Val<T>> = params.iter().map(|p| p * p).collect();
+    return ops::sum(&squares) * T::from_f64(lambda);
+}
+
+
+#[cfg(test)]
+mod loss_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn mse_loss() {
+        {
+            let p: Vec<Val> = vec![Val::new(1.0), Val::new(2.0)];
+            let loss: Val = mse(&p, &[1.0, 2.0]);
+
+            assert_eq!(loss.data(), 0.0);
+        }
+
+        {
+            let p: Vec<Val> = vec![Val::new(3.0), Val::new(-1.0)];
+            let loss: Val = mse(&p, &[1.0, 0.0]);
+
+            assert!(approx_eq(loss.data(), 2.5));
+
+            loss.backward();
+            assert!(approx_eq(p[0].grad(), 2.0));
+            assert!(approx_eq(p[1].grad(), -1.0));
+        }
+    }
+
+    #[test]
+    fn cross_entropy_loss() {
+        {
+            let logits: Vec<Val> = vec![Val::new(0.0), Val::new(0.0)];
+            let loss: Val = cross_entropy(&logits, 1);
+
+            assert!(approx_eq(loss.data(), 2.0_f64.ln()));
+
+            loss.backward();
+            assert!(approx_eq(logits[0].grad(), 0.5));
+            assert!(approx_eq(logits[1].grad(), -0.5));
+        }
+
+        {
+            let logits: Vec<Val> = vec![Val::new(1.0), Val::new(2.0), Val::new(3.0)];
+            let loss: Val = cross_entropy(&logits, 0);
+
+            let z: f64 = 1.0_f64.exp() + 2.0_f64.exp() + 3.0_f64.exp();
+            assert!(approx_eq(loss.data(), z.ln() - 1.0));
+
+            loss.backward();
+            assert!(approx_eq(logits[0].grad(), 1.0_f64.exp() / z - 1.0));
+            assert!(approx_eq(logits[1].grad(), 2.0_f64.exp() / z));
+            assert!(approx_eq(logits[2].grad(), 3.0_f64.exp() / z));
+        }
+
+        {
+            // Large logits would overflow a naive softmax
+            let logits: Vec<Val> = vec![Val::new(1000.0), Val::new(0.0)];
+            let loss: Val = cross_entropy(&logits, 0);
+
+            assert!(loss.data().is_finite());
+            assert!(approx_eq(loss.data(), 0.0));
+        }
+    }
+
+    #[test]
+    fn l2() {
+        {
+            let params: Vec<Val> = vec![Val::new(1.0), Val::new(-2.0), Val::new(0.5)];
+            let penalty: Val = l2_penalty(&params, 0.1);
+
+            assert!(approx_eq(penalty.data(), 0.1 * 5.25));
+
+            penalty.backward();
+            assert!(approx_eq(params[0].grad(), 0.2));
+            assert!(approx_eq(params[1].grad(), -0.4));
+            assert!(approx_eq(params[2].grad(), 0.1));
+        }
+
+        {
+            // Added to a data loss, the gradients add up
+            let w: Val = Val::new(2.0);
+            let loss: Val = mse(&[&w * 1.0], &[1.0]) + l2_penalty(std::slice::from_ref(&w), 0.5);
+            loss.backward();
+
+            assert!(approx_eq(loss.data(), 1.0 + 2.0));
+            assert!(approx_eq(w.grad(), 2.0 + 2.0));
+            assert_eq!(l2_penalty::<f64>(&[], 1.0).data(), 0.0);
+        }
+    }
+
+    #[test]
+    fn robust() {
+        {
+            let p: Vec<Val> = vec![Val::new(1.0), Val::new(-2.0), Val::new(4.0)];
+            let loss: Val = mae(&p, &[2.0, -2.0, 1.0]);
+
+            assert!(approx_eq(loss.data(), 4.0 / 3.0));
+
+            loss.backward();
+            assert!(approx_eq(p[0].grad(), -1.0 / 3.0));
+            assert_eq!(p[1].grad(), 0.0);
+            assert!(approx_eq(p[2].grad(), 1.0 / 3.0));
+        }
+
+        {
+            // Quadratic inside delta, linear outside
+            let p: Vec<Val> = vec![Val::new(0.5), Val::new(5.0), Val::new(-3.0)];
+            let loss: Val = huber(&p, &[0.0, 0.0, 0.0], 1.0);
+
+            assert!(approx_eq(loss.data(), (0.125 + 4.5 + 2.5) / 3.0));
+
+            loss.backward();
+            assert!(approx_eq(p[0].grad(), 0.5 / 3.0));
+            assert!(approx_eq(p[1].grad(), 1.0 / 3.0));
+            assert!(approx_eq(p[2].grad(), -1.0 / 3.0));
+        }
+
+        {
+            // A large delta is plain halved MSE; the two pieces meet at delta
+            let p: Vec<Val> = vec![Val::new(3.0)];
+            assert!(approx_eq(huber(&p, &[1.0], 10.0).data(), 0.5 * mse(&p, &[1.0]).data()));
+            assert!(approx_eq(huber(&[Val::new(2.0)], &[0.0], 2.0).data(), 2.0));
+            assert!(approx_eq(huber(&[Val::new(2.0 + 1e-9)], &[0.0], 2.0).data(), 2.0 + 2e-9));
+        }
+    }
+
+    #[test]
+    fn bce() {
+        {
+            let x: Val = Val::new(0.8);
+            let loss: Val = bce_with_logits(&x, 1.0);
+            let s: f64 = 1.0 / (1.0 + (-0.8_f64).exp());
+
+            assert!(approx_eq(loss.data(), -s.ln()));
+
+            loss.backward();
+            assert!(approx_eq(x.grad(), s - 1.0));
+        }
+
+        {
+            // Soft targets mix the two terms
+            let x: Val = Val::new(-1.2);
+            let loss: Val = bce_with_logits(&x, 0.25);
+            let s: f64 = 1.0 / (1.0 + 1.2_f64.exp());
+
+            assert!(approx_eq(loss.data(), -(0.25 * s.ln() + 0.75 * (1.0 - s).ln())));
+
+            loss.backward();
+            assert!(approx_eq(x.grad(), s - 0.25));
+        }
+
+        {
+            // Confident logits stay finite where sigmoid then log would not
+            let wrong: Val = Val::new(-800.0);
+            let loss: Val = bce_with_logits(&wrong, 1.0);
+            assert_eq!(loss.data(), 800.0);
+
+            loss.backward();
+            assert_eq!(wrong.grad(), -1.0);
+            assert_eq!(bce_with_logits(&Val::new(800.0), 1.0).data(), 0.0);
+            assert!(Val::new(-800.0_f64).sigmoid().log().data().is_infinite());
+        }
+    }
+
+    #[test]
+    fn margins() {
+        {
+            let s: Vec<Val> = vec![Val::new(2.0), Val::new(0.5), Val::new(0.2)];
+            let loss: Val = hinge(&s, &[1.0, 1.0, -1.0]);
+
+            // Only scores inside the margin count
+            assert!(approx_eq(loss.data(), (0.0 + 0.5 + 1.2) / 3.0));
+
+            loss.backward();
+            assert_eq!(s[0].grad(), 0.0);
+            assert!(approx_eq(s[1].grad(), -1.0 / 3.0));
+            assert!(approx_eq(s[2].grad(), 1.0 / 3.0));
+        }
+
+        {
+            let a: Vec<Val> = vec![Val::new(1.0), Val::new(0.0)];
+            let b: Vec<Val> = vec![Val::new(0.8), Val::new(2.0)];
+            let loss: Val = margin_ranking(&a, &b, &[1.0, -1.0], 0.5);
+
+            assert!(approx_eq(loss.data(), 0.3 / 2.0));
+
+            loss.backward();
+            assert!(approx_eq(a[0].grad(), -0.5));
+            assert!(approx_eq(b[0].grad(), 0.5));
+            assert_eq!((a[1].grad(), b[1].grad()), (0.0, 0.0));
+        }
+
+        {
+            // A separable set trains to zero hinge loss, as in the moons demo
+            use crate::nn::{Module, Neuron};
+            use crate::optim::{Optimizer, SGD};
+
+            let model: Neuron = Neuron::from_weights(&[0.1, -0.1], 0.0, false);
+            let points: Vec<([f64; 2], f64)> = vec![([2.0, 1.0], 1.0), ([1.5, 2.0], 1.0), ([-1.0, -0.5], -1.0), ([-2.0, 0.5], -1.0)];
+            let mut opt: SGD = SGD::new(model.parameters(), 0.1);
+
+            let loss = || -> Val {
+                let scores: Vec<Val> = points.iter()
+                    .map(|(x, _)| model.forward(&[Val::new(x[0]), Val::new(x[1])])[0].clone())
+                    .collect();
+                let labels: Vec<f64> = points.iter().map(|(_, y)| *y).collect();
+                return hinge(&scores, &labels);
+            };
+
+            for _ in 0..50 {
+                opt.zero_grad();
+                loss().backward();
+                opt.step();
+            }
+            assert_eq!(loss().data(), 0.0);
+        }
+    }
+
+    #[test]
+    fn nll_loss() {
+        {
+            let log_probs: Vec<Val> = [0.2, 0.5, 0.3].iter().map(|p: &f64| Val::new(p.ln())).collect();
+            let loss: Val = nll(&log_probs, 1);
+
+            assert!(approx_eq(loss.data(), -0.5_f64.ln()));
+
+            loss.backward();
+            assert_eq!(log_probs[1].grad(), -1.0);
+            assert_eq!(log_probs[0].grad(), 0.0);
+        }
+
+        {
+            // Over log(softmax) it matches cross_entropy on the logits
+            let logits: Vec<Val> = vec![Val::new(1.0), Val::new(-0.5), Val::new(2.0)];
+            let log_probs: Vec<Val> = ops::softmax(&logits).into_iter().map(|p| p.log()).collect();
+            assert!(approx_eq(nll(&log_probs, 2).data(), cross_entropy(&logits, 2).data()));
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{Float, Val};
+
+
+// Index of the largest score, e.g. the predicted class of a row of logits.
+// Ties go to the first.
+pub fn argmax<T: Float>(scores: &[Val<T>]) -> usize {
+    assert!(!scores.is_empty(), "argmax of an empty slice");
+
+    let mut best: usize = 0;
+    for (i, s) in scores.iter().enumerate() {
+        if s.data() > scores[best].data() {
+            best = i;
+        }
+    }
+
+    return best;
+}
+
+
+// Fraction of predictions equal to their target
+pub fn accuracy(predicted: &[usize], targets: &[usize]) -> f64 {
+    assert_eq!(predicted.len(), targets.len(), "accuracy expects one target per prediction");
+    assert!(!predicted.is_empty(), "accuracy of an empty batch");
+
+    let hits: usize = predicted.iter().zip(targets.iter()).filter(|(p, t)| p == t).count();
+    return hits as f64 / predicted.len() as f64;
+}
+
+
+// One-vs-rest scores for `class`, over labels numbered below the largest seen
+pub fn precision(predicted: &[usize], targets: &[usize], class: usize) -> f64 {
+    return ConfusionMatrix::from_labels(predicted, targets).precision(class);
+}
+
+
+pub fn recall(predicted: &[usize], targets: &[usize], class: usize) -> f64 {
+    return ConfusionMatrix::from_labels(predicted, targets).recall(class);
+}
+
+
+pub fn f1(predicted: &[usize], targets: &[usize], class: usize) -> f64 {
+    return ConfusionMatrix::from_labels(predicted, targets).f1(class);
+}
+
+
+// counts[actual][predicted]. Classes with no predictions (or no examples)
+// score 0 rather than NaN.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConfusionMatrix {
+    counts: Vec<Vec<usize>>
+}
+
+
+impl ConfusionMatrix {
+    pub fn new(num_classes: usize) -> ConfusionMatrix {
+        return ConfusionMatrix { counts: vec![vec![0; num_classes]; num_classes] };
+    }
+
+    // Sized to the largest label in either slice
+    pub fn from_labels(predicted: &[usize], targets: &[usize]) -> ConfusionMatrix {
+        assert_eq!(predicted.len(), targets.len(), "expected one target per prediction");
+
+        let n: usize = predicted.iter().chain(targets.iter()).max().map_or(0, |&m| m + 1);
+        let mut matrix: ConfusionMatrix = ConfusionMatrix::new(n);
+        for (&p, &t) in predicted.iter().zip(targets.iter()) {
+            matrix.add(p, t);
+        }
+
+        return matrix;
+    }
+
+    pub fn add(&mut self, predicted: usize, actual: usize) {
+        let n: usize = self.num_classes();
+        assert!(predicted < n && actual < n, "label out of range for {} classes", n);
+
+        self.counts[actual][predicted] += 1;
+    }
+
+    pub fn num_classes(&self) -> usize {
+        return self.counts.len();
+    }
+
+    pub fn count(&self, actual: usize, predicted: usize) -> usize {
+        return self.counts[actual][predicted];
+    }
+
+    pub fn total(&self) -> usize {
+        return self.counts.iter().flatten().sum();
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        let hits: usize = (0..self.num_classes()).map(|c| self.counts[c][c]).sum();
+        return ratio(hits, self.total());
+    }
+
+    // Of the examples predicted as class, the fraction that were
+    pub fn precision(&self, class: usize) -> f64 {
+        let predicted: usize = self.counts.iter().map(|row| row.get(class).copied().unwrap_or(0)).sum();
+        return ratio(self.true_positives(class), predicted);
+    }
+
+    // Of the examples of class, the fraction predicted as it
+    pub fn recall(&self, class: usize) -> f64 {
+        let actual: usize = self.counts.get(class).map_or(0, |row| row.iter().sum());
+        return ratio(self.true_positives(class), actual);
+    }
+
+    pub fn f1(&self, class: usize) -> f64 {
+        let (p, r): (f64, f64) = (self.precision(class), self.recall(class));
+        return if p + r == 0.0 { 0.0 } else { 2.0 * p * r / (p + r) };
+    }
+
+    // Unweighted mean of the per-class F1 scores
+    pub fn macro_f1(&self) -> f64 {
+        let n: usize = self.num_classes();
+        return if n == 0 { 0.0 } else { (0..n).map(|c| self.f1(c)).sum::<f64>() / n as f64 };
+    }
+
+    fn true_positives(&self, class: usize) -> usize {
+        return self.counts.get(class).and_then(|row| row.get(class)).copied().unwrap_or(0);
+    }
+}
+
+
+fn ratio(a: usize, b: usize) -> f64 {
+    return if b == 0 { 0.0 } else { a as f64 / b as f64 };
+}
+
+
+
+#[cfg(test)]
+mod metrics_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn labels() {
+        {
+            let predicted: [usize; 6] = [0, 1, 1, 2, 0, 1];
+            let targets: [usize; 6] = [0, 1, 2, 2, 1, 1];
+
+            assert!(approx_eq(accuracy(&predicted, &targets), 4.0 / 6.0));
+            assert!(approx_eq(precision(&predicted, &targets, 1), 2.0 / 3.0));
+            assert!(approx_eq(recall(&predicted, &targets, 1), 2.0 / 3.0));
+            assert!(approx_eq(recall(&predicted, &targets, 2), 0.5));
+            assert!(approx_eq(precision(&predicted, &targets, 2), 1.0));
+            assert!(approx_eq(f1(&predicted, &targets, 2), 2.0 / 3.0));
+
+            let logits: Vec<Val> = vec![Val::new(0.1), Val::new(2.0), Val::new(2.0), Val::new(-1.0)];
+            assert_eq!(argmax(&logits), 1);
+        }
+    }
+
+    #[test]
+    fn confusion() {
+        {
+            let mut m: ConfusionMatrix = ConfusionMatrix::new(3);
+            for (p, t) in [(0, 0), (1, 1), (1, 2), (2, 2), (0, 1), (1, 1)].iter() {
+                m.add(*p, *t);
+            }
+
+            assert_eq!(m, ConfusionMatrix::from_labels(&[0, 1, 1, 2, 0, 1], &[0, 1, 2, 2, 1, 1]));
+            assert_eq!(m.total(), 6);
+            assert_eq!(m.count(2, 1), 1);
+            assert_eq!(m.count(1, 0), 1);
+            assert!(approx_eq(m.accuracy(), 4.0 / 6.0));
+            assert!(approx_eq(m.macro_f1(), (2.0 / 3.0 + 2.0 / 3.0 + 2.0 / 3.0) / 3.0));
+        }
+
+        {
+            // A class that is never predicted or never present scores 0
+            let m: ConfusionMatrix = ConfusionMatrix::from_labels(&[0, 0], &[0, 1]);
+            assert_eq!(m.precision(1), 0.0);
+            assert_eq!(m.f1(1), 0.0);
+            assert_eq!(ConfusionMatrix::new(2).accuracy(), 0.0);
+            assert_eq!(ConfusionMatrix::new(4).recall(3), 0.0);
+        }
+    }
+}
@@ -0,0 +1,1724 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::{fs, io};
+
+use crate::init::Init;
+use crate::json::Json;
+use crate::rand::{self, Rng};
+use crate::vecval::VecVal;
+use crate::{ops, Float, Val};
+
+mod activation;
+mod attention;
+mod conv;
+mod quantized;
+mod recurrent;
+pub use activation::Activation;
+pub use attention::SelfAttention;
+pub use conv::{Conv1d, Conv2d, MaxPool2d};
+pub use quantized::QuantizedMLP;
+pub use recurrent::{hidden_outputs, unroll, GRUCell, LSTMCell, RNNCell, Recurrent};
+
+
+// Anything holding trainable Vals, so optimizers and the training loop can work
+// over any model structure
+pub trait Module<T: Float = f64> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>>;
+
+    // One output per example. Layers that look across the batch (BatchNorm1d)
+    // override this, as do containers holding them; by default each example
+    // goes through forward on its own.
+    fn forward_examples(&self, batch: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>> {
+        return batch.iter().map(|x| self.forward(x)).collect();
+    }
+
+    fn parameters(&self) -> Vec<Val<T>>;
+
+    fn num_parameters(&self) -> usize {
+        return self.parameters().len();
+    }
+
+    // The parameters in the same order, each with a path such as
+    // "layer1.weight[3][0]" for selecting them by name. Modules without names
+    // of their own number them, "param[i]".
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return self.parameters().into_iter().enumerate().map(|(i, p)| (format!("param[{}]", i), p)).collect();
+    }
+
+    fn zero_grad(&mut self) {
+        for p in self.parameters().iter() {
+            p.set_grad(T::zero());
+        }
+    }
+
+    // Holds every parameter fixed: no gradients, and optimizers skip them
+    fn freeze(&self) {
+        for p in self.parameters().iter() {
+            p.set_requires_grad(false);
+        }
+    }
+
+    fn unfreeze(&self) {
+        for p in self.parameters().iter() {
+            p.set_requires_grad(true);
+        }
+    }
+
+    // State that training changes but optimizers don't, such as BatchNorm1d's
+    // running statistics, flattened; containers concatenate their children's.
+    // fit_parallel copies it between the model and its replicas.
+    fn buffers(&self) -> Vec<T> {
+        return Vec::new();
+    }
+
+    fn load_buffers(&self, buffers: &[T]) {
+        assert!(buffers.is_empty(), "expected no buffers, got {}", buffers.len());
+    }
+
+    // Layers that act differently while training (Dropout) override this, and
+    // containers pass it on to their children; everything else ignores the mode.
+    // It takes &self, like forward, so evaluate can switch a shared model.
+    fn set_training(&self, _training: bool) {}
+
+    // Whether some layer that cares about the mode is in training mode; modules
+    // that ignore it report false, and containers ask their children
+    fn is_training(&self) -> bool {
+        return false;
+    }
+
+    fn train(&self) {
+        self.set_training(true);
+    }
+
+    fn eval(&self) {
+        self.set_training(false);
+    }
+}
+
+
+// A child's names under "prefix."
+fn prefixed<T: Float>(prefix: &str, named: Vec<(String, Val<T>)>) -> Vec<(String, Val<T>)> {
+    return named.into_iter().map(|(name, p)| (format!("{}.{}", prefix, name), p)).collect();
+}
+
+
+// "name[i]" for a flat list, or "name[i][j]" for rows of them
+fn indexed<T: Float>(name: &str, xs: &[Val<T>]) -> Vec<(String, Val<T>)> {
+    return xs.iter().enumerate().map(|(i, p)| (format!("{}[{}]", name, i), p.clone())).collect();
+}
+
+
+fn indexed_rows<T: Float>(name: &str, rows: &[Vec<Val<T>>]) -> Vec<(String, Val<T>)> {
+    return rows.iter().enumerate().flat_map(|(i, r)| indexed(&format!("{}[{}]", name, i), r)).collect();
+}
+
+
+pub struct Neuron<T: Float = f64> {
+    w:          Vec<Val<T>>,
+    b:          Val<T>,
+    activation: Activation
+}
+
+
+pub struct Layer<T: Float = f64> {
+    neurons: Vec<Neuron<T>>
+}
+
+
+pub struct MLP<T: Float = f64> {
+    layers: Vec<Layer<T>>
+}
+
+
+// Runs its modules in order, each one's outputs being the next one's inputs.
+// Batches and the training mode are passed through, so layers like
+// BatchNorm1d and Dropout work inside it.
+pub struct Sequential<T: Float = f64> {
+    modules: Vec<Box<dyn Module<T>>>
+}
+
+
+// Zeroes each input with probability p while training and scales the rest by
+// 1 / (1 - p), so the expected activation is unchanged. An identity in eval mode.
+pub struct Dropout<T: Float = f64> {
+    p:        f64,
+    training: Cell<bool>,
+    _float:   PhantomData<T>
+}
+
+
+// Normalizes every feature over the batch, then scales by gamma and shifts by
+// beta. Running estimates of the mean and (unbiased) variance are kept while
+// training and used instead of the batch statistics in eval mode.
+pub struct BatchNorm1d<T: Float = f64> {
+    gamma:        Vec<Val<T>>,
+    beta:         Vec<Val<T>>,
+    running_mean: RefCell<Vec<T>>,
+    running_var:  RefCell<Vec<T>>,
+    momentum:     f64,
+    eps:          f64,
+    training:     Cell<bool>
+}
+
+
+// Normalizes the features of each example on their own, then scales by gamma
+// and shifts by beta. Nothing depends on the batch, so it behaves the same in
+// training and eval mode.
+pub struct LayerNorm<T: Float = f64> {
+    gamma: Vec<Val<T>>,
+    beta:  Vec<Val<T>>,
+    eps:   f64
+}
+
+
+// A trainable vector per index. Looked-up rows are the table's own Vals, so
+// their gradients land straight on the parameters.
+pub struct Embedding<T: Float = f64> {
+    table: Vec<Vec<Val<T>>>
+}
+
+
+impl<T: Float> Neuron<T> {
+    pub fn new<A: Into<Activation>>(nin: usize, activation: A) -> Neuron<T> {
+        return Neuron::from_weights(&vec![T::zero(); nin], T::zero(), activation);
+    }
+
+    pub fn from_weights<A: Into<Activation>>(weights: &[T], bias: T, activation: A) -> Neuron<T> {
+        let w: Vec<Val<T>> = weights.iter().map(|&x| Val::new(x)).collect();
+        return Neuron { w, b: Val::new(bias), activation: activation.into() };
+    }
+
+    // fan_out is taken as 1 for a free-standing neuron
+    pub fn with_init<A: Into<Activation>>(nin: usize, activation: A, init: Init, rng: &mut Rng) -> Neuron<T> {
+        return Neuron::init_fan(nin, 1, activation.into(), init, rng);
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random<A: Into<Activation>>(nin: usize, activation: A, init: Init) -> Neuron<T> {
+        let activation: Activation = activation.into();
+        return rand::with_global(|rng| Neuron::with_init(nin, activation, init, rng));
+    }
+
+    fn init_fan(nin: usize, fan_out: usize, activation: Activation, init: Init, rng: &mut Rng) -> Neuron<T> {
+        let w: Vec<T> = init.weights(nin, nin, fan_out, rng).into_iter().map(T::from_f64).collect();
+        return Neuron::from_weights(&w, T::from_f64(init.bias(rng)), activation);
+    }
+
+    pub fn nin(&self) -> usize {
+        return self.w.len();
+    }
+
+    pub fn weights(&self) -> &[Val<T>] {
+        return &self.w;
+    }
+
+    pub fn bias(&self) -> &Val<T> {
+        return &self.b;
+    }
+
+    pub fn activation(&self) -> Activation {
+        return self.activation;
+    }
+
+    pub fn nonlin(&self) -> bool {
+        return self.activation != Activation::Identity;
+    }
+
+    // forward on plain numbers, building no graph
+    pub fn predict(&self, inputs: &[T]) -> T {
+        assert_eq!(inputs.len(), self.w.len(), "Neuron expects {} inputs", self.w.len());
+
+        let mut act: T = self.b.data();
+        for (wi, &xi) in self.w.iter().zip(inputs.iter()) {
+            act += wi.data() * xi;
+        }
+
+        return self.activation.eval(act);
+    }
+}
+
+
+impl<T: Float> Module<T> for Neuron<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        assert_eq!(inputs.len(), self.w.len(), "Neuron expects {} inputs", self.w.len());
+
+        let mut act: Val<T> = self.b.clone();
+        for (wi, xi) in self.w.iter().zip(inputs.iter()) {
+            act += wi * xi;
+        }
+
+        return vec![self.activation.apply(act)];
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.w.clone();
+        params.push(self.b.clone());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = indexed("weight", &self.w);
+        named.push((String::from("bias"), self.b.clone()));
+
+        return named;
+    }
+}
+
+
+// The same forward pass over a whole batch at once, one VecVal slot per example
+impl Neuron {
+    pub fn forward_batch(&self, inputs: &[VecVal]) -> Vec<VecVal> {
+        assert_eq!(inputs.len(), self.w.len(), "Neuron expects {} inputs", self.w.len());
+
+        let mut act: VecVal = VecVal::from_val(&self.b);
+        for (wi, xi) in self.w.iter().zip(inputs.iter()) {
+            act = &act + &(&VecVal::from_val(wi) * xi);
+        }
+
+        return vec![self.activation.apply_batch(act)];
+    }
+}
+
+
+impl<T: Float> Layer<T> {
+    pub fn new<A: Into<Activation>>(nin: usize, nout: usize, activation: A) -> Layer<T> {
+        let activation: Activation = activation.into();
+        let neurons: Vec<Neuron<T>> = (0..nout).map(|_| Neuron::new(nin, activation)).collect();
+        return Layer { neurons };
+    }
+
+    pub fn with_init<A: Into<Activation>>(nin: usize, nout: usize, activation: A, init: Init, rng: &mut Rng) -> Layer<T> {
+        let activation: Activation = activation.into();
+        let neurons: Vec<Neuron<T>> = (0..nout).map(|_| Neuron::init_fan(nin, nout, activation, init, rng)).collect();
+        return Layer { neurons };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random<A: Into<Activation>>(nin: usize, nout: usize, activation: A, init: Init) -> Layer<T> {
+        let activation: Activation = activation.into();
+        return rand::with_global(|rng| Layer::with_init(nin, nout, activation, init, rng));
+    }
+
+    pub fn from_neurons(neurons: Vec<Neuron<T>>) -> Layer<T> {
+        return Layer { neurons };
+    }
+
+    pub fn nout(&self) -> usize {
+        return self.neurons.len();
+    }
+
+    pub fn neurons(&self) -> &[Neuron<T>] {
+        return &self.neurons;
+    }
+
+    pub fn predict(&self, inputs: &[T]) -> Vec<T> {
+        return self.neurons.iter().map(|n| n.predict(inputs)).collect();
+    }
+}
+
+
+impl<T: Float> Module<T> for Layer<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        return self.neurons.iter().flat_map(|n| n.forward(inputs)).collect();
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.neurons.iter().flat_map(|n| n.parameters()).collect();
+    }
+
+    // Neuron j's weights are weight[j][..] and its bias bias[j]
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = Vec::new();
+        for (j, n) in self.neurons.iter().enumerate() {
+            named.extend(indexed(&format!("weight[{}]", j), &n.w));
+            named.push((format!("bias[{}]", j), n.b.clone()));
+        }
+
+        return named;
+    }
+
+    fn set_training(&self, training: bool) {
+        for n in self.neurons.iter() {
+            n.set_training(training);
+        }
+    }
+
+    fn is_training(&self) -> bool {
+        return self.neurons.iter().any(|n| n.is_training());
+    }
+}
+
+
+impl Layer {
+    pub fn forward_batch(&self, inputs: &[VecVal]) -> Vec<VecVal> {
+        return self.neurons.iter().flat_map(|n| n.forward_batch(inputs)).collect();
+    }
+}
+
+
+impl<T: Float> MLP<T> {
+    // Hidden layers use tanh, the output layer is left linear
+    pub fn new(nin: usize, nouts: &[usize]) -> MLP<T> {
+        let mut sizes: Vec<usize> = vec![nin];
+        sizes.extend_from_slice(nouts);
+
+        let layers: Vec<Layer<T>> = (0..nouts.len())
+            .map(|i| Layer::new(sizes[i], sizes[i + 1], i + 1 != nouts.len()))
+            .collect();
+
+        return MLP { layers };
+    }
+
+    pub fn with_init(nin: usize, nouts: &[usize], init: Init, rng: &mut Rng) -> MLP<T> {
+        let mut sizes: Vec<usize> = vec![nin];
+        sizes.extend_from_slice(nouts);
+
+        let layers: Vec<Layer<T>> = (0..nouts.len())
+            .map(|i| Layer::with_init(sizes[i], sizes[i + 1], i + 1 != nouts.len(), init, rng))
+            .collect();
+
+        return MLP { layers };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(nin: usize, nouts: &[usize], init: Init) -> MLP<T> {
+        return rand::with_global(|rng| MLP::with_init(nin, nouts, init, rng));
+    }
+
+    pub fn from_layers(layers: Vec<Layer<T>>) -> MLP<T> {
+        return MLP { layers };
+    }
+
+    pub fn layers(&self) -> &[Layer<T>] {
+        return &self.layers;
+    }
+
+    // The same outputs as forward, computed on plain numbers: no nodes are
+    // made, so this is the path for inference once training is done
+    pub fn predict(&self, inputs: &[T]) -> Vec<T> {
+        let mut x: Vec<T> = inputs.to_vec();
+        for layer in self.layers.iter() {
+            x = layer.predict(&x);
+        }
+
+        return x;
+    }
+
+    // A table of every layer's shape, activation and parameter count, then the
+    // trainable total (and the frozen count, if any are):
+    //
+    //   Layer  Shape    Activation  Params
+    //   0      2 -> 16  tanh            48
+    //   1      16 -> 1  linear          17
+    //   Total trainable parameters: 65
+    pub fn summary(&self) -> String {
+        let mut rows: Vec<[String; 4]> = vec![[String::from("Layer"), String::from("Shape"), String::from("Activation"), String::from("Params")]];
+        let (mut total, mut frozen): (usize, usize) = (0, 0);
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            let nin: usize = layer.neurons.first().map_or(0, |n| n.nin());
+            let activation: &str = match layer.neurons.first() {
+                Some(first) if layer.neurons.iter().all(|n| n.activation == first.activation) => first.activation.name(),
+                Some(_)                                                                   => "mixed",
+                None                                                                      => "-"
+            };
+            let params: Vec<Val<T>> = layer.parameters();
+            total += params.len();
+            frozen += params.iter().filter(|p| !p.requires_grad()).count();
+
+            rows.push([i.to_string(), format!("{} -> {}", nin, layer.nout()), String::from(activation), params.len().to_string()]);
+        }
+
+        let widths: Vec<usize> = (0..4).map(|c| rows.iter().map(|r| r[c].len()).max().unwrap_or(0)).collect();
+        let mut out: String = String::new();
+        for r in rows.iter() {
+            out += &format!("{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}\n", r[0], r[1], r[2], r[3], w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3]);
+        }
+        out += &format!("Total trainable parameters: {}\n", total - frozen);
+        if frozen > 0 {
+            out += &format!("Frozen parameters: {}\n", frozen);
+        }
+
+        return out;
+    }
+
+    pub fn print_summary(&self) {
+        print!("{}", self.summary());
+    }
+
+    // {"layers": [{"nin", "nout", "activation", "weights": [[..] per neuron], "biases"}]},
+    // with "alpha" after the activation for leaky_relu
+    pub fn to_json(&self) -> Result<Json, String> {
+        let mut layers: Vec<Json> = Vec::with_capacity(self.layers.len());
+        for (i, layer) in self.layers.iter().enumerate() {
+            let activation: Activation = layer.neurons.first().map_or(Activation::Identity, |n| n.activation);
+            if layer.neurons.iter().any(|n| n.activation != activation) {
+                return Err(format!("layer {} mixes activations", i));
+            }
+
+            let num = |v: &Val<T>| -> Json { Json::Num(v.data().to_f64()) };
+            let weights: Vec<Json> = layer.neurons.iter()
+                .map(|n| Json::Arr(n.w.iter().map(num).collect()))
+                .collect();
+            let biases: Vec<Json> = layer.neurons.iter().map(|n| num(&n.b)).collect();
+
+            let mut fields: Vec<(String, Json)> = vec![
+                (String::from("nin"),        Json::Num(layer.neurons.first().map_or(0, |n| n.nin()) as f64)),
+                (String::from("nout"),       Json::Num(layer.nout() as f64)),
+                (String::from("activation"), Json::Str(String::from(activation.name())))
+            ];
+            if let Activation::LeakyRelu(alpha) = activation {
+                fields.push((String::from("alpha"), Json::Num(alpha)));
+            }
+            fields.push((String::from("weights"), Json::Arr(weights)));
+            fields.push((String::from("biases"),  Json::Arr(biases)));
+
+            layers.push(Json::Obj(fields));
+        }
+
+        return Ok(Json::Obj(vec![(String::from("layers"), Json::Arr(layers))]));
+    }
+
+    pub fn from_json(json: &Json) -> Result<MLP<T>, String> {
+        let layers: &Vec<Json> = json.get("layers").and_then(Json::as_array).ok_or("missing \"layers\"")?;
+
+        let mut out: Vec<Layer<T>> = Vec::with_capacity(layers.len());
+        for (i, layer) in layers.iter().enumerate() {
+            let field = |key: &str| -> Result<&Json, String> {
+                return layer.get(key).ok_or(format!("layer {}: missing \"{}\"", i, key));
+            };
+            let nums = |xs: &Json| -> Result<Vec<T>, String> {
+                return xs.as_array()
+                    .ok_or(format!("layer {}: expected an array", i))?
+                    .iter()
+                    .map(|x| x.as_f64().map(T::from_f64).ok_or(format!("layer {}: expected a number", i)))
+                    .collect();
+            };
+
+            let nin: usize = field("nin")?.as_usize().ok_or(format!("layer {}: bad \"nin\"", i))?;
+            let nout: usize = field("nout")?.as_usize().ok_or(format!("layer {}: bad \"nout\"", i))?;
+            let alpha: f64 = layer.get("alpha").and_then(Json::as_f64).unwrap_or(0.01);
+            let activation: Activation = field("activation")?.as_str()
+                .and_then(|name| Activation::from_name(name, alpha))
+                .ok_or(format!("layer {}: unknown activation", i))?;
+            let weights: &Vec<Json> = field("weights")?.as_array().ok_or(format!("layer {}: bad \"weights\"", i))?;
+            let biases: Vec<T> = nums(field("biases")?)?;
+
+            if weights.len() != nout || biases.len() != nout {
+                return Err(format!("layer {}: expected {} neurons", i, nout));
+            }
+            if let Some(prev) = out.last() {
+                if prev.nout() != nin {
+                    return Err(format!("layer {}: expected {} inputs, previous layer gives {}", i, nin, prev.nout()));
+                }
+            }
+
+            let mut neurons: Vec<Neuron<T>> = Vec::with_capacity(nout);
+            for (w, &b) in weights.iter().zip(biases.iter()) {
+                let w: Vec<T> = nums(w)?;
+                if w.len() != nin {
+                    return Err(format!("layer {}: expected {} weights per neuron", i, nin));
+                }
+                neurons.push(Neuron::from_weights(&w, b, activation));
+            }
+            out.push(Layer::from_neurons(neurons));
+        }
+
+        return Ok(MLP { layers: out });
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let json: Json = self.to_json().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        return fs::write(path, json.to_string());
+    }
+
+    pub fn load(path: &str) -> io::Result<MLP<T>> {
+        let text: String = fs::read_to_string(path)?;
+        return Json::parse(&text)
+            .and_then(|json| MLP::from_json(&json))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+}
+
+
+impl MLP {
+    pub fn forward_batch(&self, inputs: &[VecVal]) -> Vec<VecVal> {
+        let mut x: Vec<VecVal> = inputs.to_vec();
+        for layer in self.layers.iter() {
+            x = layer.forward_batch(&x);
+        }
+
+        return x;
+    }
+}
+
+
+impl<T: Float> Module<T> for MLP<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let mut x: Vec<Val<T>> = inputs.to_vec();
+        for layer in self.layers.iter() {
+            x = layer.forward(&x);
+        }
+
+        return x;
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.layers.iter().flat_map(|l| l.parameters()).collect();
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return self.layers.iter().enumerate().flat_map(|(i, l)| prefixed(&format!("layer{}", i), l.named_parameters())).collect();
+    }
+
+    fn set_training(&self, training: bool) {
+        for l in self.layers.iter() {
+            l.set_training(training);
+        }
+    }
+
+    fn is_training(&self) -> bool {
+        return self.layers.iter().any(|l| l.is_training());
+    }
+}
+
+
+
+impl<T: Float> Dropout<T> {
+    // Starts in training mode
+    pub fn new(p: f64) -> Dropout<T> {
+        assert!((0.0..=1.0).contains(&p), "dropout probability must be in [0, 1]");
+        return Dropout { p, training: Cell::new(true), _float: PhantomData };
+    }
+
+    pub fn p(&self) -> f64 {
+        return self.p;
+    }
+}
+
+
+// Masks are drawn from the global generator, see set_seed
+impl<T: Float> Module<T> for Dropout<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        if !self.training.get() || self.p == 0.0 {
+            return inputs.to_vec();
+        }
+
+        let scale: T = if self.p < 1.0 { T::from_f64(1.0 / (1.0 - self.p)) } else { T::zero() };
+        return rand::with_global(|rng| {
+            return inputs.iter()
+                .map(|x| if rng.next_f64() < self.p { x * T::zero() } else { x * scale })
+                .collect();
+        });
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return Vec::new();
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+
+    fn is_training(&self) -> bool {
+        return self.training.get();
+    }
+}
+
+
+
+impl<T: Float> BatchNorm1d<T> {
+    // gamma = 1, beta = 0, momentum 0.1 and eps 1e-5 as in PyTorch; starts in training mode
+    pub fn new(dim: usize) -> BatchNorm1d<T> {
+        return BatchNorm1d {
+            gamma:        (0..dim).map(|_| Val::new(T::one())).collect(),
+            beta:         (0..dim).map(|_| Val::new(T::zero())).collect(),
+            running_mean: RefCell::new(vec![T::zero(); dim]),
+            running_var:  RefCell::new(vec![T::one(); dim]),
+            momentum:     0.1,
+            eps:          1e-5,
+            training:     Cell::new(true)
+        };
+    }
+
+    // Weight of the newest batch in the running statistics
+    pub fn momentum(mut self, momentum: f64) -> BatchNorm1d<T> {
+        self.momentum = momentum;
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> BatchNorm1d<T> {
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn dim(&self) -> usize {
+        return self.gamma.len();
+    }
+
+    pub fn gamma(&self) -> &[Val<T>] {
+        return &self.gamma;
+    }
+
+    pub fn beta(&self) -> &[Val<T>] {
+        return &self.beta;
+    }
+
+    pub fn running_mean(&self) -> Vec<T> {
+        return self.running_mean.borrow().clone();
+    }
+
+    pub fn running_var(&self) -> Vec<T> {
+        return self.running_var.borrow().clone();
+    }
+}
+
+
+impl<T: Float> Module<T> for BatchNorm1d<T> {
+    // A single example has no batch statistics, so this only trains through forward_examples
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        return self.forward_examples(&[inputs.to_vec()]).remove(0);
+    }
+
+    fn forward_examples(&self, batch: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>> {
+        let dim: usize = self.dim();
+        assert!(batch.iter().all(|x| x.len() == dim), "BatchNorm1d expects {} inputs", dim);
+
+        let eps: T = T::from_f64(self.eps);
+        let mut outs: Vec<Vec<Val<T>>> = vec![Vec::with_capacity(dim); batch.len()];
+
+        if !self.training.get() {
+            let (mean, var) = (self.running_mean.borrow(), self.running_var.borrow());
+            for j in 0..dim {
+                let inv_std: T = T::one() / (var[j] + eps).sqrt();
+                for (out, x) in outs.iter_mut().zip(batch.iter()) {
+                    out.push(&(&(&(&x[j] - mean[j]) * inv_std) * &self.gamma[j]) + &self.beta[j]);
+                }
+            }
+            return outs;
+        }
+
+        let n: usize = batch.len();
+        assert!(n > 1, "BatchNorm1d needs more than one example per batch while training");
+
+        let m: T = T::from_f64(self.momentum);
+        for j in 0..dim {
+            let column: Vec<Val<T>> = batch.iter().map(|x| x[j].clone()).collect();
+            let mu: Val<T> = ops::mean(&column);
+            let centered: Vec<Val<T>> = column.iter().map(|x| x - &mu).collect();
+            let squares: Vec<Val<T>> = centered.iter().map(|c| c * c).collect();
+            let var: Val<T> = ops::mean(&squares);
+            let inv_std: Val<T> = (var.clone() + eps).pow(T::from_f64(-0.5));
+
+            for (out, c) in outs.iter_mut().zip(centered.iter()) {
+                out.push(&(&(c * &inv_std) * &self.gamma[j]) + &self.beta[j]);
+            }
+
+            // The batch variance is biased; the running estimate is not
+            let unbiased: T = var.data() * T::from_f64(n as f64 / (n - 1) as f64);
+            let mut rm = self.running_mean.borrow_mut();
+            let mut rv = self.running_var.borrow_mut();
+            rm[j] = (T::one() - m) * rm[j] + m * mu.data();
+            rv[j] = (T::one() - m) * rv[j] + m * unbiased;
+        }
+
+        return outs;
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.gamma.clone();
+        params.extend(self.beta.iter().cloned());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = indexed("gamma", &self.gamma);
+        named.extend(indexed("beta", &self.beta));
+
+        return named;
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+
+    fn is_training(&self) -> bool {
+        return self.training.get();
+    }
+
+    // The running mean, then the running variance
+    fn buffers(&self) -> Vec<T> {
+        let mut buffers: Vec<T> = self.running_mean();
+        buffers.extend(self.running_var());
+
+        return buffers;
+    }
+
+    fn load_buffers(&self, buffers: &[T]) {
+        let dim: usize = self.dim();
+        assert_eq!(buffers.len(), 2 * dim, "BatchNorm1d expects {} buffers", 2 * dim);
+        self.running_mean.borrow_mut().copy_from_slice(&buffers[..dim]);
+        self.running_var.borrow_mut().copy_from_slice(&buffers[dim..]);
+    }
+}
+
+
+
+impl<T: Float> LayerNorm<T> {
+    // gamma = 1, beta = 0 and eps 1e-5
+    pub fn new(dim: usize) -> LayerNorm<T> {
+        return LayerNorm {
+            gamma: (0..dim).map(|_| Val::new(T::one())).collect(),
+            beta:  (0..dim).map(|_| Val::new(T::zero())).collect(),
+            eps:   1e-5
+        };
+    }
+
+    pub fn eps(mut self, eps: f64) -> LayerNorm<T> {
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn dim(&self) -> usize {
+        return self.gamma.len();
+    }
+
+    pub fn gamma(&self) -> &[Val<T>] {
+        return &self.gamma;
+    }
+
+    pub fn beta(&self) -> &[Val<T>] {
+        return &self.beta;
+    }
+}
+
+
+impl<T: Float> Module<T> for LayerNorm<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        assert_eq!(inputs.len(), self.dim(), "LayerNorm expects {} inputs", self.dim());
+
+        let mu: Val<T> = ops::mean(inputs);
+        let centered: Vec<Val<T>> = inputs.iter().map(|x| x - &mu).collect();
+        let squares: Vec<Val<T>> = centered.iter().map(|c| c * c).collect();
+        let inv_std: Val<T> = (ops::mean(&squares) + T::from_f64(self.eps)).pow(T::from_f64(-0.5));
+
+        return centered.iter()
+            .zip(self.gamma.iter().zip(self.beta.iter()))
+            .map(|(c, (g, b))| &(&(c * &inv_std) * g) + b)
+            .collect();
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.gamma.clone();
+        params.extend(self.beta.iter().cloned());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = indexed("gamma", &self.gamma);
+        named.extend(indexed("beta", &self.beta));
+
+        return named;
+    }
+}
+
+
+
+impl<T: Float> Embedding<T> {
+    pub fn new(num_embeddings: usize, dim: usize) -> Embedding<T> {
+        let table: Vec<Vec<Val<T>>> = (0..num_embeddings)
+            .map(|_| (0..dim).map(|_| Val::new(T::zero())).collect())
+            .collect();
+        return Embedding { table };
+    }
+
+    pub fn with_init(num_embeddings: usize, dim: usize, init: Init, rng: &mut Rng) -> Embedding<T> {
+        let table: Vec<Vec<Val<T>>> = (0..num_embeddings)
+            .map(|_| init.weights(dim, num_embeddings, dim, rng).into_iter().map(|w| Val::new(T::from_f64(w))).collect())
+            .collect();
+        return Embedding { table };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(num_embeddings: usize, dim: usize, init: Init) -> Embedding<T> {
+        return rand::with_global(|rng| Embedding::with_init(num_embeddings, dim, init, rng));
+    }
+
+    pub fn num_embeddings(&self) -> usize {
+        return self.table.len();
+    }
+
+    pub fn dim(&self) -> usize {
+        return self.table.first().map_or(0, |row| row.len());
+    }
+
+    pub fn lookup(&self, index: usize) -> Vec<Val<T>> {
+        assert!(index < self.table.len(), "index {} out of range for {} embeddings", index, self.table.len());
+        return self.table[index].clone();
+    }
+
+    // The rows for a context window, one after another
+    pub fn lookup_all(&self, indices: &[usize]) -> Vec<Val<T>> {
+        return indices.iter().flat_map(|&i| self.lookup(i)).collect();
+    }
+}
+
+
+// Inputs are indices stored as values, so an Embedding can front a model
+// trained with fit on numeric datasets
+impl<T: Float> Module<T> for Embedding<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let n: usize = self.table.len();
+        let indices: Vec<usize> = inputs.iter()
+            .map(|x| {
+                let i: f64 = x.data().to_f64();
+                assert!(i.is_finite() && i >= 0.0 && i.fract() == 0.0, "Embedding input {} is not an index", i);
+                assert!(i < n as f64, "index {} out of range for {} embeddings", i, n);
+                i as usize
+            })
+            .collect();
+        return self.lookup_all(&indices);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.table.iter().flatten().cloned().collect();
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return indexed_rows("weight", &self.table);
+    }
+}
+
+
+
+impl<T: Float> Sequential<T> {
+    pub fn new(modules: Vec<Box<dyn Module<T>>>) -> Sequential<T> {
+        return Sequential { modules };
+    }
+
+    pub fn layer<M: Module<T> + 'static>(mut self, module: M) -> Sequential<T> {
+        self.modules.push(Box::new(module));
+        return self;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.modules.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.modules.is_empty();
+    }
+
+    pub fn modules(&self) -> &[Box<dyn Module<T>>] {
+        return &self.modules;
+    }
+}
+
+
+impl<T: Float> Default for Sequential<T> {
+    fn default() -> Sequential<T> {
+        return Sequential::new(Vec::new());
+    }
+}
+
+
+impl<T: Float> Module<T> for Sequential<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let mut x: Vec<Val<T>> = inputs.to_vec();
+        for m in self.modules.iter() {
+            x = m.forward(&x);
+        }
+
+        return x;
+    }
+
+    fn forward_examples(&self, batch: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>> {
+        let mut x: Vec<Vec<Val<T>>> = batch.to_vec();
+        for m in self.modules.iter() {
+            x = m.forward_examples(&x);
+        }
+
+        return x;
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.modules.iter().flat_map(|m| m.parameters()).collect();
+    }
+
+    // Children are numbered by position: "0.weight[0][1]"
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return self.modules.iter().enumerate().flat_map(|(i, m)| prefixed(&i.to_string(), m.named_parameters())).collect();
+    }
+
+    fn set_training(&self, training: bool) {
+        for m in self.modules.iter() {
+            m.set_training(training);
+        }
+    }
+
+    fn buffers(&self) -> Vec<T> {
+        return self.modules.iter().flat_map(|m| m.buffers()).collect();
+    }
+
+    fn load_buffers(&self, buffers: &[T]) {
+        let mut at: usize = 0;
+        for m in self.modules.iter() {
+            let n: usize = m.buffers().len();
+            assert!(at + n <= buffers.len(), "expected {} buffers, got {}", self.buffers().len(), buffers.len());
+            m.load_buffers(&buffers[at..at + n]);
+            at += n;
+        }
+        assert_eq!(at, buffers.len(), "expected {} buffers, got {}", at, buffers.len());
+    }
+
+    fn is_training(&self) -> bool {
+        return self.modules.iter().any(|m| m.is_training());
+    }
+}
+
+
+
+#[cfg(test)]
+mod nn_ops {
+    use super::*;
+    use std::rc::Rc;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        return xs.iter().map(|&x| Val::new(x)).collect();
+    }
+
+    #[test]
+    fn neuron() {
+        {
+            let n: Neuron = Neuron::new(3, true);
+            assert_eq!(n.nin(), 3);
+            assert_eq!(n.parameters().len(), 4);
+
+            let out: Vec<Val> = n.forward(&vals(&[1.0, 2.0, 3.0]));
+            assert_eq!(out.len(), 1);
+            assert_eq!(out[0].data(), 0.0);
+        }
+
+        {
+            let n: Neuron = Neuron::from_weights(&[-3.0, 1.0], 6.881373587019543, true);
+            let out: Vec<Val> = n.forward(&vals(&[2.0, 0.0]));
+
+            assert!(approx_eq(out[0].data(), 0.7071067811865477));
+        }
+
+        {
+            let n: Neuron = Neuron::from_weights(&[0.5, -1.0], 0.25, false);
+            let out: Vec<Val> = n.forward(&vals(&[2.0, 3.0]));
+
+            assert!(approx_eq(out[0].data(), -1.75));
+
+            out[0].backward();
+            let params: Vec<Val> = n.parameters();
+            assert!(approx_eq(params[0].grad(), 2.0));
+            assert!(approx_eq(params[1].grad(), 3.0));
+            assert!(approx_eq(params[2].grad(), 1.0));
+        }
+    }
+
+    #[test]
+    fn layer() {
+        {
+            let l: Layer = Layer::from_neurons(vec![
+                Neuron::from_weights(&[1.0, 1.0], 0.0, Activation::Relu),
+                Neuron::from_weights(&[1.0, -1.0], 0.0, Activation::Relu)
+            ]);
+            let out: Vec<Val> = l.forward(&vals(&[2.0, 3.0]));
+
+            assert_eq!(out[0].data(), 5.0);
+            assert_eq!(out[1].data(), 0.0);
+            assert!(l.neurons()[0].nonlin());
+            assert!(!Neuron::<f64>::new(2, false).nonlin());
+        }
+
+        {
+            let l: Layer = Layer::new(3, 4, true);
+            assert_eq!(l.nout(), 4);
+            assert_eq!(l.parameters().len(), 16);
+            assert_eq!(l.forward(&vals(&[1.0, 2.0, 3.0])).len(), 4);
+        }
+
+        {
+            let l: Layer = Layer::from_neurons(vec![
+                Neuron::from_weights(&[1.0, 1.0], 0.0, false),
+                Neuron::from_weights(&[1.0, -1.0], 0.5, false)
+            ]);
+            let out: Vec<Val> = l.forward(&vals(&[2.0, 3.0]));
+
+            assert!(approx_eq(out[0].data(), 5.0));
+            assert!(approx_eq(out[1].data(), -0.5));
+        }
+    }
+
+    #[test]
+    fn mlp() {
+        {
+            let m: MLP = MLP::new(3, &[4, 4, 1]);
+            assert_eq!(m.parameters().len(), 4 * 4 + 4 * 5 + 5);
+            assert_eq!(m.forward(&vals(&[2.0, 3.0, -1.0])).len(), 1);
+        }
+
+        {
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[1.0, 0.0], 0.0, true),
+                    Neuron::from_weights(&[0.0, 1.0], 0.0, true)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[2.0, -1.0], 1.0, false)])
+            ]);
+            let out: Vec<Val> = m.forward(&vals(&[0.5, -0.25]));
+
+            assert!(approx_eq(out[0].data(), 2.0 * 0.5_f64.tanh() - (-0.25_f64).tanh() + 1.0));
+            assert_eq!(m.predict(&[0.5, -0.25]), vec![out[0].data()]);
+        }
+
+        {
+            // predict matches forward exactly, and leaves no graph behind
+            let mut rng: Rng = Rng::new(4);
+            let m: MLP = MLP::from_layers(vec![
+                Layer::with_init(3, 5, Activation::Gelu, Init::He, &mut rng),
+                Layer::with_init(5, 4, Activation::LeakyRelu(0.1), Init::He, &mut rng),
+                Layer::with_init(4, 2, Activation::Sigmoid, Init::Xavier, &mut rng)
+            ]);
+            let x: [f64; 3] = [0.3, -1.2, 2.0];
+            let expected: Vec<f64> = m.forward(&vals(&x)).iter().map(|v| v.data()).collect();
+            assert_eq!(m.predict(&x), expected);
+            // Held only by the model and this list of them
+            assert!(m.parameters().iter().all(|p| p.grad() == 0.0 && Rc::strong_count(&p.0) == 2));
+        }
+    }
+
+    #[test]
+    fn module() {
+        {
+            let mut n: Neuron = Neuron::from_weights(&[0.5, -1.0], 0.25, false);
+            let out: Vec<Val> = n.forward(&vals(&[2.0, 3.0]));
+            out[0].backward();
+            assert!(n.parameters().iter().any(|p| p.grad() != 0.0));
+
+            n.zero_grad();
+            assert!(n.parameters().iter().all(|p| p.grad() == 0.0));
+        }
+
+        {
+            let mut m: MLP = MLP::new(2, &[3, 1]);
+            let out: Vec<Val> = m.forward(&vals(&[1.0, -1.0]));
+            out[0].backward();
+            assert!(m.parameters().iter().any(|p| p.grad() != 0.0));
+
+            m.zero_grad();
+            assert!(m.parameters().iter().all(|p| p.grad() == 0.0));
+        }
+
+        {
+            // Generic code only needs the trait
+            fn count(m: &dyn Module) -> usize {
+                return m.parameters().len();
+            }
+
+            assert_eq!(count(&Neuron::new(4, true)), 5);
+            assert_eq!(count(&Layer::new(4, 2, true)), 10);
+            assert_eq!(count(&MLP::new(4, &[2, 1])), 13);
+        }
+    }
+
+    #[test]
+    fn f32s() {
+        {
+            let m: MLP<f32> = MLP::from_layers(vec![
+                Layer::from_neurons(vec![Neuron::from_weights(&[0.5, -0.5], 0.1, true)]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[2.0], 0.0, false)])
+            ]);
+            let x: Vec<Val<f32>> = vec![Val::new(1.0), Val::new(2.0)];
+            let out: Vec<Val<f32>> = m.forward(&x);
+
+            assert!((out[0].data() - 2.0 * (-0.4_f32).tanh()).abs() < 1e-6);
+
+            out[0].backward();
+            assert_eq!(m.parameters().len(), 5);
+            assert!(m.parameters().iter().all(|p| p.grad() != 0.0));
+        }
+    }
+
+    #[test]
+    fn random_init() {
+        {
+            let mut rng: Rng = Rng::new(1);
+            let n: Neuron = Neuron::with_init(4, true, Init::Uniform, &mut rng);
+            let params: Vec<Val> = n.parameters();
+
+            assert_eq!(params.len(), 5);
+            assert!(params.iter().all(|p| p.data() != 0.0 && p.data().abs() < 1.0));
+        }
+
+        {
+            let mut rng: Rng = Rng::new(1);
+            let l: Layer = Layer::with_init(8, 4, true, Init::Xavier, &mut rng);
+            let a: f64 = (6.0_f64 / 12.0).sqrt();
+            let params: Vec<Val> = l.parameters();
+
+            assert_eq!(params.len(), 36);
+            for (i, p) in params.iter().enumerate() {
+                if i % 9 == 8 {
+                    assert_eq!(p.data(), 0.0);
+                } else {
+                    assert!(p.data().abs() <= a);
+                }
+            }
+        }
+
+        {
+            // Seeded construction is reproducible and breaks symmetry
+            let mut r1: Rng = Rng::new(3);
+            let mut r2: Rng = Rng::new(3);
+            let m1: MLP = MLP::with_init(3, &[4, 1], Init::He, &mut r1);
+            let m2: MLP = MLP::with_init(3, &[4, 1], Init::He, &mut r2);
+            let p1: Vec<f64> = m1.parameters().iter().map(|p| p.data()).collect();
+            let p2: Vec<f64> = m2.parameters().iter().map(|p| p.data()).collect();
+
+            assert_eq!(p1, p2);
+            assert_eq!(p1.len(), 4 * 4 + 5);
+            assert_ne!(p1[0], p1[4]);
+        }
+    }
+
+    #[test]
+    fn seeded() {
+        {
+            let weights = || -> Vec<f64> {
+                let m: MLP = MLP::random(2, &[3, 1], Init::Uniform);
+                return m.parameters().iter().map(|p| p.data()).collect();
+            };
+
+            crate::set_seed(17);
+            let a: Vec<f64> = weights();
+            let b: Vec<f64> = weights();
+            crate::set_seed(17);
+            let c: Vec<f64> = weights();
+
+            assert_eq!(a, c);
+            assert_ne!(a, b);
+        }
+
+        {
+            crate::set_seed(4);
+            let n: Neuron = Neuron::random(3, false, Init::Xavier);
+            let l: Layer = Layer::random(3, 2, false, Init::He);
+
+            assert_eq!(n.parameters().len(), 4);
+            assert_eq!(l.parameters().len(), 8);
+        }
+    }
+
+    // Names line up with parameters() and never repeat
+    fn names<M: Module>(m: &M) -> Vec<String> {
+        let named: Vec<(String, Val)> = m.named_parameters();
+        let params: Vec<Val> = m.parameters();
+
+        assert_eq!(named.len(), params.len());
+        assert_eq!(m.num_parameters(), params.len());
+        assert!(named.iter().zip(params.iter()).all(|((_, a), b)| Rc::ptr_eq(&a.0, &b.0)));
+
+        let mut unique: Vec<String> = named.iter().map(|(n, _)| n.clone()).collect();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), named.len());
+
+        return named.into_iter().map(|(n, _)| n).collect();
+    }
+
+    #[test]
+    fn named_parameters() {
+        {
+            assert_eq!(names(&Neuron::new(2, true)), vec!["weight[0]", "weight[1]", "bias"]);
+            assert_eq!(names(&Layer::new(1, 2, true)), vec!["weight[0][0]", "bias[0]", "weight[1][0]", "bias[1]"]);
+
+            let m: MLP = MLP::new(2, &[3, 1]);
+            let n: Vec<String> = names(&m);
+            assert_eq!(m.num_parameters(), 13);
+            assert_eq!(n[0], "layer0.weight[0][0]");
+            assert_eq!(n[12], "layer1.bias[0]");
+        }
+
+        {
+            // Selecting by name, e.g. to treat the output layer on its own
+            let m: MLP = MLP::new(2, &[3, 1]);
+            let head: Vec<Val> = m.named_parameters().into_iter()
+                .filter(|(name, _)| name.starts_with("layer1."))
+                .map(|(_, p)| p)
+                .collect();
+            assert_eq!(head.len(), 4);
+        }
+
+        {
+            let seq: Sequential = Sequential::new(vec![Box::new(Layer::new(2, 2, true)), Box::new(Dropout::new(0.5)), Box::new(BatchNorm1d::new(2))]);
+            let n: Vec<String> = names(&seq);
+            assert_eq!(n[0], "0.weight[0][0]");
+            assert_eq!(n[6..], ["2.gamma[0]", "2.gamma[1]", "2.beta[0]", "2.beta[1]"]);
+
+            assert_eq!(names(&LayerNorm::new(1)), vec!["gamma[0]", "beta[0]"]);
+            assert_eq!(names(&Embedding::new(2, 1)), vec!["weight[0][0]", "weight[1][0]"]);
+            assert_eq!(names(&Conv1d::new(1, 2, 1)), vec!["weight[0][0]", "weight[1][0]", "bias[0]", "bias[1]"]);
+            assert_eq!(names(&Conv2d::new(1, 1, 2)).len(), 5);
+        }
+
+        {
+            assert!(names(&SelfAttention::new(2, 2)).iter().any(|n| n == "value.weight[1][0]"));
+            assert_eq!(names(&RNNCell::new(1, 1)), vec!["weight[0][0]", "weight[0][1]", "bias[0]"]);
+            assert!(names(&LSTMCell::new(1, 1))[0].starts_with("gates."));
+            assert_eq!(names(&GRUCell::new(1, 1)).last().unwrap(), "candidate.bias[0]");
+        }
+    }
+
+    #[test]
+    fn freeze() {
+        use crate::optim::{Adam, Optimizer, SGD};
+        use crate::loss::mse;
+
+        {
+            // Fine-tuning the head of a model whose first layer stays fixed
+            let m: MLP = MLP::with_init(2, &[3, 1], Init::Xavier, &mut Rng::new(1));
+            m.layers()[0].freeze();
+            let before: Vec<f64> = m.layers()[0].parameters().iter().map(|p| p.data()).collect();
+            let head: Vec<f64> = m.layers()[1].parameters().iter().map(|p| p.data()).collect();
+
+            let mut opt: Adam = Adam::new(m.parameters(), 0.05);
+            for _ in 0..5 {
+                opt.zero_grad();
+                let loss: Val = mse(&m.forward(&vals(&[0.5, -1.0])), &[1.0]);
+                loss.backward();
+                assert!(m.layers()[0].parameters().iter().all(|p| p.grad() == 0.0));
+                opt.step();
+            }
+
+            assert_eq!(m.layers()[0].parameters().iter().map(|p| p.data()).collect::<Vec<f64>>(), before);
+            assert_ne!(m.layers()[1].parameters().iter().map(|p| p.data()).collect::<Vec<f64>>(), head);
+            assert!(m.summary().ends_with("Total trainable parameters: 4\nFrozen parameters: 9\n"));
+        }
+
+        {
+            // Momentum carried from before freezing doesn't move the parameters either
+            let n: Neuron = Neuron::from_weights(&[1.0], 0.0, false);
+            let mut opt: SGD = SGD::with_momentum(n.parameters(), 0.1, 0.9);
+            mse(&n.forward(&vals(&[1.0])), &[3.0]).backward();
+            opt.step();
+            let w: f64 = n.parameters()[0].data();
+
+            n.freeze();
+            opt.step();
+            assert_eq!(n.parameters()[0].data(), w);
+
+            n.unfreeze();
+            opt.step();
+            assert_ne!(n.parameters()[0].data(), w);
+        }
+    }
+
+    #[test]
+    fn summary() {
+        {
+            let m: MLP = MLP::new(2, &[16, 1]);
+            assert_eq!(m.summary(), concat!(
+                "Layer  Shape    Activation  Params\n",
+                "0      2 -> 16  tanh            48\n",
+                "1      16 -> 1  linear          17\n",
+                "Total trainable parameters: 65\n"
+            ));
+        }
+
+        {
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![Neuron::new(3, Activation::Relu), Neuron::new(3, Activation::Gelu)]),
+                Layer::new(2, 4, Activation::LeakyRelu(0.1))
+            ]);
+            let lines: Vec<String> = m.summary().lines().map(String::from).collect();
+            assert!(lines[1].contains("3 -> 2") && lines[1].contains("mixed"));
+            assert!(lines[2].contains("leaky_relu") && lines[2].ends_with(" 12"));
+            assert_eq!(lines[3], "Total trainable parameters: 20");
+        }
+    }
+
+    #[test]
+    fn save_load() {
+        {
+            let mut rng: Rng = Rng::new(9);
+            let m: MLP = MLP::with_init(3, &[4, 2], Init::Uniform, &mut rng);
+            let path: String = std::env::temp_dir().join("rusty_nn_save_load.json").to_string_lossy().into_owned();
+
+            m.save(&path).unwrap();
+            let loaded: MLP = MLP::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let p1: Vec<f64> = m.parameters().iter().map(|p| p.data()).collect();
+            let p2: Vec<f64> = loaded.parameters().iter().map(|p| p.data()).collect();
+            assert_eq!(p1, p2);
+
+            let x: Vec<Val> = vals(&[0.3, -1.2, 2.0]);
+            let y1: Vec<f64> = m.forward(&x).iter().map(|v| v.data()).collect();
+            let y2: Vec<f64> = loaded.forward(&x).iter().map(|v| v.data()).collect();
+            assert_eq!(y1, y2);
+        }
+
+        {
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![Neuron::from_weights(&[1.0, -2.0], 0.5, true)]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[3.0], 0.0, false)])
+            ]);
+            let json: Json = m.to_json().unwrap();
+            let layers: &Vec<Json> = json.get("layers").unwrap().as_array().unwrap();
+
+            assert_eq!(layers.len(), 2);
+            assert_eq!(layers[0].get("activation").unwrap().as_str(), Some("tanh"));
+            assert_eq!(layers[1].get("activation").unwrap().as_str(), Some("linear"));
+            assert_eq!(layers[1].get("nin").unwrap().as_usize(), Some(1));
+        }
+
+        {
+            let mixed: MLP = MLP::from_layers(vec![Layer::from_neurons(vec![
+                Neuron::from_weights(&[1.0], 0.0, true),
+                Neuron::from_weights(&[1.0], 0.0, false)
+            ])]);
+            assert!(mixed.to_json().is_err());
+
+            // Every activation survives a round trip, LeakyRelu's alpha included
+            let acts: MLP = MLP::from_layers(vec![
+                Layer::new(2, 2, Activation::LeakyRelu(0.2)),
+                Layer::new(2, 2, Activation::Gelu),
+                Layer::new(2, 1, Activation::Sigmoid)
+            ]);
+            let loaded: MLP = MLP::from_json(&Json::parse(&acts.to_json().unwrap().to_string()).unwrap()).unwrap();
+            let kinds: Vec<Activation> = loaded.layers().iter().map(|l| l.neurons()[0].activation()).collect();
+            assert_eq!(kinds, vec![Activation::LeakyRelu(0.2), Activation::Gelu, Activation::Sigmoid]);
+
+            let bad: Json = Json::parse("{\"layers\": [{\"nin\": 2, \"nout\": 1, \"activation\": \"tanh\", \"weights\": [[1]], \"biases\": [0]}]}").unwrap();
+            assert!(MLP::<f64>::from_json(&bad).is_err());
+            assert!(MLP::<f64>::load("/nonexistent/model.json").is_err());
+        }
+    }
+
+    #[test]
+    fn dropout() {
+        {
+            crate::set_seed(5);
+            let d: Dropout = Dropout::new(0.5);
+            let x: Vec<Val> = vals(&vec![1.0; 1000]);
+            let out: Vec<Val> = d.forward(&x);
+
+            // Survivors are scaled so the mean stays near 1
+            assert!(out.iter().all(|v| v.data() == 0.0 || v.data() == 2.0));
+            let kept: usize = out.iter().filter(|v| v.data() != 0.0).count();
+            assert!(kept > 400 && kept < 600);
+
+            // Dropped inputs get no gradient
+            let total: Val = crate::ops::sum(&out);
+            total.backward();
+            for (xi, oi) in x.iter().zip(out.iter()) {
+                assert_eq!(xi.grad(), oi.data());
+            }
+
+            d.eval();
+            assert!(!d.is_training());
+            let same: Vec<Val> = d.forward(&x);
+            assert!(same.iter().zip(x.iter()).all(|(a, b)| Rc::ptr_eq(&a.0, &b.0)));
+
+            d.train();
+            assert!(d.forward(&x).iter().any(|v| v.data() == 0.0));
+        }
+
+        {
+            crate::set_seed(5);
+            let a: Vec<f64> = Dropout::<f64>::new(0.3).forward(&vals(&[1.0; 20])).iter().map(|v| v.data()).collect();
+            crate::set_seed(5);
+            let b: Vec<f64> = Dropout::<f64>::new(0.3).forward(&vals(&[1.0; 20])).iter().map(|v| v.data()).collect();
+            assert_eq!(a, b);
+
+            let x: Vec<Val> = vals(&[1.0, 2.0]);
+            assert!(Dropout::<f64>::new(1.0).forward(&x).iter().all(|v| v.data() == 0.0));
+            assert_eq!(Dropout::<f64>::new(0.5).parameters().len(), 0);
+        }
+    }
+
+    // What a model mixing layers would write: pass the mode down to every child
+    struct Net {
+        hidden:  Layer,
+        dropout: Dropout,
+        out:     Layer
+    }
+
+    impl Module for Net {
+        fn forward(&self, inputs: &[Val]) -> Vec<Val> {
+            return self.out.forward(&self.dropout.forward(&self.hidden.forward(inputs)));
+        }
+
+        fn parameters(&self) -> Vec<Val> {
+            let mut params: Vec<Val> = self.hidden.parameters();
+            params.extend(self.out.parameters());
+            return params;
+        }
+
+        fn set_training(&self, training: bool) {
+            self.hidden.set_training(training);
+            self.dropout.set_training(training);
+            self.out.set_training(training);
+        }
+
+        fn is_training(&self) -> bool {
+            return self.dropout.is_training();
+        }
+    }
+
+    #[test]
+    fn modes() {
+        {
+            let mut rng: Rng = Rng::new(2);
+            let net: Net = Net {
+                hidden:  Layer::with_init(2, 16, true, Init::Uniform, &mut rng),
+                dropout: Dropout::new(0.5),
+                out:     Layer::with_init(16, 1, false, Init::Uniform, &mut rng)
+            };
+            let x: Vec<Val> = vals(&[0.4, -0.7]);
+
+            net.eval();
+            assert!(!net.dropout.is_training());
+            let a: f64 = net.forward(&x)[0].data();
+            let b: f64 = net.forward(&x)[0].data();
+            assert_eq!(a, b);
+
+            net.train();
+            assert!(net.dropout.is_training());
+            crate::set_seed(8);
+            let outs: Vec<f64> = (0..5).map(|_| net.forward(&x)[0].data()).collect();
+            assert!(outs.iter().any(|&y| y != a));
+
+            net.set_training(false);
+            assert_eq!(net.forward(&x)[0].data(), a);
+        }
+
+        {
+            // Mode changes on plain models are harmless
+            let m: MLP = MLP::new(2, &[3, 1]);
+            let before: f64 = m.forward(&vals(&[1.0, 2.0]))[0].data();
+            m.eval();
+            m.train();
+            assert_eq!(m.forward(&vals(&[1.0, 2.0]))[0].data(), before);
+        }
+    }
+
+    #[test]
+    fn batch_norm() {
+        {
+            let bn: BatchNorm1d = BatchNorm1d::new(2);
+            let batch: Vec<Vec<Val>> = vec![vals(&[1.0, 10.0]), vals(&[2.0, 20.0]), vals(&[3.0, 60.0])];
+            let out: Vec<Vec<Val>> = bn.forward_examples(&batch);
+
+            // Each feature comes out with zero mean and unit variance
+            for j in 0..2 {
+                let ys: Vec<f64> = out.iter().map(|o| o[j].data()).collect();
+                let mean: f64 = ys.iter().sum::<f64>() / 3.0;
+                let var: f64 = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / 3.0;
+                assert!(mean.abs() < 1e-12);
+                assert!((var - 1.0).abs() < 1e-4);
+            }
+
+            assert!(approx_eq(bn.running_mean()[0], 0.1 * 2.0));
+            assert!(approx_eq(bn.running_var()[0], 0.9 + 0.1 * 1.0));
+            assert!(approx_eq(bn.running_mean()[1], 0.1 * 30.0));
+            assert_eq!(bn.parameters().len(), 4);
+        }
+
+        {
+            // Normalizing couples the examples; compare with central differences
+            let f = |x: &[Val]| -> Val {
+                let bn: BatchNorm1d = BatchNorm1d::new(2).eps(1e-3);
+                bn.gamma()[0].set_data(1.5);
+                bn.beta()[1].set_data(-0.5);
+
+                let batch: Vec<Vec<Val>> = x.chunks(2).map(|c| c.to_vec()).collect();
+                let out: Vec<Vec<Val>> = bn.forward_examples(&batch);
+                let weighted: Vec<Val> = out.iter()
+                    .flatten()
+                    .enumerate()
+                    .map(|(i, y)| (y * (i as f64 - 2.0)).tanh())
+                    .collect();
+                return crate::ops::sum(&weighted);
+            };
+            let checks = crate::grad_check(f, &[0.3, -1.0, 1.2, 0.4, -0.5, 2.0], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-5));
+        }
+
+        {
+            let bn: BatchNorm1d = BatchNorm1d::new(1).momentum(1.0);
+            bn.forward_examples(&[vals(&[1.0]), vals(&[3.0])]);
+            assert!(approx_eq(bn.running_mean()[0], 2.0));
+            assert!(approx_eq(bn.running_var()[0], 2.0));
+
+            // Eval mode uses the running statistics and leaves them alone
+            bn.eval();
+            let y: Vec<Val> = bn.forward(&vals(&[4.0]));
+            assert!((y[0].data() - 2.0 / 2.0_f64.sqrt()).abs() < 1e-5);
+
+            y[0].backward();
+            assert!((bn.gamma()[0].grad() - 2.0 / 2.0_f64.sqrt()).abs() < 1e-5);
+            assert_eq!(bn.beta()[0].grad(), 1.0);
+            assert!(approx_eq(bn.running_mean()[0], 2.0));
+
+            // Running mean then variance, as fit_parallel copies them
+            assert_eq!(bn.buffers(), vec![bn.running_mean()[0], bn.running_var()[0]]);
+            bn.load_buffers(&[0.5, 4.0]);
+            assert_eq!((bn.running_mean(), bn.running_var()), (vec![0.5], vec![4.0]));
+            let model: Sequential = Sequential::default().layer(Layer::new(1, 2, false)).layer(BatchNorm1d::new(2));
+            assert_eq!(model.buffers(), vec![0.0, 0.0, 1.0, 1.0]);
+            model.load_buffers(&[1.0, 2.0, 3.0, 4.0]);
+            assert_eq!(model.buffers(), vec![1.0, 2.0, 3.0, 4.0]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "more than one example")]
+    fn batch_norm_single() {
+        BatchNorm1d::<f64>::new(2).forward(&vals(&[1.0, 2.0]));
+    }
+
+    #[test]
+    fn layer_norm() {
+        {
+            let ln: LayerNorm = LayerNorm::new(4);
+            let out: Vec<Val> = ln.forward(&vals(&[1.0, 2.0, 4.0, 9.0]));
+            let ys: Vec<f64> = out.iter().map(|v| v.data()).collect();
+            let mean: f64 = ys.iter().sum::<f64>() / 4.0;
+            let var: f64 = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / 4.0;
+
+            assert!(mean.abs() < 1e-12);
+            assert!((var - 1.0).abs() < 1e-5);
+            assert_eq!(ln.parameters().len(), 8);
+
+            // Batches are just examples one after another
+            let batch: Vec<Vec<Val>> = vec![vals(&[1.0, 2.0, 4.0, 9.0]), vals(&[0.0, 0.0, 0.0, 1.0])];
+            assert_eq!(ln.forward_examples(&batch)[0].iter().map(|v| v.data()).collect::<Vec<f64>>(), ys);
+        }
+
+        {
+            let f = |x: &[Val]| -> Val {
+                let ln: LayerNorm = LayerNorm::new(3).eps(1e-3);
+                ln.gamma()[1].set_data(2.0);
+                ln.beta()[2].set_data(0.3);
+
+                let out: Vec<Val> = ln.forward(x);
+                return &(&out[0] * &out[1]) + &out[2].clone().tanh();
+            };
+            let checks = crate::grad_check(f, &[0.5, -1.5, 2.0], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-5));
+        }
+    }
+
+    #[test]
+    fn embedding() {
+        {
+            let mut rng: Rng = Rng::new(6);
+            let emb: Embedding = Embedding::with_init(27, 3, Init::Uniform, &mut rng);
+
+            assert_eq!(emb.num_embeddings(), 27);
+            assert_eq!(emb.dim(), 3);
+            assert_eq!(emb.parameters().len(), 81);
+
+            let row: Vec<Val> = emb.lookup(5);
+            assert!(row.iter().zip(emb.parameters()[15..18].iter()).all(|(a, b)| Rc::ptr_eq(&a.0, &b.0)));
+
+            let context: Vec<Val> = emb.lookup_all(&[0, 5, 5]);
+            assert_eq!(context.len(), 9);
+            assert_eq!(context[3].data(), row[0].data());
+        }
+
+        {
+            // A repeated index accumulates its gradient
+            let emb: Embedding = Embedding::new(4, 2);
+            let out: Vec<Val> = emb.forward(&vals(&[1.0, 3.0, 1.0]));
+            let loss: Val = crate::ops::sum(&[&out[0] * 2.0, out[2].clone(), out[4].clone()]);
+            loss.backward();
+
+            let grads: Vec<f64> = emb.parameters().iter().map(|p| p.grad()).collect();
+            assert_eq!(grads, vec![0.0, 0.0, 3.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn embedding_range() {
+        Embedding::<f64>::new(3, 2).lookup(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn embedding_forward_range() {
+        Embedding::<f64>::new(3, 2).forward(&vals(&[0.0, 3.0]));
+    }
+
+    #[test]
+    fn embedding_not_index() {
+        // Negative, fractional and NaN inputs fail rather than reading row 0
+        for bad in [-1.0, 0.5, 1.25, f64::NAN, f64::INFINITY] {
+            let caught = std::panic::catch_unwind(|| Embedding::<f64>::new(3, 2).forward(&vals(&[bad])));
+            let message: String = *caught.unwrap_err().downcast::<String>().unwrap();
+            assert!(message.contains("is not an index"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn sequential() {
+        {
+            let mut rng: Rng = Rng::new(7);
+            let model: Sequential = Sequential::default()
+                .layer(Layer::with_init(3, 8, true, Init::Xavier, &mut rng))
+                .layer(Dropout::new(0.5))
+                .layer(Layer::with_init(8, 2, false, Init::Xavier, &mut rng));
+
+            assert_eq!(model.len(), 3);
+            assert_eq!(model.parameters().len(), 8 * 4 + 2 * 9);
+
+            // Matches chaining the layers by hand once dropout is off
+            model.eval();
+            let x: Vec<Val> = vals(&[0.2, -0.4, 1.0]);
+            let hidden: Vec<Val> = model.modules()[0].forward(&x);
+            let expected: Vec<Val> = model.modules()[2].forward(&hidden);
+            let out: Vec<Val> = model.forward(&x);
+            assert_eq!(out.len(), 2);
+            for (a, b) in out.iter().zip(expected.iter()) {
+                assert_eq!(a.data(), b.data());
+            }
+        }
+
+        {
+            // Batches reach BatchNorm1d, and its mode follows the container's
+            use crate::loss::mse;
+            use crate::optim::Adam;
+            use crate::train::{fit_batched, BatchConfig};
+
+            let mut rng: Rng = Rng::new(1);
+            let model: Sequential = Sequential::new(vec![
+                Box::new(Layer::with_init(1, 4, false, Init::Xavier, &mut rng)),
+                Box::new(BatchNorm1d::new(4)),
+                Box::new(Layer::with_init(4, 1, false, Init::Xavier, &mut rng))
+            ]);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..8).map(|i| (vec![i as f64], vec![3.0 * i as f64 - 2.0])).collect();
+            let mut opt: Adam = Adam::new(model.parameters(), 0.1);
+
+            let history: Vec<f64> = fit_batched(&model, &data, &mut opt, mse, 100, &BatchConfig::new(8));
+            assert!(history[99] < history[0] * 0.05);
+
+            model.eval();
+            assert_eq!(model.forward(&vals(&[2.0])).len(), 1);
+            assert!(Sequential::<f64>::default().is_empty());
+        }
+    }
+}
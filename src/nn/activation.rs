@@ -0,0 +1,146 @@
+use crate::grad_fn::{self, GELU_C};
+use crate::vecval::VecVal;
+use crate::{Float, GradFn, Val};
+
+
+// The nonlinearity a Neuron applies after its weighted sum. A bool converts
+// too, true being Tanh and false Identity, as the constructors took before.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Activation {
+    Identity,
+    Tanh,
+    Relu,
+    Sigmoid,
+    // max(x, alpha x)
+    LeakyRelu(f64),
+    // The tanh approximation, 0.5 x (1 + tanh(sqrt(2/π) (x + 0.044715 x³)))
+    Gelu
+}
+
+
+impl Activation {
+    pub fn apply<T: Float>(&self, x: Val<T>) -> Val<T> {
+        return match self {
+            Activation::Identity     => x,
+            Activation::Tanh         => x.tanh(),
+            Activation::Relu         => x.relu(),
+            Activation::Sigmoid      => x.sigmoid(),
+            Activation::LeakyRelu(a) => x.leaky_relu(T::from_f64(*a)),
+            Activation::Gelu         => x.gelu()
+        };
+    }
+
+    // apply on a plain number, through the same forward as the ops
+    pub fn eval<T: Float>(&self, x: T) -> T {
+        return match self {
+            Activation::Identity     => x,
+            Activation::Tanh         => grad_fn::Tanh.forward(&[x]),
+            Activation::Relu         => grad_fn::Relu.forward(&[x]),
+            Activation::Sigmoid      => grad_fn::Sigmoid.forward(&[x]),
+            Activation::LeakyRelu(a) => grad_fn::LeakyRelu(T::from_f64(*a)).forward(&[x]),
+            Activation::Gelu         => grad_fn::Gelu.forward(&[x])
+        };
+    }
+
+    pub fn apply_batch(&self, x: VecVal) -> VecVal {
+        return match self {
+            Activation::Identity     => x,
+            Activation::Tanh         => x.tanh(),
+            Activation::Relu         => x.relu(),
+            Activation::Sigmoid      => x.sigmoid(),
+            Activation::LeakyRelu(a) => &x.relu() - &(&(-&x).relu() * *a),
+            Activation::Gelu         => {
+                let inner: VecVal = &(&x + &(&x.pow(3.0) * 0.044715)) * GELU_C;
+                &(&x * 0.5) * &(1.0 + &inner.tanh())
+            }
+        };
+    }
+
+    // Used in saved models; LeakyRelu's alpha is stored alongside
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Activation::Identity     => "linear",
+            Activation::Tanh         => "tanh",
+            Activation::Relu         => "relu",
+            Activation::Sigmoid      => "sigmoid",
+            Activation::LeakyRelu(_) => "leaky_relu",
+            Activation::Gelu         => "gelu"
+        };
+    }
+
+    pub fn from_name(name: &str, alpha: f64) -> Option<Activation> {
+        return match name {
+            "linear"     => Some(Activation::Identity),
+            "tanh"       => Some(Activation::Tanh),
+            "relu"       => Some(Activation::Relu),
+            "sigmoid"    => Some(Activation::Sigmoid),
+            "leaky_relu" => Some(Activation::LeakyRelu(alpha)),
+            "gelu"       => Some(Activation::Gelu),
+            _            => None
+        };
+    }
+}
+
+
+impl From<bool> for Activation {
+    fn from(nonlin: bool) -> Activation {
+        return if nonlin { Activation::Tanh } else { Activation::Identity };
+    }
+}
+
+
+
+#[cfg(test)]
+mod activation_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn activations() {
+        {
+            let at = |a: Activation, x: f64| -> f64 { a.apply(Val::new(x)).data() };
+
+            assert_eq!(at(Activation::Identity, -2.0), -2.0);
+            assert!(approx_eq(at(Activation::Tanh, 0.5), 0.5_f64.tanh()));
+            assert_eq!(at(Activation::Relu, -2.0), 0.0);
+            assert!(approx_eq(at(Activation::Sigmoid, 0.0), 0.5));
+            assert!(approx_eq(at(Activation::LeakyRelu(0.1), -2.0), -0.2));
+            assert!(approx_eq(at(Activation::LeakyRelu(0.1), 3.0), 3.0));
+            assert!(approx_eq(at(Activation::Gelu, 1.0), 0.8411919906082768));
+            assert!(approx_eq(at(Activation::Gelu, -1.0), -0.15880800939172324));
+
+            assert_eq!(Activation::from(true), Activation::Tanh);
+            assert_eq!(Activation::from(false), Activation::Identity);
+        }
+
+        {
+            // The batched forms agree slot by slot, gradients included
+            let all: [Activation; 6] = [
+                Activation::Identity, Activation::Tanh, Activation::Relu,
+                Activation::Sigmoid, Activation::LeakyRelu(0.2), Activation::Gelu
+            ];
+            let xs: [f64; 3] = [-1.5, 0.3, 2.0];
+
+            for a in all.iter() {
+                let batch: VecVal = VecVal::new(xs.to_vec());
+                let out: VecVal = a.apply_batch(batch.clone());
+                out.backward();
+
+                for (i, &x) in xs.iter().enumerate() {
+                    let v: Val = Val::new(x);
+                    let y: Val = a.apply(v.clone());
+                    y.backward();
+                    assert!(approx_eq(out.data()[i], y.data()));
+                    assert!(approx_eq(batch.grad()[i], v.grad()));
+                    assert_eq!(a.eval(x), y.data());
+                }
+
+                assert_eq!(Activation::from_name(a.name(), 0.2), Some(*a));
+            }
+            assert_eq!(Activation::from_name("swish", 0.0), None);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::init::Init;
+use crate::ops;
+use crate::rand::{self, Rng};
+use crate::{Float, Val};
+
+use super::{prefixed, Layer, Module};
+
+
+// Single-head scaled dot-product attention over a sequence of d_model vectors:
+// a_ij = softmax_j(q_i · k_j / sqrt(d_head)), out_i = W_o Σ_j a_ij v_j.
+// With causal set, position i only attends to positions j <= i.
+pub struct SelfAttention<T: Float = f64> {
+    query:  Layer<T>,
+    key:    Layer<T>,
+    value:  Layer<T>,
+    output: Layer<T>,
+    causal: bool
+}
+
+
+impl<T: Float> SelfAttention<T> {
+    pub fn new(d_model: usize, d_head: usize) -> SelfAttention<T> {
+        return SelfAttention {
+            query:  Layer::new(d_model, d_head, false),
+            key:    Layer::new(d_model, d_head, false),
+            value:  Layer::new(d_model, d_head, false),
+            output: Layer::new(d_head, d_model, false),
+            causal: false
+        };
+    }
+
+    pub fn with_init(d_model: usize, d_head: usize, init: Init, rng: &mut Rng) -> SelfAttention<T> {
+        return SelfAttention {
+            query:  Layer::with_init(d_model, d_head, false, init, rng),
+            key:    Layer::with_init(d_model, d_head, false, init, rng),
+            value:  Layer::with_init(d_model, d_head, false, init, rng),
+            output: Layer::with_init(d_head, d_model, false, init, rng),
+            causal: false
+        };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(d_model: usize, d_head: usize, init: Init) -> SelfAttention<T> {
+        return rand::with_global(|rng| SelfAttention::with_init(d_model, d_head, init, rng));
+    }
+
+    pub fn causal(mut self, causal: bool) -> SelfAttention<T> {
+        self.causal = causal;
+        return self;
+    }
+
+    pub fn d_model(&self) -> usize {
+        return self.output.nout();
+    }
+
+    pub fn d_head(&self) -> usize {
+        return self.query.nout();
+    }
+
+    // The attention weights, one row per position
+    pub fn weights(&self, xs: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>> {
+        let q: Vec<Vec<Val<T>>> = xs.iter().map(|x| self.query.forward(x)).collect();
+        let k: Vec<Vec<Val<T>>> = xs.iter().map(|x| self.key.forward(x)).collect();
+        let scale: T = T::from_f64(1.0 / (self.d_head() as f64).sqrt());
+
+        return q.iter()
+            .enumerate()
+            .map(|(i, qi)| {
+                let visible: usize = if self.causal { i + 1 } else { k.len() };
+                let scores: Vec<Val<T>> = k[..visible].iter()
+                    .map(|kj| {
+                        let products: Vec<Val<T>> = qi.iter().zip(kj.iter()).map(|(a, b)| a * b).collect();
+                        return ops::sum(&products) * scale;
+                    })
+                    .collect();
+                return ops::softmax(&scores);
+            })
+            .collect();
+    }
+
+    // One d_model output per position
+    pub fn forward_sequence(&self, xs: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>> {
+        let v: Vec<Vec<Val<T>>> = xs.iter().map(|x| self.value.forward(x)).collect();
+
+        return self.weights(xs).iter()
+            .map(|a| {
+                let mixed: Vec<Val<T>> = (0..self.d_head())
+                    .map(|d| {
+                        let terms: Vec<Val<T>> = a.iter().zip(v.iter()).map(|(aj, vj)| aj * &vj[d]).collect();
+                        return ops::sum(&terms);
+                    })
+                    .collect();
+                return self.output.forward(&mixed);
+            })
+            .collect();
+    }
+}
+
+
+// Inputs are the sequence flattened position by position: [len * d_model]
+impl<T: Float> Module<T> for SelfAttention<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let d: usize = self.d_model();
+        assert!(inputs.len().is_multiple_of(d), "SelfAttention expects a multiple of {} inputs", d);
+
+        let xs: Vec<Vec<Val<T>>> = inputs.chunks(d).map(|c| c.to_vec()).collect();
+        return self.forward_sequence(&xs).into_iter().flatten().collect();
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return [&self.query, &self.key, &self.value, &self.output].iter()
+            .flat_map(|l| l.parameters())
+            .collect();
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return [("query", &self.query), ("key", &self.key), ("value", &self.value), ("output", &self.output)].iter()
+            .flat_map(|(name, l)| prefixed(name, l.named_parameters()))
+            .collect();
+    }
+}
+
+
+
+#[cfg(test)]
+mod attention_ops {
+    use super::*;
+    use crate::nn::Neuron;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        return xs.iter().map(|&x| Val::new(x)).collect();
+    }
+
+    fn identity(n: usize) -> Layer {
+        return Layer::from_neurons((0..n).map(|i| {
+            let w: Vec<f64> = (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect();
+            return Neuron::from_weights(&w, 0.0, false);
+        }).collect());
+    }
+
+    #[test]
+    fn attention() {
+        {
+            // With identity projections the output is a softmax-weighted mix of the inputs
+            let attn: SelfAttention = SelfAttention { query: identity(2), key: identity(2), value: identity(2), output: identity(2), causal: false };
+            let xs: Vec<Vec<Val>> = vec![vals(&[1.0, 0.0]), vals(&[0.0, 2.0])];
+            let out: Vec<Vec<Val>> = attn.forward_sequence(&xs);
+
+            let s: f64 = 0.5_f64.sqrt();
+            let a0: f64 = 1.0 / (1.0 + (-s).exp());
+            let a1: f64 = 1.0 / (1.0 + (4.0 * s).exp());
+            assert!(approx_eq(out[0][0].data(), a0));
+            assert!(approx_eq(out[0][1].data(), 2.0 * (1.0 - a0)));
+            assert!(approx_eq(out[1][0].data(), a1));
+            assert!(approx_eq(out[1][1].data(), 2.0 * (1.0 - a1)));
+
+            let rows: Vec<Vec<Val>> = attn.weights(&xs);
+            assert!(rows.iter().all(|r| approx_eq(r.iter().map(|a| a.data()).sum(), 1.0)));
+        }
+
+        {
+            // Causal: the first position only sees itself, and later inputs don't reach it
+            let attn: SelfAttention = SelfAttention::with_init(3, 2, Init::Xavier, &mut Rng::new(5)).causal(true);
+            let xs: Vec<Vec<Val>> = vec![vals(&[0.1, 0.2, 0.3]), vals(&[1.0, -1.0, 0.5]), vals(&[0.0, 2.0, -0.5])];
+            let rows: Vec<Vec<Val>> = attn.weights(&xs);
+            assert_eq!(rows.iter().map(|r| r.len()).collect::<Vec<usize>>(), vec![1, 2, 3]);
+
+            let out: Vec<Vec<Val>> = attn.forward_sequence(&xs);
+            ops::sum(&out[0]).backward();
+            assert!(xs[1].iter().chain(xs[2].iter()).all(|x| x.grad() == 0.0));
+            assert!(xs[0].iter().any(|x| x.grad() != 0.0));
+            assert_eq!(attn.parameters().len(), 3 * (2 * 4) + 3 * 3);
+        }
+
+        {
+            let f = |x: &[Val]| -> Val {
+                let attn: SelfAttention = SelfAttention::with_init(2, 2, Init::Uniform, &mut Rng::new(6));
+                let out: Vec<Val> = attn.forward(x);
+                assert_eq!(out.len(), 6);
+                let squares: Vec<Val> = out.iter().map(|o| o * o).collect();
+                return ops::sum(&squares);
+            };
+            let checks = crate::grad_check(f, &[0.5, -0.2, 1.0, 0.3, -0.7, 0.9], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-5));
+        }
+    }
+}
@@ -0,0 +1,597 @@
+use crate::init::Init;
+use crate::rand::{self, Rng};
+use crate::{Float, GradFn, Operations, Val};
+
+use super::{indexed, indexed_rows, Module};
+
+
+// One output of a convolution: Σ x_i w_i + b over a window. The inputs are the
+// n window values, then the n matching weights, then the bias, so the whole
+// window is a single node rather than 2n.
+struct Conv;
+
+
+impl<T: Float> GradFn<T> for Conv {
+    fn op(&self) -> Operations {
+        return Operations::Conv;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        let n: usize = inputs.len() / 2;
+        let (x, w): (&[T], &[T]) = (&inputs[..n], &inputs[n..2 * n]);
+
+        return x.iter().zip(w.iter()).fold(inputs[2 * n], |acc, (&xi, &wi)| acc + xi * wi);
+    }
+
+    // d/dx_i = w_i, d/dw_i = x_i, d/db = 1
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        let n: usize = inputs.len() / 2;
+
+        let mut grads: Vec<T> = inputs[n..2 * n].iter().map(|&w| w * grad).collect();
+        grads.extend(inputs[..n].iter().map(|&x| x * grad));
+        grads.push(grad);
+
+        return grads;
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let n: usize = inputs.len() / 2;
+
+        let mut grads: Vec<Val<T>> = inputs[n..2 * n].iter().map(|w| w * grad).collect();
+        grads.extend(inputs[..n].iter().map(|x| x * grad));
+        grads.push(grad.clone());
+
+        return Some(grads);
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Conv"));
+    }
+}
+
+
+// The largest input; the gradient goes to the first one that attains it
+struct Max;
+
+
+impl<T: Float> GradFn<T> for Max {
+    fn op(&self) -> Operations {
+        return Operations::Max;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs.iter().fold(T::neg_infinity(), |acc, &x| acc.max(x));
+    }
+
+    fn backward(&self, inputs: &[T], output: T, grad: T) -> Vec<T> {
+        let mut grads: Vec<T> = vec![T::zero(); inputs.len()];
+        if let Some(i) = inputs.iter().position(|&x| x == output) {
+            grads[i] = grad;
+        }
+
+        return grads;
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let i: Option<usize> = inputs.iter().position(|x| x.data() == output.data());
+        return Some((0..inputs.len()).map(|j| if Some(j) == i { grad.clone() } else { grad * T::zero() }).collect());
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(String::from("Max"));
+    }
+}
+
+
+// Collects a window's (input, weight) pairs and builds its Conv node
+fn window<T: Float>(pairs: Vec<(Val<T>, Val<T>)>, bias: &Val<T>) -> Val<T> {
+    let (mut inputs, weights): (Vec<Val<T>>, Vec<Val<T>>) = pairs.into_iter().unzip();
+    inputs.extend(weights);
+    inputs.push(bias.clone());
+
+    return Val::apply(Conv, &inputs);
+}
+
+
+// Inputs and outputs are flattened channel by channel: [channels * length].
+// Padding adds zeros at both ends, which simply drop out of the windows.
+// It works on Vals rather than Tensors so it is a Module<T> like every other
+// layer: Tensor is f64 only and has no way in from a Val graph. Each output is
+// one Conv node, a row of the im2col matrix dotted with a row of weights.
+pub struct Conv1d<T: Float = f64> {
+    // [out_channels][in_channels * kernel_size]
+    weights:     Vec<Vec<Val<T>>>,
+    bias:        Vec<Val<T>>,
+    in_channels: usize,
+    kernel_size: usize,
+    stride:      usize,
+    padding:     usize
+}
+
+
+impl<T: Float> Conv1d<T> {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize) -> Conv1d<T> {
+        let n: usize = in_channels * kernel_size;
+        return Conv1d::from_weights(&vec![vec![T::zero(); n]; out_channels], &vec![T::zero(); out_channels], in_channels, kernel_size);
+    }
+
+    // One row of in_channels * kernel_size weights per output channel
+    pub fn from_weights(weights: &[Vec<T>], bias: &[T], in_channels: usize, kernel_size: usize) -> Conv1d<T> {
+        assert!(kernel_size > 0, "kernel size must be positive");
+        assert_eq!(weights.len(), bias.len(), "expected one bias per output channel");
+        assert!(weights.iter().all(|w| w.len() == in_channels * kernel_size), "expected {} weights per output channel", in_channels * kernel_size);
+
+        return Conv1d {
+            weights:     weights.iter().map(|w| w.iter().map(|&x| Val::new(x)).collect()).collect(),
+            bias:        bias.iter().map(|&b| Val::new(b)).collect(),
+            in_channels,
+            kernel_size,
+            stride:      1,
+            padding:     0
+        };
+    }
+
+    pub fn with_init(in_channels: usize, out_channels: usize, kernel_size: usize, init: Init, rng: &mut Rng) -> Conv1d<T> {
+        let (fan_in, fan_out): (usize, usize) = (in_channels * kernel_size, out_channels * kernel_size);
+        let weights: Vec<Vec<T>> = (0..out_channels)
+            .map(|_| init.weights(fan_in, fan_in, fan_out, rng).into_iter().map(T::from_f64).collect())
+            .collect();
+        let bias: Vec<T> = (0..out_channels).map(|_| T::from_f64(init.bias(rng))).collect();
+
+        return Conv1d::from_weights(&weights, &bias, in_channels, kernel_size);
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(in_channels: usize, out_channels: usize, kernel_size: usize, init: Init) -> Conv1d<T> {
+        return rand::with_global(|rng| Conv1d::with_init(in_channels, out_channels, kernel_size, init, rng));
+    }
+
+    pub fn stride(mut self, stride: usize) -> Conv1d<T> {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        return self;
+    }
+
+    pub fn padding(mut self, padding: usize) -> Conv1d<T> {
+        self.padding = padding;
+        return self;
+    }
+
+    pub fn in_channels(&self) -> usize {
+        return self.in_channels;
+    }
+
+    pub fn out_channels(&self) -> usize {
+        return self.bias.len();
+    }
+
+    // Length of each output channel for inputs of `length`
+    pub fn output_len(&self, length: usize) -> usize {
+        let padded: usize = length + 2 * self.padding;
+        assert!(padded >= self.kernel_size, "input of length {} is shorter than the kernel", length);
+
+        return (padded - self.kernel_size) / self.stride + 1;
+    }
+}
+
+
+impl<T: Float> Module<T> for Conv1d<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        assert!(inputs.len().is_multiple_of(self.in_channels), "Conv1d expects a multiple of {} inputs", self.in_channels);
+
+        let length: usize = inputs.len() / self.in_channels;
+        let out_len: usize = self.output_len(length);
+
+        let mut out: Vec<Val<T>> = Vec::with_capacity(self.out_channels() * out_len);
+        for (w, b) in self.weights.iter().zip(self.bias.iter()) {
+            for o in 0..out_len {
+                let mut pairs: Vec<(Val<T>, Val<T>)> = Vec::with_capacity(self.in_channels * self.kernel_size);
+                for c in 0..self.in_channels {
+                    for k in 0..self.kernel_size {
+                        // Position in the unpadded input, skipped when it falls in the padding
+                        let pos: usize = o * self.stride + k;
+                        if pos < self.padding || pos - self.padding >= length {
+                            continue;
+                        }
+                        pairs.push((inputs[c * length + pos - self.padding].clone(), w[c * self.kernel_size + k].clone()));
+                    }
+                }
+                out.push(window(pairs, b));
+            }
+        }
+
+        return out;
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.weights.iter().flatten().cloned().collect();
+        params.extend(self.bias.iter().cloned());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = indexed_rows("weight", &self.weights);
+        named.extend(indexed("bias", &self.bias));
+
+        return named;
+    }
+}
+
+
+
+// Side of a square image with n pixels
+fn side(n: usize) -> usize {
+    let s: usize = (n as f64).sqrt().round() as usize;
+    assert_eq!(s * s, n, "{} pixels is not a square image; use forward_image", n);
+
+    return s;
+}
+
+
+// Images are flattened channel by channel, each row-major: [channels * height * width].
+// forward takes square images; forward_image gives the height and width.
+pub struct Conv2d<T: Float = f64> {
+    // [out_channels][in_channels * kernel_size * kernel_size]
+    weights:     Vec<Vec<Val<T>>>,
+    bias:        Vec<Val<T>>,
+    in_channels: usize,
+    kernel_size: usize,
+    stride:      usize,
+    padding:     usize
+}
+
+
+impl<T: Float> Conv2d<T> {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize) -> Conv2d<T> {
+        let n: usize = in_channels * kernel_size * kernel_size;
+        return Conv2d::from_weights(&vec![vec![T::zero(); n]; out_channels], &vec![T::zero(); out_channels], in_channels, kernel_size);
+    }
+
+    // One row of in_channels * kernel_size^2 weights per output channel, each kernel row-major
+    pub fn from_weights(weights: &[Vec<T>], bias: &[T], in_channels: usize, kernel_size: usize) -> Conv2d<T> {
+        let n: usize = in_channels * kernel_size * kernel_size;
+        assert!(kernel_size > 0, "kernel size must be positive");
+        assert_eq!(weights.len(), bias.len(), "expected one bias per output channel");
+        assert!(weights.iter().all(|w| w.len() == n), "expected {} weights per output channel", n);
+
+        return Conv2d {
+            weights:     weights.iter().map(|w| w.iter().map(|&x| Val::new(x)).collect()).collect(),
+            bias:        bias.iter().map(|&b| Val::new(b)).collect(),
+            in_channels,
+            kernel_size,
+            stride:      1,
+            padding:     0
+        };
+    }
+
+    pub fn with_init(in_channels: usize, out_channels: usize, kernel_size: usize, init: Init, rng: &mut Rng) -> Conv2d<T> {
+        let area: usize = kernel_size * kernel_size;
+        let (fan_in, fan_out): (usize, usize) = (in_channels * area, out_channels * area);
+        let weights: Vec<Vec<T>> = (0..out_channels)
+            .map(|_| init.weights(fan_in, fan_in, fan_out, rng).into_iter().map(T::from_f64).collect())
+            .collect();
+        let bias: Vec<T> = (0..out_channels).map(|_| T::from_f64(init.bias(rng))).collect();
+
+        return Conv2d::from_weights(&weights, &bias, in_channels, kernel_size);
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(in_channels: usize, out_channels: usize, kernel_size: usize, init: Init) -> Conv2d<T> {
+        return rand::with_global(|rng| Conv2d::with_init(in_channels, out_channels, kernel_size, init, rng));
+    }
+
+    pub fn stride(mut self, stride: usize) -> Conv2d<T> {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        return self;
+    }
+
+    pub fn padding(mut self, padding: usize) -> Conv2d<T> {
+        self.padding = padding;
+        return self;
+    }
+
+    pub fn in_channels(&self) -> usize {
+        return self.in_channels;
+    }
+
+    pub fn out_channels(&self) -> usize {
+        return self.bias.len();
+    }
+
+    // (height, width) of each output channel
+    pub fn output_shape(&self, height: usize, width: usize) -> (usize, usize) {
+        let (ph, pw): (usize, usize) = (height + 2 * self.padding, width + 2 * self.padding);
+        assert!(ph >= self.kernel_size && pw >= self.kernel_size, "{}x{} image is smaller than the kernel", height, width);
+
+        return ((ph - self.kernel_size) / self.stride + 1, (pw - self.kernel_size) / self.stride + 1);
+    }
+
+    pub fn forward_image(&self, inputs: &[Val<T>], height: usize, width: usize) -> Vec<Val<T>> {
+        assert_eq!(inputs.len(), self.in_channels * height * width, "Conv2d expects {} channels of {}x{}", self.in_channels, height, width);
+
+        let (oh, ow): (usize, usize) = self.output_shape(height, width);
+        let k: usize = self.kernel_size;
+        let p: usize = self.padding;
+
+        let mut out: Vec<Val<T>> = Vec::with_capacity(self.out_channels() * oh * ow);
+        for (w, b) in self.weights.iter().zip(self.bias.iter()) {
+            for oy in 0..oh {
+                for ox in 0..ow {
+                    let mut pairs: Vec<(Val<T>, Val<T>)> = Vec::with_capacity(self.in_channels * k * k);
+                    for c in 0..self.in_channels {
+                        for ky in 0..k {
+                            for kx in 0..k {
+                                let (y, x): (usize, usize) = (oy * self.stride + ky, ox * self.stride + kx);
+                                if y < p || x < p || y - p >= height || x - p >= width {
+                                    continue;
+                                }
+                                let pixel: usize = c * height * width + (y - p) * width + (x - p);
+                                pairs.push((inputs[pixel].clone(), w[(c * k + ky) * k + kx].clone()));
+                            }
+                        }
+                    }
+                    out.push(window(pairs, b));
+                }
+            }
+        }
+
+        return out;
+    }
+}
+
+
+impl<T: Float> Module<T> for Conv2d<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        assert!(inputs.len().is_multiple_of(self.in_channels), "Conv2d expects a multiple of {} inputs", self.in_channels);
+
+        let s: usize = side(inputs.len() / self.in_channels);
+        return self.forward_image(inputs, s, s);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.weights.iter().flatten().cloned().collect();
+        params.extend(self.bias.iter().cloned());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = indexed_rows("weight", &self.weights);
+        named.extend(indexed("bias", &self.bias));
+
+        return named;
+    }
+}
+
+
+// The largest value of each window, channel by channel. The stride defaults to
+// the kernel size, so windows don't overlap.
+pub struct MaxPool2d {
+    channels:    usize,
+    kernel_size: usize,
+    stride:      usize
+}
+
+
+impl MaxPool2d {
+    pub fn new(channels: usize, kernel_size: usize) -> MaxPool2d {
+        assert!(kernel_size > 0, "kernel size must be positive");
+        return MaxPool2d { channels, kernel_size, stride: kernel_size };
+    }
+
+    pub fn stride(mut self, stride: usize) -> MaxPool2d {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        return self;
+    }
+
+    pub fn output_shape(&self, height: usize, width: usize) -> (usize, usize) {
+        assert!(height >= self.kernel_size && width >= self.kernel_size, "{}x{} image is smaller than the kernel", height, width);
+        return ((height - self.kernel_size) / self.stride + 1, (width - self.kernel_size) / self.stride + 1);
+    }
+
+    pub fn forward_image<T: Float>(&self, inputs: &[Val<T>], height: usize, width: usize) -> Vec<Val<T>> {
+        assert_eq!(inputs.len(), self.channels * height * width, "MaxPool2d expects {} channels of {}x{}", self.channels, height, width);
+
+        let (oh, ow): (usize, usize) = self.output_shape(height, width);
+        let k: usize = self.kernel_size;
+
+        let mut out: Vec<Val<T>> = Vec::with_capacity(self.channels * oh * ow);
+        for c in 0..self.channels {
+            for oy in 0..oh {
+                for ox in 0..ow {
+                    let window: Vec<Val<T>> = (0..k * k)
+                        .map(|i| (oy * self.stride + i / k, ox * self.stride + i % k))
+                        .map(|(y, x)| inputs[c * height * width + y * width + x].clone())
+                        .collect();
+                    out.push(Val::apply(Max, &window));
+                }
+            }
+        }
+
+        return out;
+    }
+}
+
+
+impl<T: Float> Module<T> for MaxPool2d {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        assert!(inputs.len().is_multiple_of(self.channels), "MaxPool2d expects a multiple of {} inputs", self.channels);
+
+        let s: usize = side(inputs.len() / self.channels);
+        return self.forward_image(inputs, s, s);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return Vec::new();
+    }
+}
+
+
+
+#[cfg(test)]
+mod conv_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        return xs.iter().map(|&x| Val::new(x)).collect();
+    }
+
+    fn datas(xs: &[Val]) -> Vec<f64> {
+        return xs.iter().map(|v| v.data()).collect();
+    }
+
+    #[test]
+    fn conv1d() {
+        {
+            let conv: Conv1d = Conv1d::from_weights(&[vec![1.0, 0.0, -1.0]], &[0.5], 1, 3);
+            let x: Vec<Val> = vals(&[1.0, 2.0, 4.0, 8.0, 16.0]);
+
+            assert_eq!(datas(&conv.forward(&x)), vec![-2.5, -5.5, -11.5]);
+            let strided: Conv1d = Conv1d::from_weights(&[vec![1.0, 0.0, -1.0]], &[0.5], 1, 3).stride(2).padding(1);
+            assert_eq!(datas(&strided.forward(&x)), vec![-1.5, -5.5, 8.5]);
+            assert_eq!(conv.output_len(5), 3);
+            assert_eq!(conv.forward(&x)[0].op(), Operations::Conv);
+        }
+
+        {
+            // Two input channels into two output channels
+            let conv: Conv1d = Conv1d::from_weights(&[vec![1.0, 1.0, 0.0, 0.0], vec![0.0, 0.0, 1.0, -1.0]], &[0.0, 1.0], 2, 2);
+            let x: Vec<Val> = vals(&[1.0, 2.0, 3.0, 10.0, 20.0, 30.0]);
+            assert_eq!(datas(&conv.forward(&x)), vec![3.0, 5.0, -9.0, -9.0]);
+            assert_eq!(conv.parameters().len(), 2 * 4 + 2);
+        }
+
+        {
+            // Kernel and input gradients against central differences
+            let f = |x: &[Val]| -> Val {
+                let conv: Conv1d = Conv1d::with_init(2, 3, 2, Init::Uniform, &mut Rng::new(21)).stride(2).padding(1);
+                let out: Vec<Val> = conv.forward(x);
+                assert_eq!(out.len(), 3 * 3);
+                let squares: Vec<Val> = out.iter().map(|o| o * o).collect();
+                return crate::ops::sum(&squares);
+            };
+            let checks = crate::grad_check(f, &[0.5, -1.0, 2.0, 0.3, 1.1, -0.4, 0.8, -0.9], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-6));
+
+            let conv: Conv1d = Conv1d::from_weights(&[vec![2.0, -1.0]], &[0.0], 1, 2);
+            let out: Vec<Val> = conv.forward(&vals(&[1.0, 3.0, 5.0]));
+            crate::ops::sum(&out).backward();
+            let params: Vec<Val> = conv.parameters();
+            assert!(approx_eq(params[0].grad(), 1.0 + 3.0));
+            assert!(approx_eq(params[1].grad(), 3.0 + 5.0));
+            assert!(approx_eq(params[2].grad(), 2.0));
+        }
+    }
+
+    #[test]
+    fn conv2d() {
+        {
+            // A 2x2 kernel picking out the main diagonal
+            let conv: Conv2d = Conv2d::from_weights(&[vec![1.0, 0.0, 0.0, 1.0]], &[0.0], 1, 2);
+            let x: Vec<Val> = vals(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+
+            assert_eq!(datas(&conv.forward(&x)), vec![6.0, 8.0, 12.0, 14.0]);
+            assert_eq!(conv.output_shape(3, 3), (2, 2));
+
+            let padded: Conv2d = Conv2d::from_weights(&[vec![1.0, 0.0, 0.0, 1.0]], &[0.0], 1, 2).padding(1).stride(2);
+            assert_eq!(padded.output_shape(3, 3), (2, 2));
+            assert_eq!(datas(&padded.forward(&x)), vec![1.0, 3.0, 7.0, 14.0]);
+
+            // Non-square images go through forward_image
+            assert_eq!(datas(&conv.forward_image(&x[..6], 2, 3)), vec![6.0, 8.0]);
+        }
+
+        {
+            let f = |x: &[Val]| -> Val {
+                let conv: Conv2d = Conv2d::with_init(2, 2, 2, Init::Uniform, &mut Rng::new(8)).padding(1);
+                let out: Vec<Val> = conv.forward(x).into_iter().map(|o| o.tanh()).collect();
+                return crate::ops::sum(&out);
+            };
+            let x: Vec<f64> = (0..8).map(|i| (i as f64 * 0.7).sin()).collect();
+            let checks = crate::grad_check(f, &x, 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn max_pool() {
+        {
+            let pool: MaxPool2d = MaxPool2d::new(2, 2);
+            let x: Vec<Val> = vals(&[
+                1.0, 5.0, 2.0, 0.0,
+                3.0, 4.0, 8.0, 1.0,
+                0.0, 0.0, 1.0, 1.0,
+                -1.0, 2.0, 1.0, 0.5,
+
+                -1.0, -2.0, 0.0, 0.0,
+                -3.0, -4.0, 0.0, 0.0,
+                0.0, 0.0, 0.0, 0.0,
+                0.0, 0.0, 0.0, 9.0
+            ]);
+            let out: Vec<Val> = pool.forward(&x);
+            assert_eq!(datas(&out), vec![5.0, 8.0, 2.0, 1.0, -1.0, 0.0, 0.0, 9.0]);
+            assert_eq!(out[0].op(), Operations::Max);
+
+            // Only the maximum of each window gets a gradient
+            crate::ops::sum(&out[..4]).backward();
+            let grads: Vec<f64> = x[..16].iter().map(|v| v.grad()).collect();
+            assert_eq!(grads.iter().sum::<f64>(), 4.0);
+            assert_eq!((grads[1], grads[6], grads[13]), (1.0, 1.0, 1.0));
+        }
+
+        {
+            let pool: MaxPool2d = MaxPool2d::new(1, 2).stride(1);
+            assert_eq!(pool.output_shape(3, 3), (2, 2));
+            assert_eq!(datas(&pool.forward(&vals(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]))), vec![5.0, 6.0, 8.0, 9.0]);
+        }
+    }
+
+    #[test]
+    fn lenet() {
+        {
+            // Vertical against horizontal bars on 6x6 images: conv, relu, pool, linear
+            use crate::loss::cross_entropy;
+            use crate::nn::Layer;
+            use crate::optim::{Adam, Optimizer};
+
+            let image = |vertical: bool, at: usize| -> Vec<f64> {
+                return (0..36).map(|i| if (if vertical { i % 6 } else { i / 6 }) == at { 1.0 } else { 0.0 }).collect();
+            };
+            let data: Vec<(Vec<f64>, usize)> = (0..6).flat_map(|at| vec![(image(true, at), 0), (image(false, at), 1)]).collect();
+
+            let mut rng: Rng = Rng::new(3);
+            let conv: Conv2d = Conv2d::with_init(1, 2, 3, Init::He, &mut rng);
+            let pool: MaxPool2d = MaxPool2d::new(2, 2);
+            let head: Layer = Layer::with_init(2 * 2 * 2, 2, false, Init::Xavier, &mut rng);
+
+            let loss = || -> Val {
+                let losses: Vec<Val> = data.iter()
+                    .map(|(x, y)| {
+                        let features: Vec<Val> = conv.forward(&vals(x)).into_iter().map(|v| v.relu()).collect();
+                        return cross_entropy(&head.forward(&pool.forward(&features)), *y);
+                    })
+                    .collect();
+                return crate::ops::mean(&losses);
+            };
+
+            let mut params: Vec<Val> = conv.parameters();
+            params.extend(head.parameters());
+            let mut opt: Adam = Adam::new(params, 0.05);
+
+            let first: f64 = loss().data();
+            for _ in 0..60 {
+                opt.zero_grad();
+                loss().backward();
+                opt.step();
+            }
+            assert!(loss().data() < first * 0.2);
+        }
+    }
+}
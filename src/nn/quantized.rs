@@ -0,0 +1,251 @@
+use std::{fs, io};
+
+use crate::Float;
+
+use super::{Activation, MLP};
+
+
+// An MLP with int8 weights, for inference only. Each layer keeps one scale,
+// max |w| / 127, so a weight is stored as round(w / scale); biases stay f64.
+// predict quantizes each layer's input the same way on the fly and takes the
+// dot products in i32, rescaling once per neuron.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedMLP {
+    layers: Vec<QuantizedLayer>
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+struct QuantizedLayer {
+    nin:         usize,
+    scale:       f64,
+    // Row-major, one row of nin per neuron
+    weights:     Vec<i8>,
+    biases:      Vec<f64>,
+    activations: Vec<Activation>
+}
+
+
+// Symmetric int8 over [-max |x|, max |x|], and the scale to undo it
+fn quantize(xs: &[f64]) -> (Vec<i8>, f64) {
+    let max: f64 = xs.iter().fold(0.0, |m: f64, x| m.max(x.abs()));
+    if max == 0.0 {
+        return (vec![0; xs.len()], 0.0);
+    }
+
+    let scale: f64 = max / 127.0;
+    return (xs.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect(), scale);
+}
+
+
+impl QuantizedMLP {
+    const MAGIC: &'static [u8; 4] = b"RNNQ";
+
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let mut x: Vec<f64> = inputs.to_vec();
+        for layer in self.layers.iter() {
+            assert_eq!(x.len(), layer.nin, "layer expects {} inputs", layer.nin);
+            let (xq, x_scale): (Vec<i8>, f64) = quantize(&x);
+            let scale: f64 = layer.scale * x_scale;
+
+            x = layer.weights.chunks(layer.nin.max(1))
+                .zip(layer.biases.iter().zip(layer.activations.iter()))
+                .map(|(row, (b, a))| {
+                    let dot: i32 = row.iter().zip(xq.iter()).map(|(&w, &xi)| w as i32 * xi as i32).sum();
+                    a.eval(b + dot as f64 * scale)
+                })
+                .collect();
+        }
+
+        return x;
+    }
+
+    pub fn num_layers(&self) -> usize {
+        return self.layers.len();
+    }
+
+    // "RNNQ", then for each layer little-endian: nin and nout as u32, the f64
+    // scale, per neuron an activation tag byte, its f64 alpha and the f64 bias,
+    // and last the nout * nin weight bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = QuantizedMLP::MAGIC.to_vec();
+        out.extend_from_slice(&(self.layers.len() as u32).to_le_bytes());
+        for layer in self.layers.iter() {
+            out.extend_from_slice(&(layer.nin as u32).to_le_bytes());
+            out.extend_from_slice(&(layer.biases.len() as u32).to_le_bytes());
+            out.extend_from_slice(&layer.scale.to_le_bytes());
+            for (a, b) in layer.activations.iter().zip(layer.biases.iter()) {
+                let (tag, alpha): (u8, f64) = match a {
+                    Activation::Identity     => (0, 0.0),
+                    Activation::Tanh         => (1, 0.0),
+                    Activation::Relu         => (2, 0.0),
+                    Activation::Sigmoid      => (3, 0.0),
+                    Activation::LeakyRelu(a) => (4, *a),
+                    Activation::Gelu         => (5, 0.0)
+                };
+                out.push(tag);
+                out.extend_from_slice(&alpha.to_le_bytes());
+                out.extend_from_slice(&b.to_le_bytes());
+            }
+            out.extend(layer.weights.iter().map(|&w| w as u8));
+        }
+
+        return out;
+    }
+
+    pub fn from_bytes(raw: &[u8]) -> Result<QuantizedMLP, String> {
+        if raw.len() < 8 || &raw[..4] != QuantizedMLP::MAGIC {
+            return Err(String::from("not a quantized model"));
+        }
+
+        let mut at: usize = 4;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            let bytes: &[u8] = raw.get(at..at + n).ok_or("quantized model is truncated")?;
+            at += n;
+            return Ok(bytes);
+        };
+        let u32_at = |b: &[u8]| -> usize { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize };
+        let f64_at = |b: &[u8]| -> f64 { f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) };
+
+        let count: usize = u32_at(take(4)?);
+        let mut layers: Vec<QuantizedLayer> = Vec::with_capacity(count.min(1024));
+        for i in 0..count {
+            let (nin, nout): (usize, usize) = (u32_at(take(4)?), u32_at(take(4)?));
+            let scale: f64 = f64_at(take(8)?);
+
+            let (mut activations, mut biases): (Vec<Activation>, Vec<f64>) = (Vec::new(), Vec::new());
+            for _ in 0..nout {
+                let tag: u8 = take(1)?[0];
+                let alpha: f64 = f64_at(take(8)?);
+                activations.push(match tag {
+                    0 => Activation::Identity,
+                    1 => Activation::Tanh,
+                    2 => Activation::Relu,
+                    3 => Activation::Sigmoid,
+                    4 => Activation::LeakyRelu(alpha),
+                    5 => Activation::Gelu,
+                    _ => return Err(format!("layer {} has unknown activation {}", i, tag))
+                });
+                biases.push(f64_at(take(8)?));
+            }
+
+            let weights: Vec<i8> = take(nin * nout)?.iter().map(|&w| w as i8).collect();
+            if let Some(prev) = layers.last() {
+                if prev.biases.len() != nin {
+                    return Err(format!("layer {} expects {} inputs but layer {} has {} outputs", i, nin, i - 1, prev.biases.len()));
+                }
+            }
+            layers.push(QuantizedLayer { nin, scale, weights, biases, activations });
+        }
+        if at != raw.len() {
+            return Err(format!("{} bytes left over after the last layer", raw.len() - at));
+        }
+
+        return Ok(QuantizedMLP { layers });
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        return fs::write(path, self.to_bytes());
+    }
+
+    pub fn load(path: &str) -> io::Result<QuantizedMLP> {
+        let raw: Vec<u8> = fs::read(path)?;
+        return QuantizedMLP::from_bytes(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+}
+
+
+impl<T: Float> MLP<T> {
+    // Post-training quantization of the current weights
+    pub fn quantize(&self) -> QuantizedMLP {
+        let layers: Vec<QuantizedLayer> = self.layers.iter()
+            .map(|layer| {
+                let w: Vec<f64> = layer.neurons.iter().flat_map(|n| n.w.iter().map(|wi| wi.data().to_f64())).collect();
+                let (weights, scale): (Vec<i8>, f64) = quantize(&w);
+                QuantizedLayer {
+                    nin:         layer.neurons.first().map_or(0, |n| n.nin()),
+                    scale,
+                    weights,
+                    biases:      layer.neurons.iter().map(|n| n.b.data().to_f64()).collect(),
+                    activations: layer.neurons.iter().map(|n| n.activation).collect()
+                }
+            })
+            .collect();
+
+        return QuantizedMLP { layers };
+    }
+
+    // predict through int8 weights, e.g. to see what quantizing costs; for
+    // repeated inference, quantize once and keep the QuantizedMLP
+    pub fn predict_quantized(&self, inputs: &[T]) -> Vec<T> {
+        let xs: Vec<f64> = inputs.iter().map(|x| x.to_f64()).collect();
+        return self.quantize().predict(&xs).into_iter().map(T::from_f64).collect();
+    }
+}
+
+
+
+#[cfg(test)]
+mod quantized_ops {
+    use super::*;
+    use crate::init::Init;
+    use crate::nn::{Layer, Neuron};
+    use crate::rand::Rng;
+
+    #[test]
+    fn int8() {
+        {
+            let (q, scale): (Vec<i8>, f64) = quantize(&[0.5, -1.27, 0.0, 1.0]);
+            assert_eq!(q, vec![50, -127, 0, 100]);
+            assert_eq!(scale, 0.01);
+            assert_eq!(quantize(&[0.0, 0.0]), (vec![0, 0], 0.0));
+        }
+
+        {
+            // Weights that are exact multiples of the scale, on inputs that are too
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![Neuron::from_weights(&[1.27, -0.5], 0.25, Activation::Relu)]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[2.0], -1.0, false)])
+            ]);
+            let out: Vec<f64> = m.predict_quantized(&[1.27, 0.5]);
+            assert!((out[0] - m.predict(&[1.27, 0.5])[0]).abs() < 1e-12);
+        }
+
+        {
+            // Close to the float model, round trips through bytes, and a quarter of
+            // the size of the f64 weights
+            let mut rng: Rng = Rng::new(9);
+            let m: MLP = MLP::from_layers(vec![
+                Layer::with_init(6, 16, Activation::Tanh, Init::Xavier, &mut rng),
+                Layer::with_init(16, 8, Activation::LeakyRelu(0.1), Init::He, &mut rng),
+                Layer::with_init(8, 2, false, Init::Xavier, &mut rng)
+            ]);
+            let q: QuantizedMLP = m.quantize();
+            assert_eq!(q.num_layers(), 3);
+
+            for i in 0..10 {
+                let x: Vec<f64> = (0..6).map(|j| ((i * 6 + j) as f64 * 0.37).sin()).collect();
+                for (a, b) in q.predict(&x).iter().zip(m.predict(&x).iter()) {
+                    assert!((a - b).abs() < 0.05, "{} vs {}", a, b);
+                }
+            }
+
+            let bytes: Vec<u8> = q.to_bytes();
+            assert_eq!(QuantizedMLP::from_bytes(&bytes), Ok(q.clone()));
+            let weights: usize = 6 * 16 + 16 * 8 + 8 * 2;
+            assert_eq!(bytes.len(), 8 + 3 * 16 + 26 * 17 + weights);
+            assert!(bytes.len() < (weights + 26) * 8 / 2);
+
+            assert_eq!(QuantizedMLP::from_bytes(b"RNNX\0\0\0\0"), Err(String::from("not a quantized model")));
+            assert_eq!(QuantizedMLP::from_bytes(&bytes[..bytes.len() - 1]), Err(String::from("quantized model is truncated")));
+            let mut extra: Vec<u8> = bytes.clone();
+            extra.push(0);
+            assert!(QuantizedMLP::from_bytes(&extra).is_err());
+
+            let path: String = std::env::temp_dir().join("rusty_nn_quantized.bin").to_string_lossy().into_owned();
+            q.save(&path).unwrap();
+            assert_eq!(QuantizedMLP::load(&path).unwrap(), q);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
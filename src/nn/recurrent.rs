@@ -0,0 +1,426 @@
+use crate::init::Init;
+use crate::rand::{self, Rng};
+use crate::{Float, Val};
+
+use super::{prefixed, Layer, Module};
+
+
+// A cell that is stepped over a sequence, carrying its state from one input to
+// the next. As a Module, forward takes the input followed by the state and
+// returns the next state, whose first hidden_size values are the output.
+pub trait Recurrent<T: Float = f64>: Module<T> {
+    fn input_size(&self) -> usize;
+
+    fn hidden_size(&self) -> usize;
+
+    // Cells that carry more than the output (LSTMCell) override this
+    fn state_size(&self) -> usize {
+        return self.hidden_size();
+    }
+
+    fn step(&self, x: &[Val<T>], state: &[Val<T>]) -> Vec<Val<T>>;
+
+    fn initial_state(&self) -> Vec<Val<T>> {
+        return (0..self.state_size()).map(|_| Val::new(T::zero())).collect();
+    }
+}
+
+
+// Steps the cell over every input in turn and returns the state after each one.
+// The steps share one graph, so backward from any of them runs through time.
+pub fn unroll<T, C>(cell: &C, xs: &[Vec<Val<T>>], state: &[Val<T>]) -> Vec<Vec<Val<T>>>
+where T: Float,
+      C: Recurrent<T> + ?Sized,
+{
+    assert_eq!(state.len(), cell.state_size(), "expected a state of {} values", cell.state_size());
+
+    let mut states: Vec<Vec<Val<T>>> = Vec::with_capacity(xs.len());
+    let mut h: Vec<Val<T>> = state.to_vec();
+    for x in xs.iter() {
+        h = cell.step(x, &h);
+        states.push(h.clone());
+    }
+
+    return states;
+}
+
+
+// The outputs of unroll, without any extra state the cell carries
+pub fn hidden_outputs<T, C>(cell: &C, states: &[Vec<Val<T>>]) -> Vec<Vec<Val<T>>>
+where T: Float,
+      C: Recurrent<T> + ?Sized,
+{
+    return states.iter().map(|s| s[..cell.hidden_size()].to_vec()).collect();
+}
+
+
+fn concat<T: Float>(a: &[Val<T>], b: &[Val<T>]) -> Vec<Val<T>> {
+    let mut out: Vec<Val<T>> = a.to_vec();
+    out.extend_from_slice(b);
+
+    return out;
+}
+
+
+// h' = tanh(W [x, h] + b)
+pub struct RNNCell<T: Float = f64> {
+    layer:      Layer<T>,
+    input_size: usize
+}
+
+
+impl<T: Float> RNNCell<T> {
+    pub fn new(input_size: usize, hidden_size: usize) -> RNNCell<T> {
+        return RNNCell { layer: Layer::new(input_size + hidden_size, hidden_size, true), input_size };
+    }
+
+    pub fn with_init(input_size: usize, hidden_size: usize, init: Init, rng: &mut Rng) -> RNNCell<T> {
+        let layer: Layer<T> = Layer::with_init(input_size + hidden_size, hidden_size, true, init, rng);
+        return RNNCell { layer, input_size };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(input_size: usize, hidden_size: usize, init: Init) -> RNNCell<T> {
+        return rand::with_global(|rng| RNNCell::with_init(input_size, hidden_size, init, rng));
+    }
+}
+
+
+impl<T: Float> Recurrent<T> for RNNCell<T> {
+    fn input_size(&self) -> usize {
+        return self.input_size;
+    }
+
+    fn hidden_size(&self) -> usize {
+        return self.layer.nout();
+    }
+
+    fn step(&self, x: &[Val<T>], state: &[Val<T>]) -> Vec<Val<T>> {
+        assert_eq!(x.len(), self.input_size, "RNNCell expects {} inputs", self.input_size);
+        return self.layer.forward(&concat(x, state));
+    }
+}
+
+
+impl<T: Float> Module<T> for RNNCell<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let (x, state): (&[Val<T>], &[Val<T>]) = inputs.split_at(self.input_size.min(inputs.len()));
+        return self.step(x, state);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.layer.parameters();
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return self.layer.named_parameters();
+    }
+}
+
+
+
+// Gates i, f, o = σ(·) and candidate g = tanh(·), each of W [x, h] + b;
+// c' = f c + i g and h' = o tanh(c'). The state is h followed by c.
+pub struct LSTMCell<T: Float = f64> {
+    gates:      Layer<T>,
+    input_size: usize
+}
+
+
+impl<T: Float> LSTMCell<T> {
+    pub fn new(input_size: usize, hidden_size: usize) -> LSTMCell<T> {
+        return LSTMCell { gates: Layer::new(input_size + hidden_size, 4 * hidden_size, false), input_size };
+    }
+
+    pub fn with_init(input_size: usize, hidden_size: usize, init: Init, rng: &mut Rng) -> LSTMCell<T> {
+        let gates: Layer<T> = Layer::with_init(input_size + hidden_size, 4 * hidden_size, false, init, rng);
+        return LSTMCell { gates, input_size };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(input_size: usize, hidden_size: usize, init: Init) -> LSTMCell<T> {
+        return rand::with_global(|rng| LSTMCell::with_init(input_size, hidden_size, init, rng));
+    }
+}
+
+
+impl<T: Float> Recurrent<T> for LSTMCell<T> {
+    fn input_size(&self) -> usize {
+        return self.input_size;
+    }
+
+    fn hidden_size(&self) -> usize {
+        return self.gates.nout() / 4;
+    }
+
+    fn state_size(&self) -> usize {
+        return 2 * self.hidden_size();
+    }
+
+    fn step(&self, x: &[Val<T>], state: &[Val<T>]) -> Vec<Val<T>> {
+        assert_eq!(x.len(), self.input_size, "LSTMCell expects {} inputs", self.input_size);
+
+        let n: usize = self.hidden_size();
+        let (h, c): (&[Val<T>], &[Val<T>]) = state.split_at(n);
+        let z: Vec<Val<T>> = self.gates.forward(&concat(x, h));
+
+        let mut hs: Vec<Val<T>> = Vec::with_capacity(n);
+        let mut cs: Vec<Val<T>> = Vec::with_capacity(n);
+        for j in 0..n {
+            let i: Val<T> = z[j].clone().sigmoid();
+            let f: Val<T> = z[n + j].clone().sigmoid();
+            let g: Val<T> = z[2 * n + j].clone().tanh();
+            let o: Val<T> = z[3 * n + j].clone().sigmoid();
+
+            let c_next: Val<T> = &(&f * &c[j]) + &(&i * &g);
+            hs.push(&o * &c_next.clone().tanh());
+            cs.push(c_next);
+        }
+
+        hs.extend(cs);
+        return hs;
+    }
+}
+
+
+impl<T: Float> Module<T> for LSTMCell<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let (x, state): (&[Val<T>], &[Val<T>]) = inputs.split_at(self.input_size.min(inputs.len()));
+        return self.step(x, state);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        return self.gates.parameters();
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        return prefixed("gates", self.gates.named_parameters());
+    }
+}
+
+
+
+// Update z and reset r = σ(W [x, h] + b), candidate n = tanh(W [x, r h] + b),
+// then h' = (1 - z) n + z h
+pub struct GRUCell<T: Float = f64> {
+    gates:      Layer<T>,
+    candidate:  Layer<T>,
+    input_size: usize
+}
+
+
+impl<T: Float> GRUCell<T> {
+    pub fn new(input_size: usize, hidden_size: usize) -> GRUCell<T> {
+        return GRUCell {
+            gates:      Layer::new(input_size + hidden_size, 2 * hidden_size, false),
+            candidate:  Layer::new(input_size + hidden_size, hidden_size, true),
+            input_size
+        };
+    }
+
+    pub fn with_init(input_size: usize, hidden_size: usize, init: Init, rng: &mut Rng) -> GRUCell<T> {
+        return GRUCell {
+            gates:      Layer::with_init(input_size + hidden_size, 2 * hidden_size, false, init, rng),
+            candidate:  Layer::with_init(input_size + hidden_size, hidden_size, true, init, rng),
+            input_size
+        };
+    }
+
+    // Draws from the global generator, see set_seed
+    pub fn random(input_size: usize, hidden_size: usize, init: Init) -> GRUCell<T> {
+        return rand::with_global(|rng| GRUCell::with_init(input_size, hidden_size, init, rng));
+    }
+}
+
+
+impl<T: Float> Recurrent<T> for GRUCell<T> {
+    fn input_size(&self) -> usize {
+        return self.input_size;
+    }
+
+    fn hidden_size(&self) -> usize {
+        return self.candidate.nout();
+    }
+
+    fn step(&self, x: &[Val<T>], state: &[Val<T>]) -> Vec<Val<T>> {
+        assert_eq!(x.len(), self.input_size, "GRUCell expects {} inputs", self.input_size);
+
+        let n: usize = self.hidden_size();
+        let gates: Vec<Val<T>> = self.gates.forward(&concat(x, state));
+        let z: Vec<Val<T>> = gates[..n].iter().map(|g| g.clone().sigmoid()).collect();
+        let reset: Vec<Val<T>> = gates[n..].iter().zip(state.iter()).map(|(g, h)| &g.clone().sigmoid() * h).collect();
+        let candidate: Vec<Val<T>> = self.candidate.forward(&concat(x, &reset));
+
+        return candidate.iter()
+            .zip(z.iter().zip(state.iter()))
+            .map(|(c, (z, h))| c + &(z * &(h - c)))
+            .collect();
+    }
+}
+
+
+impl<T: Float> Module<T> for GRUCell<T> {
+    fn forward(&self, inputs: &[Val<T>]) -> Vec<Val<T>> {
+        let (x, state): (&[Val<T>], &[Val<T>]) = inputs.split_at(self.input_size.min(inputs.len()));
+        return self.step(x, state);
+    }
+
+    fn parameters(&self) -> Vec<Val<T>> {
+        let mut params: Vec<Val<T>> = self.gates.parameters();
+        params.extend(self.candidate.parameters());
+
+        return params;
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Val<T>)> {
+        let mut named: Vec<(String, Val<T>)> = prefixed("gates", self.gates.named_parameters());
+        named.extend(prefixed("candidate", self.candidate.named_parameters()));
+
+        return named;
+    }
+}
+
+
+
+#[cfg(test)]
+mod recurrent_ops {
+    use super::*;
+    use crate::nn::Neuron;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        return xs.iter().map(|&x| Val::new(x)).collect();
+    }
+
+    #[test]
+    fn rnn() {
+        {
+            // One hidden unit: h' = tanh(0.5 x - h + 0.1)
+            let cell: RNNCell = RNNCell { layer: Layer::from_neurons(vec![Neuron::from_weights(&[0.5, -1.0], 0.1, true)]), input_size: 1 };
+            let xs: Vec<Vec<Val>> = vec![vals(&[1.0]), vals(&[2.0]), vals(&[-1.0])];
+            let states: Vec<Vec<Val>> = unroll(&cell, &xs, &cell.initial_state());
+
+            let mut h: f64 = 0.0;
+            for (x, s) in [1.0, 2.0, -1.0].iter().zip(states.iter()) {
+                h = (0.5 * x - h + 0.1).tanh();
+                assert!(approx_eq(s[0].data(), h));
+            }
+
+            assert_eq!(cell.forward(&vals(&[1.0, 0.0]))[0].data(), states[0][0].data());
+            assert_eq!(hidden_outputs(&cell, &states).len(), 3);
+        }
+
+        {
+            // Backprop through time reaches the first input
+            let cell: RNNCell = RNNCell::with_init(2, 3, Init::Xavier, &mut Rng::new(4));
+            assert_eq!(cell.parameters().len(), 3 * 6);
+
+            let f = |x: &[Val]| -> Val {
+                let cell: RNNCell = RNNCell::with_init(2, 3, Init::Xavier, &mut Rng::new(4));
+                let xs: Vec<Vec<Val>> = x.chunks(2).map(|c| c.to_vec()).collect();
+                let states: Vec<Vec<Val>> = unroll(&cell, &xs, &cell.initial_state());
+                return crate::ops::sum(&states[states.len() - 1]);
+            };
+            let checks = crate::grad_check(f, &[0.3, -0.8, 1.1, 0.2, -0.5, 0.9], 1e-6);
+            assert!(checks[0].analytic != 0.0);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn lstm() {
+        {
+            // One unit with every gate weight set by hand
+            let gate = |w: &[f64], b: f64| -> Neuron { Neuron::from_weights(w, b, false) };
+            let cell: LSTMCell = LSTMCell {
+                gates: Layer::from_neurons(vec![
+                    gate(&[1.0, 0.5], 0.0),
+                    gate(&[-0.5, 1.0], 1.0),
+                    gate(&[2.0, -1.0], 0.1),
+                    gate(&[0.3, 0.3], -0.2)
+                ]),
+                input_size: 1
+            };
+            assert_eq!(cell.hidden_size(), 1);
+            assert_eq!(cell.state_size(), 2);
+
+            let sigmoid = |z: f64| -> f64 { 1.0 / (1.0 + (-z).exp()) };
+            let (mut h, mut c): (f64, f64) = (0.0, 0.0);
+            let xs: Vec<Vec<Val>> = vec![vals(&[0.5]), vals(&[-1.0])];
+            let states: Vec<Vec<Val>> = unroll(&cell, &xs, &cell.initial_state());
+
+            for (x, s) in [0.5, -1.0].iter().zip(states.iter()) {
+                let i: f64 = sigmoid(x + 0.5 * h);
+                let f: f64 = sigmoid(-0.5 * x + h + 1.0);
+                let g: f64 = (2.0 * x - h + 0.1).tanh();
+                let o: f64 = sigmoid(0.3 * x + 0.3 * h - 0.2);
+                c = f * c + i * g;
+                h = o * c.tanh();
+
+                assert!(approx_eq(s[0].data(), h));
+                assert!(approx_eq(s[1].data(), c));
+            }
+        }
+
+        {
+            let cell: LSTMCell = LSTMCell::random(3, 2, Init::Uniform);
+            assert_eq!(cell.parameters().len(), 8 * 6);
+
+            let f = |x: &[Val]| -> Val {
+                let cell: LSTMCell = LSTMCell::with_init(1, 2, Init::Uniform, &mut Rng::new(11));
+                let xs: Vec<Vec<Val>> = x.iter().map(|xi| vec![xi.clone()]).collect();
+                let states: Vec<Vec<Val>> = unroll(&cell, &xs, &cell.initial_state());
+                let out: Vec<Vec<Val>> = hidden_outputs(&cell, &states);
+                return &out[3][0] - &out[3][1];
+            };
+            let checks = crate::grad_check(f, &[0.4, -1.0, 0.7, 1.5], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+
+    #[test]
+    fn gru() {
+        {
+            let cell: GRUCell = GRUCell {
+                gates:      Layer::from_neurons(vec![
+                    Neuron::from_weights(&[1.0, -1.0], 0.2, false),
+                    Neuron::from_weights(&[0.5, 0.5], 0.0, false)
+                ]),
+                candidate:  Layer::from_neurons(vec![Neuron::from_weights(&[-1.0, 2.0], 0.1, true)]),
+                input_size: 1
+            };
+
+            let sigmoid = |z: f64| -> f64 { 1.0 / (1.0 + (-z).exp()) };
+            let mut h: f64 = 0.3;
+            let states: Vec<Vec<Val>> = unroll(&cell, &[vals(&[1.0]), vals(&[-0.5])], &vals(&[0.3]));
+
+            for (x, s) in [1.0, -0.5].iter().zip(states.iter()) {
+                let z: f64 = sigmoid(x - h + 0.2);
+                let r: f64 = sigmoid(0.5 * x + 0.5 * h);
+                let n: f64 = (-x + 2.0 * r * h + 0.1).tanh();
+                h = (1.0 - z) * n + z * h;
+
+                assert!(approx_eq(s[0].data(), h));
+            }
+        }
+
+        {
+            let cell: GRUCell = GRUCell::new(2, 3);
+            assert_eq!(cell.parameters().len(), 6 * 6 + 3 * 6);
+            assert_eq!(cell.state_size(), 3);
+
+            // The same helpers drive any cell, including through a trait object
+            let f = |x: &[Val]| -> Val {
+                let cell: Box<dyn Recurrent> = Box::new(GRUCell::with_init(2, 2, Init::Uniform, &mut Rng::new(12)));
+                let xs: Vec<Vec<Val>> = x.chunks(2).map(|c| c.to_vec()).collect();
+                let states: Vec<Vec<Val>> = unroll(cell.as_ref(), &xs, &cell.initial_state());
+                return &states[2][0] * &states[2][1];
+            };
+            let checks = crate::grad_check(f, &[0.4, -1.0, 0.7, 1.5, -0.2, 0.8], 1e-6);
+            assert!(checks.iter().all(|c| c.rel_error < 1e-6));
+        }
+    }
+}
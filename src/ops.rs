@@ -0,0 +1,287 @@
+use crate::{Float, GradFn, Operations, Val};
+
+
+// Output `index` of a softmax over all of the inputs
+struct Softmax {
+    index: usize
+}
+
+
+impl<T: Float> GradFn<T> for Softmax {
+    fn op(&self) -> Operations {
+        return Operations::Softmax;
+    }
+
+    // Shifted by the max so exp() never overflows
+    fn forward(&self, inputs: &[T]) -> T {
+        let m: T = inputs.iter().fold(T::neg_infinity(), |acc, &x| acc.max(x));
+        let total: T = inputs.iter().fold(T::zero(), |acc, &x| acc + (x - m).exp());
+        return (inputs[self.index] - m).exp() / total;
+    }
+
+    // d s_i / d x_j = s_i (δij - s_j)
+    fn backward(&self, inputs: &[T], s_i: T, grad: T) -> Vec<T> {
+        let m: T = inputs.iter().fold(T::neg_infinity(), |acc, &x| acc.max(x));
+        let total: T = inputs.iter().fold(T::zero(), |acc, &x| acc + (x - m).exp());
+
+        return inputs.iter()
+            .enumerate()
+            .map(|(j, &x)| {
+                let delta: T = if j == self.index { T::one() } else { T::zero() };
+                grad * s_i * (delta - (x - m).exp() / total)
+            })
+            .collect();
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], s_i: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        let g: Val<T> = grad * s_i;
+        return Some(softmax(inputs)
+            .iter()
+            .enumerate()
+            .map(|(j, s_j)| if j == self.index { &g * &(-s_j + T::one()) } else { &g * &-s_j })
+            .collect());
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(format!("Softmax {}", self.index));
+    }
+}
+
+
+// Every output depends on every input
+pub fn softmax<T: Float>(xs: &[Val<T>]) -> Vec<Val<T>> {
+    return (0..xs.len()).map(|index| Val::apply(Softmax { index }, xs)).collect();
+}
+
+
+// Output `index` of a log-softmax, x_i - log Σ e^x_j
+struct LogSoftmax {
+    index: usize
+}
+
+
+// The shifted log-sum-exp, log Σ e^x_j
+fn logsumexp<T: Float>(xs: &[T]) -> T {
+    let m: T = xs.iter().fold(T::neg_infinity(), |acc, &x| acc.max(x));
+    return m + xs.iter().fold(T::zero(), |acc, &x| acc + (x - m).exp()).ln();
+}
+
+
+impl<T: Float> GradFn<T> for LogSoftmax {
+    fn op(&self) -> Operations {
+        return Operations::LogSoftmax;
+    }
+
+    fn forward(&self, inputs: &[T]) -> T {
+        return inputs[self.index] - logsumexp(inputs);
+    }
+
+    // d/dx_j = δij - s_j, one pass without the softmax Jacobian
+    fn backward(&self, inputs: &[T], _output: T, grad: T) -> Vec<T> {
+        let lse: T = logsumexp(inputs);
+
+        return inputs.iter()
+            .enumerate()
+            .map(|(j, &x)| {
+                let delta: T = if j == self.index { T::one() } else { T::zero() };
+                grad * (delta - (x - lse).exp())
+            })
+            .collect();
+    }
+
+    fn backward_graph(&self, inputs: &[Val<T>], _output: &Val<T>, grad: &Val<T>) -> Option<Vec<Val<T>>> {
+        return Some(softmax(inputs)
+            .iter()
+            .enumerate()
+            .map(|(j, s_j)| if j == self.index { grad * &(-s_j + T::one()) } else { grad * &-s_j })
+            .collect());
+    }
+
+    fn dedup_key(&self) -> Option<String> {
+        return Some(format!("LogSoftmax {}", self.index));
+    }
+}
+
+
+// Stable where softmax followed by log underflows to log(0)
+pub fn log_softmax<T: Float>(xs: &[Val<T>]) -> Vec<Val<T>> {
+    return (0..xs.len()).map(|index| Val::apply(LogSoftmax { index }, xs)).collect();
+}
+
+
+
+// Pairs up halves recursively, so the graph is log2(n) deep rather than n.
+// An empty slice sums to a constant zero.
+pub fn sum<T: Float>(xs: &[Val<T>]) -> Val<T> {
+    return match xs.len() {
+        0 => Val::new(T::zero()),
+        1 => xs[0].clone(),
+        n => sum(&xs[..n / 2]) + sum(&xs[n / 2..])
+    };
+}
+
+
+pub fn mean<T: Float>(xs: &[Val<T>]) -> Val<T> {
+    assert!(!xs.is_empty(), "mean of an empty slice");
+    return sum(xs) * T::from_f64(1.0 / xs.len() as f64);
+}
+
+
+#[cfg(test)]
+mod ops_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    fn vals(xs: &[f64]) -> Vec<Val> {
+        return xs.iter().map(|&x| Val::new(x)).collect();
+    }
+
+    #[test]
+    fn softmax_values() {
+        {
+            let s: Vec<Val> = softmax(&vals(&[1.0, 2.0, 3.0]));
+            let e: Vec<f64> = vec![1.0_f64.exp(), 2.0_f64.exp(), 3.0_f64.exp()];
+            let total: f64 = e.iter().sum();
+
+            for (si, ei) in s.iter().zip(e.iter()) {
+                assert!(approx_eq(si.data(), ei / total));
+            }
+            assert!(approx_eq(s.iter().map(|v| v.data()).sum(), 1.0));
+            assert_eq!(s[0].op(), Operations::Softmax);
+            assert_eq!(s[0].prev().len(), 3);
+        }
+
+        {
+            // Large logits don't overflow
+            let s: Vec<Val> = softmax(&vals(&[1000.0, 1000.0]));
+            assert!(approx_eq(s[0].data(), 0.5));
+            assert!(approx_eq(s[1].data(), 0.5));
+
+            assert!(softmax::<f64>(&[]).is_empty());
+        }
+    }
+
+    #[test]
+    fn softmax_grads() {
+        {
+            // A single output: d s_0 / d x_j = s_0 (δ0j - s_j)
+            let x: Vec<Val> = vals(&[0.5, -1.0, 2.0]);
+            let s: Vec<Val> = softmax(&x);
+            s[0].backward();
+
+            let p: Vec<f64> = s.iter().map(|v| v.data()).collect();
+            assert!(approx_eq(x[0].grad(), p[0] * (1.0 - p[0])));
+            assert!(approx_eq(x[1].grad(), -p[0] * p[1]));
+            assert!(approx_eq(x[2].grad(), -p[0] * p[2]));
+        }
+
+        {
+            // The outputs sum to one, so the sum has no gradient
+            let x: Vec<Val> = vals(&[0.3, 1.7, -0.4]);
+            let s: Vec<Val> = softmax(&x);
+            let total: Val = &(&s[0] + &s[1]) + &s[2];
+            total.backward();
+
+            assert!(x.iter().all(|v| v.grad().abs() < 1e-12));
+        }
+
+        {
+            // Weighted outputs: dL/dx_j = s_j (w_j - Σ w_i s_i)
+            let x: Vec<Val> = vals(&[0.3, 1.7, -0.4]);
+            let w: [f64; 3] = [2.0, -1.0, 0.5];
+            let s: Vec<Val> = softmax(&x);
+            let loss: Val = &(&(&s[0] * w[0]) + &(&s[1] * w[1])) + &(&s[2] * w[2]);
+            loss.backward();
+
+            let p: Vec<f64> = s.iter().map(|v| v.data()).collect();
+            let mean: f64 = (0..3).map(|i| w[i] * p[i]).sum();
+            for j in 0..3 {
+                assert!(approx_eq(x[j].grad(), p[j] * (w[j] - mean)));
+            }
+        }
+    }
+
+    #[test]
+    fn log_softmax_values() {
+        {
+            let x: Vec<Val> = vals(&[0.5, -1.0, 2.0]);
+            let ls: Vec<Val> = log_softmax(&x);
+            let s: Vec<Val> = softmax(&x);
+
+            for (l, p) in ls.iter().zip(s.iter()) {
+                assert!(approx_eq(l.data(), p.data().ln()));
+            }
+            assert_eq!(ls[0].op(), Operations::LogSoftmax);
+
+            // d ls_1 / d x_j = δ1j - s_j
+            ls[1].backward();
+            for (j, xj) in x.iter().enumerate() {
+                let delta: f64 = if j == 1 { 1.0 } else { 0.0 };
+                assert!(approx_eq(xj.grad(), delta - s[j].data()));
+            }
+        }
+
+        {
+            // Far-apart logits: softmax then log gives -inf, log_softmax the exact gap
+            let x: Vec<Val> = vals(&[0.0, 1000.0]);
+            assert_eq!(log_softmax(&x)[0].data(), -1000.0);
+            assert!(softmax(&x)[0].clone().log().data().is_infinite());
+
+            // nll over it is cross_entropy, gradients included
+            use crate::loss::{cross_entropy, nll};
+            let a: Vec<Val> = vals(&[1.0, 3.0, -2.0]);
+            let b: Vec<Val> = vals(&[1.0, 3.0, -2.0]);
+            let la: Val = nll(&log_softmax(&a), 0);
+            let lb: Val = cross_entropy(&b, 0);
+            assert!(approx_eq(la.data(), lb.data()));
+
+            la.backward();
+            lb.backward();
+            for (ai, bi) in a.iter().zip(b.iter()) {
+                assert!(approx_eq(ai.grad(), bi.grad()));
+            }
+        }
+    }
+
+    fn depth(v: &Val) -> usize {
+        return 1 + v.prev().iter().map(depth).max().unwrap_or(0);
+    }
+
+    #[test]
+    fn reductions() {
+        {
+            let xs: Vec<Val> = vals(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+            let s: Val = sum(&xs);
+            let m: Val = mean(&xs);
+
+            assert!(approx_eq(s.data(), 15.0));
+            assert!(approx_eq(m.data(), 3.0));
+
+            m.backward();
+            assert!(xs.iter().all(|x| approx_eq(x.grad(), 0.2)));
+        }
+
+        {
+            // Balanced: 1024 leaves are 11 levels deep, not 1024
+            let xs: Vec<Val> = (0..1024).map(|i| Val::new(i as f64)).collect();
+            let s: Val = sum(&xs);
+
+            assert_eq!(s.data(), 1023.0 * 1024.0 / 2.0);
+            assert_eq!(depth(&s), 11);
+        }
+
+        {
+            let x: Val = Val::new(2.0);
+            assert_eq!(sum(std::slice::from_ref(&x)).data(), 2.0);
+            assert_eq!(sum::<f64>(&[]).data(), 0.0);
+
+            // Repeated inputs accumulate
+            let s: Val = sum(&[x.clone(), x.clone(), x.clone()]);
+            s.backward();
+            assert_eq!(x.grad(), 3.0);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::{Float, Val};
+
+pub mod scheduler;
+
+
+// Shared surface of every optimizer, so the training loop and schedulers can drive any of them
+pub trait Optimizer {
+    fn step(&mut self);
+
+    fn zero_grad(&mut self);
+
+    fn lr(&self) -> f64;
+
+    fn set_lr(&mut self, lr: f64);
+
+    // Everything step() depends on besides the parameters, flattened for checkpoints
+    fn state(&self) -> Vec<f64>;
+
+    fn load_state(&mut self, state: &[f64]) -> Result<(), String>;
+}
+
+
+pub struct SGD<T: Float = f64> {
+    params:   Vec<Val<T>>,
+    lr:       f64,
+    momentum: f64,
+    velocity: Vec<T>
+}
+
+
+impl<T: Float> SGD<T> {
+    pub fn new(params: Vec<Val<T>>, lr: f64) -> SGD<T> {
+        return SGD::with_momentum(params, lr, 0.0);
+    }
+
+    pub fn with_momentum(params: Vec<Val<T>>, lr: f64, momentum: f64) -> SGD<T> {
+        let velocity: Vec<T> = vec![T::zero(); params.len()];
+        return SGD { params, lr, momentum, velocity };
+    }
+}
+
+
+impl<T: Float> Optimizer for SGD<T> {
+    fn step(&mut self) {
+        for (p, v) in self.params.iter().zip(self.velocity.iter_mut()) {
+            if !p.requires_grad() {
+                continue;
+            }
+            *v = T::from_f64(self.momentum) * *v + p.grad();
+            p.set_data(p.data() - T::from_f64(self.lr) * *v);
+        }
+    }
+
+    fn zero_grad(&mut self) {
+        for p in self.params.iter() {
+            p.set_grad(T::zero());
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    // [lr, velocity..]
+    fn state(&self) -> Vec<f64> {
+        let mut state: Vec<f64> = vec![self.lr];
+        state.extend(self.velocity.iter().map(|v| v.to_f64()));
+
+        return state;
+    }
+
+    fn load_state(&mut self, state: &[f64]) -> Result<(), String> {
+        if state.len() != 1 + self.velocity.len() {
+            return Err(format!("SGD state has {} values, expected {}", state.len(), 1 + self.velocity.len()));
+        }
+
+        self.lr = state[0];
+        self.velocity = state[1..].iter().map(|&v| T::from_f64(v)).collect();
+
+        return Ok(());
+    }
+}
+
+
+pub struct Adam<T: Float = f64> {
+    params:       Vec<Val<T>>,
+    lr:           f64,
+    beta1:        f64,
+    beta2:        f64,
+    eps:          f64,
+    weight_decay: f64,
+    t:            i32,
+    m:            Vec<T>,
+    v:            Vec<T>
+}
+
+
+impl<T: Float> Adam<T> {
+    pub fn new(params: Vec<Val<T>>, lr: f64) -> Adam<T> {
+        let n: usize = params.len();
+        return Adam {
+            params, lr, beta1: 0.9, beta2: 0.999, eps: 1e-8, weight_decay: 0.0,
+            t: 0, m: vec![T::zero(); n], v: vec![T::zero(); n]
+        };
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Adam<T> {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> Adam<T> {
+        self.eps = eps;
+        return self;
+    }
+
+    // Decoupled from the gradient, as in AdamW
+    pub fn weight_decay(mut self, weight_decay: f64) -> Adam<T> {
+        self.weight_decay = weight_decay;
+        return self;
+    }
+}
+
+
+impl<T: Float> Optimizer for Adam<T> {
+    fn step(&mut self) {
+        self.t += 1;
+        let bc1: f64 = 1.0 - self.beta1.powi(self.t);
+        let bc2: f64 = 1.0 - self.beta2.powi(self.t);
+
+        let (b1, b2): (T, T) = (T::from_f64(self.beta1), T::from_f64(self.beta2));
+        let (lr, eps, wd): (T, T, T) = (T::from_f64(self.lr), T::from_f64(self.eps), T::from_f64(self.weight_decay));
+
+        for (i, p) in self.params.iter().enumerate() {
+            if !p.requires_grad() {
+                continue;
+            }
+            let g: T = p.grad();
+            self.m[i] = b1 * self.m[i] + (T::one() - b1) * g;
+            self.v[i] = b2 * self.v[i] + (T::one() - b2) * g * g;
+
+            let m_hat: T = self.m[i] / T::from_f64(bc1);
+            let v_hat: T = self.v[i] / T::from_f64(bc2);
+
+            let mut d: T = p.data();
+            d = d - lr * wd * d;
+            d = d - lr * m_hat / (v_hat.sqrt() + eps);
+            p.set_data(d);
+        }
+    }
+
+    fn zero_grad(&mut self) {
+        for p in self.params.iter() {
+            p.set_grad(T::zero());
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    // [lr, t, m.., v..]
+    fn state(&self) -> Vec<f64> {
+        let mut state: Vec<f64> = vec![self.lr, self.t as f64];
+        state.extend(self.m.iter().map(|m| m.to_f64()));
+        state.extend(self.v.iter().map(|v| v.to_f64()));
+
+        return state;
+    }
+
+    fn load_state(&mut self, state: &[f64]) -> Result<(), String> {
+        let n: usize = self.params.len();
+        if state.len() != 2 + 2 * n {
+            return Err(format!("Adam state has {} values, expected {}", state.len(), 2 + 2 * n));
+        }
+
+        self.lr = state[0];
+        self.t = state[1] as i32;
+        self.m = state[2..2 + n].iter().map(|&m| T::from_f64(m)).collect();
+        self.v = state[2 + n..].iter().map(|&v| T::from_f64(v)).collect();
+
+        return Ok(());
+    }
+}
+
+
+
+// Rescales every gradient together so their L2 norm is at most max_norm.
+// Call between backward() and step(); returns the norm before clipping.
+pub fn clip_grad_norm<T: Float>(params: &[Val<T>], max_norm: f64) -> f64 {
+    let norm: f64 = params.iter().map(|p| p.grad().to_f64().powi(2)).sum::<f64>().sqrt();
+    if norm > max_norm {
+        let scale: T = T::from_f64(max_norm / norm);
+        for p in params.iter() {
+            p.set_grad(p.grad() * scale);
+        }
+    }
+
+    return norm;
+}
+
+
+// Clamps each gradient into [-max_abs, max_abs]
+pub fn clip_grad_value<T: Float>(params: &[Val<T>], max_abs: f64) {
+    let (lo, hi): (T, T) = (T::from_f64(-max_abs), T::from_f64(max_abs));
+    for p in params.iter() {
+        p.set_grad(p.grad().max(lo).min(hi));
+    }
+}
+
+
+// Polyak averaging, target ← tau source + (1 - tau) target, parameter by
+// parameter: the slow-moving target network of DQN-style methods, or an EMA
+// of the weights with tau = 1 - decay. tau = 1 copies source outright.
+pub fn ema_update<T: Float>(target: &[Val<T>], source: &[Val<T>], tau: f64) {
+    assert_eq!(target.len(), source.len(), "expected one source parameter per target parameter");
+    assert!((0.0..=1.0).contains(&tau), "tau must be in [0, 1]");
+
+    let (keep, take): (T, T) = (T::from_f64(1.0 - tau), T::from_f64(tau));
+    for (t, s) in target.iter().zip(source.iter()) {
+        t.set_data(take * s.data() + keep * t.data());
+    }
+}
+
+
+#[cfg(test)]
+mod optim_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn sgd() {
+        {
+            let w: Val = Val::new(2.0);
+            let mut opt: SGD = SGD::new(vec![w.clone()], 0.1);
+
+            let loss: Val = &w * 3.0;
+            loss.backward();
+            opt.step();
+            assert!(approx_eq(w.data(), 1.7));
+
+            opt.zero_grad();
+            assert_eq!(w.grad(), 0.0);
+        }
+
+        {
+            let w: Val = Val::new(2.0);
+            let mut opt: SGD = SGD::with_momentum(vec![w.clone()], 0.1, 0.9);
+
+            for _ in 0..2 {
+                opt.zero_grad();
+                let loss: Val = &w * 3.0;
+                loss.backward();
+                opt.step();
+            }
+
+            // v1 = 3, v2 = 0.9 * 3 + 3
+            assert!(approx_eq(w.data(), 2.0 - 0.1 * 3.0 - 0.1 * 5.7));
+        }
+
+        {
+            // Minimise (w - 4)^2
+            let w: Val = Val::new(0.0);
+            let mut opt: SGD = SGD::new(vec![w.clone()], 0.1);
+
+            for _ in 0..200 {
+                opt.zero_grad();
+                let d: Val = &w - 4.0;
+                let loss: Val = &d * &d;
+                loss.backward();
+                opt.step();
+            }
+
+            assert!((w.data() - 4.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn adam() {
+        {
+            // The first bias-corrected step moves every parameter by exactly lr
+            let a: Val = Val::new(1.0);
+            let b: Val = Val::new(1.0);
+            let mut opt: Adam = Adam::new(vec![a.clone(), b.clone()], 0.01);
+
+            let loss: Val = &a * 100.0 + &b * -0.001;
+            loss.backward();
+            opt.step();
+
+            assert!((a.data() - 0.99).abs() < 1e-8);
+            assert!((b.data() - 1.01).abs() < 1e-4);
+        }
+
+        {
+            // Decoupled weight decay shrinks the weight even with zero gradient
+            let w: Val = Val::new(2.0);
+            let mut opt: Adam = Adam::new(vec![w.clone()], 0.1).weight_decay(0.5);
+            opt.step();
+
+            assert!(approx_eq(w.data(), 1.9));
+        }
+
+        {
+            // Minimise (w - 4)^2
+            let w: Val = Val::new(0.0);
+            let mut opt: Adam = Adam::new(vec![w.clone()], 0.1).betas(0.8, 0.99).eps(1e-10);
+
+            for _ in 0..500 {
+                opt.zero_grad();
+                let d: Val = &w - 4.0;
+                let loss: Val = &d * &d;
+                loss.backward();
+                opt.step();
+            }
+
+            assert!((w.data() - 4.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn clipping() {
+        {
+            let (a, b): (Val, Val) = (Val::new(1.0), Val::new(1.0));
+            let loss: Val = &a * 3.0 + &b * 4.0;
+            loss.backward();
+
+            let params: Vec<Val> = vec![a.clone(), b.clone()];
+            assert!(approx_eq(clip_grad_norm(&params, 1.0), 5.0));
+            assert!(approx_eq(a.grad(), 0.6));
+            assert!(approx_eq(b.grad(), 0.8));
+
+            // Already within the limit: untouched
+            assert!(approx_eq(clip_grad_norm(&params, 2.0), 1.0));
+            assert!(approx_eq(a.grad(), 0.6));
+        }
+
+        {
+            let (a, b, c): (Val, Val, Val) = (Val::new(1.0), Val::new(1.0), Val::new(1.0));
+            let loss: Val = &(&a * 3.0 + &b * -0.25) + &(&c * -7.0);
+            loss.backward();
+
+            clip_grad_value(&[a.clone(), b.clone(), c.clone()], 0.5);
+            assert_eq!(a.grad(), 0.5);
+            assert_eq!(b.grad(), -0.25);
+            assert_eq!(c.grad(), -0.5);
+        }
+    }
+
+    #[test]
+    fn ema() {
+        {
+            let target: Vec<Val> = vec![Val::new(0.0), Val::new(4.0)];
+            let source: Vec<Val> = vec![Val::new(1.0), Val::new(2.0)];
+            ema_update(&target, &source, 0.25);
+            assert_eq!(target[0].data(), 0.25);
+            assert_eq!(target[1].data(), 3.5);
+            assert_eq!(source[1].data(), 2.0);
+
+            // Repeated updates converge on a fixed source
+            for _ in 0..200 {
+                ema_update(&target, &source, 0.25);
+            }
+            assert!(approx_eq(target[0].data(), 1.0));
+
+            ema_update(&target, &[Val::new(-3.0), Val::new(8.0)], 1.0);
+            assert_eq!((target[0].data(), target[1].data()), (-3.0, 8.0));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use super::Optimizer;
+
+
+// Adjusts the learning rate of a wrapped optimizer. Call step() once per
+// epoch (or per batch, with the periods counted in batches).
+pub trait Scheduler {
+    fn step(&mut self);
+
+    fn lr(&self) -> f64;
+}
+
+
+// lr = base * gamma^(t / step_size), decaying in stairs
+pub struct StepLR<O: Optimizer> {
+    optimizer: O,
+    base_lr:   f64,
+    step_size: usize,
+    gamma:     f64,
+    t:         usize
+}
+
+
+// lr = base * gamma^t
+pub struct ExponentialLR<O: Optimizer> {
+    optimizer: O,
+    base_lr:   f64,
+    gamma:     f64,
+    t:         usize
+}
+
+
+// Half a cosine from the base rate down to eta_min over t_max steps, then held there
+pub struct CosineAnnealing<O: Optimizer> {
+    optimizer: O,
+    base_lr:   f64,
+    eta_min:   f64,
+    t_max:     usize,
+    t:         usize
+}
+
+
+impl<O: Optimizer> StepLR<O> {
+    pub fn new(optimizer: O, step_size: usize, gamma: f64) -> StepLR<O> {
+        assert!(step_size > 0, "step size must be positive");
+        let base_lr: f64 = optimizer.lr();
+        return StepLR { optimizer, base_lr, step_size, gamma, t: 0 };
+    }
+
+    pub fn optimizer(&mut self) -> &mut O {
+        return &mut self.optimizer;
+    }
+
+    pub fn into_inner(self) -> O {
+        return self.optimizer;
+    }
+}
+
+
+impl<O: Optimizer> Scheduler for StepLR<O> {
+    fn step(&mut self) {
+        self.t += 1;
+        let lr: f64 = self.base_lr * self.gamma.powi((self.t / self.step_size) as i32);
+        self.optimizer.set_lr(lr);
+    }
+
+    fn lr(&self) -> f64 {
+        return self.optimizer.lr();
+    }
+}
+
+
+impl<O: Optimizer> ExponentialLR<O> {
+    pub fn new(optimizer: O, gamma: f64) -> ExponentialLR<O> {
+        let base_lr: f64 = optimizer.lr();
+        return ExponentialLR { optimizer, base_lr, gamma, t: 0 };
+    }
+
+    pub fn optimizer(&mut self) -> &mut O {
+        return &mut self.optimizer;
+    }
+
+    pub fn into_inner(self) -> O {
+        return self.optimizer;
+    }
+}
+
+
+impl<O: Optimizer> Scheduler for ExponentialLR<O> {
+    fn step(&mut self) {
+        self.t += 1;
+        let lr: f64 = self.base_lr * self.gamma.powi(self.t as i32);
+        self.optimizer.set_lr(lr);
+    }
+
+    fn lr(&self) -> f64 {
+        return self.optimizer.lr();
+    }
+}
+
+
+impl<O: Optimizer> CosineAnnealing<O> {
+    pub fn new(optimizer: O, t_max: usize) -> CosineAnnealing<O> {
+        assert!(t_max > 0, "t_max must be positive");
+        let base_lr: f64 = optimizer.lr();
+        return CosineAnnealing { optimizer, base_lr, eta_min: 0.0, t_max, t: 0 };
+    }
+
+    pub fn eta_min(mut self, eta_min: f64) -> CosineAnnealing<O> {
+        self.eta_min = eta_min;
+        return self;
+    }
+
+    pub fn optimizer(&mut self) -> &mut O {
+        return &mut self.optimizer;
+    }
+
+    pub fn into_inner(self) -> O {
+        return self.optimizer;
+    }
+}
+
+
+impl<O: Optimizer> Scheduler for CosineAnnealing<O> {
+    fn step(&mut self) {
+        self.t = (self.t + 1).min(self.t_max);
+        let progress: f64 = self.t as f64 / self.t_max as f64;
+        let lr: f64 = self.eta_min + (self.base_lr - self.eta_min) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0;
+        self.optimizer.set_lr(lr);
+    }
+
+    fn lr(&self) -> f64 {
+        return self.optimizer.lr();
+    }
+}
+
+
+
+#[cfg(test)]
+mod scheduler_ops {
+    use super::*;
+    use crate::optim::{Adam, SGD};
+    use crate::Val;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn step_lr() {
+        {
+            let mut s: StepLR<SGD> = StepLR::new(SGD::new(vec![Val::new(1.0)], 0.1), 2, 0.5);
+            let mut lrs: Vec<f64> = vec![s.lr()];
+            for _ in 0..5 {
+                s.step();
+                lrs.push(s.lr());
+            }
+
+            let expected: Vec<f64> = vec![0.1, 0.1, 0.05, 0.05, 0.025, 0.025];
+            assert!(lrs.iter().zip(expected.iter()).all(|(a, b)| approx_eq(*a, *b)));
+        }
+    }
+
+    #[test]
+    fn exponential_lr() {
+        {
+            let mut s: ExponentialLR<Adam> = ExponentialLR::new(Adam::new(vec![Val::new(1.0)], 1.0), 0.9);
+            s.step();
+            s.step();
+
+            assert!(approx_eq(s.lr(), 0.81));
+            assert!(approx_eq(s.optimizer().lr(), 0.81));
+        }
+    }
+
+    #[test]
+    fn cosine_annealing() {
+        {
+            let mut s: CosineAnnealing<SGD> = CosineAnnealing::new(SGD::new(vec![Val::new(1.0)], 1.0), 4).eta_min(0.2);
+            let mut lrs: Vec<f64> = Vec::new();
+            for _ in 0..6 {
+                s.step();
+                lrs.push(s.lr());
+            }
+
+            // Halfway is the midpoint, the end is eta_min, and it stays there
+            assert!(approx_eq(lrs[1], 0.6));
+            assert!(approx_eq(lrs[3], 0.2));
+            assert!(approx_eq(lrs[5], 0.2));
+            assert!(lrs.windows(2).all(|w| w[1] <= w[0]));
+        }
+
+        {
+            // The wrapped optimizer keeps training with the scheduled rate
+            let w: Val = Val::new(0.0);
+            let mut s: CosineAnnealing<SGD> = CosineAnnealing::new(SGD::new(vec![w.clone()], 0.5), 10);
+            for _ in 0..10 {
+                s.optimizer().zero_grad();
+                let d: Val = &w - 3.0;
+                (&d * &d).backward();
+                s.optimizer().step();
+                s.step();
+            }
+
+            assert!((w.data() - 3.0).abs() < 1e-3);
+            let opt: SGD = s.into_inner();
+            assert!(approx_eq(opt.lr(), 0.0));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::nn::Module;
+use crate::{Float, Val};
+
+
+// The parameters pruning works on: those named weight (not biases or norm
+// scales), or every parameter of a module that doesn't name its own
+fn weights<T: Float, M: Module<T> + ?Sized>(model: &M) -> Vec<Val<T>> {
+    let named: Vec<(String, Val<T>)> = model.named_parameters();
+    let is_weight = |name: &str| -> bool { name.rsplit('.').next().is_some_and(|last| last.starts_with("weight")) };
+    if !named.iter().any(|(name, _)| is_weight(name)) {
+        return named.into_iter().map(|(_, p)| p).collect();
+    }
+
+    return named.into_iter().filter(|(name, _)| is_weight(name)).map(|(_, p)| p).collect();
+}
+
+
+// Zeroes the given fraction of the model's weights, smallest magnitude first
+// over the whole model rather than per layer, and freezes them so optimizers
+// leave them at zero. Pruning again with a higher sparsity extends the mask.
+// Unfreezing the model lets pruned weights train again. Returns how many
+// weights are pruned.
+pub fn magnitude<T: Float, M: Module<T> + ?Sized>(model: &M, sparsity: f64) -> usize {
+    assert!((0.0..=1.0).contains(&sparsity), "sparsity must be in [0, 1]");
+
+    let mut ws: Vec<Val<T>> = weights(model);
+    let count: usize = (sparsity * ws.len() as f64).round() as usize;
+    ws.sort_by(|a, b| a.data().abs().to_f64().total_cmp(&b.data().abs().to_f64()));
+    for w in ws.iter().take(count) {
+        w.set_data(T::zero());
+        w.set_grad(T::zero());
+        w.set_requires_grad(false);
+    }
+
+    return count;
+}
+
+
+// The fraction of the model's weights that are exactly zero
+pub fn sparsity<T: Float, M: Module<T> + ?Sized>(model: &M) -> f64 {
+    let ws: Vec<Val<T>> = weights(model);
+    if ws.is_empty() {
+        return 0.0;
+    }
+
+    return ws.iter().filter(|w| w.data() == T::zero()).count() as f64 / ws.len() as f64;
+}
+
+
+
+#[cfg(test)]
+mod prune_ops {
+    use super::*;
+    use crate::init::Init;
+    use crate::loss::mse;
+    use crate::nn::{Layer, Neuron, MLP};
+    use crate::optim::Adam;
+    use crate::rand::Rng;
+    use crate::train::fit;
+
+    #[test]
+    fn pruning() {
+        {
+            let n: Neuron = Neuron::from_weights(&[0.3, -2.0, 0.05, 1.0], 0.01, false);
+            assert_eq!(magnitude(&n, 0.5), 2);
+            let w: Vec<f64> = n.weights().iter().map(|w| w.data()).collect();
+            assert_eq!(w, vec![0.0, -2.0, 0.0, 1.0]);
+            // The bias is smaller than every weight, but isn't one
+            assert_eq!(n.bias().data(), 0.01);
+            assert_eq!(sparsity(&n), 0.5);
+
+            assert_eq!(magnitude(&n, 0.75), 3);
+            assert_eq!(n.weights()[1].data(), -2.0);
+            assert_eq!(sparsity(&n), 0.75);
+        }
+
+        {
+            // Across layers as one pool, and held at zero through training
+            let mut rng: Rng = Rng::new(2);
+            let m: MLP = MLP::from_layers(vec![
+                Layer::with_init(2, 8, true, Init::Xavier, &mut rng),
+                Layer::from_neurons(vec![Neuron::from_weights(&[0.0; 8], 0.0, false)])
+            ]);
+            assert_eq!(magnitude(&m, 0.4), 10);
+            // The output layer's zeros go first: 8 of the 10 pruned
+            assert!(m.layers()[1].neurons()[0].weights().iter().all(|w| !w.requires_grad()));
+            assert_eq!(m.layers()[0].neurons().iter().flat_map(|n| n.weights()).filter(|w| !w.requires_grad()).count(), 2);
+
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..6).map(|i| (vec![i as f64 * 0.2, 1.0 - i as f64 * 0.1], vec![0.5])).collect();
+            let mut opt: Adam = Adam::new(m.parameters(), 0.05);
+            fit(&m, &data, &mut opt, mse, 5);
+            assert_eq!(sparsity(&m), 10.0 / 24.0);
+            assert!(m.layers()[1].neurons()[0].bias().data() != 0.0);
+        }
+    }
+}
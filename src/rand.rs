@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+
+
+// Small seedable generator (SplitMix64). Not cryptographic, but fast, and
+// a seed always reproduces the same stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64
+}
+
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        return Rng { state: seed };
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    // Rng::new(rng.state()) continues the stream exactly where rng is
+    pub fn state(&self) -> u64 {
+        return self.state;
+    }
+
+    // Uniform in [0, 1), from the top 53 bits
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+    // Uniform in [0, n)
+    pub fn below(&mut self, n: usize) -> usize {
+        return (self.next_f64() * n as f64) as usize;
+    }
+
+    // Uniform in [lo, hi)
+    pub fn uniform(&mut self, lo: f64, hi: f64) -> f64 {
+        return lo + (hi - lo) * self.next_f64();
+    }
+
+    // Standard normal, by Box-Muller
+    pub fn normal(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.next_f64();
+        let u2: f64 = self.next_f64();
+        return (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+    }
+
+    // Fisher-Yates
+    pub fn shuffle<X>(&mut self, xs: &mut [X]) {
+        for i in (1..xs.len()).rev() {
+            let j: usize = self.below(i + 1);
+            xs.swap(i, j);
+        }
+    }
+}
+
+
+
+thread_local! {
+    static GLOBAL: RefCell<Rng> = RefCell::new(Rng::new(0));
+}
+
+
+// Reseeds the generator behind every draw that isn't handed an explicit Rng:
+// weight init, shuffling, dropout. The generator is per thread, so a run
+// is reproducible as long as it stays on one.
+pub fn set_seed(seed: u64) {
+    GLOBAL.with(|g| *g.borrow_mut() = Rng::new(seed));
+}
+
+
+pub fn with_global<R, F>(f: F) -> R
+where F: FnOnce(&mut Rng) -> R,
+{
+    return GLOBAL.with(|g| f(&mut g.borrow_mut()));
+}
+
+
+// Pair with set_seed to save and restore the global stream
+pub fn global_state() -> u64 {
+    return with_global(|g| g.state());
+}
+
+
+// An independent generator seeded from the global one
+pub fn fork() -> Rng {
+    return Rng::new(with_global(|g| g.next_u64()));
+}
+
+
+
+#[cfg(test)]
+mod rand_ops {
+    use super::*;
+
+    #[test]
+    fn rng() {
+        {
+            let mut a: Rng = Rng::new(42);
+            let mut b: Rng = Rng::new(42);
+            let mut c: Rng = Rng::new(43);
+
+            let xs: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+            let ys: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+            let zs: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+
+            assert_eq!(xs, ys);
+            assert_ne!(xs, zs);
+        }
+
+        {
+            let mut r: Rng = Rng::new(7);
+            let xs: Vec<f64> = (0..10_000).map(|_| r.next_f64()).collect();
+            let mean: f64 = xs.iter().sum::<f64>() / xs.len() as f64;
+
+            assert!(xs.iter().all(|&x| (0.0..1.0).contains(&x)));
+            assert!((mean - 0.5).abs() < 0.02);
+        }
+
+        {
+            let mut r: Rng = Rng::new(1);
+            let mut xs: Vec<usize> = (0..20).collect();
+            r.shuffle(&mut xs);
+
+            assert_ne!(xs, (0..20).collect::<Vec<usize>>());
+            xs.sort();
+            assert_eq!(xs, (0..20).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn distributions() {
+        {
+            let mut r: Rng = Rng::new(5);
+            let xs: Vec<f64> = (0..10_000).map(|_| r.uniform(-1.0, 1.0)).collect();
+
+            assert!(xs.iter().all(|&x| (-1.0..1.0).contains(&x)));
+            assert!((xs.iter().sum::<f64>() / xs.len() as f64).abs() < 0.03);
+        }
+
+        {
+            let mut r: Rng = Rng::new(5);
+            let xs: Vec<f64> = (0..20_000).map(|_| r.normal()).collect();
+            let mean: f64 = xs.iter().sum::<f64>() / xs.len() as f64;
+            let var: f64 = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+
+            assert!(mean.abs() < 0.03);
+            assert!((var - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn global() {
+        {
+            set_seed(123);
+            let a: Vec<u64> = (0..4).map(|_| with_global(|g| g.next_u64())).collect();
+            let f1: u64 = fork().next_u64();
+
+            set_seed(123);
+            let b: Vec<u64> = (0..4).map(|_| with_global(|g| g.next_u64())).collect();
+            let f2: u64 = fork().next_u64();
+
+            assert_eq!(a, b);
+            assert_eq!(f1, f2);
+
+            let mut r: Rng = Rng::new(123);
+            assert_eq!(a, (0..4).map(|_| r.next_u64()).collect::<Vec<u64>>());
+        }
+
+        {
+            set_seed(5);
+            with_global(|g| g.next_u64());
+            let saved: u64 = global_state();
+            let a: u64 = with_global(|g| g.next_u64());
+
+            set_seed(saved);
+            assert_eq!(with_global(|g| g.next_u64()), a);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::rand::Rng;
+use crate::{metrics, ops, Float, Val};
+
+
+// What an environment hands back after each action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub observation: Vec<f64>,
+    pub reward:      f64,
+    pub done:        bool
+}
+
+
+// An episodic task with a fixed number of discrete actions, 0..actions()
+pub trait Env {
+    // Starts a new episode and returns its first observation
+    fn reset(&mut self, rng: &mut Rng) -> Vec<f64>;
+
+    fn step(&mut self, action: usize) -> Step;
+
+    fn observation_size(&self) -> usize;
+
+    fn actions(&self) -> usize;
+}
+
+
+// The classic pole balanced on a cart (Barto, Sutton and Anderson, 1983), with
+// the constants of Gym's CartPole-v1. The observation is cart position and
+// velocity, then pole angle and angular velocity. Action 0 pushes left and 1
+// right; every step the pole stays up is worth 1. An episode ends once the
+// pole tips past 12° or the cart leaves [-2.4, 2.4], or after max_steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartPole {
+    state:     [f64; 4],
+    steps:     usize,
+    max_steps: usize
+}
+
+
+impl CartPole {
+    const GRAVITY:    f64 = 9.8;
+    const CART_MASS:  f64 = 1.0;
+    const POLE_MASS:  f64 = 0.1;
+    // Half the pole's length
+    const LENGTH:     f64 = 0.5;
+    const FORCE:      f64 = 10.0;
+    const DT:         f64 = 0.02;
+    const MAX_ANGLE:  f64 = 12.0 * std::f64::consts::PI / 180.0;
+    const MAX_OFFSET: f64 = 2.4;
+
+    pub fn new() -> CartPole {
+        return CartPole { state: [0.0; 4], steps: 0, max_steps: 500 };
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> CartPole {
+        assert!(max_steps > 0, "episodes need at least one step");
+        self.max_steps = max_steps;
+        return self;
+    }
+}
+
+
+impl Default for CartPole {
+    fn default() -> CartPole {
+        return CartPole::new();
+    }
+}
+
+
+impl Env for CartPole {
+    fn reset(&mut self, rng: &mut Rng) -> Vec<f64> {
+        self.state = [(); 4].map(|_| rng.uniform(-0.05, 0.05));
+        self.steps = 0;
+        return self.state.to_vec();
+    }
+
+    // Semi-implicit Euler, as Gym's default
+    fn step(&mut self, action: usize) -> Step {
+        assert!(action < 2, "CartPole has two actions, got {}", action);
+        let [x, x_dot, theta, theta_dot]: [f64; 4] = self.state;
+        let force: f64 = if action == 1 { CartPole::FORCE } else { -CartPole::FORCE };
+
+        let total: f64 = CartPole::CART_MASS + CartPole::POLE_MASS;
+        let pole_moment: f64 = CartPole::POLE_MASS * CartPole::LENGTH;
+        let (sin, cos): (f64, f64) = theta.sin_cos();
+        let temp: f64 = (force + pole_moment * theta_dot * theta_dot * sin) / total;
+        let theta_acc: f64 = (CartPole::GRAVITY * sin - cos * temp)
+            / (CartPole::LENGTH * (4.0 / 3.0 - CartPole::POLE_MASS * cos * cos / total));
+        let x_acc: f64 = temp - pole_moment * theta_acc * cos / total;
+
+        let x_dot: f64 = x_dot + CartPole::DT * x_acc;
+        let theta_dot: f64 = theta_dot + CartPole::DT * theta_acc;
+        self.state = [x + CartPole::DT * x_dot, x_dot, theta + CartPole::DT * theta_dot, theta_dot];
+        self.steps += 1;
+
+        let fallen: bool = self.state[0].abs() > CartPole::MAX_OFFSET || self.state[2].abs() > CartPole::MAX_ANGLE;
+        return Step { observation: self.state.to_vec(), reward: 1.0, done: fallen || self.steps >= self.max_steps };
+    }
+
+    fn observation_size(&self) -> usize {
+        return 4;
+    }
+
+    fn actions(&self) -> usize {
+        return 2;
+    }
+}
+
+
+// An index drawn with probability proportional to its weight
+fn sample(probs: &[f64], rng: &mut Rng) -> usize {
+    let mut u: f64 = rng.next_f64() * probs.iter().sum::<f64>();
+    for (i, &p) in probs.iter().enumerate() {
+        if u < p {
+            return i;
+        }
+        u -= p;
+    }
+
+    return probs.len() - 1;
+}
+
+
+// The action `policy` rates highest, for running a trained policy
+pub fn greedy<T: Float, M: Module<T>>(policy: &M, observation: &[f64]) -> usize {
+    let inputs: Vec<Val<T>> = observation.iter().map(|&o| Val::constant(T::from_f64(o))).collect();
+    return metrics::argmax(&policy.forward(&inputs));
+}
+
+
+// Plays one episode choosing greedily, at most `limit` steps. Returns the total
+// reward. As with train::evaluate, the policy plays in eval mode and gets its
+// mode back afterwards.
+pub fn evaluate<T, M, E>(policy: &M, env: &mut E, limit: usize, rng: &mut Rng) -> f64
+where T: Float,
+      M: Module<T>,
+      E: Env,
+{
+    let training: bool = policy.is_training();
+    policy.eval();
+
+    let mut observation: Vec<f64> = env.reset(rng);
+    let mut total: f64 = 0.0;
+    for _ in 0..limit {
+        let step: Step = env.step(crate::no_grad(|| greedy(policy, &observation)));
+        total += step.reward;
+        if step.done {
+            break;
+        }
+        observation = step.observation;
+    }
+    policy.set_training(training);
+
+    return total;
+}
+
+
+// Monte Carlo policy gradient (Williams, 1992). The policy maps an observation
+// to one logit per action; each episode is played by sampling from their
+// softmax, then the optimizer takes one step on
+//
+//   -Σ_t log π(a_t | s_t) Ĝ_t
+//
+// where Ĝ_t is the discounted return from step t, standardised over the
+// episode as a baseline. Returns each episode's total reward.
+pub fn reinforce<T, M, O, E>(policy: &M, env: &mut E, optimizer: &mut O, episodes: usize, gamma: f64, rng: &mut Rng) -> Vec<f64>
+where T: Float,
+      M: Module<T>,
+      O: Optimizer,
+      E: Env,
+{
+    assert!((0.0..=1.0).contains(&gamma), "gamma must be in [0, 1]");
+
+    let mut history: Vec<f64> = Vec::with_capacity(episodes);
+    for _ in 0..episodes {
+        let mut observation: Vec<f64> = env.reset(rng);
+        let mut log_probs: Vec<Val<T>> = Vec::new();
+        let mut rewards: Vec<f64> = Vec::new();
+
+        loop {
+            let inputs: Vec<Val<T>> = observation.iter().map(|&o| Val::constant(T::from_f64(o))).collect();
+            let logits: Vec<Val<T>> = policy.forward(&inputs);
+            assert_eq!(logits.len(), env.actions(), "the policy needs one output per action");
+
+            let log_pi: Vec<Val<T>> = ops::log_softmax(&logits);
+            let probs: Vec<f64> = log_pi.iter().map(|l| l.data().to_f64().exp()).collect();
+            let action: usize = sample(&probs, rng);
+
+            let step: Step = env.step(action);
+            log_probs.push(log_pi[action].clone());
+            rewards.push(step.reward);
+            if step.done {
+                break;
+            }
+            observation = step.observation;
+        }
+
+        let mut returns: Vec<f64> = vec![0.0; rewards.len()];
+        let mut g: f64 = 0.0;
+        for t in (0..rewards.len()).rev() {
+            g = rewards[t] + gamma * g;
+            returns[t] = g;
+        }
+        let n: f64 = returns.len() as f64;
+        let mean: f64 = returns.iter().sum::<f64>() / n;
+        let std: f64 = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        let terms: Vec<Val<T>> = log_probs.iter()
+            .zip(returns.iter())
+            .map(|(l, r)| l * T::from_f64(-(r - mean) / (std + 1e-8)))
+            .collect();
+        optimizer.zero_grad();
+        ops::sum(&terms).backward();
+        optimizer.step();
+
+        history.push(rewards.iter().sum());
+    }
+
+    return history;
+}
+
+
+
+#[cfg(test)]
+mod rl_ops {
+    use super::*;
+    use crate::nn::{Dropout, Layer, Neuron, Sequential, MLP};
+    use crate::optim::Adam;
+
+    #[test]
+    fn cart_pole() {
+        {
+            // Pushing one way the whole time tips the pole within a few dozen steps
+            let mut env: CartPole = CartPole::new();
+            let mut rng: Rng = Rng::new(0);
+            let first: Vec<f64> = env.reset(&mut rng);
+            assert_eq!(first.len(), env.observation_size());
+            assert!(first.iter().all(|o| o.abs() <= 0.05));
+
+            let mut steps: usize = 0;
+            loop {
+                steps += 1;
+                let step: Step = env.step(1);
+                assert_eq!(step.reward, 1.0);
+                if step.done {
+                    assert!(step.observation[2].abs() > CartPole::MAX_ANGLE);
+                    break;
+                }
+            }
+            assert!(steps > 5 && steps < 50);
+
+            let mut short: CartPole = CartPole::new().max_steps(3);
+            short.reset(&mut rng);
+            assert!(!short.step(0).done && !short.step(1).done && short.step(0).done);
+        }
+    }
+
+    #[test]
+    fn greedy_eval() {
+        {
+            // Rewards action 1 on each of five steps
+            struct Pick(usize);
+
+            impl Env for Pick {
+                fn reset(&mut self, _rng: &mut Rng) -> Vec<f64> {
+                    self.0 = 0;
+                    return vec![1.0];
+                }
+
+                fn step(&mut self, action: usize) -> Step {
+                    self.0 += 1;
+                    return Step { observation: vec![1.0], reward: action as f64, done: self.0 == 5 };
+                }
+
+                fn observation_size(&self) -> usize {
+                    return 1;
+                }
+
+                fn actions(&self) -> usize {
+                    return 2;
+                }
+            }
+
+            // While training, the dropout zeroes both logits and the tie goes to action 0
+            let policy: Sequential = Sequential::default()
+                .layer(Layer::from_neurons(vec![Neuron::from_weights(&[0.0], 0.0, false), Neuron::from_weights(&[0.0], 1.0, false)]))
+                .layer(Dropout::new(1.0));
+            assert_eq!(evaluate(&policy, &mut Pick(0), 10, &mut Rng::new(0)), 5.0);
+            assert!(policy.is_training());
+        }
+    }
+
+    #[test]
+    fn policy_gradient() {
+        {
+            let mut rng: Rng = Rng::new(3);
+            let policy: MLP = MLP::with_init(4, &[16, 2], crate::init::Init::Xavier, &mut rng);
+            let mut opt: Adam = Adam::new(policy.parameters(), 0.02);
+            let mut env: CartPole = CartPole::new().max_steps(100);
+
+            let history: Vec<f64> = reinforce(&policy, &mut env, &mut opt, 60, 0.99, &mut rng);
+            assert_eq!(history.len(), 60);
+
+            // Up from short episodes to balancing most of the way to the limit
+            let early: f64 = history[..10].iter().sum::<f64>() / 10.0;
+            let late: f64 = history[50..].iter().sum::<f64>() / 10.0;
+            assert!(late > 80.0 && late > 1.5 * early, "{} -> {}", early, late);
+            assert!(evaluate(&policy, &mut env, 100, &mut rng) > early);
+        }
+    }
+}
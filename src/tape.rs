@@ -0,0 +1,273 @@
+use std::cell::RefCell;
+use std::ops;
+
+use crate::grad_fn;
+use crate::Float;
+
+
+// One recorded operation: the indices of its inputs and the partial
+// derivative of the output with respect to each, taken when it ran. Leaves
+// and unary ops point their unused slots at themselves with a zero weight.
+#[derive(Clone, Copy)]
+struct Entry<T: Float> {
+    deps: [(usize, T); 2]
+}
+
+
+// A Wengert list. Every operation on a Var appends an entry holding its local
+// partials, so backward is one reverse scan over plain numbers: no graph, no
+// shared ownership, no closures. Vars borrow the tape, so the compiler stops
+// it being cleared while any are alive.
+pub struct Tape<T: Float = f64> {
+    entries: RefCell<Vec<Entry<T>>>
+}
+
+
+// A value on a tape. Cheap to copy; operators work on Vars and on plain numbers.
+#[derive(Clone, Copy)]
+pub struct Var<'t, T: Float = f64> {
+    tape:  &'t Tape<T>,
+    index: usize,
+    value: T
+}
+
+
+// The adjoints from one backward pass, one per entry on the tape
+pub struct Grads<T: Float = f64>(Vec<T>);
+
+
+impl<T: Float> Default for Tape<T> {
+    fn default() -> Tape<T> {
+        return Tape::new();
+    }
+}
+
+
+impl<T: Float> Tape<T> {
+    pub fn new() -> Tape<T> {
+        return Tape { entries: RefCell::new(Vec::new()) };
+    }
+
+    pub fn var(&self, value: T) -> Var<'_, T> {
+        let index: usize = self.entries.borrow().len();
+        return self.record(value, [(index, T::zero()), (index, T::zero())]);
+    }
+
+    fn record(&self, value: T, deps: [(usize, T); 2]) -> Var<'_, T> {
+        let mut entries = self.entries.borrow_mut();
+        entries.push(Entry { deps });
+        return Var { tape: self, index: entries.len() - 1, value };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.borrow().len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.borrow().is_empty();
+    }
+
+    // Only possible once every Var on the tape is gone
+    pub fn clear(&mut self) {
+        self.entries.get_mut().clear();
+    }
+}
+
+
+impl<'t, T: Float> Var<'t, T> {
+    pub fn value(&self) -> T {
+        return self.value;
+    }
+
+    pub fn index(&self) -> usize {
+        return self.index;
+    }
+
+    fn unary(&self, value: T, d: T) -> Var<'t, T> {
+        return self.tape.record(value, [(self.index, d), (self.index, T::zero())]);
+    }
+
+    fn binary(&self, other: &Var<'t, T>, value: T, da: T, db: T) -> Var<'t, T> {
+        assert!(std::ptr::eq(self.tape, other.tape), "Vars are on different tapes");
+        return self.tape.record(value, [(self.index, da), (other.index, db)]);
+    }
+
+    pub fn tanh(&self) -> Var<'t, T> {
+        let t: T = grad_fn::tanh_stable(self.value);
+        return self.unary(t, T::one() - t * t);
+    }
+
+    pub fn relu(&self) -> Var<'t, T> {
+        return if self.value > T::zero() { self.unary(self.value, T::one()) } else { self.unary(T::zero(), T::zero()) };
+    }
+
+    pub fn sigmoid(&self) -> Var<'t, T> {
+        let s: T = T::one() / (T::one() + (-self.value).exp());
+        return self.unary(s, s * (T::one() - s));
+    }
+
+    pub fn exp(&self) -> Var<'t, T> {
+        let e: T = self.value.exp();
+        return self.unary(e, e);
+    }
+
+    // Natural log
+    pub fn log(&self) -> Var<'t, T> {
+        return self.unary(self.value.ln(), T::one() / self.value);
+    }
+
+    pub fn pow(&self, n: T) -> Var<'t, T> {
+        return self.unary(self.value.powf(n), n * self.value.powf(n - T::one()));
+    }
+
+    // d self / d x for every x on the tape up to this Var
+    pub fn backward(&self) -> Grads<T> {
+        let entries = self.tape.entries.borrow();
+        let mut adjoints: Vec<T> = vec![T::zero(); entries.len()];
+        adjoints[self.index] = T::one();
+
+        for i in (0..=self.index).rev() {
+            let a: T = adjoints[i];
+            for &(j, d) in entries[i].deps.iter() {
+                adjoints[j] += d * a;
+            }
+        }
+
+        return Grads(adjoints);
+    }
+}
+
+
+impl<T: Float> Grads<T> {
+    pub fn wrt(&self, v: &Var<'_, T>) -> T {
+        return self.0.get(v.index).copied().unwrap_or(T::zero());
+    }
+
+    pub fn wrt_all(&self, vs: &[Var<'_, T>]) -> Vec<T> {
+        return vs.iter().map(|v| self.wrt(v)).collect();
+    }
+}
+
+
+
+/*** Overloads ***/
+
+macro_rules! impl_var_op {
+    ($trait:ident, $method:ident, |$a:ident, $b:ident| $value:expr, $da:expr, $db:expr) => {
+        impl<'t, T: Float> ops::$trait<Var<'t, T>> for Var<'t, T> {
+            type Output = Var<'t, T>;
+
+            fn $method(self, other: Var<'t, T>) -> Var<'t, T> {
+                let ($a, $b): (T, T) = (self.value, other.value);
+                return self.binary(&other, $value, $da, $db);
+            }
+        }
+
+        // With a constant on the right, which records nothing of its own
+        impl<'t, T: Float> ops::$trait<T> for Var<'t, T> {
+            type Output = Var<'t, T>;
+
+            fn $method(self, other: T) -> Var<'t, T> {
+                let ($a, $b): (T, T) = (self.value, other);
+                return self.unary($value, $da);
+            }
+        }
+    };
+}
+
+impl_var_op!(Add, add, |a, b| a + b, T::one(), T::one());
+impl_var_op!(Sub, sub, |a, b| a - b, T::one(), -T::one());
+impl_var_op!(Mul, mul, |a, b| a * b, b, a);
+impl_var_op!(Div, div, |a, b| a / b, T::one() / b, -a / (b * b));
+
+
+impl<'t, T: Float> ops::Neg for Var<'t, T> {
+    type Output = Var<'t, T>;
+
+    fn neg(self) -> Var<'t, T> {
+        return self.unary(-self.value, -T::one());
+    }
+}
+
+/*** End Overloads ***/
+
+
+
+#[cfg(test)]
+mod tape_ops {
+    use super::*;
+    use crate::Val;
+
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn tape() {
+        {
+            // tanh(a b + a^2) - exp(b) / 2 + log(a) sigmoid(b), as in the Val engine
+            let (va, vb): (Val, Val) = (Val::new(0.7), Val::new(-0.4));
+            let left: Val = (&(&va * &vb) + &va.clone().pow(2.0)).tanh();
+            let right: Val = &(vb.clone().exp() * 0.5) - &(&va.clone().log() * &vb.clone().sigmoid());
+            let out: Val = &left - &right;
+            out.backward();
+
+            let t: Tape = Tape::new();
+            let (a, b): (Var, Var) = (t.var(0.7), t.var(-0.4));
+            let y: Var = (a * b + a.pow(2.0)).tanh() - (b.exp() / 2.0 - a.log() * b.sigmoid());
+            let g: Grads = y.backward();
+
+            assert!(approx_eq(y.value(), out.data()));
+            assert!(approx_eq(g.wrt(&a), va.grad()));
+            assert!(approx_eq(g.wrt(&b), vb.grad()));
+        }
+
+        {
+            // tanh saturates instead of overflowing to NaN
+            let t: Tape = Tape::new();
+            let x: Var = t.var(-400.0);
+            let y: Var = x.tanh();
+            assert_eq!(y.value(), -1.0);
+            assert_eq!(y.backward().wrt(&x), 0.0);
+        }
+
+        {
+            // Division, negation, relu, and a Var used many times over
+            let t: Tape = Tape::new();
+            let x: Var = t.var(3.0);
+            let y: Var = -(x * x * x) / (x + 1.0) + (x - 5.0).relu() + x.relu();
+            let g: Grads = y.backward();
+
+            // d/dx -x³/(x+1) = -(2x³ + 3x²)/(x+1)², then + 0 + 1
+            assert!(approx_eq(y.value(), -27.0 / 4.0 + 3.0));
+            assert!(approx_eq(g.wrt(&x), -(54.0 + 27.0) / 16.0 + 1.0));
+
+            // Gradients only flow back from the Var that backward started at
+            let z: Var = x * 2.0;
+            assert_eq!(z.backward().wrt(&x), 2.0);
+            assert_eq!(z.backward().wrt(&y), 0.0);
+        }
+
+        {
+            let mut t: Tape<f32> = Tape::new();
+            {
+                let w: Vec<Var<f32>> = vec![t.var(0.5), t.var(-1.0)];
+                let s: Var<f32> = (w[0] * 2.0 + w[1] * 3.0).sigmoid();
+                let g: Vec<f32> = s.backward().wrt_all(&w);
+                let ds: f32 = s.value() * (1.0 - s.value());
+                assert_eq!(g, vec![2.0 * ds, 3.0 * ds]);
+                assert_eq!(t.len(), 6);
+            }
+            t.clear();
+            assert!(t.is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected="different tapes")]
+    fn mixed_tapes() {
+        let (t1, t2): (Tape, Tape) = (Tape::new(), Tape::new());
+        let _ = t1.var(1.0) + t2.var(2.0);
+    }
+}
@@ -0,0 +1,624 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops;
+use std::rc::Rc;
+
+use crate::backend::{self, Backend, Binary, Unary};
+use crate::{GradFn, Operations, Val};
+
+
+// Like GradFn::backward, these are pure: given the gradient of the node they
+// return one gradient per entry of `prev`. That keeps backward linear in the
+// seed, so a pass can be started from any Val built on top of a tensor.
+type TensorBackward = Box<dyn Fn(&[f64]) -> Vec<Vec<f64>>>;
+
+
+struct TensorData {
+    data:     Vec<f64>,
+    grad:     Vec<f64>,
+    shape:    Vec<usize>,
+    prev:     Vec<Tensor>,
+    op:       Operations,
+    backward: Option<TensorBackward>
+}
+
+
+// Iterative teardown, as for ValData
+impl Drop for TensorData {
+    fn drop(&mut self) {
+        let mut stack: Vec<Tensor> = std::mem::take(&mut self.prev);
+        while let Some(t) = stack.pop() {
+            if let Ok(cell) = Rc::try_unwrap(t.0) {
+                stack.append(&mut cell.borrow_mut().prev);
+            }
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct Tensor(Rc<RefCell<TensorData>>);
+
+
+impl Tensor {
+    pub fn new(data: Vec<f64>, shape: &[usize]) -> Tensor {
+        assert_eq!(data.len(), shape.iter().product::<usize>(), "data does not fit shape {:?}", shape);
+
+        let grad: Vec<f64> = vec![0.0; data.len()];
+        let node: TensorData = TensorData { data, grad, shape: shape.to_vec(), prev: Vec::new(), op: Operations::Non, backward: None };
+        return Tensor(Rc::new(RefCell::new(node)));
+    }
+
+    pub fn zeros(shape: &[usize]) -> Tensor {
+        return Tensor::new(vec![0.0; shape.iter().product()], shape);
+    }
+
+    pub fn data(&self) -> Vec<f64> {
+        return self.0.borrow().data.clone();
+    }
+
+    pub fn grad(&self) -> Vec<f64> {
+        return self.0.borrow().grad.clone();
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        return self.0.borrow().shape.clone();
+    }
+
+    pub fn numel(&self) -> usize {
+        return self.0.borrow().data.len();
+    }
+
+    pub fn prev(&self) -> Vec<Tensor> {
+        return self.0.borrow().prev.clone();
+    }
+
+    pub fn op(&self) -> Operations {
+        return self.0.borrow().op;
+    }
+
+    fn from_op(data: Vec<f64>, shape: &[usize], prev: Vec<Tensor>, op: Operations, backward: TensorBackward) -> Tensor {
+        let result: Tensor = Tensor::new(data, shape);
+        if !crate::is_grad_enabled() {
+            return result;
+        }
+
+        {
+            let mut node = result.0.borrow_mut();
+            node.prev = prev;
+            node.op = op;
+            node.backward = Some(backward);
+        }
+
+        return result;
+    }
+
+    // Seeds every element with 1, i.e. the gradient of the sum of the tensor
+    pub fn backward(&self) {
+        self.propagate(vec![1.0; self.numel()]);
+    }
+
+    pub fn zero_grad(&self) {
+        for node in self.topo().iter() {
+            let mut inner = node.0.borrow_mut();
+            let n: usize = inner.grad.len();
+            inner.grad = vec![0.0; n];
+        }
+    }
+
+    // Pushes `seed` back through the graph, adding each node's share into its grad
+    fn propagate(&self, seed: Vec<f64>) {
+        let mut pending: HashMap<*const RefCell<TensorData>, Vec<f64>> = HashMap::new();
+        pending.insert(Rc::as_ptr(&self.0), seed);
+
+        for node in self.topo().iter().rev() {
+            let g: Vec<f64> = match pending.remove(&Rc::as_ptr(&node.0)) {
+                Some(g) => g,
+                None    => continue
+            };
+
+            let inner = node.0.borrow();
+            if let Some(func) = &inner.backward {
+                for (child, cg) in inner.prev.iter().zip(func(&g)) {
+                    let slot = pending.entry(Rc::as_ptr(&child.0)).or_insert_with(|| vec![0.0; cg.len()]);
+                    for (s, c) in slot.iter_mut().zip(cg.iter()) {
+                        *s += c;
+                    }
+                }
+            }
+            drop(inner);
+
+            let mut inner = node.0.borrow_mut();
+            for (acc, d) in inner.grad.iter_mut().zip(g.iter()) {
+                *acc += d;
+            }
+        }
+    }
+
+    // Children before parents, each shared node appearing once
+    fn topo(&self) -> Vec<Tensor> {
+        let mut visited: HashSet<*const RefCell<TensorData>> = HashSet::new();
+        let mut order: Vec<Tensor> = Vec::new();
+
+        // Same explicit-stack walk as Val::topo
+        let mut stack: Vec<(Tensor, bool)> = vec![(self.clone(), false)];
+        while let Some((t, expanded)) = stack.pop() {
+            if expanded {
+                order.push(t);
+                continue;
+            }
+            if !visited.insert(Rc::as_ptr(&t.0)) {
+                continue;
+            }
+
+            stack.push((t.clone(), true));
+            for child in t.0.borrow().prev.iter().rev() {
+                if !visited.contains(&Rc::as_ptr(&child.0)) {
+                    stack.push((child.clone(), false));
+                }
+            }
+        }
+
+        return order;
+    }
+
+    fn elementwise(&self, rhs: &Tensor, op: Operations) -> Tensor {
+        assert_eq!(self.shape(), rhs.shape(), "elementwise {} needs matching shapes", op);
+
+        let be: Rc<dyn Backend> = backend::current();
+        let (a, b): (Vec<f64>, Vec<f64>) = (self.data(), rhs.data());
+        let data: Vec<f64> = match op {
+            Operations::Add => be.binary(Binary::Add, &a, &b),
+            Operations::Sub => be.binary(Binary::Sub, &a, &b),
+            _               => be.binary(Binary::Mul, &a, &b)
+        };
+
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            return match op {
+                Operations::Add => vec![g.to_vec(), g.to_vec()],
+                Operations::Sub => vec![g.to_vec(), be.unary(Unary::Scale(-1.0), g)],
+                _               => vec![be.binary(Binary::Mul, g, &b), be.binary(Binary::Mul, g, &a)]
+            };
+        });
+
+        return Tensor::from_op(data, &self.shape(), vec![self.clone(), rhs.clone()], op, backward);
+    }
+
+    // A scalar constant broadcast over every element; `local` is d(result)/d(self)
+    fn with_constant(&self, data: Vec<f64>, op: Operations, local: f64) -> Tensor {
+        let be: Rc<dyn Backend> = backend::current();
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            return vec![be.unary(Unary::Scale(local), g)];
+        });
+
+        return Tensor::from_op(data, &self.shape(), vec![self.clone()], op, backward);
+    }
+
+    pub fn tanh(&self) -> Tensor {
+        let be: Rc<dyn Backend> = backend::current();
+        let t: Vec<f64> = be.unary(Unary::Tanh, &self.data());
+        let out: Vec<f64> = t.clone();
+
+        // g (1 - t²)
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            let slope: Vec<f64> = be.unary(Unary::Shift(1.0), &be.unary(Unary::Scale(-1.0), &be.binary(Binary::Mul, &out, &out)));
+            return vec![be.binary(Binary::Mul, g, &slope)];
+        });
+
+        return Tensor::from_op(t, &self.shape(), vec![self.clone()], Operations::Tanh, backward);
+    }
+
+    pub fn relu(&self) -> Tensor {
+        let be: Rc<dyn Backend> = backend::current();
+        let x: Vec<f64> = self.data();
+        let data: Vec<f64> = be.unary(Unary::Relu, &x);
+
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            return vec![be.binary(Binary::Mul, g, &be.unary(Unary::Step, &x))];
+        });
+
+        return Tensor::from_op(data, &self.shape(), vec![self.clone()], Operations::Relu, backward);
+    }
+
+    // Over the last dimension, each row on its own
+    pub fn softmax(&self) -> Tensor {
+        let shape: Vec<usize> = self.shape();
+        let cols: usize = shape.last().copied().unwrap_or(1);
+        let rows: usize = self.numel().checked_div(cols).unwrap_or(0);
+        let be: Rc<dyn Backend> = backend::current();
+        let data: Vec<f64> = be.softmax(&self.data(), rows, cols);
+        let s: Vec<f64> = data.clone();
+
+        // s (g - Σ g s), the sum taken over the row
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            let gs: Vec<f64> = be.binary(Binary::Mul, g, &s);
+            let dots: Vec<f64> = gs.chunks(cols.max(1)).flat_map(|row| vec![be.sum(row); row.len()]).collect();
+            return vec![be.binary(Binary::Sub, &gs, &be.binary(Binary::Mul, &s, &dots))];
+        });
+
+        return Tensor::from_op(data, &shape, vec![self.clone()], Operations::Softmax, backward);
+    }
+
+    // [m, k] @ [k, n] -> [m, n]
+    pub fn matmul(&self, rhs: &Tensor) -> Tensor {
+        let (sa, sb): (Vec<usize>, Vec<usize>) = (self.shape(), rhs.shape());
+        assert!(sa.len() == 2 && sb.len() == 2, "matmul needs 2-d tensors, got {:?} and {:?}", sa, sb);
+        assert_eq!(sa[1], sb[0], "matmul inner dimensions differ: {:?} @ {:?}", sa, sb);
+
+        let (m, k, n): (usize, usize, usize) = (sa[0], sa[1], sb[1]);
+        let be: Rc<dyn Backend> = backend::current();
+        let (a, b): (Vec<f64>, Vec<f64>) = (self.data(), rhs.data());
+        let data: Vec<f64> = be.matmul(&a, &b, m, k, n);
+
+        // dA = dOut @ B^T, dB = A^T @ dOut
+        let backward: TensorBackward = Box::new(move |g: &[f64]| {
+            let da: Vec<f64> = be.matmul(g, &be.transpose(&b, k, n), m, n, k);
+            let db: Vec<f64> = be.matmul(&be.transpose(&a, m, k), g, k, m, n);
+            return vec![da, db];
+        });
+
+        return Tensor::from_op(data, &[m, n], vec![self.clone(), rhs.clone()], Operations::MatMul, backward);
+    }
+
+    // Bridges into the scalar graph; backward through the Val continues into the tensor
+    pub fn sum(&self) -> Val {
+        return Val::apply(Bridge { src: self.clone(), index: None }, &[]);
+    }
+
+    pub fn get(&self, i: usize) -> Val {
+        return Val::apply(Bridge { src: self.clone(), index: Some(i) }, &[]);
+    }
+}
+
+
+// A scalar read out of a tensor, either its sum or one element. It has no Val
+// inputs; its backward seeds the tensor graph instead.
+struct Bridge {
+    src:   Tensor,
+    index: Option<usize>
+}
+
+
+impl GradFn for Bridge {
+    fn op(&self) -> Operations {
+        return if self.index.is_some() { Operations::Index } else { Operations::Sum };
+    }
+
+    fn forward(&self, _inputs: &[f64]) -> f64 {
+        let data = &self.src.0.borrow().data;
+        return match self.index {
+            Some(i) => data[i],
+            None    => backend::current().sum(data)
+        };
+    }
+
+    fn backward(&self, _inputs: &[f64], _output: f64, grad: f64) -> Vec<f64> {
+        let seed: Vec<f64> = match self.index {
+            Some(i) => {
+                let mut seed: Vec<f64> = vec![0.0; self.src.numel()];
+                seed[i] = grad;
+                seed
+            },
+            None    => vec![grad; self.src.numel()]
+        };
+        self.src.propagate(seed);
+
+        return Vec::new();
+    }
+}
+
+
+/*** Operator Overloads ***/
+
+impl ops::Add<&Tensor> for &Tensor {
+    type Output = Tensor;
+    fn add(self, rhs: &Tensor) -> Tensor {
+        return self.elementwise(rhs, Operations::Add);
+    }
+}
+
+
+impl ops::Sub<&Tensor> for &Tensor {
+    type Output = Tensor;
+    fn sub(self, rhs: &Tensor) -> Tensor {
+        return self.elementwise(rhs, Operations::Sub);
+    }
+}
+
+
+impl ops::Mul<&Tensor> for &Tensor {
+    type Output = Tensor;
+    fn mul(self, rhs: &Tensor) -> Tensor {
+        return self.elementwise(rhs, Operations::Mul);
+    }
+}
+
+
+impl ops::Add<f64> for &Tensor {
+    type Output = Tensor;
+    fn add(self, rhs: f64) -> Tensor {
+        let data: Vec<f64> = backend::current().unary(Unary::Shift(rhs), &self.data());
+        return self.with_constant(data, Operations::Add, 1.0);
+    }
+}
+
+
+impl ops::Sub<f64> for &Tensor {
+    type Output = Tensor;
+    fn sub(self, rhs: f64) -> Tensor {
+        let data: Vec<f64> = backend::current().unary(Unary::Shift(-rhs), &self.data());
+        return self.with_constant(data, Operations::Sub, 1.0);
+    }
+}
+
+
+impl ops::Mul<f64> for &Tensor {
+    type Output = Tensor;
+    fn mul(self, rhs: f64) -> Tensor {
+        let data: Vec<f64> = backend::current().unary(Unary::Scale(rhs), &self.data());
+        return self.with_constant(data, Operations::Mul, rhs);
+    }
+}
+
+
+impl ops::Add<&Tensor> for f64 {
+    type Output = Tensor;
+    fn add(self, rhs: &Tensor) -> Tensor {
+        return rhs + self;
+    }
+}
+
+
+impl ops::Sub<&Tensor> for f64 {
+    type Output = Tensor;
+    fn sub(self, rhs: &Tensor) -> Tensor {
+        let be: Rc<dyn Backend> = backend::current();
+        let data: Vec<f64> = be.unary(Unary::Shift(self), &be.unary(Unary::Scale(-1.0), &rhs.data()));
+        return rhs.with_constant(data, Operations::Sub, -1.0);
+    }
+}
+
+
+impl ops::Mul<&Tensor> for f64 {
+    type Output = Tensor;
+    fn mul(self, rhs: &Tensor) -> Tensor {
+        return rhs * self;
+    }
+}
+
+
+impl ops::Neg for &Tensor {
+    type Output = Tensor;
+    fn neg(self) -> Tensor {
+        return self * -1.0;
+    }
+}
+/*** End Overloads ***/
+
+
+impl fmt::Display for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Shape: {:?}, Data: {:?}, Grad: {:?}, Op: {}", self.shape(), self.data(), self.grad(), self.op());
+    }
+}
+
+
+
+#[cfg(test)]
+mod tensor_ops {
+    use super::*;
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn tensor() {
+        {
+            let t: Tensor = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+
+            assert_eq!(t.shape(), vec![2, 3]);
+            assert_eq!(t.numel(), 6);
+            assert_eq!(t.grad(), vec![0.0; 6]);
+            assert_eq!(t.op(), Operations::Non);
+        }
+
+        {
+            let t: Tensor = Tensor::zeros(&[4]);
+
+            assert_eq!(t.data(), vec![0.0; 4]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn bad_shape() {
+        Tensor::new(vec![1.0, 2.0, 3.0], &[2, 2]);
+    }
+
+    #[test]
+    fn elementwise() {
+        {
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+            let b: Tensor = Tensor::new(vec![4.0, -5.0, 6.0], &[3]);
+
+            assert_eq!((&a + &b).data(), vec![5.0, -3.0, 9.0]);
+            assert_eq!((&a - &b).data(), vec![-3.0, 7.0, -3.0]);
+            assert_eq!((&a * &b).data(), vec![4.0, -10.0, 18.0]);
+            assert_eq!((&a * &b).op(), Operations::Mul);
+        }
+
+        {
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+            let b: Tensor = Tensor::new(vec![4.0, -5.0, 6.0], &[3]);
+            let o: Tensor = &(&a * &b) + &a;
+
+            o.backward();
+            assert_eq!(a.grad(), vec![5.0, -4.0, 7.0]);
+            assert_eq!(b.grad(), vec![1.0, 2.0, 3.0]);
+            assert_eq!(o.grad(), vec![1.0, 1.0, 1.0]);
+        }
+
+        {
+            // Scalars broadcast over every element
+            let a: Tensor = Tensor::new(vec![1.0, -2.0], &[2]);
+            let o: Tensor = &(2.0 * &(&a + 1.0)) - 0.5;
+
+            assert_eq!(o.data(), vec![3.5, -2.5]);
+
+            o.backward();
+            assert_eq!(a.grad(), vec![2.0, 2.0]);
+        }
+
+        {
+            let a: Tensor = Tensor::new(vec![1.0, -2.0], &[2]);
+            let o: Tensor = 1.0 - &(-&a);
+
+            assert_eq!(o.data(), vec![2.0, -1.0]);
+
+            o.backward();
+            assert_eq!(a.grad(), vec![1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn activations() {
+        {
+            let a: Tensor = Tensor::new(vec![0.5, -1.0], &[2]);
+            let o: Tensor = a.tanh();
+
+            o.backward();
+            assert!(approx_eq(o.data()[0], 0.5_f64.tanh()));
+            assert!(approx_eq(a.grad()[1], 1.0 - (-1.0_f64).tanh().powi(2)));
+        }
+
+        {
+            let a: Tensor = Tensor::new(vec![0.5, -1.0], &[2]);
+            let o: Tensor = a.relu();
+
+            o.backward();
+            assert_eq!(o.data(), vec![0.5, 0.0]);
+            assert_eq!(a.grad(), vec![1.0, 0.0]);
+        }
+
+        {
+            // Row by row; d s_0 / d x_j = s_0 (δ_0j - s_j)
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 1000.0, 1000.0], &[2, 2]);
+            let o: Tensor = a.softmax();
+            let (s0, s1): (f64, f64) = (1.0 / (1.0 + 1.0_f64.exp()), 1.0 / (1.0 + (-1.0_f64).exp()));
+            assert!(approx_eq(o.data()[0], s0) && approx_eq(o.data()[1], s1));
+            assert_eq!(o.data()[2..], [0.5, 0.5]);
+            assert_eq!(o.op(), Operations::Softmax);
+
+            o.get(0).backward();
+            assert!(approx_eq(a.grad()[0], s0 * (1.0 - s0)));
+            assert!(approx_eq(a.grad()[1], -s0 * s1));
+            assert_eq!(a.grad()[2..], [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn bridge() {
+        {
+            // Tensor graph feeding a scalar loss
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+            let b: Tensor = Tensor::new(vec![0.5, 0.5, -1.0], &[3]);
+            let loss: Val = (&a * &b).sum() * 2.0;
+
+            assert!(approx_eq(loss.data(), -3.0));
+
+            loss.backward();
+            assert_eq!(a.grad(), vec![1.0, 1.0, -2.0]);
+            assert_eq!(b.grad(), vec![2.0, 4.0, 6.0]);
+        }
+
+        {
+            // Several scalar views of one tensor each contribute once
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+            let sq: Tensor = &a * &a;
+            let loss: Val = sq.get(0) + sq.get(2) * 3.0 + sq.sum();
+
+            loss.backward();
+            assert_eq!(a.grad(), vec![4.0, 4.0, 24.0]);
+            assert_eq!(sq.grad(), vec![2.0, 1.0, 4.0]);
+        }
+
+        {
+            let a: Tensor = Tensor::new(vec![1.0, 2.0], &[2]);
+            let loss: Val = a.sum().tanh();
+            loss.backward();
+            a.zero_grad();
+
+            assert_eq!(a.grad(), vec![0.0, 0.0]);
+        }
+
+        {
+            // Nothing is recorded under no_grad
+            let a: Tensor = Tensor::new(vec![1.0, 2.0], &[2]);
+            let loss: Val = crate::no_grad(|| (&a * &a).sum());
+
+            assert_eq!(loss.data(), 5.0);
+            loss.backward();
+            assert_eq!(a.grad(), vec![0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn matmul() {
+        {
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+            let b: Tensor = Tensor::new(vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0], &[3, 2]);
+            let c: Tensor = a.matmul(&b);
+
+            assert_eq!(c.shape(), vec![2, 2]);
+            assert_eq!(c.data(), vec![58.0, 64.0, 139.0, 154.0]);
+            assert_eq!(c.op(), Operations::MatMul);
+        }
+
+        {
+            // With a ones seed, dA[i][p] = sum_j B[p][j] and dB[p][j] = sum_i A[i][p]
+            let a: Tensor = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+            let b: Tensor = Tensor::new(vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0], &[3, 2]);
+            a.matmul(&b).backward();
+
+            assert_eq!(a.grad(), vec![15.0, 19.0, 23.0, 15.0, 19.0, 23.0]);
+            assert_eq!(b.grad(), vec![5.0, 5.0, 7.0, 7.0, 9.0, 9.0]);
+        }
+
+        {
+            // Weighted seed through get(), checked against hand-derived values
+            let a: Tensor = Tensor::new(vec![1.0, -1.0, 2.0, 0.5], &[2, 2]);
+            let b: Tensor = Tensor::new(vec![3.0, 1.0, -2.0, 4.0], &[2, 2]);
+            let c: Tensor = a.matmul(&b);
+            let loss: Val = c.get(1) * 2.0 + c.get(2);
+
+            loss.backward();
+            // dOut = [[0, 2], [1, 0]]
+            assert_eq!(a.grad(), vec![2.0, 8.0, 3.0, -2.0]);
+            assert_eq!(b.grad(), vec![2.0, 2.0, 0.5, -2.0]);
+        }
+
+        {
+            // A small linear layer: relu(x @ W + b) summed
+            let x: Tensor = Tensor::new(vec![1.0, 2.0], &[1, 2]);
+            let w: Tensor = Tensor::new(vec![0.5, -1.0, 0.25, 1.0], &[2, 2]);
+            let b: Tensor = Tensor::new(vec![0.0, -0.5], &[1, 2]);
+            let loss: Val = (&x.matmul(&w) + &b).relu().sum();
+
+            assert!(approx_eq(loss.data(), 1.0 + 0.5));
+
+            loss.backward();
+            assert_eq!(w.grad(), vec![1.0, 1.0, 2.0, 2.0]);
+            assert_eq!(x.grad(), vec![-0.5, 1.25]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn matmul_mismatch() {
+        let a: Tensor = Tensor::zeros(&[2, 3]);
+        let b: Tensor = Tensor::zeros(&[2, 3]);
+        a.matmul(&b);
+    }
+}
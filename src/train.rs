@@ -0,0 +1,621 @@
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::rand::{self, Rng};
+use crate::{ops, Float, Val};
+
+mod callback;
+mod cross_validation;
+mod early_stopping;
+mod history;
+mod parallel;
+mod progress;
+pub use callback::{Callback, LrSchedule, TrainState, Validation};
+pub use cross_validation::{cross_validate, CrossValidation};
+pub use early_stopping::EarlyStopping;
+pub use history::{HistoryFormat, HistoryLogger};
+pub use parallel::fit_parallel;
+pub use progress::Progress;
+
+
+pub struct BatchConfig {
+    pub batch_size: usize,
+    pub shuffle:    bool,
+    pub seed:       Option<u64>,
+    pub threads:    usize
+}
+
+
+impl BatchConfig {
+    pub fn new(batch_size: usize) -> BatchConfig {
+        assert!(batch_size > 0, "batch size must be positive");
+        return BatchConfig { batch_size, shuffle: false, seed: None, threads: 1 };
+    }
+
+    // Reorders the examples at the start of every epoch
+    pub fn shuffle(mut self) -> BatchConfig {
+        self.shuffle = true;
+        return self;
+    }
+
+    // Shuffles with a private generator instead of the global one
+    pub fn seed(mut self, seed: u64) -> BatchConfig {
+        self.seed = Some(seed);
+        return self;
+    }
+
+    // Worker threads for fit_parallel; the other fits run on the calling thread
+    pub fn threads(mut self, threads: usize) -> BatchConfig {
+        assert!(threads > 0, "thread count must be positive");
+        self.threads = threads;
+        return self;
+    }
+}
+
+
+// One optimizer step per example. Returns the mean loss of every epoch.
+pub fn fit<T, M, O, L>(model: &M, dataset: &[(Vec<T>, Vec<T>)], optimizer: &mut O, loss_fn: L, epochs: usize) -> Vec<f64>
+where T: Float,
+      M: Module<T>,
+      O: Optimizer,
+      L: Fn(&[Val<T>], &[T]) -> Val<T>,
+{
+    return fit_batched(model, dataset, optimizer, loss_fn, epochs, &BatchConfig::new(1));
+}
+
+
+// The losses of a batch are averaged into one graph, so there is a single
+// backward and step per batch. The last batch may be short.
+pub fn fit_batched<T, M, O, L>(model: &M, dataset: &[(Vec<T>, Vec<T>)], optimizer: &mut O, loss_fn: L, epochs: usize, config: &BatchConfig) -> Vec<f64>
+where T: Float,
+      M: Module<T>,
+      O: Optimizer,
+      L: Fn(&[Val<T>], &[T]) -> Val<T>,
+{
+    return fit_with(model, dataset, optimizer, loss_fn, epochs, config, &mut []);
+}
+
+
+// fit_batched with callbacks. If one stops training part way through an
+// epoch, that epoch's entry is the mean over the examples it got through.
+pub fn fit_with<T, M, O, L>(model: &M, dataset: &[(Vec<T>, Vec<T>)], optimizer: &mut O, loss_fn: L, epochs: usize, config: &BatchConfig, callbacks: &mut [&mut dyn Callback<T>]) -> Vec<f64>
+where T: Float,
+      M: Module<T>,
+      O: Optimizer,
+      L: Fn(&[Val<T>], &[T]) -> Val<T>,
+{
+    assert!(!dataset.is_empty(), "fit on an empty dataset");
+
+    let mut rng: Rng = match config.seed {
+        Some(seed) => Rng::new(seed),
+        None       => rand::fork()
+    };
+    let mut order: Vec<usize> = (0..dataset.len()).collect();
+
+    let mut state: TrainState<T> = TrainState {
+        model,
+        optimizer,
+        epoch:    0,
+        epochs,
+        batch:    0,
+        batches:  dataset.len().div_ceil(config.batch_size),
+        examples: 0,
+        loss:     0.0,
+        val_loss: None,
+        outputs:  Vec::new(),
+        targets:  Vec::new(),
+        stop:     false
+    };
+
+    let mut history: Vec<f64> = Vec::with_capacity(epochs);
+    for epoch in 0..epochs {
+        if config.shuffle {
+            rng.shuffle(&mut order);
+        }
+
+        state.epoch = epoch;
+        state.val_loss = None;
+        for cb in callbacks.iter_mut() {
+            cb.on_epoch_start(&mut state);
+        }
+
+        let (mut total, mut seen): (f64, usize) = (0.0, 0);
+        for (b, batch) in order.chunks(config.batch_size).enumerate() {
+            if state.stop {
+                break;
+            }
+            state.optimizer.zero_grad();
+
+            // The whole batch goes through at once, for layers that look across it
+            let inputs: Vec<Vec<Val<T>>> = batch.iter()
+                .map(|&i| dataset[i].0.iter().map(|&xi| Val::constant(xi)).collect())
+                .collect();
+            let outputs: Vec<Vec<Val<T>>> = model.forward_examples(&inputs);
+            let losses: Vec<Val<T>> = outputs.iter()
+                .zip(batch.iter())
+                .map(|(out, &i)| loss_fn(out, &dataset[i].1))
+                .collect();
+            let loss: Val<T> = ops::mean(&losses);
+
+            loss.backward();
+            state.optimizer.step();
+
+            state.outputs = outputs.iter().map(|out| out.iter().map(|v| v.data()).collect()).collect();
+            state.targets = batch.iter().map(|&i| dataset[i].1.clone()).collect();
+
+            total += loss.data().to_f64() * batch.len() as f64;
+            seen += batch.len();
+
+            (state.batch, state.examples, state.loss) = (b, batch.len(), loss.data().to_f64());
+            for cb in callbacks.iter_mut() {
+                cb.on_batch_end(&mut state);
+            }
+        }
+
+        if seen > 0 {
+            history.push(total / seen as f64);
+            state.loss = total / seen as f64;
+            for cb in callbacks.iter_mut() {
+                cb.on_epoch_end(&mut state);
+            }
+        }
+        if state.stop {
+            break;
+        }
+    }
+
+    for cb in callbacks.iter_mut() {
+        cb.on_train_end(&mut state);
+    }
+
+    return history;
+}
+
+
+// The mean loss over a dataset, without stepping; e.g. for a validation split.
+// The model runs in eval mode with no graph recorded, so Dropout is off and
+// BatchNorm1d's running statistics stay as they were. Its mode is put back after.
+pub fn evaluate<T, M, L>(model: &M, dataset: &[(Vec<T>, Vec<T>)], loss_fn: L) -> f64
+where T: Float,
+      M: Module<T> + ?Sized,
+      L: Fn(&[Val<T>], &[T]) -> Val<T>,
+{
+    assert!(!dataset.is_empty(), "evaluate on an empty dataset");
+
+    let training: bool = model.is_training();
+    model.eval();
+    let total: f64 = crate::no_grad(|| {
+        let inputs: Vec<Vec<Val<T>>> = dataset.iter()
+            .map(|(x, _)| x.iter().map(|&xi| Val::constant(xi)).collect())
+            .collect();
+        return model.forward_examples(&inputs).iter()
+            .zip(dataset.iter())
+            .map(|(out, (_, y))| loss_fn(out, y).data().to_f64())
+            .sum();
+    });
+    model.set_training(training);
+
+    return total / dataset.len() as f64;
+}
+
+
+
+#[cfg(test)]
+mod train_ops {
+    use super::*;
+    use crate::checkpoint::Checkpoint;
+    use crate::loss::mse;
+    use crate::init::Init;
+    use crate::nn::{BatchNorm1d, Dropout, Layer, Neuron, Sequential, MLP};
+    use crate::optim::{Adam, SGD};
+    use crate::rand::Rng;
+
+    #[test]
+    fn fit_linear() {
+        {
+            // y = 2x + 1
+            let model: Neuron = Neuron::new(1, false);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..5)
+                .map(|i| (vec![i as f64 * 0.5], vec![i as f64 + 1.0]))
+                .collect();
+            let mut opt: SGD = SGD::new(model.parameters(), 0.05);
+
+            let history: Vec<f64> = fit(&model, &data, &mut opt, mse, 200);
+
+            assert_eq!(history.len(), 200);
+            assert!(history[199] < history[0]);
+            assert!(history[199] < 1e-6);
+
+            let params: Vec<Val> = model.parameters();
+            assert!((params[0].data() - 2.0).abs() < 1e-3);
+            assert!((params[1].data() - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn fit_xor() {
+        {
+            let model: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[0.5, -0.4], 0.1, true),
+                    Neuron::from_weights(&[-0.3, 0.6], -0.2, true),
+                    Neuron::from_weights(&[0.2, 0.7], 0.05, true)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[0.4, -0.5, 0.3], 0.0, false)])
+            ]);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = vec![
+                (vec![0.0, 0.0], vec![-1.0]),
+                (vec![0.0, 1.0], vec![1.0]),
+                (vec![1.0, 0.0], vec![1.0]),
+                (vec![1.0, 1.0], vec![-1.0])
+            ];
+            let mut opt: Adam = Adam::new(model.parameters(), 0.05);
+
+            let history: Vec<f64> = fit(&model, &data, &mut opt, mse, 300);
+
+            assert!(history[299] < 0.05);
+            for (x, y) in data.iter() {
+                let inputs: Vec<Val> = x.iter().map(|&xi| Val::new(xi)).collect();
+                assert_eq!(model.forward(&inputs)[0].data().signum(), y[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_batches() {
+        {
+            // One full batch with SGD is plain gradient descent on the mean loss
+            let model: Neuron = Neuron::new(1, false);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = vec![(vec![1.0], vec![2.0]), (vec![2.0], vec![4.0])];
+            let mut opt: SGD = SGD::new(model.parameters(), 0.1);
+
+            let history: Vec<f64> = fit_batched(&model, &data, &mut opt, mse, 1, &BatchConfig::new(2));
+
+            // mean of (w x + b - y)^2 at zero: (4 + 16) / 2
+            assert!((history[0] - 10.0).abs() < 1e-12);
+            // dw = mean(2 (0 - y) x) = -10, db = mean(2 (0 - y)) = -6
+            let params: Vec<Val> = model.parameters();
+            assert!((params[0].data() - 1.0).abs() < 1e-12);
+            assert!((params[1].data() - 0.6).abs() < 1e-12);
+        }
+
+        {
+            // y = 2x + 1, batches of 3 over 10 examples, shuffled
+            let model: Neuron = Neuron::new(1, false);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..10)
+                .map(|i| (vec![i as f64 * 0.2], vec![i as f64 * 0.4 + 1.0]))
+                .collect();
+            let mut opt: SGD = SGD::new(model.parameters(), 0.2);
+
+            let history: Vec<f64> = fit_batched(&model, &data, &mut opt, mse, 300, &BatchConfig::new(3).shuffle().seed(11));
+
+            assert!(history[299] < 1e-6);
+            let params: Vec<Val> = model.parameters();
+            assert!((params[0].data() - 2.0).abs() < 1e-3);
+            assert!((params[1].data() - 1.0).abs() < 1e-3);
+        }
+
+        {
+            // The same seed reproduces a run exactly
+            let run = |seed: u64| -> Vec<f64> {
+                let model: Neuron = Neuron::from_weights(&[0.3], -0.1, true);
+                let data: Vec<(Vec<f64>, Vec<f64>)> = (0..7).map(|i| (vec![i as f64], vec![(i % 2) as f64])).collect();
+                let mut opt: SGD = SGD::new(model.parameters(), 0.05);
+                return fit_batched(&model, &data, &mut opt, mse, 5, &BatchConfig::new(2).shuffle().seed(seed));
+            };
+
+            assert_eq!(run(3), run(3));
+            assert_ne!(run(3), run(4));
+        }
+    }
+
+    #[test]
+    fn evaluation() {
+        {
+            // Eval mode: BatchNorm1d normalizes with its running statistics and
+            // doesn't update them, and the model comes back still training
+            let bn: BatchNorm1d = BatchNorm1d::new(1);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = vec![(vec![10.0], vec![0.0]), (vec![20.0], vec![0.0])];
+            let loss: f64 = evaluate(&bn, &data, mse);
+
+            assert!((loss - 250.0 / (1.0 + 1e-5)).abs() < 1e-9);
+            assert_eq!(bn.running_mean(), vec![0.0]);
+            assert_eq!(bn.running_var(), vec![1.0]);
+            assert!(bn.is_training());
+
+            bn.eval();
+            assert_eq!(evaluate(&bn, &data, mse), loss);
+            assert!(!bn.is_training());
+        }
+
+        {
+            // Dropout is off, so every call agrees, and nothing is recorded
+            let model: Sequential = Sequential::default()
+                .layer(Layer::new(2, 8, true))
+                .layer(Dropout::new(0.5))
+                .layer(Layer::new(8, 1, false));
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..4).map(|i| (vec![i as f64, 1.0], vec![0.5])).collect();
+            let leaves = |out: &[Val], y: &[f64]| -> Val {
+                assert!(out.iter().all(|o| o.prev().is_empty()));
+                return mse(out, y);
+            };
+
+            let loss: f64 = evaluate(&model, &data, leaves);
+            assert_eq!(evaluate(&model, &data, mse), loss);
+            assert!(model.is_training());
+            model.eval();
+            assert_eq!(evaluate(&model, &data, mse), loss);
+        }
+    }
+
+    #[test]
+    fn fit_callbacks() {
+        // Records every hook as it fires
+        struct Record {
+            events: Vec<String>,
+            stop_at: Option<(usize, usize)>
+        }
+
+        impl Callback for Record {
+            fn on_epoch_start(&mut self, s: &mut TrainState) {
+                self.events.push(format!("start {} lr {}", s.epoch, s.optimizer.lr()));
+            }
+
+            fn on_batch_end(&mut self, s: &mut TrainState) {
+                self.events.push(format!("batch {}/{} of {}", s.batch, s.batches, s.examples));
+                s.stop = self.stop_at == Some((s.epoch, s.batch));
+            }
+
+            fn on_epoch_end(&mut self, s: &mut TrainState) {
+                self.events.push(format!("end {}", s.epoch));
+            }
+
+            fn on_train_end(&mut self, s: &mut TrainState) {
+                self.events.push(format!("done after {}", s.epoch));
+            }
+        }
+
+        let data: Vec<(Vec<f64>, Vec<f64>)> = (0..5).map(|i| (vec![i as f64], vec![i as f64])).collect();
+
+        {
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.01);
+            let mut record: Record = Record { events: Vec::new(), stop_at: None };
+            let mut halve = LrSchedule::new(|epoch, base| base * 0.5_f64.powi(epoch as i32));
+
+            // The schedule runs first, so the record sees the rate it set
+            let history: Vec<f64> = fit_with(&model, &data, &mut opt, mse, 2, &BatchConfig::new(2), &mut [&mut halve, &mut record]);
+
+            assert_eq!(history.len(), 2);
+            assert_eq!(record.events, vec![
+                "start 0 lr 0.01", "batch 0/3 of 2", "batch 1/3 of 2", "batch 2/3 of 1", "end 0",
+                "start 1 lr 0.005", "batch 0/3 of 2", "batch 1/3 of 2", "batch 2/3 of 1", "end 1",
+                "done after 1"
+            ]);
+            assert_eq!(opt.lr(), 0.005);
+        }
+
+        {
+            // Stopping mid-epoch skips the rest of it; its loss covers what ran
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.0);
+            let mut record: Record = Record { events: Vec::new(), stop_at: Some((1, 0)) };
+
+            let history: Vec<f64> = fit_with(&model, &data, &mut opt, mse, 10, &BatchConfig::new(2), &mut [&mut record]);
+
+            assert_eq!(history, vec![6.0, 0.5]);
+            assert_eq!(record.events[5..], ["start 1 lr 0", "batch 0/3 of 2", "end 1", "done after 1"]);
+        }
+
+        {
+            // Hooks see the whole model and optimizer, e.g. to checkpoint
+            struct Snapshot(Vec<Checkpoint>);
+
+            impl Callback for Snapshot {
+                fn on_epoch_end(&mut self, s: &mut TrainState) {
+                    self.0.push(Checkpoint::capture(s.model, s.optimizer, s.epoch + 1));
+                }
+            }
+
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.01);
+            let mut snaps: Snapshot = Snapshot(Vec::new());
+            fit_with(&model, &data, &mut opt, mse, 3, &BatchConfig::new(5), &mut [&mut snaps]);
+
+            assert_eq!(snaps.0.iter().map(|c| c.epoch).collect::<Vec<usize>>(), vec![1, 2, 3]);
+            assert_eq!(snaps.0[2].params, model.parameters().iter().map(|p| p.data()).collect::<Vec<f64>>());
+        }
+
+        {
+            // Validation scores in eval mode, so training runs as it would without it
+            let val: Vec<(Vec<f64>, Vec<f64>)> = vec![(vec![10.0], vec![1.0]), (vec![-4.0], vec![0.0])];
+            let run = |validate: bool| -> (Vec<f64>, Vec<f64>) {
+                let bn: BatchNorm1d = BatchNorm1d::new(1);
+                let mut opt: SGD = SGD::new(bn.parameters(), 0.1);
+                let mut validation = Validation::new(&val, mse);
+                let callbacks: &mut [&mut dyn Callback] = if validate { &mut [&mut validation] } else { &mut [] };
+                let history: Vec<f64> = fit_with(&bn, &data, &mut opt, mse, 3, &BatchConfig::new(5), callbacks);
+                assert!(bn.is_training());
+                return (history, bn.running_mean());
+            };
+
+            assert_eq!(run(true), run(false));
+        }
+    }
+
+    #[test]
+    fn fit_global_seed() {
+        {
+            let run = || -> Vec<f64> {
+                let model: MLP = MLP::random(1, &[3, 1], Init::Uniform);
+                let data: Vec<(Vec<f64>, Vec<f64>)> = (0..6).map(|i| (vec![i as f64 * 0.1], vec![(i % 2) as f64])).collect();
+                let mut opt: SGD = SGD::new(model.parameters(), 0.05);
+                return fit_batched(&model, &data, &mut opt, mse, 3, &BatchConfig::new(2).shuffle());
+            };
+
+            crate::set_seed(8);
+            let a: Vec<f64> = run();
+            crate::set_seed(8);
+            let b: Vec<f64> = run();
+
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn fit_threads() {
+        {
+            // Same seed and starting weights: the same run as fit_batched, up to summation order
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..11).map(|i| (vec![i as f64 * 0.1, 1.0 - i as f64 * 0.05], vec![(i % 3) as f64 - 1.0])).collect();
+            let build = || -> MLP { MLP::random(2, &[4, 1], Init::Uniform) };
+            let config = |threads: usize| -> BatchConfig { BatchConfig::new(4).shuffle().seed(5).threads(threads) };
+
+            crate::set_seed(3);
+            let serial: MLP = build();
+            let mut opt: Adam = Adam::new(serial.parameters(), 0.05);
+            let expected: Vec<f64> = fit_batched(&serial, &data, &mut opt, mse, 4, &config(1));
+
+            for threads in [1, 3, 8] {
+                crate::set_seed(3);
+                let model: MLP = build();
+                let mut opt: Adam = Adam::new(model.parameters(), 0.05);
+                let history: Vec<f64> = fit_parallel(build, &model, &data, &mut opt, mse, 4, &config(threads));
+
+                for (a, b) in history.iter().zip(expected.iter()) {
+                    assert!((a - b).abs() < 1e-12);
+                }
+                for (p, q) in model.parameters().iter().zip(serial.parameters().iter()) {
+                    assert!((p.data() - q.data()).abs() < 1e-12);
+                }
+            }
+        }
+
+        {
+            // Frozen parameters stay put
+            let model: MLP = MLP::random(1, &[2, 1], Init::Uniform);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..6).map(|i| (vec![i as f64], vec![1.0])).collect();
+            model.parameters()[0].set_requires_grad(false);
+            let start: Vec<f64> = model.parameters().iter().map(|p| p.data()).collect();
+
+            let mut opt: SGD = SGD::new(model.parameters(), 0.01);
+            fit_parallel(|| MLP::random(1, &[2, 1], Init::Uniform), &model, &data, &mut opt, mse, 2, &BatchConfig::new(3).threads(2));
+            let end: Vec<f64> = model.parameters().iter().map(|p| p.data()).collect();
+
+            assert_eq!(end[0], start[0]);
+            assert_ne!(end[1], start[1]);
+        }
+
+        {
+            // Dropout on the workers follows the fit's seed, or set_seed without one
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..12).map(|i| (vec![i as f64 * 0.1, (i % 4) as f64], vec![(i % 3) as f64])).collect();
+            let build = || -> Sequential {
+                return Sequential::default()
+                    .layer(Layer::with_init(2, 8, true, Init::Xavier, &mut Rng::new(4)))
+                    .layer(Dropout::new(0.5))
+                    .layer(Layer::with_init(8, 1, false, Init::Xavier, &mut Rng::new(5)));
+            };
+            let run = |config: &BatchConfig, eval: bool| -> Vec<f64> {
+                let model: Sequential = build();
+                if eval {
+                    model.eval();
+                }
+                let mut opt: Adam = Adam::new(model.parameters(), 0.05);
+                return fit_parallel(build, &model, &data, &mut opt, mse, 3, config);
+            };
+
+            let seeded = |seed: u64| -> BatchConfig { BatchConfig::new(6).shuffle().seed(seed).threads(3) };
+            assert_eq!(run(&seeded(5), false), run(&seeded(5), false));
+            // Without shuffling only the masks can tell the seeds apart
+            let unshuffled = |seed: u64| -> BatchConfig { BatchConfig::new(6).seed(seed).threads(3) };
+            assert_ne!(run(&unshuffled(5), false), run(&unshuffled(6), false));
+
+            crate::set_seed(9);
+            let a: Vec<f64> = run(&BatchConfig::new(6).threads(3), false);
+            crate::set_seed(9);
+            assert_eq!(run(&BatchConfig::new(6).threads(3), false), a);
+
+            // Replicas take the model's mode: in eval, dropout is off and the run is
+            // fit_batched's
+            let model: Sequential = build();
+            model.eval();
+            let mut opt: Adam = Adam::new(model.parameters(), 0.05);
+            let expected: Vec<f64> = fit_batched(&model, &data, &mut opt, mse, 3, &seeded(5));
+            for (a, b) in run(&seeded(5), true).iter().zip(expected.iter()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+        }
+
+        {
+            // BatchNorm1d trains on each share, and its running statistics come back
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..8).map(|i| (vec![i as f64], vec![3.0 * i as f64 - 2.0])).collect();
+            let build = || -> Sequential {
+                return Sequential::default()
+                    .layer(Layer::with_init(1, 4, false, Init::Xavier, &mut Rng::new(1)))
+                    .layer(BatchNorm1d::new(4))
+                    .layer(Layer::with_init(4, 1, false, Init::Xavier, &mut Rng::new(2)));
+            };
+            let run = |threads: usize| -> (Vec<f64>, Vec<f64>) {
+                let model: Sequential = build();
+                let mut opt: Adam = Adam::new(model.parameters(), 0.1);
+                let history: Vec<f64> = fit_parallel(build, &model, &data, &mut opt, mse, 30, &BatchConfig::new(8).shuffle().seed(3).threads(threads));
+                return (history, model.buffers());
+            };
+
+            // One thread is fit_batched exactly
+            let model: Sequential = build();
+            let mut opt: Adam = Adam::new(model.parameters(), 0.1);
+            let expected: Vec<f64> = fit_batched(&model, &data, &mut opt, mse, 30, &BatchConfig::new(8).shuffle().seed(3));
+            let (history, buffers): (Vec<f64>, Vec<f64>) = run(1);
+            for (a, b) in history.iter().zip(expected.iter()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+            for (a, b) in buffers.iter().zip(model.buffers().iter()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+
+            // Shares of 2, 3 and 3 on three threads, mixed up by shuffling
+            let (history, buffers): (Vec<f64>, Vec<f64>) = run(3);
+            assert!(history[29] < history[0] * 0.2);
+            assert_ne!(buffers, build().buffers());
+        }
+    }
+
+    #[test]
+    fn cross_validation() {
+        {
+            // A noiseless line is learnt from any four of the five folds
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..10).map(|i| (vec![i as f64 * 0.2], vec![1.0 - i as f64 * 0.3])).collect();
+            let build = || -> Neuron { Neuron::from_weights(&[0.0], 0.0, false) };
+            let cv: CrossValidation = cross_validate(build, |p| SGD::new(p, 0.1), &data, mse, 300, 5, &BatchConfig::new(2));
+
+            assert_eq!((cv.train.len(), cv.validation.len()), (5, 5));
+            assert!(cv.validation.iter().all(|&v| v < 1e-6));
+            assert!(cv.mean < 1e-6 && cv.std < 1e-6);
+        }
+
+        {
+            // Too few epochs to fit: each fold's score is its own model's
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..9).map(|i| (vec![1.0], vec![i as f64])).collect();
+            let cv: CrossValidation = cross_validate(|| Neuron::from_weights(&[0.0], 0.0, false), |p| SGD::new(p, 0.0), &data, mse, 1, 3, &BatchConfig::new(9));
+
+            // Predicting zero everywhere: fold i holds 3i, 3i + 1 and 3i + 2
+            let expected: Vec<f64> = (0..3).map(|f| (0..3).map(|j| ((3 * f + j) as f64).powi(2)).sum::<f64>() / 3.0).collect();
+            assert_eq!(cv.validation, expected);
+            let mean: f64 = expected.iter().sum::<f64>() / 3.0;
+            assert!((cv.mean - mean).abs() < 1e-12);
+            assert!((cv.std - (expected.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / 3.0).sqrt()).abs() < 1e-12);
+
+            // Shuffled folds still cover every example once
+            let cv: CrossValidation = cross_validate(|| Neuron::from_weights(&[0.0], 0.0, false), |p| SGD::new(p, 0.0), &data, mse, 1, 3, &BatchConfig::new(9).shuffle().seed(1));
+            assert!((cv.mean - mean).abs() < 1e-12);
+        }
+
+        {
+            // Folds are scored in eval mode: dropping everything while training,
+            // the identity after
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (1..7).map(|i| (vec![i as f64], vec![i as f64])).collect();
+            let cv: CrossValidation = cross_validate(|| Dropout::new(1.0), |p| SGD::new(p, 0.0), &data, mse, 1, 2, &BatchConfig::new(3));
+            assert!(cv.train.iter().all(|&t| t > 1.0));
+            assert_eq!(cv.validation, vec![0.0, 0.0]);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::{Float, Val};
+
+use super::evaluate;
+
+
+// What fit_with shows its callbacks. Epochs and batches count from zero.
+// `loss` is the batch's mean loss in on_batch_end and the epoch's in
+// on_epoch_end; `outputs` and `targets` hold the last batch's values, and
+// the parameters still carry its gradients. Setting `stop` ends training
+// after the current hook, and a hook may change the learning rate through
+// the optimizer.
+pub struct TrainState<'a, T: Float = f64> {
+    pub model:     &'a dyn Module<T>,
+    pub optimizer: &'a mut dyn Optimizer,
+    pub epoch:     usize,
+    pub epochs:    usize,
+    pub batch:     usize,
+    pub batches:   usize,
+    pub examples:  usize,
+    pub loss:      f64,
+    pub val_loss:  Option<f64>,
+    pub outputs:   Vec<Vec<T>>,
+    pub targets:   Vec<Vec<T>>,
+    pub stop:      bool
+}
+
+
+// Hooks into the training loop; every one defaults to doing nothing. They run
+// in the order the callbacks were passed, so later ones see what earlier ones
+// left in the state (a Validation before an EarlyStopping, say).
+pub trait Callback<T: Float = f64> {
+    fn on_epoch_start(&mut self, _state: &mut TrainState<T>) {}
+
+    fn on_batch_end(&mut self, _state: &mut TrainState<T>) {}
+
+    fn on_epoch_end(&mut self, _state: &mut TrainState<T>) {}
+
+    fn on_train_end(&mut self, _state: &mut TrainState<T>) {}
+}
+
+
+// Fills in val_loss at the end of every epoch, through evaluate, so the model
+// is scored in eval mode and goes back to training unchanged
+pub struct Validation<'d, T: Float, L: Fn(&[Val<T>], &[T]) -> Val<T>> {
+    dataset: &'d [(Vec<T>, Vec<T>)],
+    loss_fn: L
+}
+
+
+impl<'d, T: Float, L: Fn(&[Val<T>], &[T]) -> Val<T>> Validation<'d, T, L> {
+    pub fn new(dataset: &'d [(Vec<T>, Vec<T>)], loss_fn: L) -> Validation<'d, T, L> {
+        return Validation { dataset, loss_fn };
+    }
+}
+
+
+impl<T: Float, L: Fn(&[Val<T>], &[T]) -> Val<T>> Callback<T> for Validation<'_, T, L> {
+    fn on_epoch_end(&mut self, state: &mut TrainState<T>) {
+        state.val_loss = Some(evaluate(state.model, self.dataset, &self.loss_fn));
+    }
+}
+
+
+// Sets the learning rate at the start of every epoch to schedule(epoch, base),
+// base being the rate the optimizer had when training began
+pub struct LrSchedule<F: FnMut(usize, f64) -> f64> {
+    schedule: F,
+    base_lr:  Option<f64>
+}
+
+
+impl<F: FnMut(usize, f64) -> f64> LrSchedule<F> {
+    pub fn new(schedule: F) -> LrSchedule<F> {
+        return LrSchedule { schedule, base_lr: None };
+    }
+}
+
+
+impl<T: Float, F: FnMut(usize, f64) -> f64> Callback<T> for LrSchedule<F> {
+    fn on_epoch_start(&mut self, state: &mut TrainState<T>) {
+        let base: f64 = *self.base_lr.get_or_insert(state.optimizer.lr());
+        state.optimizer.set_lr((self.schedule)(state.epoch, base));
+    }
+}
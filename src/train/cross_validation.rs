@@ -0,0 +1,64 @@
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::rand::{self, Rng};
+use crate::{Float, Val};
+
+use super::{evaluate, fit_batched, BatchConfig};
+
+
+// Per-fold results of cross_validate, fold i validating on the i-th slice.
+// mean and std summarise the validation losses; std is the population one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossValidation {
+    pub train:      Vec<f64>,
+    pub validation: Vec<f64>,
+    pub mean:       f64,
+    pub std:        f64
+}
+
+
+// k-fold cross-validation: the dataset is cut into k near-equal folds, and
+// for each a fresh model from `build` with an optimizer from `optimizer` is
+// fit on the other k - 1 and scored on it in eval mode. Train is each
+// model's loss over its last epoch. With config.shuffle the folds are drawn
+// at random, from config.seed if set; otherwise they are contiguous runs.
+pub fn cross_validate<T, M, B, O, P, L>(build: B, optimizer: P, dataset: &[(Vec<T>, Vec<T>)], loss_fn: L, epochs: usize, k: usize, config: &BatchConfig) -> CrossValidation
+where T: Float,
+      M: Module<T>,
+      B: Fn() -> M,
+      O: Optimizer,
+      P: Fn(Vec<Val<T>>) -> O,
+      L: Fn(&[Val<T>], &[T]) -> Val<T>,
+{
+    assert!(k >= 2, "cross-validation needs at least two folds");
+    assert!(k <= dataset.len(), "more folds than examples");
+
+    let mut order: Vec<usize> = (0..dataset.len()).collect();
+    if config.shuffle {
+        let mut rng: Rng = match config.seed {
+            Some(seed) => Rng::new(seed),
+            None       => rand::fork()
+        };
+        rng.shuffle(&mut order);
+    }
+
+    let (mut train, mut validation): (Vec<f64>, Vec<f64>) = (Vec::with_capacity(k), Vec::with_capacity(k));
+    for fold in 0..k {
+        let (lo, hi): (usize, usize) = (fold * order.len() / k, (fold + 1) * order.len() / k);
+        let held: Vec<(Vec<T>, Vec<T>)> = order[lo..hi].iter().map(|&i| dataset[i].clone()).collect();
+        let rest: Vec<(Vec<T>, Vec<T>)> = order[..lo].iter().chain(order[hi..].iter()).map(|&i| dataset[i].clone()).collect();
+
+        let model: M = build();
+        let mut opt: O = optimizer(model.parameters());
+        let history: Vec<f64> = fit_batched(&model, &rest, &mut opt, &loss_fn, epochs, config);
+
+        train.push(history.last().copied().unwrap_or(f64::NAN));
+        model.eval();
+        validation.push(evaluate(&model, &held, &loss_fn));
+    }
+
+    let mean: f64 = validation.iter().sum::<f64>() / k as f64;
+    let std: f64 = (validation.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / k as f64).sqrt();
+
+    return CrossValidation { train, validation, mean, std };
+}
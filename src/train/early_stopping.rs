@@ -0,0 +1,165 @@
+use crate::{Float, Val};
+
+use super::{Callback, TrainState};
+
+
+// Watches a validation loss once per epoch and says when to stop: after
+// `patience` epochs in a row without improving on the best loss by more than
+// min_delta. The weights of the best epoch are kept so they can be restored.
+//
+// As a callback it reads val_loss (the training loss if nothing filled it
+// in), and when it stops training it puts the best weights back.
+pub struct EarlyStopping<T: Float = f64> {
+    patience:  usize,
+    min_delta: f64,
+    best:      Option<f64>,
+    best_step: usize,
+    weights:   Vec<T>,
+    waited:    usize,
+    steps:     usize,
+    restore:   bool
+}
+
+
+impl<T: Float> EarlyStopping<T> {
+    pub fn new(patience: usize) -> EarlyStopping<T> {
+        return EarlyStopping { patience, min_delta: 0.0, best: None, best_step: 0, weights: Vec::new(), waited: 0, steps: 0, restore: true };
+    }
+
+    // Smaller gains than this count as no improvement
+    pub fn min_delta(mut self, min_delta: f64) -> EarlyStopping<T> {
+        self.min_delta = min_delta;
+        return self;
+    }
+
+    // Whether stopping restores the best weights, as it does by default
+    pub fn restore_best(mut self, restore: bool) -> EarlyStopping<T> {
+        self.restore = restore;
+        return self;
+    }
+
+    // Records this epoch's loss, snapshotting the parameters if it is the best
+    // so far. True once patience has run out.
+    pub fn check(&mut self, val_loss: f64, params: &[Val<T>]) -> bool {
+        self.steps += 1;
+
+        let improved: bool = self.best.is_none_or(|best| val_loss < best - self.min_delta);
+        if improved {
+            self.best = Some(val_loss);
+            self.best_step = self.steps;
+            self.weights = params.iter().map(|p| p.data()).collect();
+            self.waited = 0;
+        } else {
+            self.waited += 1;
+        }
+
+        return self.should_stop();
+    }
+
+    pub fn should_stop(&self) -> bool {
+        return self.best.is_some() && self.waited >= self.patience;
+    }
+
+    pub fn best_loss(&self) -> Option<f64> {
+        return self.best;
+    }
+
+    // 1-based epoch of the best loss, 0 before the first check
+    pub fn best_epoch(&self) -> usize {
+        return self.best_step;
+    }
+
+    // Copies the best weights back into the parameters they were taken from
+    pub fn restore(&self, params: &[Val<T>]) {
+        if self.best.is_none() {
+            return;
+        }
+        assert_eq!(params.len(), self.weights.len(), "expected the parameters that were checked");
+        for (p, &w) in params.iter().zip(self.weights.iter()) {
+            p.set_data(w);
+        }
+    }
+}
+
+
+impl<T: Float> Callback<T> for EarlyStopping<T> {
+    fn on_epoch_end(&mut self, state: &mut TrainState<T>) {
+        let loss: f64 = state.val_loss.unwrap_or(state.loss);
+        if self.check(loss, &state.model.parameters()) {
+            state.stop = true;
+        }
+    }
+
+    fn on_train_end(&mut self, state: &mut TrainState<T>) {
+        if self.restore && self.should_stop() {
+            self.restore(&state.model.parameters());
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod early_stopping_ops {
+    use super::*;
+    use crate::data::{split, synthetic, InMemoryDataset};
+    use crate::loss::mse;
+    use crate::nn::{Module, Neuron};
+    use crate::optim::SGD;
+    use crate::rand::Rng;
+    use crate::train::{evaluate, fit_with, BatchConfig, Validation};
+
+    #[test]
+    fn early_stopping() {
+        {
+            let p: Vec<Val> = vec![Val::new(0.0)];
+            let mut stop: EarlyStopping = EarlyStopping::new(2).min_delta(0.05);
+            let losses: [f64; 6] = [1.0, 0.5, 0.48, 0.6, 0.3, 0.29];
+
+            let mut stopped: Option<usize> = None;
+            for (epoch, &l) in losses.iter().enumerate() {
+                p[0].set_data(l);
+                if stop.check(l, &p) {
+                    stopped = Some(epoch + 1);
+                    break;
+                }
+            }
+
+            // 0.48 is within min_delta of 0.5, and so is no better
+            assert_eq!(stopped, Some(4));
+            assert_eq!((stop.best_loss(), stop.best_epoch()), (Some(0.5), 2));
+
+            stop.restore(&p);
+            assert_eq!(p[0].data(), 0.5);
+        }
+
+        {
+            // Restoring before any check leaves the weights alone
+            let p: Vec<Val> = vec![Val::new(3.0)];
+            let stop: EarlyStopping = EarlyStopping::new(0);
+            assert!(!stop.should_stop());
+            stop.restore(&p);
+            assert_eq!(p[0].data(), 3.0);
+        }
+
+        {
+            // Noisy linear data: stops once validation loss bottoms out, and
+            // leaves the model at its best epoch
+            let ds: InMemoryDataset = synthetic::linear(60, &[1.5], -0.5, 0.3, &mut Rng::new(2));
+            let parts: Vec<InMemoryDataset> = split(&ds, &[0.5, 0.5], 1);
+            let train: &[(Vec<f64>, Vec<f64>)] = parts[0].examples();
+            let val: &[(Vec<f64>, Vec<f64>)] = parts[1].examples();
+
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.1);
+            let mut validation = Validation::new(val, mse);
+            let mut stop: EarlyStopping = EarlyStopping::new(3).min_delta(1e-4);
+
+            let history: Vec<f64> = fit_with(&model, train, &mut opt, mse, 500, &BatchConfig::new(train.len()), &mut [&mut validation, &mut stop]);
+
+            assert!(history.len() < 500);
+            assert_eq!(history.len(), stop.best_epoch() + 3);
+            assert_eq!(Some(evaluate(&model, val, mse)), stop.best_loss());
+        }
+    }
+}
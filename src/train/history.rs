@@ -0,0 +1,183 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
+
+use crate::json::Json;
+use crate::Float;
+
+use super::{Callback, TrainState};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    // A header line, then comma separated rows
+    Csv,
+    // One JSON object per line
+    JsonLines
+}
+
+
+// Appends a row per batch: epoch, batch, loss, learning rate and the norm of
+// the gradient the optimizer just stepped with, plus accuracy if asked for.
+// Rows are buffered and flushed at the end of every epoch.
+pub struct HistoryLogger<W: Write = BufWriter<fs::File>> {
+    out:      W,
+    format:   HistoryFormat,
+    accuracy: bool,
+    header:   bool
+}
+
+
+impl HistoryLogger {
+    // Appends to the file if it exists; a CSV header is only written to an empty one
+    pub fn create(path: &str, format: HistoryFormat) -> io::Result<HistoryLogger> {
+        let file: fs::File = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty: bool = file.metadata()?.len() == 0;
+
+        let mut logger: HistoryLogger = HistoryLogger::with_writer(BufWriter::new(file), format);
+        logger.header = empty;
+        return Ok(logger);
+    }
+}
+
+
+impl<W: Write> HistoryLogger<W> {
+    pub fn with_writer(out: W, format: HistoryFormat) -> HistoryLogger<W> {
+        return HistoryLogger { out, format, accuracy: false, header: true };
+    }
+
+    // The batch's classification accuracy: outputs give a score per class and
+    // targets hold the class index
+    pub fn accuracy(mut self) -> HistoryLogger<W> {
+        self.accuracy = true;
+        return self;
+    }
+
+    pub fn writer(&self) -> &W {
+        return &self.out;
+    }
+
+    pub fn into_inner(self) -> W {
+        return self.out;
+    }
+
+    fn columns(&self) -> Vec<&'static str> {
+        let mut cols: Vec<&'static str> = vec!["epoch", "batch", "loss", "lr", "grad_norm"];
+        if self.accuracy {
+            cols.push("accuracy");
+        }
+        return cols;
+    }
+
+    fn write_row(&mut self, values: &[f64]) -> io::Result<()> {
+        let cols: Vec<&'static str> = self.columns();
+        match self.format {
+            HistoryFormat::Csv => {
+                if self.header {
+                    writeln!(self.out, "{}", cols.join(","))?;
+                    self.header = false;
+                }
+                let row: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                writeln!(self.out, "{}", row.join(","))
+            },
+            HistoryFormat::JsonLines => {
+                let fields: Vec<(String, Json)> = cols.iter().zip(values.iter())
+                    .map(|(c, &v)| (String::from(*c), Json::Num(v)))
+                    .collect();
+                writeln!(self.out, "{}", Json::Obj(fields))
+            }
+        }
+    }
+}
+
+
+fn accuracy<T: Float>(outputs: &[Vec<T>], targets: &[Vec<T>]) -> f64 {
+    let correct: usize = outputs.iter().zip(targets.iter())
+        .filter(|(out, y)| {
+            let best: Option<usize> = (0..out.len()).reduce(|a, b| if out[b] > out[a] { b } else { a });
+            best == Some(y[0].to_f64().round() as usize)
+        })
+        .count();
+    return correct as f64 / outputs.len().max(1) as f64;
+}
+
+
+// Logging is best effort: a failed write doesn't stop training
+impl<T: Float, W: Write> Callback<T> for HistoryLogger<W> {
+    fn on_batch_end(&mut self, state: &mut TrainState<T>) {
+        let grad_norm: f64 = state.model.parameters().iter()
+            .map(|p| p.grad().to_f64().powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        let mut values: Vec<f64> = vec![state.epoch as f64, state.batch as f64, state.loss, state.optimizer.lr(), grad_norm];
+        if self.accuracy {
+            values.push(accuracy(&state.outputs, &state.targets));
+        }
+        let _ = self.write_row(&values);
+    }
+
+    fn on_epoch_end(&mut self, _state: &mut TrainState<T>) {
+        let _ = self.out.flush();
+    }
+
+    fn on_train_end(&mut self, _state: &mut TrainState<T>) {
+        let _ = self.out.flush();
+    }
+}
+
+
+
+#[cfg(test)]
+mod history_ops {
+    use super::*;
+    use crate::loss::{cross_entropy, mse};
+    use crate::nn::{Layer, Module, Neuron};
+    use crate::optim::SGD;
+    use crate::train::{fit_with, BatchConfig};
+
+    #[test]
+    fn logging() {
+        {
+            assert_eq!(accuracy(&[vec![0.1, 0.9], vec![0.8, 0.2], vec![0.3, 0.7]], &[vec![1.0], vec![1.0], vec![1.0]]), 2.0 / 3.0);
+        }
+
+        {
+            // One step from w = b = 0 on y = 2: loss 4, gradient (-4, -4)
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.1);
+            let mut log: HistoryLogger<Vec<u8>> = HistoryLogger::with_writer(Vec::new(), HistoryFormat::Csv);
+            fit_with(&model, &[(vec![1.0], vec![2.0])], &mut opt, mse, 2, &BatchConfig::new(1), &mut [&mut log]);
+
+            let text: String = String::from_utf8(log.into_inner()).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0], "epoch,batch,loss,lr,grad_norm");
+            assert_eq!(lines[1], format!("0,0,4,0.1,{}", 32.0_f64.sqrt()));
+            assert!(lines[2].starts_with("1,0,"));
+        }
+
+        {
+            let model: Layer = Layer::new(2, 3, false);
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..4).map(|i| (vec![i as f64, 1.0], vec![(i % 3) as f64])).collect();
+            let mut opt: SGD = SGD::new(model.parameters(), 0.1);
+            let loss = |out: &[crate::Val], y: &[f64]| cross_entropy(out, y[0] as usize);
+
+            let path: String = std::env::temp_dir().join("rusty_nn_history.jsonl").to_string_lossy().into_owned();
+            let _ = fs::remove_file(&path);
+            for _ in 0..2 {
+                let mut log: HistoryLogger = HistoryLogger::create(&path, HistoryFormat::JsonLines).unwrap().accuracy();
+                fit_with(&model, &data, &mut opt, loss, 1, &BatchConfig::new(2), &mut [&mut log]);
+            }
+            let text: String = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            // Appended, not overwritten
+            let rows: Vec<Json> = text.lines().map(|l| Json::parse(l).unwrap()).collect();
+            assert_eq!(rows.len(), 4);
+            assert_eq!(rows[1].get("batch").and_then(Json::as_f64), Some(1.0));
+            // Zero weights score every class alike, so argmax picks class 0
+            assert_eq!(rows[0].get("accuracy").and_then(Json::as_f64), Some(0.5));
+            assert!(rows.iter().all(|r| r.get("grad_norm").and_then(Json::as_f64).is_some_and(|g| g > 0.0)));
+        }
+    }
+}
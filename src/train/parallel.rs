@@ -0,0 +1,160 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::nn::Module;
+use crate::optim::Optimizer;
+use crate::rand::{self, Rng};
+use crate::{ops, Float, Val};
+
+use super::BatchConfig;
+
+
+// A slice of one batch for a worker: the current parameters and buffers, and its examples
+struct Job<T> {
+    params:   Arc<Vec<T>>,
+    buffers:  Arc<Vec<T>>,
+    examples: Vec<usize>
+}
+
+
+// What a worker sends back: its gradient sums, total loss and updated buffers
+type Partial<T> = (Vec<T>, f64, Vec<T>);
+
+// The main thread's ends of a worker's two channels
+type Worker<T> = (Sender<Job<T>>, Receiver<Partial<T>>);
+
+
+// fit_batched with each batch split across config.threads worker threads.
+// Graphs are Rc-based and can't leave the thread that built them, so every
+// worker makes its own replica with `build` once, then per batch loads the
+// current parameters, runs forward_examples and backward on its share of the
+// examples and sends back plain gradient sums. The summed, averaged gradients
+// go on `model`, which the optimizer steps as usual.
+//
+// Replicas take model's training mode. Each worker's global generator, which
+// Dropout draws from, is seeded from the fit's own generator, so a run with
+// config.seed (or after set_seed) is reproducible and the workers' masks differ.
+//
+// Batches are split into near-equal shares of at least two examples, so
+// BatchNorm1d normalizes over each worker's share rather than the whole batch.
+// Its running statistics go out with the parameters and the replicas' updates
+// are averaged back into model's, weighted by share.
+pub fn fit_parallel<T, M, B, O, L>(build: B, model: &M, dataset: &[(Vec<T>, Vec<T>)], optimizer: &mut O, loss_fn: L, epochs: usize, config: &BatchConfig) -> Vec<f64>
+where T: Float + Send + Sync,
+      M: Module<T>,
+      B: Fn() -> M + Sync,
+      O: Optimizer,
+      L: Fn(&[Val<T>], &[T]) -> Val<T> + Sync,
+{
+    assert!(!dataset.is_empty(), "fit on an empty dataset");
+    assert!(config.threads > 0, "fit_parallel needs at least one thread");
+
+    let mut rng: Rng = match config.seed {
+        Some(seed) => Rng::new(seed),
+        None       => rand::fork()
+    };
+    let mut order: Vec<usize> = (0..dataset.len()).collect();
+    let params: Vec<Val<T>> = model.parameters();
+    let frozen: Vec<bool> = params.iter().map(|p| !p.requires_grad()).collect();
+    let training: bool = model.is_training();
+
+    // From a copy, so the shuffles are the same as fit_batched's
+    let mut seeds: Rng = Rng::new(rng.clone().next_u64());
+
+    return thread::scope(|scope| {
+        let mut workers: Vec<Worker<T>> = Vec::with_capacity(config.threads);
+        for _ in 0..config.threads {
+            let (job_tx, job_rx): (Sender<Job<T>>, Receiver<Job<T>>) = mpsc::channel();
+            let (result_tx, result_rx): (Sender<Partial<T>>, Receiver<Partial<T>>) = mpsc::channel();
+            let (build, loss_fn, frozen): (&B, &L, &[bool]) = (&build, &loss_fn, &frozen);
+            let seed: u64 = seeds.next_u64();
+
+            scope.spawn(move || {
+                rand::set_seed(seed);
+                let replica: M = build();
+                replica.set_training(training);
+                let local: Vec<Val<T>> = replica.parameters();
+                assert_eq!(local.len(), frozen.len(), "build made a model with a different number of parameters");
+                for (p, &f) in local.iter().zip(frozen.iter()) {
+                    p.set_requires_grad(!f);
+                }
+
+                for job in job_rx {
+                    for (p, &x) in local.iter().zip(job.params.iter()) {
+                        p.set_data(x);
+                        p.set_grad(T::zero());
+                    }
+                    replica.load_buffers(&job.buffers);
+
+                    // The whole share at once, as fit_batched passes a batch
+                    let inputs: Vec<Vec<Val<T>>> = job.examples.iter()
+                        .map(|&i| dataset[i].0.iter().map(|&xi| Val::constant(xi)).collect())
+                        .collect();
+                    let losses: Vec<Val<T>> = replica.forward_examples(&inputs).iter()
+                        .zip(job.examples.iter())
+                        .map(|(out, &i)| loss_fn(out, &dataset[i].1))
+                        .collect();
+                    let loss: Val<T> = ops::sum(&losses);
+                    loss.backward();
+
+                    let partial: Partial<T> = (local.iter().map(|p| p.grad()).collect(), loss.data().to_f64(), replica.buffers());
+                    if result_tx.send(partial).is_err() {
+                        return;
+                    }
+                }
+            });
+            workers.push((job_tx, result_rx));
+        }
+
+        let mut history: Vec<f64> = Vec::with_capacity(epochs);
+        for _ in 0..epochs {
+            if config.shuffle {
+                rng.shuffle(&mut order);
+            }
+
+            let mut total: f64 = 0.0;
+            for batch in order.chunks(config.batch_size) {
+                let snapshot: Arc<Vec<T>> = Arc::new(params.iter().map(|p| p.data()).collect());
+                let buffers: Arc<Vec<T>> = Arc::new(model.buffers());
+                let n: usize = batch.len();
+                let busy: usize = config.threads.min(n / 2).max(1);
+                let shares: Vec<&[usize]> = (0..busy).map(|w| &batch[w * n / busy..(w + 1) * n / busy]).collect();
+                for ((tx, _), examples) in workers.iter().zip(shares.iter()) {
+                    tx.send(Job { params: Arc::clone(&snapshot), buffers: Arc::clone(&buffers), examples: examples.to_vec() })
+                        .expect("a worker thread panicked");
+                }
+
+                let mut grads: Vec<T> = vec![T::zero(); params.len()];
+                let mut updated: Vec<T> = vec![T::zero(); buffers.len()];
+                let mut loss: f64 = 0.0;
+                for ((_, rx), examples) in workers.iter().zip(shares.iter()) {
+                    let (g, l, b): Partial<T> = rx.recv().expect("a worker thread panicked");
+                    for (acc, gi) in grads.iter_mut().zip(g) {
+                        *acc += gi;
+                    }
+                    let weight: T = T::from_f64(examples.len() as f64 / n as f64);
+                    for (acc, bi) in updated.iter_mut().zip(b) {
+                        *acc += bi * weight;
+                    }
+                    loss += l;
+                }
+                model.load_buffers(&updated);
+
+                // The mean over the batch, as fit_batched's single mean-loss graph gives
+                let scale: T = T::from_f64(1.0 / batch.len() as f64);
+                for (p, g) in params.iter().zip(grads) {
+                    p.set_grad(g * scale);
+                }
+                optimizer.step();
+
+                total += loss;
+            }
+            history.push(total / dataset.len() as f64);
+        }
+
+        // Hanging up ends the workers' loops
+        drop(workers);
+        return history;
+    });
+}
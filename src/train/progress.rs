@@ -0,0 +1,157 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::Float;
+
+use super::{Callback, TrainState};
+
+
+// Prints a line per epoch to stderr: loss, validation loss if there is one,
+// throughput in examples per second, and the time left at that rate. With
+// every(n) it also prints every nth batch. Write errors are ignored, so a
+// closed stderr never stops training.
+pub struct Progress<W: Write = io::Stderr> {
+    out:      W,
+    every:    Option<usize>,
+    start:    Option<Instant>,
+    epoch:    Instant,
+    seen:     usize,
+    in_epoch: usize
+}
+
+
+impl Progress {
+    pub fn new() -> Progress {
+        return Progress::with_writer(io::stderr());
+    }
+}
+
+
+impl Default for Progress {
+    fn default() -> Progress {
+        return Progress::new();
+    }
+}
+
+
+impl<W: Write> Progress<W> {
+    pub fn with_writer(out: W) -> Progress<W> {
+        return Progress { out, every: None, start: None, epoch: Instant::now(), seen: 0, in_epoch: 0 };
+    }
+
+    pub fn every(mut self, batches: usize) -> Progress<W> {
+        assert!(batches > 0, "report interval must be positive");
+        self.every = Some(batches);
+        return self;
+    }
+
+    pub fn writer(&self) -> &W {
+        return &self.out;
+    }
+
+    pub fn into_inner(self) -> W {
+        return self.out;
+    }
+}
+
+
+fn rate(examples: usize, elapsed: Duration) -> f64 {
+    let secs: f64 = elapsed.as_secs_f64();
+    return if secs > 0.0 { examples as f64 / secs } else { 0.0 };
+}
+
+
+// 75s as 1m15s, 3700s as 1h01m
+fn duration(secs: f64) -> String {
+    let s: u64 = secs.round() as u64;
+    return match s {
+        0..60    => format!("{}s", s),
+        60..3600 => format!("{}m{:02}s", s / 60, s % 60),
+        _        => format!("{}h{:02}m", s / 3600, s % 3600 / 60)
+    };
+}
+
+
+impl<T: Float, W: Write> Callback<T> for Progress<W> {
+    fn on_epoch_start(&mut self, _state: &mut TrainState<T>) {
+        let now: Instant = Instant::now();
+        self.start.get_or_insert(now);
+        self.epoch = now;
+        self.in_epoch = 0;
+    }
+
+    fn on_batch_end(&mut self, state: &mut TrainState<T>) {
+        self.seen += state.examples;
+        self.in_epoch += state.examples;
+
+        if let Some(n) = self.every {
+            if (state.batch + 1).is_multiple_of(n) || state.batch + 1 == state.batches {
+                let _ = writeln!(self.out, "epoch {}/{}  batch {}/{}  loss {:.6}  {:.0} ex/s",
+                    state.epoch + 1, state.epochs, state.batch + 1, state.batches, state.loss,
+                    rate(self.in_epoch, self.epoch.elapsed()));
+            }
+        }
+    }
+
+    fn on_epoch_end(&mut self, state: &mut TrainState<T>) {
+        let total: Duration = self.start.map_or(Duration::ZERO, |s| s.elapsed());
+        let per_epoch: f64 = total.as_secs_f64() / (state.epoch + 1) as f64;
+        let left: f64 = per_epoch * (state.epochs - state.epoch - 1) as f64;
+
+        let mut line: String = format!("epoch {}/{}  loss {:.6}", state.epoch + 1, state.epochs, state.loss);
+        if let Some(v) = state.val_loss {
+            line += &format!("  val {:.6}", v);
+        }
+        line += &format!("  {:.0} ex/s  eta {}", rate(self.in_epoch, self.epoch.elapsed()), duration(left));
+
+        let _ = writeln!(self.out, "{}", line);
+        let _ = self.out.flush();
+    }
+
+    fn on_train_end(&mut self, _state: &mut TrainState<T>) {
+        let total: Duration = self.start.map_or(Duration::ZERO, |s| s.elapsed());
+        let _ = writeln!(self.out, "trained on {} examples in {}", self.seen, duration(total.as_secs_f64()));
+        let _ = self.out.flush();
+    }
+}
+
+
+
+#[cfg(test)]
+mod progress_ops {
+    use super::*;
+    use crate::loss::mse;
+    use crate::nn::{Module, Neuron};
+    use crate::optim::SGD;
+    use crate::train::{fit_with, BatchConfig, Validation};
+
+    #[test]
+    fn report() {
+        {
+            assert_eq!(duration(4.4), "4s");
+            assert_eq!(duration(75.0), "1m15s");
+            assert_eq!(duration(3700.0), "1h01m");
+            assert_eq!(rate(10, Duration::ZERO), 0.0);
+            assert_eq!(rate(10, Duration::from_millis(500)), 20.0);
+        }
+
+        {
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..5).map(|i| (vec![i as f64], vec![0.0])).collect();
+            let model: Neuron = Neuron::new(1, false);
+            let mut opt: SGD = SGD::new(model.parameters(), 0.0);
+            let mut validation = Validation::new(&data[..2], mse);
+            let mut progress: Progress<Vec<u8>> = Progress::with_writer(Vec::new()).every(2);
+
+            fit_with(&model, &data, &mut opt, mse, 2, &BatchConfig::new(2), &mut [&mut validation, &mut progress]);
+
+            let text: String = String::from_utf8(progress.into_inner()).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), 2 * 3 + 1);
+            assert!(lines[0].starts_with("epoch 1/2  batch 2/3  loss 0.000000  "));
+            assert!(lines[1].starts_with("epoch 1/2  batch 3/3  loss 0.000000  "));
+            assert!(lines[2].starts_with("epoch 1/2  loss 0.000000  val 0.000000  "));
+            assert!(lines[5].contains(" ex/s  eta 0s"));
+            assert!(lines[6].starts_with("trained on 10 examples in "));
+        }
+    }
+}
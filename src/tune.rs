@@ -0,0 +1,225 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::rand::Rng;
+
+
+// One setting to try. What the fields mean is up to the objective handed to
+// search; hidden is typically the MLP's hidden layer sizes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperparams {
+    pub lr:           f64,
+    pub hidden:       Vec<usize>,
+    pub weight_decay: f64
+}
+
+
+// The values each hyperparameter may take. Unset ones keep a single default:
+// lr 0.01, one hidden layer of 16, no weight decay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Space {
+    lr:           Vec<f64>,
+    hidden:       Vec<Vec<usize>>,
+    weight_decay: Vec<f64>
+}
+
+
+impl Space {
+    pub fn new() -> Space {
+        return Space { lr: vec![0.01], hidden: vec![vec![16]], weight_decay: vec![0.0] };
+    }
+
+    pub fn lr(mut self, lr: &[f64]) -> Space {
+        assert!(!lr.is_empty(), "expected at least one learning rate");
+        self.lr = lr.to_vec();
+        return self;
+    }
+
+    pub fn hidden(mut self, hidden: &[&[usize]]) -> Space {
+        assert!(!hidden.is_empty(), "expected at least one hidden layout");
+        self.hidden = hidden.iter().map(|h| h.to_vec()).collect();
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: &[f64]) -> Space {
+        assert!(!weight_decay.is_empty(), "expected at least one weight decay");
+        self.weight_decay = weight_decay.to_vec();
+        return self;
+    }
+
+    // Every combination, lr varying slowest
+    pub fn grid(&self) -> Vec<Hyperparams> {
+        let mut out: Vec<Hyperparams> = Vec::with_capacity(self.lr.len() * self.hidden.len() * self.weight_decay.len());
+        for &lr in self.lr.iter() {
+            for hidden in self.hidden.iter() {
+                for &weight_decay in self.weight_decay.iter() {
+                    out.push(Hyperparams { lr, hidden: hidden.clone(), weight_decay });
+                }
+            }
+        }
+
+        return out;
+    }
+
+    // n settings, each value picked uniformly from its list; repeats are possible
+    pub fn sample(&self, n: usize, rng: &mut Rng) -> Vec<Hyperparams> {
+        return (0..n)
+            .map(|_| Hyperparams {
+                lr:           self.lr[rng.below(self.lr.len())],
+                hidden:       self.hidden[rng.below(self.hidden.len())].clone(),
+                weight_decay: self.weight_decay[rng.below(self.weight_decay.len())]
+            })
+            .collect();
+    }
+}
+
+
+impl Default for Space {
+    fn default() -> Space {
+        return Space::new();
+    }
+}
+
+
+// A setting and the score its run got
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    pub params: Hyperparams,
+    pub score:  f64
+}
+
+
+// Runs `objective` once per candidate and ranks the results, lowest score
+// first (NaN last), so a validation loss can be returned as is. With more
+// than one thread the candidates are shared out across scoped threads; each
+// run builds its own model inside `objective`, since graphs can't be sent.
+pub fn search<F>(candidates: &[Hyperparams], threads: usize, objective: F) -> Vec<Trial>
+where F: Fn(&Hyperparams) -> f64 + Sync,
+{
+    assert!(threads > 0, "search needs at least one thread");
+
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let scores: Mutex<Vec<f64>> = Mutex::new(vec![f64::NAN; candidates.len()]);
+    let work = || {
+        loop {
+            let i: usize = next.fetch_add(1, Ordering::Relaxed);
+            if i >= candidates.len() {
+                return;
+            }
+            let score: f64 = objective(&candidates[i]);
+            scores.lock().expect("a search thread panicked")[i] = score;
+        }
+    };
+
+    if threads == 1 {
+        work();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..threads.min(candidates.len()) {
+                scope.spawn(work);
+            }
+        });
+    }
+
+    let scores: Vec<f64> = scores.into_inner().expect("a search thread panicked");
+    let mut trials: Vec<Trial> = candidates.iter()
+        .zip(scores)
+        .map(|(params, score)| Trial { params: params.clone(), score })
+        .collect();
+    trials.sort_by(|a, b| a.score.is_nan().cmp(&b.score.is_nan()).then(a.score.total_cmp(&b.score)));
+
+    return trials;
+}
+
+
+// The ranked trials as a table:
+//
+//   Rank  lr    hidden  weight_decay  score
+//   1     0.05  [8]     0             0.0132
+//   2     0.01  [8]     0.001         0.0871
+pub fn table(trials: &[Trial]) -> String {
+    let mut rows: Vec<[String; 5]> = vec![[String::from("Rank"), String::from("lr"), String::from("hidden"), String::from("weight_decay"), String::from("score")]];
+    for (i, t) in trials.iter().enumerate() {
+        rows.push([(i + 1).to_string(), t.params.lr.to_string(), format!("{:?}", t.params.hidden), t.params.weight_decay.to_string(), format!("{:.4}", t.score)]);
+    }
+
+    let widths: Vec<usize> = (0..5).map(|c| rows.iter().map(|r| r[c].len()).max().unwrap_or(0)).collect();
+    let mut out: String = String::new();
+    for r in rows.iter() {
+        let cells: Vec<String> = r.iter().zip(widths.iter()).map(|(cell, &w)| format!("{:<w$}", cell, w = w)).collect();
+        out += cells.join("  ").trim_end();
+        out += "\n";
+    }
+
+    return out;
+}
+
+
+
+#[cfg(test)]
+mod tune_ops {
+    use super::*;
+    use crate::init::Init;
+    use crate::loss::mse;
+    use crate::nn::{Module, MLP};
+    use crate::optim::Adam;
+    use crate::train::{evaluate, fit_batched, BatchConfig};
+
+    #[test]
+    fn spaces() {
+        {
+            let space: Space = Space::new().lr(&[0.1, 0.01]).hidden(&[&[4], &[8, 8]]).weight_decay(&[0.0, 1e-3, 1e-2]);
+            let grid: Vec<Hyperparams> = space.grid();
+            assert_eq!(grid.len(), 12);
+            assert_eq!(grid[0], Hyperparams { lr: 0.1, hidden: vec![4], weight_decay: 0.0 });
+            assert_eq!(grid[11], Hyperparams { lr: 0.01, hidden: vec![8, 8], weight_decay: 1e-2 });
+
+            let drawn: Vec<Hyperparams> = space.sample(20, &mut Rng::new(0));
+            assert_eq!(drawn.len(), 20);
+            assert!(drawn.iter().all(|h| grid.contains(h)));
+            assert_eq!(drawn, space.sample(20, &mut Rng::new(0)));
+
+            assert_eq!(Space::default().grid(), vec![Hyperparams { lr: 0.01, hidden: vec![16], weight_decay: 0.0 }]);
+        }
+    }
+
+    #[test]
+    fn ranked() {
+        {
+            // Threads don't change the scores or their order
+            let candidates: Vec<Hyperparams> = Space::new().lr(&[0.3, -1.0, 0.1, 0.2]).grid();
+            let objective = |h: &Hyperparams| -> f64 { if h.lr < 0.0 { f64::NAN } else { (h.lr - 0.22).abs() } };
+            let serial: Vec<Trial> = search(&candidates, 1, objective);
+            assert_eq!(serial.iter().map(|t| t.params.lr).collect::<Vec<f64>>(), vec![0.2, 0.3, 0.1, -1.0]);
+            assert!(serial[3].score.is_nan());
+            assert_eq!(search(&candidates, 3, objective).iter().map(|t| t.params.lr).collect::<Vec<f64>>(), vec![0.2, 0.3, 0.1, -1.0]);
+
+            let text: String = table(&serial);
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), 5);
+            assert!(lines[0].starts_with("Rank  lr  "));
+            assert!(lines[1].starts_with("1     0.2 "));
+            assert!(lines[4].ends_with("NaN"));
+        }
+
+        {
+            // A real fit per setting: a learning rate of zero can't beat a working one
+            let data: Vec<(Vec<f64>, Vec<f64>)> = (0..8).map(|i| (vec![i as f64 / 8.0], vec![(i as f64 / 4.0).sin()])).collect();
+            let objective = |h: &Hyperparams| -> f64 {
+                let mut nouts: Vec<usize> = h.hidden.clone();
+                nouts.push(1);
+                let model: MLP = MLP::with_init(1, &nouts, Init::Xavier, &mut Rng::new(7));
+                let mut opt: Adam = Adam::new(model.parameters(), h.lr).weight_decay(h.weight_decay);
+                fit_batched(&model, &data, &mut opt, mse, 40, &BatchConfig::new(8));
+                return evaluate(&model, &data, mse);
+            };
+
+            let candidates: Vec<Hyperparams> = Space::new().lr(&[0.0, 0.05]).hidden(&[&[4], &[8]]).grid();
+            let trials: Vec<Trial> = search(&candidates, 2, objective);
+            assert_eq!(trials.len(), 4);
+            assert!(trials[..2].iter().all(|t| t.params.lr == 0.05));
+            assert!(trials.windows(2).all(|w| w[0].score <= w[1].score));
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops;
+use std::rc::Rc;
+
+use crate::{GradFn, Operations, Val};
+
+
+// Given the gradient of the node, one gradient per entry of `prev`, each
+// already summed down to that child's length
+type VecBackward = Box<dyn Fn(&[f64]) -> Vec<Vec<f64>>>;
+
+
+struct VecData {
+    data:     Vec<f64>,
+    grad:     Vec<f64>,
+    prev:     Vec<VecVal>,
+    op:       Operations,
+    backward: Option<VecBackward>,
+    // Set for a broadcast view of a scalar Val; its gradient is summed back into it
+    source:   Option<Val>
+}
+
+
+impl Drop for VecData {
+    fn drop(&mut self) {
+        let mut stack: Vec<VecVal> = std::mem::take(&mut self.prev);
+        while let Some(v) = stack.pop() {
+            if let Ok(cell) = Rc::try_unwrap(v.0) {
+                stack.append(&mut cell.borrow_mut().prev);
+            }
+        }
+    }
+}
+
+
+// The scalar engine with one slot per batch element, so a batch runs through
+// a single graph. A length-1 VecVal broadcasts against any length, which is
+// how shared parameters enter (see from_val).
+#[derive(Clone)]
+pub struct VecVal(Rc<RefCell<VecData>>);
+
+
+impl VecVal {
+    pub fn new(data: Vec<f64>) -> VecVal {
+        assert!(!data.is_empty(), "VecVal needs at least one slot");
+
+        let grad: Vec<f64> = vec![0.0; data.len()];
+        let node: VecData = VecData { data, grad, prev: Vec::new(), op: Operations::Non, backward: None, source: None };
+        return VecVal(Rc::new(RefCell::new(node)));
+    }
+
+    // A length-1 view of `v` for every slot; backward adds the summed gradient to v
+    pub fn from_val(v: &Val) -> VecVal {
+        let result: VecVal = VecVal::new(vec![v.data()]);
+        if crate::is_grad_enabled() {
+            result.0.borrow_mut().source = Some(v.clone());
+        }
+
+        return result;
+    }
+
+    pub fn data(&self) -> Vec<f64> {
+        return self.0.borrow().data.clone();
+    }
+
+    pub fn grad(&self) -> Vec<f64> {
+        return self.0.borrow().grad.clone();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.0.borrow().data.len();
+    }
+
+    // Always false, since a VecVal has at least one slot
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn prev(&self) -> Vec<VecVal> {
+        return self.0.borrow().prev.clone();
+    }
+
+    pub fn op(&self) -> Operations {
+        return self.0.borrow().op;
+    }
+
+    fn from_op(data: Vec<f64>, prev: Vec<VecVal>, op: Operations, backward: VecBackward) -> VecVal {
+        let result: VecVal = VecVal::new(data);
+        if !crate::is_grad_enabled() {
+            return result;
+        }
+
+        {
+            let mut node = result.0.borrow_mut();
+            node.prev = prev;
+            node.op = op;
+            node.backward = Some(backward);
+        }
+
+        return result;
+    }
+
+    // Seeds every slot with 1, i.e. the gradient of the sum over the batch
+    pub fn backward(&self) {
+        self.propagate(vec![1.0; self.len()]);
+    }
+
+    pub fn zero_grad(&self) {
+        for node in self.topo().iter() {
+            let mut inner = node.0.borrow_mut();
+            let n: usize = inner.grad.len();
+            inner.grad = vec![0.0; n];
+        }
+    }
+
+    // Pushes `seed` back through the graph, adding each node's share into its grad
+    fn propagate(&self, seed: Vec<f64>) {
+        let mut pending: HashMap<*const RefCell<VecData>, Vec<f64>> = HashMap::new();
+        pending.insert(Rc::as_ptr(&self.0), seed);
+
+        for node in self.topo().iter().rev() {
+            let g: Vec<f64> = match pending.remove(&Rc::as_ptr(&node.0)) {
+                Some(g) => g,
+                None    => continue
+            };
+
+            let inner = node.0.borrow();
+            if let Some(func) = &inner.backward {
+                for (child, cg) in inner.prev.iter().zip(func(&g)) {
+                    let slot = pending.entry(Rc::as_ptr(&child.0)).or_insert_with(|| vec![0.0; cg.len()]);
+                    for (s, c) in slot.iter_mut().zip(cg.iter()) {
+                        *s += c;
+                    }
+                }
+            }
+            if let Some(v) = inner.source.as_ref().filter(|v| v.requires_grad()) {
+                v.add_grad(g.iter().sum());
+            }
+            drop(inner);
+
+            let mut inner = node.0.borrow_mut();
+            for (acc, d) in inner.grad.iter_mut().zip(g.iter()) {
+                *acc += d;
+            }
+        }
+    }
+
+    // Same explicit-stack walk as Val::topo
+    fn topo(&self) -> Vec<VecVal> {
+        let mut visited: HashSet<*const RefCell<VecData>> = HashSet::new();
+        let mut order: Vec<VecVal> = Vec::new();
+
+        let mut stack: Vec<(VecVal, bool)> = vec![(self.clone(), false)];
+        while let Some((v, expanded)) = stack.pop() {
+            if expanded {
+                order.push(v);
+                continue;
+            }
+            if !visited.insert(Rc::as_ptr(&v.0)) {
+                continue;
+            }
+
+            stack.push((v.clone(), true));
+            for child in v.0.borrow().prev.iter().rev() {
+                if !visited.contains(&Rc::as_ptr(&child.0)) {
+                    stack.push((child.clone(), false));
+                }
+            }
+        }
+
+        return order;
+    }
+
+    // Lengths must match, or one side must be a single broadcast slot.
+    // `local` gives d(out)/d(a) and d(out)/d(b) from a, b at one slot.
+    fn binary<F, L>(&self, rhs: &VecVal, op: Operations, f: F, local: L) -> VecVal
+    where F: Fn(f64, f64) -> f64,
+          L: Fn(f64, f64) -> (f64, f64) + 'static,
+    {
+        let (a, b): (Vec<f64>, Vec<f64>) = (self.data(), rhs.data());
+        let n: usize = a.len().max(b.len());
+        assert!(a.len() == b.len() || a.len() == 1 || b.len() == 1, "{} of VecVals with {} and {} slots", op, a.len(), b.len());
+
+        let at = |xs: &[f64], i: usize| -> f64 { if xs.len() == 1 { xs[0] } else { xs[i] } };
+        let data: Vec<f64> = (0..n).map(|i| f(at(&a, i), at(&b, i))).collect();
+
+        let backward: VecBackward = Box::new(move |g: &[f64]| {
+            let mut ga: Vec<f64> = vec![0.0; a.len()];
+            let mut gb: Vec<f64> = vec![0.0; b.len()];
+            for (i, gi) in g.iter().enumerate() {
+                let (ia, ib): (usize, usize) = (if a.len() == 1 { 0 } else { i }, if b.len() == 1 { 0 } else { i });
+                let (da, db): (f64, f64) = local(a[ia], b[ib]);
+                ga[ia] += da * gi;
+                gb[ib] += db * gi;
+            }
+            return vec![ga, gb];
+        });
+
+        return VecVal::from_op(data, vec![self.clone(), rhs.clone()], op, backward);
+    }
+
+    // `local` gives d(out)/d(x) from x and out at one slot
+    fn unary<F, L>(&self, op: Operations, f: F, local: L) -> VecVal
+    where F: Fn(f64) -> f64,
+          L: Fn(f64, f64) -> f64 + 'static,
+    {
+        let x: Vec<f64> = self.data();
+        let out: Vec<f64> = x.iter().map(|&v| f(v)).collect();
+        let ys: Vec<f64> = out.clone();
+
+        let backward: VecBackward = Box::new(move |g: &[f64]| {
+            return vec![g.iter().zip(x.iter().zip(ys.iter())).map(|(gi, (&xi, &yi))| gi * local(xi, yi)).collect()];
+        });
+
+        return VecVal::from_op(out, vec![self.clone()], op, backward);
+    }
+
+    pub fn tanh(&self) -> VecVal {
+        return self.unary(Operations::Tanh, f64::tanh, |_, t| 1.0 - t * t);
+    }
+
+    pub fn relu(&self) -> VecVal {
+        return self.unary(Operations::Relu, |x| x.max(0.0), |x, _| if x > 0.0 { 1.0 } else { 0.0 });
+    }
+
+    pub fn sigmoid(&self) -> VecVal {
+        let sig = |x: f64| -> f64 {
+            if x >= 0.0 {
+                return 1.0 / (1.0 + (-x).exp());
+            }
+            let e: f64 = x.exp();
+            return e / (1.0 + e);
+        };
+
+        return self.unary(Operations::Sigmoid, sig, |_, s| s * (1.0 - s));
+    }
+
+    pub fn exp(&self) -> VecVal {
+        return self.unary(Operations::Exp, f64::exp, |_, e| e);
+    }
+
+    // Natural log
+    pub fn log(&self) -> VecVal {
+        return self.unary(Operations::Log, f64::ln, |x, _| 1.0 / x);
+    }
+
+    pub fn pow(&self, n: f64) -> VecVal {
+        return self.unary(Operations::Pow, move |x| x.powf(n), move |x, _| n * x.powf(n - 1.0));
+    }
+
+    // Reduces the batch back into the scalar graph
+    pub fn sum(&self) -> Val {
+        return Val::apply(Reduce { src: self.clone(), mean: false }, &[]);
+    }
+
+    pub fn mean(&self) -> Val {
+        return Val::apply(Reduce { src: self.clone(), mean: true }, &[]);
+    }
+}
+
+
+struct Reduce {
+    src:  VecVal,
+    mean: bool
+}
+
+
+impl GradFn for Reduce {
+    fn op(&self) -> Operations {
+        return Operations::Sum;
+    }
+
+    fn forward(&self, _inputs: &[f64]) -> f64 {
+        let data = &self.src.0.borrow().data;
+        let total: f64 = data.iter().sum();
+        return if self.mean { total / data.len() as f64 } else { total };
+    }
+
+    fn backward(&self, _inputs: &[f64], _output: f64, grad: f64) -> Vec<f64> {
+        let n: usize = self.src.len();
+        let g: f64 = if self.mean { grad / n as f64 } else { grad };
+        self.src.propagate(vec![g; n]);
+
+        return Vec::new();
+    }
+}
+
+
+/*** Operator Overloads ***/
+
+impl ops::Add<&VecVal> for &VecVal {
+    type Output = VecVal;
+    fn add(self, rhs: &VecVal) -> VecVal {
+        return self.binary(rhs, Operations::Add, |a, b| a + b, |_, _| (1.0, 1.0));
+    }
+}
+
+
+impl ops::Sub<&VecVal> for &VecVal {
+    type Output = VecVal;
+    fn sub(self, rhs: &VecVal) -> VecVal {
+        return self.binary(rhs, Operations::Sub, |a, b| a - b, |_, _| (1.0, -1.0));
+    }
+}
+
+
+impl ops::Mul<&VecVal> for &VecVal {
+    type Output = VecVal;
+    fn mul(self, rhs: &VecVal) -> VecVal {
+        return self.binary(rhs, Operations::Mul, |a, b| a * b, |a, b| (b, a));
+    }
+}
+
+
+// Constants broadcast as single slots that never see their gradient
+impl ops::Add<f64> for &VecVal {
+    type Output = VecVal;
+    fn add(self, rhs: f64) -> VecVal {
+        return self + &VecVal::new(vec![rhs]);
+    }
+}
+
+
+impl ops::Sub<f64> for &VecVal {
+    type Output = VecVal;
+    fn sub(self, rhs: f64) -> VecVal {
+        return self - &VecVal::new(vec![rhs]);
+    }
+}
+
+
+impl ops::Mul<f64> for &VecVal {
+    type Output = VecVal;
+    fn mul(self, rhs: f64) -> VecVal {
+        return self * &VecVal::new(vec![rhs]);
+    }
+}
+
+
+impl ops::Add<&VecVal> for f64 {
+    type Output = VecVal;
+    fn add(self, rhs: &VecVal) -> VecVal {
+        return &VecVal::new(vec![self]) + rhs;
+    }
+}
+
+
+impl ops::Sub<&VecVal> for f64 {
+    type Output = VecVal;
+    fn sub(self, rhs: &VecVal) -> VecVal {
+        return &VecVal::new(vec![self]) - rhs;
+    }
+}
+
+
+impl ops::Mul<&VecVal> for f64 {
+    type Output = VecVal;
+    fn mul(self, rhs: &VecVal) -> VecVal {
+        return &VecVal::new(vec![self]) * rhs;
+    }
+}
+
+
+impl ops::Neg for &VecVal {
+    type Output = VecVal;
+    fn neg(self) -> VecVal {
+        return self * -1.0;
+    }
+}
+/*** End Overloads ***/
+
+
+impl fmt::Display for VecVal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Data: {:?}, Grad: {:?}, Op: {}", self.data(), self.grad(), self.op());
+    }
+}
+
+
+
+#[cfg(test)]
+mod vecval_ops {
+    use super::*;
+    use crate::nn::{Layer, Module, Neuron, MLP};
+    // Helper function for floating point arithmetic
+    fn approx_eq(a: f64, b: f64) -> bool {
+        return (a - b).abs() < 1e-12;
+    }
+
+    #[test]
+    fn vecval() {
+        {
+            let a: VecVal = VecVal::new(vec![1.0, 2.0, 3.0]);
+            let b: VecVal = VecVal::new(vec![0.5, -1.0, 2.0]);
+            let c: VecVal = &(&a * &b) + 1.0;
+
+            assert_eq!(c.len(), 3);
+            assert_eq!(c.data(), vec![1.5, -1.0, 7.0]);
+            assert_eq!(c.op(), Operations::Add);
+
+            c.backward();
+            assert_eq!(a.grad(), vec![0.5, -1.0, 2.0]);
+            assert_eq!(b.grad(), vec![1.0, 2.0, 3.0]);
+
+            c.zero_grad();
+            assert_eq!(a.grad(), vec![0.0, 0.0, 0.0]);
+        }
+
+        {
+            // Activations match the scalar engine slot by slot
+            let xs: Vec<f64> = vec![-1.5, 0.2, 0.9];
+            let v: VecVal = VecVal::new(xs.clone());
+            let out: VecVal = &(&v.tanh() * &v.sigmoid()) + &(&v.exp().log() - &v.relu().pow(2.0));
+            out.backward();
+
+            for (i, &x) in xs.iter().enumerate() {
+                let s: Val = Val::new(x);
+                let o: Val = s.clone().tanh() * s.clone().sigmoid() + (s.clone().exp().log() - s.clone().relu().pow(2.0));
+                o.backward();
+
+                assert!(approx_eq(out.data()[i], o.data()));
+                assert!(approx_eq(v.grad()[i], s.grad()));
+            }
+        }
+    }
+
+    #[test]
+    fn broadcast() {
+        {
+            let w: Val = Val::new(2.0);
+            let x: VecVal = VecVal::new(vec![1.0, 2.0, 3.0]);
+            let y: VecVal = &(&VecVal::from_val(&w) * &x) - 1.0;
+
+            assert_eq!(y.data(), vec![1.0, 3.0, 5.0]);
+
+            // d/dw mean(w x - 1) = mean(x)
+            let loss: Val = y.mean();
+            assert!(approx_eq(loss.data(), 3.0));
+            loss.backward();
+            assert!(approx_eq(w.grad(), 2.0));
+            assert_eq!(x.grad(), vec![2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "slots")]
+    fn broadcast_mismatch() {
+        let a: VecVal = VecVal::new(vec![1.0, 2.0]);
+        let b: VecVal = VecVal::new(vec![1.0, 2.0, 3.0]);
+        let _ = &a + &b;
+    }
+
+    #[test]
+    fn batch_forward() {
+        {
+            // One batched graph gives the gradients of N per-example graphs
+            let m: MLP = MLP::from_layers(vec![
+                Layer::from_neurons(vec![
+                    Neuron::from_weights(&[0.5, -0.4], 0.1, true),
+                    Neuron::from_weights(&[-0.3, 0.6], -0.2, true)
+                ]),
+                Layer::from_neurons(vec![Neuron::from_weights(&[0.4, -0.5], 0.0, false)])
+            ]);
+            let batch: Vec<[f64; 3]> = vec![[0.0, 1.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, -1.0], [0.2, -0.7, 0.5]];
+
+            let mut expected: f64 = 0.0;
+            for row in batch.iter() {
+                let out: Vec<Val> = m.forward(&[Val::new(row[0]), Val::new(row[1])]);
+                let d: Val = &out[0] - row[2];
+                let loss: Val = &d * &d * (1.0 / batch.len() as f64);
+                expected += loss.data();
+                loss.backward();
+            }
+            let per_example: Vec<f64> = m.parameters().iter().map(|p| p.grad()).collect();
+            m.parameters().iter().for_each(|p| p.set_grad(0.0));
+
+            let x: Vec<VecVal> = (0..2).map(|j| VecVal::new(batch.iter().map(|r| r[j]).collect())).collect();
+            let y: VecVal = VecVal::new(batch.iter().map(|r| r[2]).collect());
+            let out: Vec<VecVal> = m.forward_batch(&x);
+            let loss: Val = (&out[0] - &y).pow(2.0).mean();
+            loss.backward();
+
+            assert!(approx_eq(loss.data(), expected));
+            for (p, g) in m.parameters().iter().zip(per_example.iter()) {
+                assert!(approx_eq(p.grad(), *g));
+            }
+        }
+    }
+}